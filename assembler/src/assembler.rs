@@ -6,12 +6,28 @@ use crate::ast::AST;
 pub struct AssemblerOutput {
     pub sections: Vec<AsmSection>,
     pub symbols: Vec<AsmSymbol>,
+    /// Load address set with `org`; only meaningful for flat binary output.
+    pub org: Option<u64>,
 }
 
 pub struct AsmSection {
     pub name: String,
     pub data: Vec<u8>,
     pub relocs: Vec<Relocation>,
+    pub attrs: SectionAttrs,
+}
+
+/// Placement attributes from the `section` line, used by flat binary output.
+#[derive(Debug, Clone, Default)]
+pub struct SectionAttrs {
+    /// `align=`: alignment of the section in the image.
+    pub align: Option<u64>,
+    /// `start=`: file offset of the section, relative to `org`.
+    pub start: Option<u64>,
+    /// `vstart=`: address the section's code is assembled for, if not its load address.
+    pub vstart: Option<u64>,
+    /// `follows=`: place the section directly after the named one.
+    pub follows: Option<String>,
 }
 
 pub struct AsmSymbol {
@@ -29,19 +45,71 @@ pub struct Relocation {
     pub addend: i64,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum RelocKind {
     Absolute64,
+    Absolute32,
+    Absolute32S,
+    Absolute16,
     Relative32,
+    Relative16,
+    Plt32,
+    GotPcRel,
+    GotPcRelX,
+    RexGotPcRelX,
+    TpOff32,
+    GotTpOff,
+    TlsGd,
+    TlsLd,
+    DtpOff32,
+}
+
+impl RelocKind {
+    /// Whether the field is resolved relative to its own address, so the
+    /// addend must account for the distance to the end of the instruction.
+    pub fn is_pc_relative(&self) -> bool {
+        matches!(
+            self,
+            RelocKind::Relative32
+                | RelocKind::Relative16
+                | RelocKind::Plt32
+                | RelocKind::GotPcRel
+                | RelocKind::GotPcRelX
+                | RelocKind::RexGotPcRelX
+                | RelocKind::GotTpOff
+                | RelocKind::TlsGd
+                | RelocKind::TlsLd
+        )
+    }
+}
+
+/// Options that influence how instructions are encoded.
+#[derive(Debug, Clone)]
+pub struct AsmOptions {
+    /// Select the shortest equivalent encoding (e.g. `mov eax, 1` for `mov rax, 1`).
+    pub optimize: bool,
+    /// Start global functions in `.text` sections with `endbr64` (`endbr32` in
+    /// 32-bit code), as indirect branch tracking (IBT) requires.
+    pub endbr: bool,
+}
+
+impl Default for AsmOptions {
+    fn default() -> Self {
+        Self { optimize: true, endbr: false }
+    }
 }
 
 pub fn assemble(source: &str, isa: &impl ISA) -> Result<AssemblerOutput, AsmError> {
+    assemble_with_options(source, isa, &AsmOptions::default())
+}
+
+pub fn assemble_with_options(source: &str, isa: &impl ISA, options: &AsmOptions) -> Result<AssemblerOutput, AsmError> {
     let tokens = tokenize(source)
         .map_err(|e| AsmError::LexerError(e.to_string()))?;
 
     let ast: AST = isa.parse(&tokens)?;
 
-    let encoded = isa.encode(&ast)?;
+    let encoded = isa.encode(&ast, options)?;
 
     Ok(encoded)
 }
\ No newline at end of file
@@ -8,9 +8,38 @@ pub enum ASTNode {
     Instruction(Instruction),
     Directive(Directive),
     Label(String),
-    Section(String),
+    Section(String, Vec<SectionAttr>),
     Global(String),
     Extern(String),
+    /// `times <count> <line>`: repeats an instruction or data directive.
+    Times(Expr, Box<ASTNode>),
+}
+
+/// `key=value` attribute on a `section` line (`align=16`, `vstart=0x7C00`, `follows=.text`).
+#[derive(Debug, Clone)]
+pub struct SectionAttr {
+    pub key: String,
+    pub value: DirectiveValue,
+}
+
+/// Assemble-time expression, as used by `times`.
+#[derive(Debug, Clone)]
+pub enum Expr {
+    Number(i64),
+    /// `$`: the offset of the current line.
+    Here,
+    /// `$$`: the start of the current section.
+    SectionStart,
+    Neg(Box<Expr>),
+    Binary(BinOp, Box<Expr>, Box<Expr>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BinOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
 }
 
 #[derive(Debug, Clone)]
@@ -37,13 +66,30 @@ pub enum Operand {
     Register(String),
     Immediate(i64),
     Label(String),
+    LabelWrt(String, Wrt),
     Memory(MemoryOperand)
 }
 
+/// Relocation operator selected with NASM's `wrt ..<name>` syntax.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Wrt {
+    Plt,
+    GotPcRel,
+    TpOff,
+    GotTpOff,
+    TlsGd,
+    TlsLd,
+    DtpOff,
+}
+
 #[derive(Debug, Clone)]
 pub struct MemoryOperand {
     pub base: Option<String>,
     pub index: Option<String>,
     pub scale: u8,
     pub disp: i64,
+    pub segment: Option<String>,
+    pub symbol: Option<String>,
+    pub wrt: Option<Wrt>,
+    pub rip_relative: bool,
 }
\ No newline at end of file
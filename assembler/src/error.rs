@@ -6,6 +6,7 @@ pub enum AsmError {
     ParserError(String),
     EncodeError(String),
     SymbolError(String),
+    LayoutError(String),
     UnexpectedToken(String),
 }
 
@@ -16,6 +17,7 @@ impl fmt::Display for AsmError {
             AsmError::ParserError(s) => write!(f, "Parser error: {}", s),
             AsmError::EncodeError(s) => write!(f, "Encode error: {}", s),
             AsmError::SymbolError(s) => write!(f, "Symbol error: {}", s),
+            AsmError::LayoutError(s) => write!(f, "Layout error: {}", s),
             AsmError::UnexpectedToken(s) => write!(f, "Unexpected token: {}", s),
         }
     }
@@ -0,0 +1,151 @@
+//! Flat binary output: sections are laid out after `org` and every relocation
+//! is resolved to an absolute address, like NASM's `-f bin`.
+//!
+//! Sections are placed in declaration order unless `follows=` moves them after
+//! another section. Each starts at its `start=` offset or at the next multiple of
+//! its `align=` (default 4). `.bss` sections take up address space after the last
+//! initialized section but are not written to the file.
+
+use std::collections::HashMap;
+
+use crate::assembler::{AsmSection, AssemblerOutput, RelocKind};
+use crate::error::AsmError;
+
+const DEFAULT_ALIGN: u64 = 4;
+
+/// Where a section ends up: its offset in the file (if it has file contents) and
+/// the address its code was assembled for.
+#[derive(Debug, Clone, Copy)]
+struct FlatPlacement {
+    file_offset: Option<u64>,
+    address: u64,
+}
+
+/// Computes the placement of every section of `out`, indexed like `out.sections`.
+fn layout(out: &AssemblerOutput) -> Result<Vec<FlatPlacement>, AsmError> {
+    let org = out.org.unwrap_or(0);
+    let mut placements: Vec<Option<FlatPlacement>> = vec![None; out.sections.len()];
+
+    let mut pos = 0;
+    for idx in file_order(&out.sections)? {
+        let sec = &out.sections[idx];
+        let offset = match sec.attrs.start {
+            Some(start) if start < pos => {
+                return Err(AsmError::LayoutError(format!("section {} at start={:#x} overlaps the previous section", sec.name, start)));
+            }
+            Some(start) => start,
+            None => align_up(pos, sec.attrs.align.unwrap_or(DEFAULT_ALIGN)),
+        };
+        pos = offset + sec.data.len() as u64;
+        placements[idx] = Some(FlatPlacement {
+            file_offset: Some(offset),
+            address: sec.attrs.vstart.unwrap_or(org + offset),
+        });
+    }
+
+    let mut address = org + pos;
+    for (idx, sec) in out.sections.iter().enumerate().filter(|(_, s)| is_nobits(s)) {
+        if sec.data.iter().any(|&b| b != 0) {
+            return Err(AsmError::LayoutError(format!("section {} is not written to the file and must only contain zeroes", sec.name)));
+        }
+        let start = sec.attrs.vstart.unwrap_or_else(|| align_up(address, sec.attrs.align.unwrap_or(DEFAULT_ALIGN)));
+        address = start + sec.data.len() as u64;
+        placements[idx] = Some(FlatPlacement { file_offset: None, address: start });
+    }
+
+    Ok(placements.into_iter().map(|p| p.expect("every section is placed")).collect())
+}
+
+/// Lays out the sections and resolves all relocations, returning the file image.
+///
+/// The image starts at `org`; gaps left by alignment are zero-filled.
+pub fn link_flat(out: &AssemblerOutput) -> Result<Vec<u8>, AsmError> {
+    let placements = layout(out)?;
+
+    // Relocations against a section name refer to its start
+    let mut addresses = HashMap::new();
+    for (sec, placement) in out.sections.iter().zip(&placements) {
+        addresses.insert(sec.name.as_str(), placement.address);
+    }
+    for sym in &out.symbols {
+        if let Some(idx) = sym.section_index {
+            addresses.insert(sym.name.as_str(), placements[idx].address + sym.offset as u64);
+        }
+    }
+
+    let size = out.sections.iter().zip(&placements)
+        .filter_map(|(sec, p)| p.file_offset.map(|off| off + sec.data.len() as u64))
+        .max()
+        .unwrap_or(0);
+    let mut image = vec![0u8; size as usize];
+
+    for (sec, placement) in out.sections.iter().zip(&placements) {
+        let Some(file_offset) = placement.file_offset else { continue };
+        let data = &mut image[file_offset as usize..][..sec.data.len()];
+        data.copy_from_slice(&sec.data);
+
+        for r in &sec.relocs {
+            let target = *addresses.get(r.symbol.as_str()).ok_or_else(|| {
+                AsmError::SymbolError(format!("undefined symbol {} (flat binaries cannot reference externals)", r.symbol))
+            })?;
+            let place = placement.address + r.offset as u64;
+            let value = (target as i64).wrapping_add(r.addend);
+            let value = if r.kind.is_pc_relative() { value.wrapping_sub(place as i64) } else { value };
+
+            let (width, fits) = match r.kind {
+                RelocKind::Absolute64 => (8, true),
+                RelocKind::Absolute32 => (4, (0..=u32::MAX as i64).contains(&value)),
+                RelocKind::Absolute32S | RelocKind::Relative32 => (4, i32::try_from(value).is_ok()),
+                RelocKind::Absolute16 => (2, (0..=u16::MAX as i64).contains(&value)),
+                RelocKind::Relative16 => (2, i16::try_from(value).is_ok()),
+                kind => {
+                    return Err(AsmError::LayoutError(format!("{:?} relocation against {} cannot be used in a flat binary", kind, r.symbol)));
+                }
+            };
+            if !fits {
+                return Err(AsmError::LayoutError(format!(
+                    "{:?} relocation against {} in {}+{:#x}: value {:#x} does not fit",
+                    r.kind, r.symbol, sec.name, r.offset, value
+                )));
+            }
+            data[r.offset..r.offset + width].copy_from_slice(&value.to_le_bytes()[..width]);
+        }
+    }
+
+    Ok(image)
+}
+
+fn is_nobits(sec: &AsmSection) -> bool {
+    sec.name == ".bss" || sec.name.starts_with(".bss.")
+}
+
+fn align_up(value: u64, align: u64) -> u64 {
+    value.div_ceil(align) * align
+}
+
+/// Orders the sections that have file contents: declaration order, with every
+/// `follows=` section moved directly after the section it names.
+fn file_order(sections: &[AsmSection]) -> Result<Vec<usize>, AsmError> {
+    let progbits: Vec<usize> = (0..sections.len()).filter(|&i| !is_nobits(&sections[i])).collect();
+    for &idx in &progbits {
+        if let Some(target) = &sections[idx].attrs.follows {
+            if !progbits.iter().any(|&i| sections[i].name == *target) {
+                return Err(AsmError::LayoutError(format!("section {} follows unknown section {}", sections[idx].name, target)));
+            }
+        }
+    }
+
+    let mut order = Vec::new();
+    let mut stack: Vec<usize> = progbits.iter().rev().copied().filter(|&i| sections[i].attrs.follows.is_none()).collect();
+    while let Some(idx) = stack.pop() {
+        order.push(idx);
+        let followers = progbits.iter().rev().copied().filter(|&i| sections[i].attrs.follows.as_deref() == Some(sections[idx].name.as_str()));
+        stack.extend(followers);
+    }
+
+    if order.len() != progbits.len() {
+        let stuck = progbits.iter().find(|i| !order.contains(i)).map(|&i| sections[i].name.as_str()).unwrap_or("");
+        return Err(AsmError::LayoutError(format!("follows= chain through section {} never reaches a placed section", stuck)));
+    }
+    Ok(order)
+}
@@ -0,0 +1,289 @@
+use crate::ast::{Directive, DirectiveValue};
+use crate::assembler::{AsmSection, Relocation, RelocKind, SectionAttrs};
+use crate::error::AsmError;
+
+// DWARF call frame instructions (DWARF 4, section 7.23)
+const DW_CFA_ADVANCE_LOC: u8 = 0x40;
+const DW_CFA_OFFSET: u8 = 0x80;
+const DW_CFA_RESTORE: u8 = 0xC0;
+const DW_CFA_NOP: u8 = 0x00;
+const DW_CFA_ADVANCE_LOC1: u8 = 0x02;
+const DW_CFA_ADVANCE_LOC2: u8 = 0x03;
+const DW_CFA_ADVANCE_LOC4: u8 = 0x04;
+const DW_CFA_RESTORE_EXTENDED: u8 = 0x06;
+const DW_CFA_DEF_CFA: u8 = 0x0C;
+const DW_CFA_DEF_CFA_REGISTER: u8 = 0x0D;
+const DW_CFA_DEF_CFA_OFFSET: u8 = 0x0E;
+const DW_CFA_OFFSET_EXTENDED_SF: u8 = 0x11;
+
+// DW_EH_PE_pcrel | DW_EH_PE_sdata4
+const FDE_POINTER_ENCODING: u8 = 0x1B;
+
+const CODE_ALIGN: u64 = 1;
+const DATA_ALIGN: i64 = -8;
+const RA_REGISTER: u8 = 16;
+const RSP_REGISTER: u8 = 7;
+
+/// CFA at function entry is `rsp + 8` (the return address was just pushed).
+const INITIAL_CFA_OFFSET: i64 = 8;
+
+/// DWARF register numbers from the System V AMD64 psABI (figure 3.36).
+const DWARF_REGISTERS: &[(&str, u8)] = &[
+    ("rax", 0), ("rdx", 1), ("rcx", 2), ("rbx", 3),
+    ("rsi", 4), ("rdi", 5), ("rbp", 6), ("rsp", 7),
+    ("r8", 8), ("r9", 9), ("r10", 10), ("r11", 11),
+    ("r12", 12), ("r13", 13), ("r14", 14), ("r15", 15),
+    ("rip", 16),
+];
+
+struct Fde {
+    section_index: usize,
+    start: usize,
+    end: usize,
+    last_loc: usize,
+    cfa_offset: i64,
+    instructions: Vec<u8>,
+}
+
+/// Collects `.cfi_*` directives while encoding and turns them into `.eh_frame`.
+///
+/// Every `.cfi_startproc`/`.cfi_endproc` pair becomes one FDE sharing a single
+/// CIE. FDEs point at their function with an `R_X86_64_PC32` relocation against
+/// its section plus the function's offset, as gas does: one against a global
+/// function symbol could not be linked into a shared object.
+#[derive(Default)]
+pub struct CfiState {
+    current: Option<Fde>,
+    fdes: Vec<Fde>,
+}
+
+impl CfiState {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn is_cfi_directive(name: &str) -> bool {
+        name.starts_with(".cfi_")
+    }
+
+    pub fn handle(
+        &mut self,
+        dir: &Directive,
+        section_index: usize,
+        offset: usize,
+    ) -> Result<(), AsmError> {
+        if dir.name == ".cfi_startproc" {
+            if self.current.is_some() {
+                return Err(AsmError::EncodeError(".cfi_startproc inside another .cfi_startproc".into()));
+            }
+            self.current = Some(Fde {
+                section_index,
+                start: offset,
+                end: offset,
+                last_loc: offset,
+                cfa_offset: INITIAL_CFA_OFFSET,
+                instructions: Vec::new(),
+            });
+            return Ok(());
+        }
+
+        let fde = self.current.as_mut().ok_or_else(|| {
+            AsmError::EncodeError(format!("{} outside of .cfi_startproc/.cfi_endproc", dir.name))
+        })?;
+        if fde.section_index != section_index {
+            return Err(AsmError::EncodeError(format!("{} in a different section than .cfi_startproc", dir.name)));
+        }
+
+        match dir.name.as_str() {
+            ".cfi_endproc" => {
+                fde.end = offset;
+                self.fdes.extend(self.current.take());
+            }
+            ".cfi_def_cfa_offset" => {
+                let value = expect_number(dir, 0)?;
+                advance_to(fde, offset);
+                set_cfa_offset(fde, value)?;
+            }
+            ".cfi_adjust_cfa_offset" => {
+                let delta = expect_number(dir, 0)?;
+                advance_to(fde, offset);
+                let value = fde.cfa_offset + delta;
+                set_cfa_offset(fde, value)?;
+            }
+            ".cfi_def_cfa_register" => {
+                let reg = expect_register(dir, 0)?;
+                advance_to(fde, offset);
+                fde.instructions.push(DW_CFA_DEF_CFA_REGISTER);
+                write_uleb128(&mut fde.instructions, reg as u64);
+            }
+            ".cfi_offset" => {
+                let reg = expect_register(dir, 0)?;
+                let value = expect_number(dir, 1)?;
+                if value % DATA_ALIGN != 0 {
+                    return Err(AsmError::EncodeError(format!(".cfi_offset {} is not a multiple of {}", value, -DATA_ALIGN)));
+                }
+                let factored = value / DATA_ALIGN;
+                advance_to(fde, offset);
+                if reg < 64 && factored >= 0 {
+                    fde.instructions.push(DW_CFA_OFFSET | reg);
+                    write_uleb128(&mut fde.instructions, factored as u64);
+                } else {
+                    fde.instructions.push(DW_CFA_OFFSET_EXTENDED_SF);
+                    write_uleb128(&mut fde.instructions, reg as u64);
+                    write_sleb128(&mut fde.instructions, factored);
+                }
+            }
+            ".cfi_restore" => {
+                let reg = expect_register(dir, 0)?;
+                advance_to(fde, offset);
+                if reg < 64 {
+                    fde.instructions.push(DW_CFA_RESTORE | reg);
+                } else {
+                    fde.instructions.push(DW_CFA_RESTORE_EXTENDED);
+                    write_uleb128(&mut fde.instructions, reg as u64);
+                }
+            }
+            _ => return Err(AsmError::EncodeError(format!("Unsupported CFI directive {}", dir.name))),
+        }
+        Ok(())
+    }
+
+    /// Finishes CFI collection, returning the `.eh_frame` section if any FDE was
+    /// recorded. `sections` are the sections the FDEs' code is in.
+    pub fn finish(self, sections: &[AsmSection]) -> Result<Option<AsmSection>, AsmError> {
+        if self.current.is_some() {
+            return Err(AsmError::EncodeError("missing .cfi_endproc at end of input".into()));
+        }
+        if self.fdes.is_empty() {
+            return Ok(None);
+        }
+
+        let mut data = Vec::new();
+        let mut relocs = Vec::new();
+
+        // CIE
+        let mut cie = Vec::new();
+        cie.extend_from_slice(&0u32.to_le_bytes()); // CIE id
+        cie.push(1); // version
+        cie.extend_from_slice(b"zR\0");
+        write_uleb128(&mut cie, CODE_ALIGN);
+        write_sleb128(&mut cie, DATA_ALIGN);
+        write_uleb128(&mut cie, RA_REGISTER as u64);
+        write_uleb128(&mut cie, 1); // augmentation data length
+        cie.push(FDE_POINTER_ENCODING);
+        cie.push(DW_CFA_DEF_CFA);
+        write_uleb128(&mut cie, RSP_REGISTER as u64);
+        write_uleb128(&mut cie, INITIAL_CFA_OFFSET as u64);
+        cie.push(DW_CFA_OFFSET | RA_REGISTER);
+        write_uleb128(&mut cie, 1);
+        push_record(&mut data, cie);
+
+        for fde in &self.fdes {
+            let start = data.len();
+            let mut body = Vec::new();
+            // CIE pointer: distance from this field back to the CIE at offset 0
+            body.extend_from_slice(&((start + 4) as u32).to_le_bytes());
+            relocs.push(Relocation {
+                offset: start + 4 + body.len(),
+                symbol: sections[fde.section_index].name.clone(),
+                kind: RelocKind::Relative32,
+                addend: fde.start as i64,
+            });
+            body.extend_from_slice(&0u32.to_le_bytes()); // pc_begin
+            body.extend_from_slice(&((fde.end - fde.start) as u32).to_le_bytes()); // pc_range
+            write_uleb128(&mut body, 0); // augmentation data length
+            body.extend_from_slice(&fde.instructions);
+            push_record(&mut data, body);
+        }
+
+        Ok(Some(AsmSection {
+            name: ".eh_frame".to_string(),
+            data,
+            relocs,
+            attrs: SectionAttrs { align: Some(8), ..SectionAttrs::default() },
+        }))
+    }
+}
+
+/// Appends a length-prefixed CIE/FDE record padded with `DW_CFA_nop` to 8 bytes.
+fn push_record(out: &mut Vec<u8>, mut body: Vec<u8>) {
+    while !(body.len() + 4).is_multiple_of(8) {
+        body.push(DW_CFA_NOP);
+    }
+    out.extend_from_slice(&(body.len() as u32).to_le_bytes());
+    out.extend_from_slice(&body);
+}
+
+fn advance_to(fde: &mut Fde, offset: usize) {
+    let delta = (offset - fde.last_loc) as u64 / CODE_ALIGN;
+    if delta == 0 {
+        return;
+    }
+    if delta < 0x40 {
+        fde.instructions.push(DW_CFA_ADVANCE_LOC | delta as u8);
+    } else if delta <= u8::MAX as u64 {
+        fde.instructions.push(DW_CFA_ADVANCE_LOC1);
+        fde.instructions.push(delta as u8);
+    } else if delta <= u16::MAX as u64 {
+        fde.instructions.push(DW_CFA_ADVANCE_LOC2);
+        fde.instructions.extend_from_slice(&(delta as u16).to_le_bytes());
+    } else {
+        fde.instructions.push(DW_CFA_ADVANCE_LOC4);
+        fde.instructions.extend_from_slice(&(delta as u32).to_le_bytes());
+    }
+    fde.last_loc = offset;
+}
+
+fn set_cfa_offset(fde: &mut Fde, value: i64) -> Result<(), AsmError> {
+    if value < 0 {
+        return Err(AsmError::EncodeError(format!("negative CFA offset {}", value)));
+    }
+    fde.cfa_offset = value;
+    fde.instructions.push(DW_CFA_DEF_CFA_OFFSET);
+    write_uleb128(&mut fde.instructions, value as u64);
+    Ok(())
+}
+
+fn expect_number(dir: &Directive, idx: usize) -> Result<i64, AsmError> {
+    match dir.values.get(idx) {
+        Some(DirectiveValue::Number(n)) => Ok(*n),
+        _ => Err(AsmError::EncodeError(format!("{} expects a number as operand {}", dir.name, idx + 1))),
+    }
+}
+
+fn expect_register(dir: &Directive, idx: usize) -> Result<u8, AsmError> {
+    match dir.values.get(idx) {
+        Some(DirectiveValue::Identifier(name)) => DWARF_REGISTERS
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, code)| *code)
+            .ok_or_else(|| AsmError::EncodeError(format!("{}: unknown register {}", dir.name, name))),
+        Some(DirectiveValue::Number(n)) if (0..=u8::MAX as i64).contains(n) => Ok(*n as u8),
+        _ => Err(AsmError::EncodeError(format!("{} expects a register as operand {}", dir.name, idx + 1))),
+    }
+}
+
+fn write_uleb128(out: &mut Vec<u8>, mut value: u64) {
+    loop {
+        let byte = (value & 0x7F) as u8;
+        value >>= 7;
+        if value == 0 {
+            out.push(byte);
+            break;
+        }
+        out.push(byte | 0x80);
+    }
+}
+
+fn write_sleb128(out: &mut Vec<u8>, mut value: i64) {
+    loop {
+        let byte = (value & 0x7F) as u8;
+        value >>= 7;
+        let done = (value == 0 && byte & 0x40 == 0) || (value == -1 && byte & 0x40 != 0);
+        if done {
+            out.push(byte);
+            break;
+        }
+        out.push(byte | 0x80);
+    }
+}
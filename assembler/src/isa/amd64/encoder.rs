@@ -1,10 +1,21 @@
+use std::collections::{HashMap, HashSet};
+
 use crate::ast::*;
 use crate::error::AsmError;
-use crate::assembler::{AssemblerOutput, AsmSection, AsmSymbol, Relocation, RelocKind};
+use crate::assembler::{AsmOptions, AssemblerOutput, AsmSection, AsmSymbol, Relocation, RelocKind, SectionAttrs};
 use crate::isa::amd64::encoding::{ModRM, REX, encode_address, EncodedAddress, DispKind};
 use crate::isa::amd64::tables::*;
+use crate::isa::amd64::cfi::CfiState;
+use crate::isa::amd64::select::{select_alu_imm, select_mov_imm, AluImmForm, MovImmForm};
+
+/// Encoding state that changes between lines.
+struct Context<'a> {
+    options: &'a AsmOptions,
+    /// Processor mode selected with `bits`: 16, 32 or 64.
+    bits: u8,
+}
 
-pub fn encode(ast: &AST) -> Result<AssemblerOutput, AsmError> {
+pub fn encode(ast: &AST, options: &AsmOptions) -> Result<AssemblerOutput, AsmError> {
     let mut sections = Vec::new();
     let mut symbols = Vec::new();
 
@@ -13,14 +24,22 @@ pub fn encode(ast: &AST) -> Result<AssemblerOutput, AsmError> {
         name: ".text".to_string(),
         data: Vec::new(),
         relocs: Vec::new(),
+        attrs: SectionAttrs::default(),
     });
 
     let mut current_section_idx = 0;
     let mut global_symbols = Vec::new();
+    let mut cfi = CfiState::new();
+    let mut cx = Context { options, bits: 64 };
+    let mut org = None;
+    // A global function whose endbr has not been emitted yet; it goes before the
+    // first instruction, after any .cfi_startproc, so the FDE covers it
+    let mut pending_endbr = false;
 
     for node in &ast.items {
         match node {
-            ASTNode::Section(name) => {
+            ASTNode::Section(name, attrs) => {
+                pending_endbr = false;
                 if let Some(idx) = sections.iter().position(|s| s.name == *name) {
                     current_section_idx = idx;
                 } else {
@@ -28,9 +47,11 @@ pub fn encode(ast: &AST) -> Result<AssemblerOutput, AsmError> {
                         name: name.clone(),
                         data: Vec::new(),
                         relocs: Vec::new(),
+                        attrs: SectionAttrs::default(),
                     });
                     current_section_idx = sections.len() - 1;
                 }
+                apply_section_attrs(&mut sections[current_section_idx].attrs, attrs)?;
             }
 
             ASTNode::Global(name) => {
@@ -47,27 +68,210 @@ pub fn encode(ast: &AST) -> Result<AssemblerOutput, AsmError> {
             }
 
             ASTNode::Label(name) => {
+                let is_global = global_symbols.contains(name);
                 symbols.push(AsmSymbol {
                     name: name.clone(),
                     section_index: Some(current_section_idx),
                     offset: sections[current_section_idx].data.len(),
-                    is_global: global_symbols.contains(name),
+                    is_global,
                 });
+                // Other objects may reach global functions through a pointer or the PLT
+                let section = &sections[current_section_idx].name;
+                let in_code = section == ".text" || section.starts_with(".text.");
+                if options.endbr && is_global && in_code && cx.bits != 16 {
+                    pending_endbr = true;
+                }
+            }
+
+            ASTNode::Directive(dir) if CfiState::is_cfi_directive(&dir.name) => {
+                let offset = sections[current_section_idx].data.len();
+                cfi.handle(dir, current_section_idx, offset)?;
+            }
+
+            ASTNode::Directive(dir) if dir.name == "org" => {
+                let value = match dir.values.as_slice() {
+                    [DirectiveValue::Number(n)] if *n >= 0 => *n as u64,
+                    _ => return Err(AsmError::EncodeError("org expects a non-negative address".into())),
+                };
+                if org.is_some_and(|o| o != value) {
+                    return Err(AsmError::EncodeError("org can only be set once".into()));
+                }
+                org = Some(value);
+            }
+
+            ASTNode::Directive(dir) if dir.name == "bits" => {
+                cx.bits = match dir.values.as_slice() {
+                    [DirectiveValue::Number(n @ (16 | 32 | 64))] => *n as u8,
+                    _ => return Err(AsmError::EncodeError("bits expects 16, 32 or 64".into())),
+                };
             }
 
-            ASTNode::Instruction(ins) => {
-                let sec = &mut sections[current_section_idx];
-                encode_instruction(ins, &mut sec.data, &mut sec.relocs)?;
+            ASTNode::Times(count, line) => {
+                place_endbr(line, &mut pending_endbr, &cx, &mut sections[current_section_idx].data);
+                let count = eval_expr(count, sections[current_section_idx].data.len())?;
+                if count < 0 {
+                    return Err(AsmError::EncodeError(format!("times count {} is negative", count)));
+                }
+                for _ in 0..count {
+                    encode_line(line, &cx, current_section_idx, &mut sections, &mut symbols)?;
+                }
             }
 
-            ASTNode::Directive(dir) => {
-                let sec = &mut sections[current_section_idx];
-                encode_directive(dir, &mut sec.data)?;
+            line => {
+                place_endbr(line, &mut pending_endbr, &cx, &mut sections[current_section_idx].data);
+                encode_line(line, &cx, current_section_idx, &mut sections, &mut symbols)?
             }
         }
     }
 
-    Ok(AssemblerOutput { sections, symbols })
+    if let Some(eh_frame) = cfi.finish(&sections)? {
+        sections.push(eh_frame);
+    }
+    fold_temporary_labels(&mut sections, &mut symbols);
+
+    Ok(AssemblerOutput { sections, symbols, org })
+}
+
+/// Encodes an instruction or data directive into the current section.
+fn encode_line(
+    node: &ASTNode,
+    cx: &Context,
+    section_index: usize,
+    sections: &mut [AsmSection],
+    symbols: &mut Vec<AsmSymbol>,
+) -> Result<(), AsmError> {
+    let sec = &mut sections[section_index];
+    match node {
+        ASTNode::Instruction(ins) => {
+            let mut ins = ins.clone();
+            for op in &mut ins.operands {
+                match op {
+                    Operand::Label(name) => *name = here_symbol(name, section_index, sec.data.len(), symbols),
+                    Operand::Memory(MemoryOperand { symbol: Some(name), .. }) => {
+                        *name = here_symbol(name, section_index, sec.data.len(), symbols)
+                    }
+                    _ => {}
+                }
+            }
+            encode_instruction(&ins, &mut sec.data, &mut sec.relocs, cx)
+        }
+        ASTNode::Directive(dir) => {
+            let mut dir = dir.clone();
+            for value in &mut dir.values {
+                if let DirectiveValue::Identifier(name) = value {
+                    *name = here_symbol(name, section_index, sec.data.len(), symbols);
+                }
+            }
+            encode_directive(&dir, &mut sec.data, &mut sec.relocs)
+        }
+        _ => Err(AsmError::EncodeError(format!("Unexpected {:?}", node))),
+    }
+}
+
+/// Writes the pending `endbr` before `line` if it is the function's first
+/// instruction and not an `endbr` already. A global label followed by data is
+/// not a function, so a directive cancels it.
+fn place_endbr(line: &ASTNode, pending: &mut bool, cx: &Context, bytes: &mut Vec<u8>) {
+    match line {
+        ASTNode::Instruction(ins) if std::mem::take(pending) && !ins.mnemonic.starts_with("endbr") => {
+            bytes.extend_from_slice(if cx.bits == 64 { &[0xF3, 0x0F, 0x1E, 0xFA] } else { &[0xF3, 0x0F, 0x1E, 0xFB] });
+        }
+        ASTNode::Directive(_) => *pending = false,
+        _ => {}
+    }
+}
+
+/// Rewrites references to local `.L` labels, including those made up for `$`,
+/// as their section plus an offset and drops the labels, as gas does.
+/// GOT and TLS relocations need a symbol of their own, so their labels stay.
+fn fold_temporary_labels(sections: &mut [AsmSection], symbols: &mut Vec<AsmSymbol>) {
+    let section_names: Vec<String> = sections.iter().map(|s| s.name.clone()).collect();
+    let labels: HashMap<String, (usize, usize)> = symbols
+        .iter()
+        .filter(|s| s.name.starts_with(".L") && !s.is_global)
+        .filter_map(|s| s.section_index.map(|i| (s.name.clone(), (i, s.offset))))
+        .collect();
+    if labels.is_empty() {
+        return;
+    }
+
+    let mut kept = HashSet::new();
+    for r in sections.iter_mut().flat_map(|s| &mut s.relocs) {
+        let Some(&(section_index, offset)) = labels.get(&r.symbol) else { continue };
+        let foldable = matches!(
+            r.kind,
+            RelocKind::Absolute64
+                | RelocKind::Absolute32
+                | RelocKind::Absolute32S
+                | RelocKind::Absolute16
+                | RelocKind::Relative32
+                | RelocKind::Relative16
+                | RelocKind::Plt32
+        );
+        if foldable {
+            r.symbol = section_names[section_index].clone();
+            r.addend += offset as i64;
+        } else {
+            kept.insert(r.symbol.clone());
+        }
+    }
+    symbols.retain(|s| !labels.contains_key(&s.name) || kept.contains(&s.name));
+}
+
+/// Replaces `$` (this line) and `$$` (section start) with a local label at that offset.
+fn here_symbol(name: &str, section_index: usize, offset: usize, symbols: &mut Vec<AsmSymbol>) -> String {
+    let offset = match name {
+        "$" => offset,
+        "$$" => 0,
+        _ => return name.to_string(),
+    };
+    let label = format!(".Lhere{}_{}", section_index, offset);
+    if !symbols.iter().any(|s| s.name == label) {
+        symbols.push(AsmSymbol {
+            name: label.clone(),
+            section_index: Some(section_index),
+            offset,
+            is_global: false,
+        });
+    }
+    label
+}
+
+/// Evaluates a `times` count. `$` and `$$` are section offsets, so `$ - $$` is the
+/// number of bytes emitted into the section so far.
+fn eval_expr(expr: &Expr, offset: usize) -> Result<i64, AsmError> {
+    Ok(match expr {
+        Expr::Number(n) => *n,
+        Expr::Here => offset as i64,
+        Expr::SectionStart => 0,
+        Expr::Neg(e) => eval_expr(e, offset)?.wrapping_neg(),
+        Expr::Binary(op, lhs, rhs) => {
+            let (lhs, rhs) = (eval_expr(lhs, offset)?, eval_expr(rhs, offset)?);
+            match op {
+                BinOp::Add => lhs.wrapping_add(rhs),
+                BinOp::Sub => lhs.wrapping_sub(rhs),
+                BinOp::Mul => lhs.wrapping_mul(rhs),
+                BinOp::Div if rhs == 0 => return Err(AsmError::EncodeError("Division by zero in expression".into())),
+                BinOp::Div => lhs.wrapping_div(rhs),
+            }
+        }
+    })
+}
+
+fn apply_section_attrs(attrs: &mut SectionAttrs, values: &[SectionAttr]) -> Result<(), AsmError> {
+    for attr in values {
+        match (attr.key.as_str(), &attr.value) {
+            ("align", DirectiveValue::Number(n)) if *n > 0 && (*n as u64).is_power_of_two() => attrs.align = Some(*n as u64),
+            ("start", DirectiveValue::Number(n)) if *n >= 0 => attrs.start = Some(*n as u64),
+            ("vstart", DirectiveValue::Number(n)) if *n >= 0 => attrs.vstart = Some(*n as u64),
+            ("follows", DirectiveValue::Identifier(name)) => attrs.follows = Some(name.clone()),
+            ("align" | "start" | "vstart" | "follows", value) => {
+                return Err(AsmError::EncodeError(format!("Invalid value {:?} for section attribute {}", value, attr.key)));
+            }
+            (key, _) => return Err(AsmError::EncodeError(format!("Unknown section attribute {}", key))),
+        }
+    }
+    Ok(())
 }
 
 struct RegInfo { code: u8, width: u8 }
@@ -80,161 +284,434 @@ fn lookup_reg(name: &str) -> Option<RegInfo> {
     None
 }
 
-fn encode_instruction(ins: &Instruction, bytes: &mut Vec<u8>, relocs: &mut Vec<Relocation>) -> Result<(), AsmError> {
+fn segment_reg(name: &str) -> Option<u8> {
+    SEGMENT_REGISTERS.iter().find(|(n, _)| *n == name).map(|(_, code)| *code)
+}
+
+/// Looks up a register that is usable in the current mode; 64-bit registers and
+/// r8-r15 need a REX prefix, which only exists in 64-bit mode.
+fn reg(cx: &Context, name: &str) -> Result<RegInfo, AsmError> {
+    let reg = lookup_reg(name).ok_or_else(|| AsmError::EncodeError(format!("Invalid register {}", name)))?;
+    if (reg.width == 64 || reg.code >= 8) && cx.bits != 64 {
+        return Err(AsmError::EncodeError(format!("{} requires bits 64 (current mode is bits {})", name, cx.bits)));
+    }
+    Ok(reg)
+}
+
+/// Writes the operand-size prefix and REX byte for a `width`-bit operation in the current mode.
+fn write_prefixes(bytes: &mut Vec<u8>, cx: &Context, width: u8, mut rex: REX) {
+    if matches!(width, 16 | 32) && (width == 16) != (cx.bits == 16) { bytes.push(0x66); }
+    rex.w = width == 64;
+    if rex.w || rex.r || rex.x || rex.b { bytes.push(rex.encode()); }
+}
+
+/// Operand width that needs no prefix: 16 bits in 16-bit mode, 32 bits otherwise.
+fn default_width(cx: &Context) -> u8 {
+    if cx.bits == 16 { 16 } else { 32 }
+}
+
+fn encode_instruction(ins: &Instruction, bytes: &mut Vec<u8>, relocs: &mut Vec<Relocation>, cx: &Context) -> Result<(), AsmError> {
+    let first_reloc = relocs.len();
+    encode_instruction_bytes(ins, bytes, relocs, cx)?;
+
+    // PC-relative fields are relative to the end of the instruction, not to the field itself
+    let end = bytes.len();
+    for r in &mut relocs[first_reloc..] {
+        if r.kind.is_pc_relative() {
+            r.addend -= (end - r.offset) as i64;
+        }
+    }
+    Ok(())
+}
+
+fn encode_instruction_bytes(ins: &Instruction, bytes: &mut Vec<u8>, relocs: &mut Vec<Relocation>, cx: &Context) -> Result<(), AsmError> {
     match ins.mnemonic.as_str() {
-        "mov" => encode_mov(ins, bytes, relocs),
-        "add" => encode_binop(ins, 0x01, 0x03, 0, bytes, relocs),
-        "sub" => encode_binop(ins, 0x29, 0x2B, 5, bytes, relocs),
-        "and" => encode_binop(ins, 0x21, 0x23, 4, bytes, relocs),
-        "or"  => encode_binop(ins, 0x09, 0x0B, 1, bytes, relocs),
-        "xor" => encode_binop(ins, 0x31, 0x33, 6, bytes, relocs),
-        "cmp" => encode_binop(ins, 0x39, 0x3B, 7, bytes, relocs),
-        "push" => encode_push_pop(ins, 0x50, bytes),
-        "pop"  => encode_push_pop(ins, 0x58, bytes),
-        "jmp"  => encode_jump(ins, 0xE9, bytes, relocs),
-        "call" => encode_jump(ins, 0xE8, bytes, relocs),
+        "mov" => encode_mov(ins, bytes, relocs, cx),
+        "lea" => encode_lea(ins, bytes, relocs, cx),
+        "add" => encode_binop(ins, 0x01, 0x03, 0, bytes, relocs, cx),
+        "sub" => encode_binop(ins, 0x29, 0x2B, 5, bytes, relocs, cx),
+        "and" => encode_binop(ins, 0x21, 0x23, 4, bytes, relocs, cx),
+        "or"  => encode_binop(ins, 0x09, 0x0B, 1, bytes, relocs, cx),
+        "xor" => encode_binop(ins, 0x31, 0x33, 6, bytes, relocs, cx),
+        "cmp" => encode_binop(ins, 0x39, 0x3B, 7, bytes, relocs, cx),
+        "push" => encode_push_pop(ins, 0x50, bytes, cx),
+        "pop"  => encode_push_pop(ins, 0x58, bytes, cx),
+        "jmp"  => encode_jump(ins, 0xE9, bytes, relocs, cx),
+        "call" => encode_jump(ins, 0xE8, bytes, relocs, cx),
+        "int"  => encode_int(ins, bytes),
         "ret"  => { bytes.push(0xC3); Ok(()) },
         "nop"  => { bytes.push(0x90); Ok(()) },
+        "hlt"  => { bytes.push(0xF4); Ok(()) },
+        "cli"  => { bytes.push(0xFA); Ok(()) },
+        "sti"  => { bytes.push(0xFB); Ok(()) },
         "syscall" => { bytes.push(0x0F); bytes.push(0x05); Ok(()) },
         "int3" => { bytes.push(0xCC); Ok(()) },
+        "endbr64" => { bytes.extend_from_slice(&[0xF3, 0x0F, 0x1E, 0xFA]); Ok(()) },
+        "endbr32" => { bytes.extend_from_slice(&[0xF3, 0x0F, 0x1E, 0xFB]); Ok(()) },
         _ => Err(AsmError::EncodeError(format!("Unknown mnemonic {}", ins.mnemonic))),
     }
 }
 
-fn write_rex_modrm_addr(bytes: &mut Vec<u8>, opcode: u8, reg_code: u8, reg_width: u8, addr: EncodedAddress) {
+/// Writes `[segment] [67] [66] [REX] opcode ModRM [SIB] [disp]` and returns the offset of the displacement.
+fn write_rex_modrm_addr(bytes: &mut Vec<u8>, cx: &Context, opcode: u8, reg_code: u8, reg_width: u8, addr: &EncodedAddress) -> Result<usize, AsmError> {
+    if (addr.rex_b || addr.rex_x) && cx.bits != 64 {
+        return Err(AsmError::EncodeError(format!("r8-r15 in addresses require bits 64 (current mode is bits {})", cx.bits)));
+    }
+    if let Some(seg) = addr.segment { bytes.push(seg); }
+    if addr.address_override { bytes.push(0x67); }
     let mut rex = REX::new();
-    rex.w = reg_width == 64; rex.r = reg_code >= 8; rex.b = addr.rex_b; rex.x = addr.rex_x;
-    if rex.w || rex.r || rex.b || rex.x { bytes.push(rex.encode()); }
+    rex.r = reg_code >= 8; rex.b = addr.rex_b; rex.x = addr.rex_x;
+    write_prefixes(bytes, cx, reg_width, rex);
     bytes.push(opcode);
     bytes.push(ModRM::new(addr.mod_bits, reg_code, addr.rm_bits).encode());
     if let Some(sib) = addr.sib { bytes.push((sib.0 << 6) | (sib.1 << 3) | sib.2); }
-    if let Some(disp) = addr.disp {
-        match disp { DispKind::Disp8(d) => bytes.push(d as u8), DispKind::Disp32(d) => bytes.extend_from_slice(&d.to_le_bytes()) }
+    let disp_offset = bytes.len();
+    if let Some(disp) = &addr.disp {
+        match disp {
+            DispKind::Disp8(d) => bytes.push(*d as u8),
+            DispKind::Disp16(d) => bytes.extend_from_slice(&d.to_le_bytes()),
+            DispKind::Disp32(d) => bytes.extend_from_slice(&d.to_le_bytes()),
+        }
     }
+    Ok(disp_offset)
 }
 
-fn encode_mov(ins: &Instruction, bytes: &mut Vec<u8>, relocs: &mut Vec<Relocation>) -> Result<(), AsmError> {
+/// Encodes a ModRM memory operand and records the relocation for a symbolic displacement.
+fn write_mem_operand(bytes: &mut Vec<u8>, relocs: &mut Vec<Relocation>, cx: &Context, opcode: u8, reg_code: u8, reg_width: u8, mem: &MemoryOperand) -> Result<(), AsmError> {
+    let addr = encode_address(mem, cx.bits)?;
+    let has_rex = reg_width == 64 || reg_code >= 8 || addr.rex_b || addr.rex_x;
+    let disp_offset = write_rex_modrm_addr(bytes, cx, opcode, reg_code, reg_width, &addr)?;
+    if let Some(symbol) = &mem.symbol {
+        let kind = mem_reloc_kind(mem, &addr, opcode, has_rex)?;
+        relocs.push(Relocation { offset: disp_offset, symbol: symbol.clone(), kind, addend: mem.disp });
+    } else if mem.wrt.is_some() {
+        return Err(AsmError::EncodeError("wrt requires a symbol in the memory operand".into()));
+    }
+    Ok(())
+}
+
+/// Opcodes the linker may rewrite when relaxing a GOT load (x86-64 psABI, appendix B.2):
+/// `mov`, `call`/`jmp` through memory, `test` and the ALU `r, r/m` forms.
+fn is_got_relaxable(opcode: u8) -> bool {
+    matches!(opcode, 0x8B | 0xFF | 0x85 | 0x03 | 0x0B | 0x13 | 0x1B | 0x23 | 0x2B | 0x33 | 0x3B)
+}
+
+fn mem_reloc_kind(mem: &MemoryOperand, addr: &EncodedAddress, opcode: u8, has_rex: bool) -> Result<RelocKind, AsmError> {
+    if mem.wrt.is_some() && addr.address_size != 64 {
+        return Err(AsmError::EncodeError("wrt relocation operators require 64-bit addressing".into()));
+    }
+    let kind = match mem.wrt {
+        None if mem.rip_relative => RelocKind::Relative32,
+        None if addr.address_size == 16 => RelocKind::Absolute16,
+        None if addr.address_size == 32 => RelocKind::Absolute32,
+        None => RelocKind::Absolute32S,
+        Some(Wrt::GotPcRel) if !is_got_relaxable(opcode) => RelocKind::GotPcRel,
+        Some(Wrt::GotPcRel) if has_rex => RelocKind::RexGotPcRelX,
+        Some(Wrt::GotPcRel) => RelocKind::GotPcRelX,
+        Some(Wrt::Plt) => return Err(AsmError::EncodeError("wrt ..plt is only valid as a call/jmp target".into())),
+        Some(Wrt::TpOff) => RelocKind::TpOff32,
+        Some(Wrt::DtpOff) => RelocKind::DtpOff32,
+        Some(Wrt::GotTpOff) => RelocKind::GotTpOff,
+        Some(Wrt::TlsGd) => RelocKind::TlsGd,
+        Some(Wrt::TlsLd) => RelocKind::TlsLd,
+    };
+    if kind.is_pc_relative() != mem.rip_relative {
+        let want = if kind.is_pc_relative() { "requires" } else { "cannot be used with" };
+        return Err(AsmError::EncodeError(format!("{:?} {} [rel ...] addressing", kind, want)));
+    }
+    Ok(kind)
+}
+
+fn encode_mov(ins: &Instruction, bytes: &mut Vec<u8>, relocs: &mut Vec<Relocation>, cx: &Context) -> Result<(), AsmError> {
     if ins.operands.len() != 2 { return Err(AsmError::EncodeError("mov expects 2 operands".into())); }
     let dst = &ins.operands[0];
     let src = &ins.operands[1];
 
     match (dst, src) {
+        // 8E /r: mov sreg, r/m16 and 8C /r: mov r/m, sreg
+        (Operand::Register(s_name), src) if segment_reg(s_name).is_some() => {
+            let sreg = segment_reg(s_name).unwrap_or_default();
+            match src {
+                Operand::Register(r_name) => {
+                    let reg = reg(cx, r_name)?;
+                    if reg.width == 8 { return Err(AsmError::EncodeError(format!("mov {}, {} needs a 16-bit or wider register", s_name, r_name))); }
+                    if reg.code >= 8 { let mut rex = REX::new(); rex.b = true; bytes.push(rex.encode()); }
+                    bytes.push(0x8E); bytes.push(ModRM::new(0b11, sreg, reg.code).encode());
+                    Ok(())
+                }
+                Operand::Memory(mem) => write_mem_operand(bytes, relocs, cx, 0x8E, sreg, default_width(cx), mem),
+                _ => Err(AsmError::EncodeError(format!("Unsupported mov {} form", s_name))),
+            }
+        }
+        (dst, Operand::Register(s_name)) if segment_reg(s_name).is_some() => {
+            let sreg = segment_reg(s_name).unwrap_or_default();
+            match dst {
+                Operand::Register(r_name) => {
+                    let reg = reg(cx, r_name)?;
+                    if reg.width == 8 { return Err(AsmError::EncodeError(format!("mov {}, {} needs a 16-bit or wider register", r_name, s_name))); }
+                    let mut rex = REX::new(); rex.b = reg.code >= 8;
+                    write_prefixes(bytes, cx, reg.width, rex);
+                    bytes.push(0x8C); bytes.push(ModRM::new(0b11, sreg, reg.code).encode());
+                    Ok(())
+                }
+                Operand::Memory(mem) => write_mem_operand(bytes, relocs, cx, 0x8C, sreg, default_width(cx), mem),
+                _ => Err(AsmError::EncodeError(format!("Unsupported mov {} form", s_name))),
+            }
+        }
         (Operand::Register(r_name), Operand::Immediate(imm)) => {
-            let reg = lookup_reg(r_name).ok_or(AsmError::EncodeError("Invalid register".into()))?;
-            if reg.width == 64 {
-                let mut rex = REX::new(); rex.w = true; rex.b = reg.code >= 8;
-                bytes.push(rex.encode()); bytes.push(0xB8 + (reg.code & 7));
-                bytes.extend_from_slice(&imm.to_le_bytes());
-            } else if reg.width == 32 {
-                if reg.code >= 8 { let mut rex = REX::new(); rex.b = true; bytes.push(rex.encode()); }
-                bytes.push(0xB8 + (reg.code & 7)); bytes.extend_from_slice(&(*imm as i32).to_le_bytes());
-            } else { return Err(AsmError::EncodeError("Unsupported mov width for imm".into())); }
+            let reg = reg(cx, r_name)?;
+            check_imm_width(*imm, reg.width)?;
+            let mut rex = REX::new(); rex.b = reg.code >= 8;
+            match select_mov_imm(reg.width, *imm, cx.options.optimize) {
+                MovImmForm::Native => {
+                    write_prefixes(bytes, cx, reg.width, rex);
+                    if reg.width == 8 {
+                        bytes.push(0xB0 + (reg.code & 7)); bytes.push(*imm as u8);
+                    } else {
+                        bytes.push(0xB8 + (reg.code & 7)); write_imm(bytes, *imm, reg.width);
+                    }
+                }
+                MovImmForm::ZeroExtended32 => {
+                    write_prefixes(bytes, cx, 32, rex);
+                    bytes.push(0xB8 + (reg.code & 7)); bytes.extend_from_slice(&(*imm as u32).to_le_bytes());
+                }
+                MovImmForm::SignExtended32 => {
+                    write_prefixes(bytes, cx, 64, rex);
+                    bytes.push(0xC7); bytes.push(ModRM::new(0b11, 0, reg.code).encode());
+                    bytes.extend_from_slice(&(*imm as i32).to_le_bytes());
+                }
+                MovImmForm::Imm64 => {
+                    write_prefixes(bytes, cx, 64, rex);
+                    bytes.push(0xB8 + (reg.code & 7));
+                    bytes.extend_from_slice(&imm.to_le_bytes());
+                }
+            }
             Ok(())
         }
         (Operand::Register(r_name), Operand::Label(label)) => {
-            let reg = lookup_reg(r_name).ok_or(AsmError::EncodeError("Invalid register".into()))?;
-            if reg.width == 64 {
-                let mut rex = REX::new(); rex.w = true; rex.b = reg.code >= 8;
-                bytes.push(rex.encode()); bytes.push(0xB8 + (reg.code & 7));
-                relocs.push(Relocation { offset: bytes.len(), symbol: label.clone(), kind: RelocKind::Absolute64, addend: 0 });
-                bytes.extend_from_slice(&0i64.to_le_bytes());
-                Ok(())
-            } else { Err(AsmError::EncodeError("Label move only supported for r64".into())) }
+            // mov r, imm holding the label's absolute address (movabs for r64)
+            let reg = reg(cx, r_name)?;
+            let kind = match reg.width {
+                64 => RelocKind::Absolute64,
+                32 => RelocKind::Absolute32,
+                16 => RelocKind::Absolute16,
+                _ => return Err(AsmError::EncodeError("Label move needs a 16-, 32- or 64-bit register".into())),
+            };
+            let mut rex = REX::new(); rex.b = reg.code >= 8;
+            write_prefixes(bytes, cx, reg.width, rex);
+            bytes.push(0xB8 + (reg.code & 7));
+            relocs.push(Relocation { offset: bytes.len(), symbol: label.clone(), kind, addend: 0 });
+            bytes.resize(bytes.len() + reg.width as usize / 8, 0);
+            Ok(())
+        }
+        (Operand::Register(r_name), Operand::LabelWrt(label, Wrt::TpOff)) => {
+            // mov r64, imm32 (sign-extended) / mov r32, imm32 holding the TP-relative offset
+            let reg = reg(cx, r_name)?;
+            let mut rex = REX::new(); rex.b = reg.code >= 8;
+            match reg.width {
+                64 => {
+                    write_prefixes(bytes, cx, 64, rex);
+                    bytes.push(0xC7); bytes.push(ModRM::new(0b11, 0, reg.code).encode());
+                }
+                32 => {
+                    write_prefixes(bytes, cx, 32, rex);
+                    bytes.push(0xB8 + (reg.code & 7));
+                }
+                _ => return Err(AsmError::EncodeError("wrt ..tpoff move only supported for r32/r64".into())),
+            }
+            relocs.push(Relocation { offset: bytes.len(), symbol: label.clone(), kind: RelocKind::TpOff32, addend: 0 });
+            bytes.extend_from_slice(&0i32.to_le_bytes());
+            Ok(())
         }
         (Operand::Register(dst_name), Operand::Register(src_name)) => {
-            let dst_reg = lookup_reg(dst_name).ok_or(AsmError::EncodeError("Invalid dst register".into()))?;
-            let src_reg = lookup_reg(src_name).ok_or(AsmError::EncodeError("Invalid src register".into()))?;
+            let dst_reg = reg(cx, dst_name)?;
+            let src_reg = reg(cx, src_name)?;
             if dst_reg.width != src_reg.width { return Err(AsmError::EncodeError("Register width mismatch".into())); }
-            let mut rex = REX::new(); rex.w = dst_reg.width == 64; rex.r = src_reg.code >= 8; rex.b = dst_reg.code >= 8;
-            if rex.w || rex.r || rex.b { bytes.push(rex.encode()); }
-            bytes.push(0x89); bytes.push(ModRM::new(0b11, src_reg.code, dst_reg.code).encode());
+            let mut rex = REX::new(); rex.r = src_reg.code >= 8; rex.b = dst_reg.code >= 8;
+            write_prefixes(bytes, cx, dst_reg.width, rex);
+            bytes.push(byte_form(0x89, dst_reg.width)); bytes.push(ModRM::new(0b11, src_reg.code, dst_reg.code).encode());
             Ok(())
         }
         (Operand::Register(r_name), Operand::Memory(mem)) => {
-            let reg = lookup_reg(r_name).ok_or(AsmError::EncodeError("Invalid register".into()))?;
-            let addr = encode_address(mem, 64)?;
-            write_rex_modrm_addr(bytes, 0x8B, reg.code, reg.width, addr);
-            Ok(())
+            let reg = reg(cx, r_name)?;
+            write_mem_operand(bytes, relocs, cx, byte_form(0x8B, reg.width), reg.code, reg.width, mem)
         }
         (Operand::Memory(mem), Operand::Register(r_name)) => {
-            let reg = lookup_reg(r_name).ok_or(AsmError::EncodeError("Invalid register".into()))?;
-            let addr = encode_address(mem, 64)?;
-            write_rex_modrm_addr(bytes, 0x89, reg.code, reg.width, addr);
-            Ok(())
+            let reg = reg(cx, r_name)?;
+            write_mem_operand(bytes, relocs, cx, byte_form(0x89, reg.width), reg.code, reg.width, mem)
         }
         _ => Err(AsmError::EncodeError("Unsupported mov form".into())),
     }
 }
 
-fn encode_binop(ins: &Instruction, opcode_rm_r: u8, opcode_r_rm: u8, imm_op_ext: u8, bytes: &mut Vec<u8>, _relocs: &mut Vec<Relocation>) -> Result<(), AsmError> {
+/// The byte-sized variant of a `r/m, r` or `r, r/m` opcode is the one just below it (`89` -> `88`).
+fn byte_form(opcode: u8, width: u8) -> u8 {
+    if width == 8 { opcode - 1 } else { opcode }
+}
+
+/// Writes an immediate at operand width; 64-bit operands take a sign-extended imm32.
+fn write_imm(bytes: &mut Vec<u8>, imm: i64, width: u8) {
+    match width {
+        8 => bytes.push(imm as u8),
+        16 => bytes.extend_from_slice(&(imm as u16).to_le_bytes()),
+        _ => bytes.extend_from_slice(&(imm as u32).to_le_bytes()),
+    }
+}
+
+/// Accepts both signed and unsigned spellings of a `width`-bit immediate.
+fn check_imm_width(imm: i64, width: u8) -> Result<(), AsmError> {
+    if width >= 64 {
+        return Ok(());
+    }
+    let min = -(1i64 << (width - 1));
+    let max = (1i64 << width) - 1;
+    if (min..=max).contains(&imm) {
+        Ok(())
+    } else {
+        Err(AsmError::EncodeError(format!("Immediate {} does not fit in {} bits", imm, width)))
+    }
+}
+
+fn encode_lea(ins: &Instruction, bytes: &mut Vec<u8>, relocs: &mut Vec<Relocation>, cx: &Context) -> Result<(), AsmError> {
+    match ins.operands.as_slice() {
+        [Operand::Register(r_name), Operand::Memory(mem)] => {
+            let reg = reg(cx, r_name)?;
+            if reg.width < 16 { return Err(AsmError::EncodeError("lea expects a 16-, 32- or 64-bit register".into())); }
+            write_mem_operand(bytes, relocs, cx, 0x8D, reg.code, reg.width, mem)
+        }
+        _ => Err(AsmError::EncodeError("lea expects a register and a memory operand".into())),
+    }
+}
+
+fn encode_binop(ins: &Instruction, opcode_rm_r: u8, opcode_r_rm: u8, imm_op_ext: u8, bytes: &mut Vec<u8>, relocs: &mut Vec<Relocation>, cx: &Context) -> Result<(), AsmError> {
     if ins.operands.len() != 2 { return Err(AsmError::EncodeError(format!("{} expects 2 operands", ins.mnemonic))); }
     let dst = &ins.operands[0];
     let src = &ins.operands[1];
     match (dst, src) {
         (Operand::Register(dst_name), Operand::Register(src_name)) => {
-            let dst_reg = lookup_reg(dst_name).ok_or(AsmError::EncodeError("Invalid dst register".into()))?;
-            let src_reg = lookup_reg(src_name).ok_or(AsmError::EncodeError("Invalid src register".into()))?;
-            let mut rex = REX::new(); rex.w = dst_reg.width == 64; rex.r = src_reg.code >= 8; rex.b = dst_reg.code >= 8;
-            if rex.w || rex.r || rex.b { bytes.push(rex.encode()); }
-            bytes.push(opcode_rm_r); bytes.push(ModRM::new(0b11, src_reg.code, dst_reg.code).encode());
+            let dst_reg = reg(cx, dst_name)?;
+            let src_reg = reg(cx, src_name)?;
+            let mut rex = REX::new(); rex.r = src_reg.code >= 8; rex.b = dst_reg.code >= 8;
+            write_prefixes(bytes, cx, dst_reg.width, rex);
+            bytes.push(byte_form(opcode_rm_r, dst_reg.width)); bytes.push(ModRM::new(0b11, src_reg.code, dst_reg.code).encode());
             Ok(())
         }
         (Operand::Register(dst_name), Operand::Immediate(imm)) => {
-            let dst_reg = lookup_reg(dst_name).ok_or(AsmError::EncodeError("Invalid dst register".into()))?;
-            let mut rex = REX::new(); rex.w = dst_reg.width == 64; rex.b = dst_reg.code >= 8;
-            if rex.w || rex.b { bytes.push(rex.encode()); }
-            if (-128..=127).contains(imm) {
-                bytes.push(0x83); bytes.push(ModRM::new(0b11, imm_op_ext, dst_reg.code).encode()); bytes.push(*imm as u8);
-            } else {
-                bytes.push(0x81); bytes.push(ModRM::new(0b11, imm_op_ext, dst_reg.code).encode()); bytes.extend_from_slice(&(*imm as i32).to_le_bytes());
+            let dst_reg = reg(cx, dst_name)?;
+            // ALU immediates are at most 32 bits and sign-extended for 64-bit operands
+            check_imm_width(*imm, dst_reg.width.min(32))?;
+            if dst_reg.width == 64 && i32::try_from(*imm).is_err() {
+                return Err(AsmError::EncodeError(format!("{} immediate {} does not fit in a sign-extended imm32", ins.mnemonic, imm)));
+            }
+            let mut rex = REX::new(); rex.b = dst_reg.code >= 8;
+            write_prefixes(bytes, cx, dst_reg.width, rex);
+            match select_alu_imm(dst_reg.code, dst_reg.width, *imm, cx.options.optimize) {
+                AluImmForm::Imm8 => {
+                    bytes.push(0x83); bytes.push(ModRM::new(0b11, imm_op_ext, dst_reg.code).encode()); bytes.push(*imm as u8);
+                }
+                AluImmForm::Accumulator => {
+                    // 04/05 + 8*n: op al, ib / op ax|eax|rax, iw|id
+                    let byte_op = dst_reg.width == 8;
+                    bytes.push((imm_op_ext << 3) | if byte_op { 0x04 } else { 0x05 });
+                    write_imm(bytes, *imm, dst_reg.width);
+                }
+                AluImmForm::Full => {
+                    bytes.push(if dst_reg.width == 8 { 0x80 } else { 0x81 });
+                    bytes.push(ModRM::new(0b11, imm_op_ext, dst_reg.code).encode());
+                    write_imm(bytes, *imm, dst_reg.width);
+                }
             }
             Ok(())
         }
         (Operand::Register(r_name), Operand::Memory(mem)) => {
-            let reg = lookup_reg(r_name).ok_or(AsmError::EncodeError("Invalid register".into()))?;
-            let addr = encode_address(mem, 64)?;
-            write_rex_modrm_addr(bytes, opcode_r_rm, reg.code, reg.width, addr);
-            Ok(())
+            let reg = reg(cx, r_name)?;
+            write_mem_operand(bytes, relocs, cx, byte_form(opcode_r_rm, reg.width), reg.code, reg.width, mem)
         }
         (Operand::Memory(mem), Operand::Register(r_name)) => {
-            let reg = lookup_reg(r_name).ok_or(AsmError::EncodeError("Invalid register".into()))?;
-            let addr = encode_address(mem, 64)?;
-            write_rex_modrm_addr(bytes, opcode_rm_r, reg.code, reg.width, addr);
-            Ok(())
+            let reg = reg(cx, r_name)?;
+            write_mem_operand(bytes, relocs, cx, byte_form(opcode_rm_r, reg.width), reg.code, reg.width, mem)
         }
         _ => Err(AsmError::EncodeError(format!("Unsupported {} form", ins.mnemonic))),
     }
 }
 
-fn encode_push_pop(ins: &Instruction, base_opcode: u8, bytes: &mut Vec<u8>) -> Result<(), AsmError> {
+fn encode_push_pop(ins: &Instruction, base_opcode: u8, bytes: &mut Vec<u8>, cx: &Context) -> Result<(), AsmError> {
     if ins.operands.len() != 1 { return Err(AsmError::EncodeError(format!("{} expects 1 operand", ins.mnemonic))); }
     if let Operand::Register(name) = &ins.operands[0] {
-        let reg = lookup_reg(name).ok_or(AsmError::EncodeError("Invalid register".into()))?;
+        let reg = reg(cx, name)?;
+        // The stack operand size defaults to 64 bits in long mode and can only be narrowed to 16
+        match (cx.bits, reg.width) {
+            (64, 64) | (32, 32) | (16, 16) => {}
+            (_, 16) | (16, 32) => bytes.push(0x66),
+            _ => return Err(AsmError::EncodeError(format!("{} {} is not encodable in bits {}", ins.mnemonic, name, cx.bits))),
+        }
         if reg.code >= 8 { let mut rex = REX::new(); rex.b = true; bytes.push(rex.encode()); }
         bytes.push(base_opcode + (reg.code & 7));
         Ok(())
     } else { Err(AsmError::EncodeError(format!("{} only supports registers for now", ins.mnemonic))) }
 }
 
-fn encode_jump(ins: &Instruction, opcode: u8, bytes: &mut Vec<u8>, relocs: &mut Vec<Relocation>) -> Result<(), AsmError> {
+fn encode_jump(ins: &Instruction, opcode: u8, bytes: &mut Vec<u8>, relocs: &mut Vec<Relocation>, cx: &Context) -> Result<(), AsmError> {
     if ins.operands.len() != 1 { return Err(AsmError::EncodeError(format!("{} expects 1 operand", ins.mnemonic))); }
+    // FF /2 (call) and FF /4 (jmp) for indirect targets; the operand size follows the mode
+    let indirect_ext = if opcode == 0xE8 { 2 } else { 4 };
     match &ins.operands[0] {
         Operand::Label(label) => {
+            // rel16 in 16-bit mode, rel32 otherwise
+            let kind = if cx.bits == 16 { RelocKind::Relative16 } else { RelocKind::Relative32 };
+            bytes.push(opcode);
+            relocs.push(Relocation { offset: bytes.len(), symbol: label.clone(), kind, addend: 0 });
+            bytes.resize(bytes.len() + if cx.bits == 16 { 2 } else { 4 }, 0);
+            Ok(())
+        }
+        Operand::LabelWrt(label, Wrt::Plt) => {
+            if cx.bits != 64 { return Err(AsmError::EncodeError("wrt ..plt requires bits 64".into())); }
             bytes.push(opcode);
-            relocs.push(Relocation { offset: bytes.len(), symbol: label.clone(), kind: RelocKind::Relative32, addend: -4 });
+            relocs.push(Relocation { offset: bytes.len(), symbol: label.clone(), kind: RelocKind::Plt32, addend: 0 });
             bytes.extend_from_slice(&0i32.to_le_bytes());
             Ok(())
         }
-        _ => Err(AsmError::EncodeError(format!("{} only supports labels for now", ins.mnemonic))),
+        Operand::Register(name) => {
+            let reg = reg(cx, name)?;
+            if reg.width != cx.bits { return Err(AsmError::EncodeError(format!("{} expects a {}-bit register", ins.mnemonic, cx.bits))); }
+            if reg.code >= 8 { let mut rex = REX::new(); rex.b = true; bytes.push(rex.encode()); }
+            bytes.push(0xFF); bytes.push(ModRM::new(0b11, indirect_ext, reg.code).encode());
+            Ok(())
+        }
+        Operand::Memory(mem) => write_mem_operand(bytes, relocs, cx, 0xFF, indirect_ext, default_width(cx), mem),
+        _ => Err(AsmError::EncodeError(format!("Unsupported {} operand", ins.mnemonic))),
     }
 }
 
-fn encode_directive(dir: &Directive, bytes: &mut Vec<u8>) -> Result<(), AsmError> {
-    match dir.name.as_str() {
-        "db" => { for v in &dir.values { match v { DirectiveValue::Number(n) => bytes.push(*n as u8), DirectiveValue::StringLiteral(s) => bytes.extend_from_slice(s.as_bytes()), _ => return Err(AsmError::EncodeError("Unsupported db value".into())) } } Ok(()) }
-        "dw" => { for v in &dir.values { if let DirectiveValue::Number(n) = v { bytes.extend_from_slice(&(*n as i16).to_le_bytes()); } else { return Err(AsmError::EncodeError("dw only supports numbers".into())); } } Ok(()) }
-        "dd" => { for v in &dir.values { if let DirectiveValue::Number(n) = v { bytes.extend_from_slice(&(*n as i32).to_le_bytes()); } else { return Err(AsmError::EncodeError("dd only supports numbers".into())); } } Ok(()) }
-        "dq" => { for v in &dir.values { if let DirectiveValue::Number(n) = v { bytes.extend_from_slice(&(*n as i64).to_le_bytes()); } else { return Err(AsmError::EncodeError("dq only supports numbers".into())); } } Ok(()) }
-        _ => Err(AsmError::EncodeError(format!("Unknown directive {}", dir.name))),
+fn encode_int(ins: &Instruction, bytes: &mut Vec<u8>) -> Result<(), AsmError> {
+    match ins.operands.as_slice() {
+        [Operand::Immediate(n)] if (0..=255).contains(n) => {
+            bytes.push(0xCD); bytes.push(*n as u8);
+            Ok(())
+        }
+        _ => Err(AsmError::EncodeError("int expects an interrupt number from 0 to 255".into())),
+    }
+}
+
+/// Writes a data directive; symbols become absolute relocations of the element size.
+fn encode_directive(dir: &Directive, bytes: &mut Vec<u8>, relocs: &mut Vec<Relocation>) -> Result<(), AsmError> {
+    let (size, kind) = match dir.name.as_str() {
+        "db" => (1, None),
+        "dw" => (2, Some(RelocKind::Absolute16)),
+        "dd" => (4, Some(RelocKind::Absolute32)),
+        "dq" => (8, Some(RelocKind::Absolute64)),
+        _ => return Err(AsmError::EncodeError(format!("Unknown directive {}", dir.name))),
+    };
+    for v in &dir.values {
+        match (v, kind) {
+            (DirectiveValue::Number(n), _) => bytes.extend_from_slice(&n.to_le_bytes()[..size]),
+            (DirectiveValue::StringLiteral(s), None) => bytes.extend_from_slice(s.as_bytes()),
+            (DirectiveValue::Identifier(symbol), Some(kind)) => {
+                relocs.push(Relocation { offset: bytes.len(), symbol: symbol.clone(), kind, addend: 0 });
+                bytes.resize(bytes.len() + size, 0);
+            }
+            _ => return Err(AsmError::EncodeError(format!("Unsupported {} value {:?}", dir.name, v))),
+        }
     }
+    Ok(())
 }
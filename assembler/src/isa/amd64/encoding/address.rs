@@ -1,10 +1,11 @@
 use crate::ast::MemoryOperand;
 use crate::error::AsmError;
-use crate::isa::amd64::tables::{REGISTERS_32, REGISTERS_64};
+use crate::isa::amd64::tables::{REGISTERS_16, REGISTERS_32, REGISTERS_64};
 
 #[derive(Debug, Clone)]
 pub enum DispKind {
     Disp8(i8),
+    Disp16(i16),
     Disp32(i32),
 }
 
@@ -17,94 +18,281 @@ pub struct EncodedAddress {
 
     pub rex_b: bool,
     pub rex_x: bool,
+
+    pub segment: Option<u8>, // segment-override prefix byte
+
+    /// Width of the registers used to form the address (16, 32 or 64).
+    pub address_size: u8,
+    /// The address size differs from the mode's default and needs a 0x67 prefix.
+    pub address_override: bool,
 }
 
-fn reg_code(name: &str, mode: u8) -> Option<u8> {
-    let regs = match mode {
+fn reg_code(name: &str, size: u8) -> Option<u8> {
+    let regs = match size {
         64 => REGISTERS_64,
         32 => REGISTERS_32,
+        16 => REGISTERS_16,
         _ => return None,
     };
 
     regs.iter().find(|(n, _)| *n == name).map(|(_, c)| *c)
 }
 
+fn reg_size(name: &str) -> Option<u8> {
+    [64, 32, 16].into_iter().find(|&size| reg_code(name, size).is_some())
+}
+
+/// Address size implied by the base/index registers, or the mode's default without registers.
+fn address_size(mem: &MemoryOperand, bits: u8) -> Result<u8, AsmError> {
+    let mut size = None;
+    for name in mem.base.iter().chain(mem.index.iter()) {
+        let reg = reg_size(name).ok_or_else(|| AsmError::EncodeError(format!("Invalid address register {}", name)))?;
+        if size.is_some_and(|s| s != reg) {
+            return Err(AsmError::EncodeError("Base and index registers must have the same width".into()));
+        }
+        size = Some(reg);
+    }
+    let size = size.unwrap_or(if mem.rip_relative { 64 } else { bits });
+    match (bits, size) {
+        (64, 16) => Err(AsmError::EncodeError("16-bit addressing is not available in 64-bit mode".into())),
+        (16 | 32, 64) => Err(AsmError::EncodeError(format!("64-bit addressing requires bits 64 (current mode is bits {})", bits))),
+        _ => Ok(size),
+    }
+}
+
+fn segment_prefix(name: &str) -> Option<u8> {
+    match name {
+        "es" => Some(0x26),
+        "cs" => Some(0x2E),
+        "ss" => Some(0x36),
+        "ds" => Some(0x3E),
+        "fs" => Some(0x64),
+        "gs" => Some(0x65),
+        _ => None,
+    }
+}
+
+fn scale_bits(scale: u8) -> Result<u8, AsmError> {
+    match scale {
+        1 => Ok(0),
+        2 => Ok(1),
+        4 => Ok(2),
+        8 => Ok(3),
+        _ => Err(AsmError::EncodeError(format!("Invalid scale factor {}", scale))),
+    }
+}
+
 /// Encodes x86-64 memory operand
 ///
 /// Supports:
-/// - [base]
-/// - [base + disp8]
-/// - [base + disp32]
-/// - special case [rbp], [rbp + 0]
-/// - automatic REX.B
+/// - [base], [base + disp8], [base + disp32]
+/// - [base + index*scale + disp] and [index*scale + disp] through SIB
+/// - [disp32] absolute and [rel disp32] RIP-relative addressing
+/// - special cases [rbp]/[r13] (forced disp8) and [rsp]/[r12] (forced SIB)
+/// - fs:/gs: (and legacy) segment overrides
+/// - 16-bit [bx|bp + si|di + disp] forms and 0x67 address-size overrides
+/// - automatic REX.B / REX.X
 ///
-/// Does NOT (yet) support:
-/// - index register
-/// - scale factor
-/// - sib addressing
-pub fn encode_address(mem: &MemoryOperand, mode: u8) -> Result<EncodedAddress, AsmError> {
-    let base = mem.base.as_deref();
-    let index = mem.index.as_deref();
-    let scale = mem.scale;
-    let disp = mem.disp;
+/// A symbolic displacement is always encoded as a zero disp32 (disp16 for
+/// 16-bit addressing); the symbol and `mem.disp` are carried by the relocation instead.
+///
+/// `bits` is the current `bits` mode. 32-bit registers in 16- or 64-bit mode and
+/// 16-bit registers in 32-bit mode set `address_override`.
+pub fn encode_address(mem: &MemoryOperand, bits: u8) -> Result<EncodedAddress, AsmError> {
+    let size = address_size(mem, bits)?;
+    let mut addr = if size == 16 { encode_address16(mem)? } else { encode_address32(mem, size, bits)? };
+    addr.address_override = size != bits;
+    Ok(addr)
+}
 
-    if index.is_some() {
-        return Err(AsmError::EncodeError(
-            "index-based addressing not implemented yet (A-Step)".into(),
-        ));
+/// 16-bit ModRM addressing: `[bx|bp + si|di + disp]` with no SIB byte.
+fn encode_address16(mem: &MemoryOperand) -> Result<EncodedAddress, AsmError> {
+    let segment = segment_override(mem)?;
+    if mem.rip_relative {
+        return Err(AsmError::EncodeError("RIP-relative addressing requires bits 64".into()));
+    }
+    if mem.index.is_some() && mem.scale != 1 {
+        return Err(AsmError::EncodeError("16-bit addressing does not support scaled index registers".into()));
     }
 
-    if let Some(base_reg) = base {
-        let base_code =
-            reg_code(base_reg, mode).ok_or_else(|| AsmError::EncodeError("Invalid base register".into()))?;
+    let mut base = None;
+    let mut index = None;
+    for name in mem.base.iter().chain(mem.index.iter()) {
+        match name.as_str() {
+            "bx" | "bp" if base.is_none() => base = Some(name.as_str()),
+            "si" | "di" if index.is_none() => index = Some(name.as_str()),
+            _ => return Err(AsmError::EncodeError(format!("Invalid 16-bit address register combination with {}", name))),
+        }
+    }
 
-        let rex_b = base_code > 7; // REX.B = high registers (r8–r15)
-        let rm = base_code & 7;
+    let has_symbol = mem.symbol.is_some();
+    let disp = if has_symbol { 0 } else { mem.disp };
+    if !(-(1 << 15)..(1 << 16)).contains(&disp) {
+        return Err(AsmError::EncodeError(format!("Displacement {} does not fit in 16 bits", disp)));
+    }
 
-        if base_reg == "rbp" && disp == 0 {
+    let rm_bits = match (base, index) {
+        (Some("bx"), Some("si")) => 0,
+        (Some("bx"), Some("di")) => 1,
+        (Some("bp"), Some("si")) => 2,
+        (Some("bp"), Some("di")) => 3,
+        (None, Some("si")) => 4,
+        (None, Some("di")) => 5,
+        (Some("bp"), None) => 6,
+        (Some("bx"), None) => 7,
+        _ => {
+            // mod=00 rm=110 is a bare disp16
             return Ok(EncodedAddress {
-                mod_bits: 1,      // 01b = disp8
-                rm_bits: rm,
+                mod_bits: 0,
+                rm_bits: 6,
                 sib: None,
-                disp: Some(DispKind::Disp8(0)),
-                rex_b,
+                disp: Some(DispKind::Disp16(disp as i16)),
+                rex_b: false,
                 rex_x: false,
+                segment,
+                address_size: 16,
+                address_override: false,
             });
         }
+    };
 
-        if disp == 0 {
-            return Ok(EncodedAddress {
-                mod_bits: 0,      // 00b
-                rm_bits: rm,
-                sib: None,
-                disp: None,
-                rex_b,
-                rex_x: false,
-            });
+    // [bp] has no mod=00 form, so it always carries a displacement
+    let (mod_bits, disp) = if has_symbol {
+        (2, Some(DispKind::Disp16(0)))
+    } else if disp == 0 && rm_bits != 6 {
+        (0, None)
+    } else if (-128..=127).contains(&disp) {
+        (1, Some(DispKind::Disp8(disp as i8)))
+    } else {
+        (2, Some(DispKind::Disp16(disp as i16)))
+    };
+
+    Ok(EncodedAddress {
+        mod_bits,
+        rm_bits,
+        sib: None,
+        disp,
+        rex_b: false,
+        rex_x: false,
+        segment,
+        address_size: 16,
+        address_override: false,
+    })
+}
+
+fn segment_override(mem: &MemoryOperand) -> Result<Option<u8>, AsmError> {
+    match mem.segment.as_deref() {
+        Some(seg) => Ok(Some(segment_prefix(seg).ok_or_else(|| AsmError::EncodeError(format!("Invalid segment register {}", seg)))?)),
+        None => Ok(None),
+    }
+}
+
+/// 32- and 64-bit ModRM/SIB addressing.
+fn encode_address32(mem: &MemoryOperand, size: u8, bits: u8) -> Result<EncodedAddress, AsmError> {
+    let base = mem.base.as_deref();
+    let index = mem.index.as_deref();
+    let has_symbol = mem.symbol.is_some();
+    let disp = if has_symbol { 0 } else { mem.disp };
+
+    let segment = segment_override(mem)?;
+
+    let disp32 = i32::try_from(disp)
+        .map_err(|_| AsmError::EncodeError(format!("Displacement {} does not fit in 32 bits", disp)))?;
+
+    if mem.rip_relative {
+        if bits != 64 {
+            return Err(AsmError::EncodeError("RIP-relative addressing requires bits 64".into()));
+        }
+        if base.is_some() || index.is_some() {
+            return Err(AsmError::EncodeError("RIP-relative addressing cannot use base or index registers".into()));
+        }
+        return Ok(EncodedAddress {
+            mod_bits: 0,
+            rm_bits: 5, // disp32(%rip)
+            sib: None,
+            disp: Some(DispKind::Disp32(disp32)),
+            rex_b: false,
+            rex_x: false,
+            segment,
+            address_size: 64,
+            address_override: false,
+        });
+    }
+
+    let index_code = match index {
+        Some(name) => {
+            let code = reg_code(name, size).ok_or_else(|| AsmError::EncodeError("Invalid index register".into()))?;
+            if code == 4 {
+                return Err(AsmError::EncodeError(format!("{} cannot be used as an index register", name)));
+            }
+            Some(code)
         }
+        None => None,
+    };
+    let scale = if index_code.is_some() { scale_bits(mem.scale)? } else { 0 };
 
-        if (-128..=127).contains(&disp) {
+    let Some(base_reg) = base else {
+        if index_code.is_none() && bits != 64 {
+            // Outside long mode mod=00 rm=101 is a plain [disp32] rather than RIP-relative
             return Ok(EncodedAddress {
-                mod_bits: 1,  // 01b
-                rm_bits: rm,
+                mod_bits: 0,
+                rm_bits: 5,
                 sib: None,
-                disp: Some(DispKind::Disp8(disp as i8)),
-                rex_b,
+                disp: Some(DispKind::Disp32(disp32)),
+                rex_b: false,
                 rex_x: false,
+                segment,
+                address_size: size,
+                address_override: false,
             });
         }
-
+        // No base: SIB with base=101 and mod=00 means [index*scale + disp32]
         return Ok(EncodedAddress {
-            mod_bits: 2, // 10b
-            rm_bits: rm,
-            sib: None,
-            disp: Some(DispKind::Disp32(disp as i32)),
-            rex_b,
-            rex_x: false,
+            mod_bits: 0,
+            rm_bits: 4,
+            sib: Some((scale, index_code.unwrap_or(4) & 7, 5)),
+            disp: Some(DispKind::Disp32(disp32)),
+            rex_b: false,
+            rex_x: index_code.is_some_and(|c| c > 7),
+            segment,
+            address_size: size,
+            address_override: false,
         });
-    }
+    };
+
+    let base_code =
+        reg_code(base_reg, size).ok_or_else(|| AsmError::EncodeError("Invalid base register".into()))?;
+
+    let rex_b = base_code > 7; // REX.B = high registers (r8–r15)
+    let low = base_code & 7;
+
+    // mod=00 with rm/base=101 means disp32, so rbp/r13 always need a displacement
+    let (mod_bits, disp) = if has_symbol {
+        (2, Some(DispKind::Disp32(disp32))) // 10b
+    } else if disp == 0 && low != 5 {
+        (0, None) // 00b
+    } else if (-128..=127).contains(&disp) {
+        (1, Some(DispKind::Disp8(disp as i8))) // 01b
+    } else {
+        (2, Some(DispKind::Disp32(disp32))) // 10b
+    };
+
+    // rm=100 selects a SIB byte, so rsp/r12 as base always go through SIB
+    let (rm_bits, sib) = if index_code.is_some() || low == 4 {
+        (4, Some((scale, index_code.unwrap_or(4) & 7, low)))
+    } else {
+        (low, None)
+    };
 
-    Err(AsmError::EncodeError(
-        "Addressing without base register not implemented yet".into(),
-    ))
+    Ok(EncodedAddress {
+        mod_bits,
+        rm_bits,
+        sib,
+        disp,
+        rex_b,
+        rex_x: index_code.is_some_and(|c| c > 7),
+        segment,
+        address_size: size,
+        address_override: false,
+    })
 }
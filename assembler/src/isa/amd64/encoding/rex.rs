@@ -1,4 +1,4 @@
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Default)]
 pub struct REX {
     pub w: bool,
     pub r: bool,
@@ -8,7 +8,7 @@ pub struct REX {
 
 impl REX {
     pub fn new() -> Self {
-        Self { w: false, r: false, x: false, b: false }
+        Self::default()
     }
 
     pub fn encode(&self) -> u8 {
@@ -1,12 +1,14 @@
 use crate::traits::ISA;
 use crate::ast::AST;
 use crate::error::AsmError;
-use crate::assembler::{AssemblerOutput};
+use crate::assembler::{AsmOptions, AssemblerOutput};
 
 pub mod encoder;
 pub mod parser;
 pub mod tables;
 pub mod encoding;
+pub mod cfi;
+pub mod select;
 
 pub struct AMD64;
 
@@ -15,7 +17,7 @@ impl ISA for AMD64 {
         parser::parse(tokens)
     }
 
-    fn encode(&self, ast: &AST) -> Result<AssemblerOutput, AsmError> {
-        encoder::encode(ast)
+    fn encode(&self, ast: &AST, options: &AsmOptions) -> Result<AssemblerOutput, AsmError> {
+        encoder::encode(ast, options)
     }
 }
\ No newline at end of file
@@ -15,6 +15,11 @@ pub fn parse(tokens: &[Token]) -> Result<AST, AsmError> {
                     continue;
                 }
 
+                if name == "times" {
+                    items.push(parse_times(tokens, &mut pos)?);
+                    continue;
+                }
+
                 if is_directive(name) {
                     items.push(parse_directive(tokens, &mut pos)?);
                     continue;
@@ -22,6 +27,19 @@ pub fn parse(tokens: &[Token]) -> Result<AST, AsmError> {
 
                 items.push(parse_instruction(tokens, &mut pos)?);
             }
+            // NASM's primitive form: `[bits 16]`, `[org 0x7C00]`, `[section .text]`
+            TokenKind::LBracket => {
+                pos += 1;
+                let directive = match tokens.get(pos).map(|t| &t.kind) {
+                    Some(TokenKind::Identifier(name)) if is_directive(name) => parse_directive(tokens, &mut pos)?,
+                    _ => return Err(AsmError::ParserError("Expected a directive inside '[...]'".into())),
+                };
+                if !matches!(tokens.get(pos).map(|t| &t.kind), Some(TokenKind::RBracket)) {
+                    return Err(AsmError::ParserError("Expected ']' after directive".into()));
+                }
+                pos += 1;
+                items.push(directive);
+            }
             TokenKind::Newline => pos += 1,
             _ => pos += 1,
         }
@@ -29,6 +47,68 @@ pub fn parse(tokens: &[Token]) -> Result<AST, AsmError> {
     Ok(AST { items })
 }
 
+fn parse_times(tokens: &[Token], pos: &mut usize) -> Result<ASTNode, AsmError> {
+    *pos += 1;
+    let count = parse_expr(tokens, pos)?;
+    let line = match tokens.get(*pos).map(|t| &t.kind) {
+        Some(TokenKind::Identifier(name)) if matches!(name.as_str(), "db" | "dw" | "dd" | "dq") => parse_directive(tokens, pos)?,
+        Some(TokenKind::Identifier(name)) if !is_directive(name) && name != "times" => parse_instruction(tokens, pos)?,
+        _ => return Err(AsmError::ParserError("times expects an instruction or data directive".into())),
+    };
+    Ok(ASTNode::Times(count, Box::new(line)))
+}
+
+/// expr := term (('+' | '-') term)*
+fn parse_expr(tokens: &[Token], pos: &mut usize) -> Result<Expr, AsmError> {
+    let mut lhs = parse_term(tokens, pos)?;
+    loop {
+        let op = match tokens.get(*pos).map(|t| &t.kind) {
+            Some(TokenKind::Plus) => BinOp::Add,
+            Some(TokenKind::Minus) => BinOp::Sub,
+            _ => return Ok(lhs),
+        };
+        *pos += 1;
+        let rhs = parse_term(tokens, pos)?;
+        lhs = Expr::Binary(op, Box::new(lhs), Box::new(rhs));
+    }
+}
+
+/// term := factor (('*' | '/') factor)*
+fn parse_term(tokens: &[Token], pos: &mut usize) -> Result<Expr, AsmError> {
+    let mut lhs = parse_factor(tokens, pos)?;
+    loop {
+        let op = match tokens.get(*pos).map(|t| &t.kind) {
+            Some(TokenKind::Multiply) => BinOp::Mul,
+            Some(TokenKind::Divide) => BinOp::Div,
+            _ => return Ok(lhs),
+        };
+        *pos += 1;
+        let rhs = parse_factor(tokens, pos)?;
+        lhs = Expr::Binary(op, Box::new(lhs), Box::new(rhs));
+    }
+}
+
+/// factor := number | '$' | '$$' | '-' factor | '(' expr ')'
+fn parse_factor(tokens: &[Token], pos: &mut usize) -> Result<Expr, AsmError> {
+    let kind = tokens.get(*pos).map(|t| &t.kind);
+    *pos += 1;
+    match kind {
+        Some(TokenKind::Number(n)) => Ok(Expr::Number(*n)),
+        Some(TokenKind::Identifier(s)) if s == "$" => Ok(Expr::Here),
+        Some(TokenKind::Identifier(s)) if s == "$$" => Ok(Expr::SectionStart),
+        Some(TokenKind::Minus) => Ok(Expr::Neg(Box::new(parse_factor(tokens, pos)?))),
+        Some(TokenKind::LParen) => {
+            let inner = parse_expr(tokens, pos)?;
+            if !matches!(tokens.get(*pos).map(|t| &t.kind), Some(TokenKind::RParen)) {
+                return Err(AsmError::ParserError("Expected ')' in expression".into()));
+            }
+            *pos += 1;
+            Ok(inner)
+        }
+        _ => Err(AsmError::ParserError("Expected a number, '$', '$$' or '(' in expression".into())),
+    }
+}
+
 fn parse_instruction(tokens: &[Token], pos: &mut usize) -> Result<ASTNode, AsmError> {
     let mnemonic = parse_mnemonic(tokens, pos)?;
     let operands = parse_operand_list(tokens, pos)?;
@@ -75,13 +155,24 @@ fn parse_operand(tokens: &[Token], pos: &mut usize) -> Result<Operand, AsmError>
             *pos += 1;
             Ok(Operand::Immediate(*n))
         }
+        TokenKind::Minus => match tokens.get(*pos + 1).map(|t| &t.kind) {
+            Some(TokenKind::Number(n)) => {
+                *pos += 2;
+                Ok(Operand::Immediate(-*n))
+            }
+            _ => Err(AsmError::ParserError("Expected number after '-'".into())),
+        },
         TokenKind::Identifier(name) => {
             let s = name.clone();
             *pos += 1;
-            if is_register(&s) {
+            if is_register(&s) || is_segment(&s) {
                 return Ok(Operand::Register(s));
             }
-            return Ok(Operand::Label(s));
+            if matches!(tokens.get(*pos).map(|t| &t.kind), Some(TokenKind::Identifier(w)) if w == "wrt") {
+                *pos += 1;
+                return Ok(Operand::LabelWrt(s, parse_wrt(tokens, pos)?));
+            }
+            Ok(Operand::Label(s))
         }
         TokenKind::LBracket => {
             parse_memory_operand(tokens, pos)
@@ -92,42 +183,63 @@ fn parse_operand(tokens: &[Token], pos: &mut usize) -> Result<Operand, AsmError>
 
 fn parse_memory_operand(tokens: &[Token], pos: &mut usize) -> Result<Operand, AsmError> {
     *pos += 1;
-    let mut base: Option<String> = None;
-    let mut index: Option<String> = None;
-    let mut scale: u8 = 1;
-    let mut disp: i64 = 0;
+    let mut mem = MemoryOperand {
+        base: None,
+        index: None,
+        scale: 1,
+        disp: 0,
+        segment: None,
+        symbol: None,
+        wrt: None,
+        rip_relative: false,
+    };
     let mut negative = false;
     while *pos < tokens.len() {
         match &tokens[*pos].kind {
             TokenKind::Identifier(name) => {
-                let reg = name.clone();
+                let name = name.clone();
                 *pos += 1;
-                if is_register(&reg) {
-                    if base.is_none() {
-                        base = Some(reg);
-                    } else if index.is_none() {
-                        index = Some(reg);
-                        if *pos < tokens.len() && matches!(tokens[*pos].kind, TokenKind::Multiply) {
-                            *pos += 1;
-                            match &tokens[*pos].kind {
-                                TokenKind::Number(n) => {
-                                    scale = *n as u8;
-                                    *pos += 1;
-                                }
-                                _ => return Err(AsmError::ParserError("Invalid scale".into())),
+                if is_segment(&name) && matches!(tokens.get(*pos).map(|t| &t.kind), Some(TokenKind::Colon)) {
+                    if mem.segment.is_some() {
+                        return Err(AsmError::ParserError("Multiple segment overrides in memory operand".into()));
+                    }
+                    mem.segment = Some(name);
+                    *pos += 1;
+                } else if name == "rel" {
+                    mem.rip_relative = true;
+                } else if name == "wrt" {
+                    mem.wrt = Some(parse_wrt(tokens, pos)?);
+                } else if is_register(&name) {
+                    if *pos < tokens.len() && matches!(tokens[*pos].kind, TokenKind::Multiply) {
+                        *pos += 1;
+                        match tokens.get(*pos).map(|t| &t.kind) {
+                            Some(TokenKind::Number(n)) => {
+                                mem.scale = *n as u8;
+                                *pos += 1;
                             }
+                            _ => return Err(AsmError::ParserError("Invalid scale".into())),
+                        }
+                        if mem.index.is_some() {
+                            return Err(AsmError::ParserError("Too many index registers in memory operand".into()));
                         }
+                        mem.index = Some(name);
+                    } else if mem.base.is_none() {
+                        mem.base = Some(name);
+                    } else if mem.index.is_none() {
+                        mem.index = Some(name);
                     } else {
                         return Err(AsmError::ParserError("Too many registers in memory operand".into()));
                     }
                 } else {
-                   // Label-based memory? [msg]
-                   return Err(AsmError::ParserError("Label-based memory not supported in [] yet".into()));
+                    if negative || mem.symbol.is_some() {
+                        return Err(AsmError::ParserError(format!("Unsupported symbol expression with {}", name)));
+                    }
+                    mem.symbol = Some(name);
                 }
             }
             TokenKind::Number(n) => {
                 let val = if negative { -*n } else { *n };
-                disp += val;
+                mem.disp += val;
                 negative = false;
                 *pos += 1;
             }
@@ -137,7 +249,25 @@ fn parse_memory_operand(tokens: &[Token], pos: &mut usize) -> Result<Operand, As
             _ => break,
         }
     }
-    Ok(Operand::Memory(MemoryOperand { base, index, scale, disp }))
+    Ok(Operand::Memory(mem))
+}
+
+fn parse_wrt(tokens: &[Token], pos: &mut usize) -> Result<Wrt, AsmError> {
+    let name = match tokens.get(*pos).map(|t| &t.kind) {
+        Some(TokenKind::Identifier(s)) => s.clone(),
+        _ => return Err(AsmError::ParserError("Expected relocation operator after wrt".into())),
+    };
+    *pos += 1;
+    match name.as_str() {
+        "..plt" => Ok(Wrt::Plt),
+        "..gotpcrel" => Ok(Wrt::GotPcRel),
+        "..tpoff" => Ok(Wrt::TpOff),
+        "..gottpoff" => Ok(Wrt::GotTpOff),
+        "..tlsgd" => Ok(Wrt::TlsGd),
+        "..tlsld" => Ok(Wrt::TlsLd),
+        "..dtpoff" => Ok(Wrt::DtpOff),
+        _ => Err(AsmError::ParserError(format!("Unknown relocation operator wrt {}", name))),
+    }
 }
 
 fn parse_directive(tokens: &[Token], pos: &mut usize) -> Result<ASTNode, AsmError> {
@@ -151,7 +281,8 @@ fn parse_directive(tokens: &[Token], pos: &mut usize) -> Result<ASTNode, AsmErro
         "section" => {
             if let TokenKind::Identifier(sec_name) = &tokens[*pos].kind {
                 *pos += 1;
-                return Ok(ASTNode::Section(sec_name.clone()));
+                let attrs = parse_section_attrs(tokens, pos)?;
+                return Ok(ASTNode::Section(sec_name.clone(), attrs));
             }
             Err(AsmError::ParserError("Expected section name".into()))
         }
@@ -176,6 +307,12 @@ fn parse_directive(tokens: &[Token], pos: &mut usize) -> Result<ASTNode, AsmErro
                 match &tokens[*pos].kind {
                     TokenKind::Newline => { *pos += 1; break; }
                     TokenKind::Number(n) => { values.push(DirectiveValue::Number(*n)); *pos += 1; }
+                    TokenKind::Minus => {
+                        match tokens.get(*pos + 1).map(|t| &t.kind) {
+                            Some(TokenKind::Number(n)) => { values.push(DirectiveValue::Number(-*n)); *pos += 2; }
+                            _ => return Err(AsmError::ParserError(format!("Expected number after '-' in {}", name))),
+                        }
+                    }
                     TokenKind::StringLiteral(s) => { values.push(DirectiveValue::StringLiteral(s.clone())); *pos += 1; }
                     TokenKind::Identifier(s) => { values.push(DirectiveValue::Identifier(s.clone())); *pos += 1; }
                     TokenKind::Comma => { *pos += 1; continue; }
@@ -187,10 +324,32 @@ fn parse_directive(tokens: &[Token], pos: &mut usize) -> Result<ASTNode, AsmErro
     }
 }
 
+fn parse_section_attrs(tokens: &[Token], pos: &mut usize) -> Result<Vec<SectionAttr>, AsmError> {
+    let mut attrs = Vec::new();
+    while let Some(TokenKind::Identifier(key)) = tokens.get(*pos).map(|t| &t.kind) {
+        if !matches!(tokens.get(*pos + 1).map(|t| &t.kind), Some(TokenKind::Equals)) {
+            return Err(AsmError::ParserError(format!("Expected '=' after section attribute {}", key)));
+        }
+        let value = match tokens.get(*pos + 2).map(|t| &t.kind) {
+            Some(TokenKind::Number(n)) => DirectiveValue::Number(*n),
+            Some(TokenKind::Identifier(s)) => DirectiveValue::Identifier(s.clone()),
+            _ => return Err(AsmError::ParserError(format!("Expected a value for section attribute {}", key))),
+        };
+        attrs.push(SectionAttr { key: key.clone(), value });
+        *pos += 3;
+    }
+    Ok(attrs)
+}
+
 fn is_register(name: &str) -> bool {
     matches!(name, "rax" | "rbx" | "rcx" | "rdx" | "rsi" | "rdi" | "rbp" | "rsp" | "r8" | "r9" | "r10" | "r11" | "r12" | "r13" | "r14" | "r15" | "eax" | "ebx" | "ecx" | "edx" | "esi" | "edi" | "ebp" | "esp" | "r8d" | "r9d" | "r10d" | "r11d" | "r12d" | "r13d" | "r14d" | "r15d" | "ax" | "bx" | "cx" | "dx" | "si" | "di" | "bp" | "sp" | "r8w" | "r9w" | "r10w" | "r11w" | "r12w" | "r13w" | "r14w" | "r15w" | "al" | "bl" | "cl" | "dl" | "ah" | "bh" | "ch" | "dh" | "r8b" | "r9b" | "r10b" | "r11b" | "r12b" | "r13b" | "r14b" | "r15b")
 }
 
+fn is_segment(name: &str) -> bool {
+    matches!(name, "cs" | "ds" | "es" | "ss" | "fs" | "gs")
+}
+
 fn is_directive(name: &str) -> bool {
-    matches!(name, "db" | "dw" | "dd" | "dq" | "section" | "global" | "extern")
+    matches!(name, "db" | "dw" | "dd" | "dq" | "section" | "global" | "extern" | "org" | "bits")
+        || name.starts_with(".cfi_")
 }
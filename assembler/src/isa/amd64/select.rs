@@ -0,0 +1,60 @@
+//! Encoding selection: picks the shortest AMD64 form that has the same effect.
+//!
+//! Every choice here is architecturally equivalent to the long form (same
+//! register/flags result), so disassembling the output yields the same
+//! instruction semantics, just with fewer bytes.
+
+/// Form used for `mov reg, imm`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MovImmForm {
+    /// `B0+r ib` / `66 B8+r iw` / `B8+r id` at the register's own width.
+    Native,
+    /// `B8+r id` on the 32-bit alias; the write zero-extends into the full 64-bit register.
+    ZeroExtended32,
+    /// `REX.W C7 /0 id`; the immediate is sign-extended to 64 bits.
+    SignExtended32,
+    /// `REX.W B8+r io` (movabs).
+    Imm64,
+}
+
+/// Form used for the immediate variant of `add`/`or`/`and`/`sub`/`xor`/`cmp`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AluImmForm {
+    /// `83 /n ib` with a sign-extended 8-bit immediate.
+    Imm8,
+    /// Short accumulator form (`04`/`05`, `2C`/`2D`, `3C`/`3D`, ...) without a ModRM byte.
+    Accumulator,
+    /// `80 /n ib` for byte registers, `81 /n iw/id` otherwise.
+    Full,
+}
+
+pub fn select_mov_imm(width: u8, imm: i64, optimize: bool) -> MovImmForm {
+    if width != 64 {
+        return MovImmForm::Native;
+    }
+    if !optimize {
+        return MovImmForm::Imm64;
+    }
+    if (0..=u32::MAX as i64).contains(&imm) {
+        MovImmForm::ZeroExtended32
+    } else if (i32::MIN as i64..0).contains(&imm) {
+        MovImmForm::SignExtended32
+    } else {
+        MovImmForm::Imm64
+    }
+}
+
+pub fn select_alu_imm(reg_code: u8, width: u8, imm: i64, optimize: bool) -> AluImmForm {
+    if width == 8 {
+        // There is no imm8 sign-extension form for byte operands
+        return if optimize && reg_code == 0 { AluImmForm::Accumulator } else { AluImmForm::Full };
+    }
+    if (-128..=127).contains(&imm) {
+        // 83 /n ib is never longer than the accumulator form
+        AluImmForm::Imm8
+    } else if optimize && reg_code == 0 {
+        AluImmForm::Accumulator
+    } else {
+        AluImmForm::Full
+    }
+}
@@ -26,8 +26,13 @@ pub const REGISTERS_8: &[(&str, u8)] = &[
     ("r12b", 12), ("r13b", 13), ("r14b", 14), ("r15b", 15),
 ];
 
+pub const SEGMENT_REGISTERS: &[(&str, u8)] = &[
+    ("es", 0), ("cs", 1), ("ss", 2), ("ds", 3), ("fs", 4), ("gs", 5),
+];
+
 pub const MNEMONICS: &[&str] = &[
     "mov",
+    "lea",
     "add",
     "sub",
     "and",
@@ -38,8 +43,14 @@ pub const MNEMONICS: &[&str] = &[
     "pop",
     "jmp",
     "call",
+    "int",
     "ret",
     "nop",
+    "hlt",
+    "cli",
+    "sti",
     "syscall",
     "int3",
+    "endbr64",
+    "endbr32",
 ];
@@ -4,5 +4,163 @@ pub mod ast;
 pub mod error;
 pub mod tokens;
 pub mod traits;
+pub mod flat;
 
-pub use assembler::*;
\ No newline at end of file
+pub use assembler::*;
+pub use flat::link_flat;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::AsmError;
+    use crate::isa::AMD64;
+
+    #[test]
+    fn cfi_directives_emit_eh_frame() {
+        let src = "global main\nmain:\n.cfi_startproc\npush rbp\n.cfi_def_cfa_offset 16\n.cfi_offset rbp, -16\npop rbp\n.cfi_def_cfa_offset 8\nret\n.cfi_endproc\n";
+        let out = assemble(src, &AMD64).unwrap();
+
+        let eh = out.sections.iter().find(|s| s.name == ".eh_frame").unwrap();
+        // 24-byte CIE followed by one FDE, both padded to 8 bytes
+        assert_eq!(&eh.data[..4], &20u32.to_le_bytes());
+        assert_eq!(&eh.data[28..32], &28u32.to_le_bytes()); // CIE pointer
+        assert_eq!(&eh.data[36..40], &3u32.to_le_bytes()); // pc_range
+        assert_eq!(&eh.data[41..48], &[0x41, 0x0E, 0x10, 0x86, 0x02, 0x41, 0x0E]);
+
+        // Against the section, so shared objects can be linked from it
+        assert_eq!(eh.relocs.len(), 1);
+        assert_eq!(eh.relocs[0].offset, 32);
+        assert_eq!((eh.relocs[0].symbol.as_str(), eh.relocs[0].addend), (".text", 0));
+        assert!(out.symbols.iter().all(|s| s.name == "main"));
+    }
+
+    #[test]
+    fn temporary_labels_become_section_offsets() {
+        let src = "nop\n.cfi_startproc\nret\n.cfi_endproc\njmp $\nmov rax, [rel .Lgot wrt ..gotpcrel]\n.Lgot:\n";
+        let out = assemble(src, &AMD64).unwrap();
+
+        let names: Vec<_> = out.symbols.iter().map(|s| s.name.as_str()).collect();
+        assert_eq!(names, vec![".Lgot"]);
+        let eh = out.sections.iter().find(|s| s.name == ".eh_frame").unwrap();
+        assert_eq!((eh.relocs[0].symbol.as_str(), eh.relocs[0].addend), (".text", 1));
+        let jmp = &out.sections[0].relocs[0];
+        // jmp at 2: the rel32 field at 3 refers to .text+2
+        assert_eq!((jmp.symbol.as_str(), jmp.offset, jmp.addend), (".text", 3, 2 - 4));
+    }
+
+    #[test]
+    fn segment_override_and_tls_relocations() {
+        let src = "mov rax, [fs:0]\nmov rax, [rel v wrt ..gottpoff]\nmov rdx, [fs:v wrt ..tpoff]\n";
+        let out = assemble(src, &AMD64).unwrap();
+        let text = &out.sections[0];
+
+        assert_eq!(&text.data[..9], &[0x64, 0x48, 0x8B, 0x04, 0x25, 0, 0, 0, 0]);
+        assert_eq!(&text.data[9..12], &[0x48, 0x8B, 0x05]);
+
+        let kinds: Vec<_> = text.relocs.iter().map(|r| (r.offset, r.kind, r.addend)).collect();
+        assert_eq!(kinds, vec![(12, RelocKind::GotTpOff, -4), (21, RelocKind::TpOff32, 0)]);
+    }
+
+    #[test]
+    fn plt_and_relaxable_got_relocations() {
+        let src = "call f wrt ..plt\nmov rax, [rel g wrt ..gotpcrel]\ncall [rel g wrt ..gotpcrel]\nlea rax, [rel g wrt ..gotpcrel]\n";
+        let out = assemble(src, &AMD64).unwrap();
+
+        let kinds: Vec<_> = out.sections[0].relocs.iter().map(|r| (r.kind, r.addend)).collect();
+        assert_eq!(kinds, vec![
+            (RelocKind::Plt32, -4),
+            (RelocKind::RexGotPcRelX, -4),
+            (RelocKind::GotPcRelX, -4),
+            (RelocKind::GotPcRel, -4),
+        ]);
+    }
+
+    #[test]
+    fn shortest_encodings_are_selected_by_default() {
+        // Expected bytes match GNU objdump's decoding of the same instructions
+        let cases: &[(&str, &[u8])] = &[
+            ("mov rax, 1", &[0xB8, 1, 0, 0, 0]),
+            ("mov r9, 1", &[0x41, 0xB9, 1, 0, 0, 0]),
+            ("mov rax, -1", &[0x48, 0xC7, 0xC0, 0xFF, 0xFF, 0xFF, 0xFF]),
+            ("mov rax, 4294967296", &[0x48, 0xB8, 0, 0, 0, 0, 1, 0, 0, 0]),
+            ("add rax, 1000", &[0x48, 0x05, 0xE8, 0x03, 0, 0]),
+            ("add rax, 10", &[0x48, 0x83, 0xC0, 0x0A]),
+            ("cmp eax, 100000", &[0x3D, 0xA0, 0x86, 0x01, 0x00]),
+            ("and al, 15", &[0x24, 0x0F]),
+            ("xor ax, 1000", &[0x66, 0x35, 0xE8, 0x03]),
+        ];
+        for (src, expected) in cases {
+            let out = assemble(src, &AMD64).unwrap();
+            assert_eq!(&out.sections[0].data, expected, "{}", src);
+        }
+    }
+
+    #[test]
+    fn optimization_can_be_disabled() {
+        let options = AsmOptions { optimize: false, ..AsmOptions::default() };
+        let out = assemble_with_options("mov rax, 1\nadd rax, 1000\n", &AMD64, &options).unwrap();
+        assert_eq!(&out.sections[0].data, &[
+            0x48, 0xB8, 1, 0, 0, 0, 0, 0, 0, 0,
+            0x48, 0x81, 0xC0, 0xE8, 0x03, 0, 0,
+        ]);
+    }
+
+    #[test]
+    fn global_functions_start_with_endbr64() {
+        let options = AsmOptions { endbr: true, ..AsmOptions::default() };
+        let src = "global f\nglobal g\nglobal table\nsection .text\nf:\nret\nlocal:\nret\ng:\nendbr64\nret\ntable:\ndq 7\nsection .data\nglobal d\nd:\ndb 1\n";
+        let out = assemble_with_options(src, &AMD64, &options).unwrap();
+        let endbr = [0xF3, 0x0F, 0x1E, 0xFA];
+        let mut expected = endbr.to_vec();
+        expected.extend_from_slice(&[0xC3, 0xC3]);
+        expected.extend_from_slice(&endbr);
+        expected.push(0xC3);
+        // Data after a global label is not a function
+        expected.extend_from_slice(&7u64.to_le_bytes());
+        assert_eq!(out.sections[0].data, expected);
+        assert_eq!(out.sections[1].data, [1]);
+
+        // Off unless asked for
+        let out = assemble(src, &AMD64).unwrap();
+        assert_eq!(&out.sections[0].data[..3], &[0xC3, 0xC3, 0xF3]);
+    }
+
+    #[test]
+    fn unterminated_cfi_is_an_error() {
+        assert!(assemble("f:\n.cfi_startproc\nret\n", &AMD64).is_err());
+    }
+
+    #[test]
+    fn flat_boot_sector_is_resolved_against_org() {
+        let src = "bits 16\norg 0x7C00\nstart:\nmov ds, ax\nmov si, msg\ncall start\njmp $\nmsg: db \"Hi\", 0\ntimes 510-($-$$) db 0\ndw 0xAA55\n";
+        let image = link_flat(&assemble(src, &AMD64).unwrap()).unwrap();
+
+        assert_eq!(image.len(), 512);
+        assert_eq!(&image[..14], &[
+            0x8E, 0xD8,             // mov ds, ax
+            0xBE, 0x0B, 0x7C,       // mov si, 0x7C0B
+            0xE8, 0xF8, 0xFF,       // call 0x7C00
+            0xE9, 0xFD, 0xFF,       // jmp $
+            b'H', b'i', 0,
+        ]);
+        assert_eq!(&image[510..], &[0x55, 0xAA]);
+    }
+
+    #[test]
+    fn flat_sections_honour_vstart_and_follows() {
+        let src = "bits 32\norg 0x1000\nmov eax, data\ncall high\nsection .data align=16\ndata: dd high\nsection .hi vstart=0x8000 follows=.text\nhigh: ret\n";
+        let image = link_flat(&assemble(src, &AMD64).unwrap()).unwrap();
+
+        // .text (10 bytes), .hi at the next 4-byte boundary, .data at 16
+        assert_eq!(&image[..10], &[0xB8, 0x10, 0x10, 0, 0, 0xE8, 0xF6, 0x6F, 0, 0]);
+        assert_eq!(image[12], 0xC3);
+        assert_eq!(&image[16..], &0x8000u32.to_le_bytes());
+    }
+
+    #[test]
+    fn flat_output_rejects_unresolved_externals() {
+        let out = assemble("extern puts\ncall puts\n", &AMD64).unwrap();
+        assert!(matches!(link_flat(&out), Err(AsmError::SymbolError(_))));
+        assert!(assemble("bits 32\nmov rax, 1\n", &AMD64).is_err());
+    }
+}
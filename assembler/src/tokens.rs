@@ -11,6 +11,9 @@ pub enum TokenKind {
     Minus,
     Multiply,
     Divide,
+    LParen,
+    RParen,
+    Equals,
     Newline,
 }
 
@@ -81,10 +84,44 @@ pub fn tokenize(src: &str) -> Result<Vec<Token>, String> {
                 chars.next(); pos += 1;
             }
 
+            '/' => {
+                tokens.push(Token { kind: TokenKind::Divide, position: pos });
+                chars.next(); pos += 1;
+            }
+
+            '(' => {
+                tokens.push(Token { kind: TokenKind::LParen, position: pos });
+                chars.next(); pos += 1;
+            }
+
+            ')' => {
+                tokens.push(Token { kind: TokenKind::RParen, position: pos });
+                chars.next(); pos += 1;
+            }
+
+            '=' => {
+                tokens.push(Token { kind: TokenKind::Equals, position: pos });
+                chars.next(); pos += 1;
+            }
+
+            // `$` is the current address and `$$` the start of the section
+            '$' => {
+                let mut id = String::new();
+                while let Some(&'$') = chars.peek() {
+                    id.push('$');
+                    chars.next();
+                    pos += 1;
+                }
+                if id.len() > 2 {
+                    return Err(format!("Unexpected '{}' at {}", id, pos));
+                }
+                tokens.push(Token { kind: TokenKind::Identifier(id), position: pos });
+            }
+
             '"' => {
                 chars.next(); pos += 1;
                 let mut s = String::new();
-                while let Some(c) = chars.next() {
+                for c in chars.by_ref() {
                     pos += 1;
                     if c == '"' { break; }
                     s.push(c);
@@ -92,16 +129,16 @@ pub fn tokenize(src: &str) -> Result<Vec<Token>, String> {
                 tokens.push(Token { kind: TokenKind::StringLiteral(s), position: pos });
             }
 
-            c if c.is_numeric() => {
+            c if c.is_ascii_digit() => {
                 let mut n = String::new();
                 while let Some(&d) = chars.peek() {
-                    if d.is_numeric() {
+                    if d.is_ascii_alphanumeric() || d == '_' {
                         n.push(d);
                         chars.next();
                         pos += 1;
                     } else { break; }
                 }
-                let parsed = n.parse::<i64>().unwrap();
+                let parsed = parse_number(&n).ok_or_else(|| format!("Invalid number '{}' at {}", n, pos))?;
                 tokens.push(Token { kind: TokenKind::Number(parsed), position: pos });
             }
 
@@ -122,4 +159,23 @@ pub fn tokenize(src: &str) -> Result<Vec<Token>, String> {
     }
 
     Ok(tokens)
-}
\ No newline at end of file
+}
+
+/// Parses decimal, `0x`/`h`-suffixed hexadecimal and `0b`-prefixed binary literals.
+///
+/// Values up to `u64::MAX` are accepted and stored with two's-complement wrapping,
+/// so `0xFFFFFFFFFFFFFFFF` reads as `-1`.
+fn parse_number(text: &str) -> Option<i64> {
+    let digits = text.replace('_', "");
+    let lower = digits.to_ascii_lowercase();
+    let value = if let Some(hex) = lower.strip_prefix("0x") {
+        u64::from_str_radix(hex, 16).ok()?
+    } else if let Some(hex) = lower.strip_suffix('h') {
+        u64::from_str_radix(hex, 16).ok()?
+    } else if let Some(bin) = lower.strip_prefix("0b") {
+        u64::from_str_radix(bin, 2).ok()?
+    } else {
+        lower.parse::<u64>().ok()?
+    };
+    Some(value as i64)
+}
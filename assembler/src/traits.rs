@@ -1,8 +1,8 @@
 use crate::ast::AST;
 use crate::error::AsmError;
-use crate::assembler::AssemblerOutput;
+use crate::assembler::{AsmOptions, AssemblerOutput};
 
 pub trait ISA {
     fn parse(&self, tokens: &[crate::tokens::Token]) -> Result<AST, AsmError>;
-    fn encode(&self, ast: &AST) -> Result<AssemblerOutput, AsmError>;
+    fn encode(&self, ast: &AST, options: &AsmOptions) -> Result<AssemblerOutput, AsmError>;
 }
\ No newline at end of file
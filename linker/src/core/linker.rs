@@ -1,5 +1,4 @@
 use object::ObjectFile;
-use std::collections::HashMap;
 
 pub struct Linker {
     objects: Vec<ObjectFile>,
@@ -23,6 +22,13 @@ impl Linker {
     }
 
     pub fn link(&mut self) -> Result<Vec<u8>, String> {
+        // Layout and relocation processing assume power-of-two alignments and in-bounds offsets
+        for (i, obj) in self.objects.iter().enumerate() {
+            if let Err(problems) = obj.validate() {
+                let problems: Vec<String> = problems.iter().map(|p| p.to_string()).collect();
+                return Err(format!("object {} is invalid: {}", i, problems.join("; ")));
+            }
+        }
         // 1. Symbol Resolution
         // 2. Section Merging & Layout
         // 3. Relocation Processing
@@ -14,6 +14,12 @@ pub struct ResolvedSymbol {
     pub binding: SymbolBinding,
 }
 
+impl Default for SymbolTable {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl SymbolTable {
     pub fn new() -> Self {
         Self { symbols: HashMap::new() }
@@ -0,0 +1,264 @@
+//! System V / GNU `ar` archives (`.a`): the `/` symbol index, the `//`
+//! long-name table and thin archives, which store member paths instead of
+//! member contents.
+
+use std::collections::HashMap;
+
+use crate::core::error::{ParseError, WriteError};
+use crate::core::object::ObjectFile;
+use crate::core::symbol::SymbolBinding;
+use crate::formats::reader::Reader;
+
+const MAGIC: &[u8; 8] = b"!<arch>\n";
+const THIN_MAGIC: &[u8; 8] = b"!<thin>\n";
+const HEADER_SIZE: usize = 60;
+
+/// One file stored in an archive.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ArchiveMember {
+    /// File name; for thin archives, the path relative to the archive.
+    pub name: String,
+    /// Contents. Empty for members read from a thin archive.
+    pub data: Vec<u8>,
+    /// Size of the member file; equal to `data.len()` except in thin archives.
+    pub size: u64,
+    pub mtime: u64,
+    pub uid: u32,
+    pub gid: u32,
+    pub mode: u32,
+}
+
+impl ArchiveMember {
+    /// A member with zeroed timestamp and owner, so that archives built from
+    /// the same inputs are identical (`ar D`).
+    pub fn new(name: &str, data: Vec<u8>) -> Self {
+        ArchiveMember { name: name.to_string(), size: data.len() as u64, data, mtime: 0, uid: 0, gid: 0, mode: 0o644 }
+    }
+}
+
+/// An entry of the archive symbol index.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ArchiveSymbol {
+    pub name: String,
+    /// Index into `Archive::members`.
+    pub member: usize,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Archive {
+    pub members: Vec<ArchiveMember>,
+    pub thin: bool,
+    /// The symbol index as read by [`Archive::parse`]. [`Archive::write`]
+    /// always builds a fresh one from the members.
+    pub symbols: Vec<ArchiveSymbol>,
+}
+
+impl Archive {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Global and weak symbols defined by each member that is an object file.
+    pub fn symbol_index(&self) -> Vec<ArchiveSymbol> {
+        let mut symbols = Vec::new();
+        for (member, m) in self.members.iter().enumerate() {
+            let Ok(obj) = ObjectFile::parse(&m.data) else {
+                continue;
+            };
+            for sym in &obj.symbols {
+                if sym.binding != SymbolBinding::Local && (sym.section_index.is_some() || sym.absolute) {
+                    symbols.push(ArchiveSymbol { name: sym.name.clone(), member });
+                }
+            }
+        }
+        symbols
+    }
+
+    /// Index of the member defining `symbol`, according to the symbol index.
+    pub fn find_symbol(&self, symbol: &str) -> Option<usize> {
+        self.symbols.iter().find(|s| s.name == symbol).map(|s| s.member)
+    }
+
+    pub fn parse(data: &[u8]) -> Result<Self, ParseError> {
+        let thin = match data.get(..8) {
+            Some(magic) if magic == MAGIC => false,
+            Some(magic) if magic == THIN_MAGIC => true,
+            _ => return Err(ParseError::new(0, "not an ar archive")),
+        };
+        let r = Reader { data };
+        let mut archive = Archive { thin, ..Archive::default() };
+        let mut long_names: &[u8] = &[];
+        // Symbol index entries still pointing at member header offsets
+        let mut index: Vec<(String, u64)> = Vec::new();
+        let mut member_at = HashMap::new();
+
+        let mut offset = 8u64;
+        while (offset as usize) < data.len() {
+            let header = r.bytes(offset, HEADER_SIZE as u64, "archive member header")?;
+            if &header[58..60] != b"`\n" {
+                return Err(ParseError::new(offset as usize + 58, "bad archive member header terminator"));
+            }
+            let field = |start: usize, len: usize| std::str::from_utf8(&header[start..start + len]).unwrap_or("").trim_end();
+            let number = |start: usize, len: usize, radix: u32, what: &str| -> Result<u64, ParseError> {
+                let text = field(start, len);
+                if text.is_empty() {
+                    return Ok(0);
+                }
+                u64::from_str_radix(text, radix).map_err(|_| ParseError::new(offset as usize + start, format!("invalid {} '{}'", what, text)))
+            };
+            let raw_name = field(0, 16).to_string();
+            let size = number(48, 10, 10, "member size")?;
+            let body = offset + HEADER_SIZE as u64;
+
+            // Special members are always stored, even in thin archives
+            let stored = !thin || raw_name == "/" || raw_name == "//" || raw_name == "/SYM64/";
+            let contents = if stored { r.bytes(body, size, "archive member")? } else { &[][..] };
+
+            match raw_name.as_str() {
+                "/" => index = parse_symbol_index(&Reader { data: contents }, 4, body)?,
+                "/SYM64/" => index = parse_symbol_index(&Reader { data: contents }, 8, body)?,
+                "//" => long_names = contents,
+                _ => {
+                    let name = match raw_name.strip_prefix('/') {
+                        Some(start) => {
+                            let start: usize = start.parse().map_err(|_| ParseError::new(offset as usize, format!("invalid member name '{}'", raw_name)))?;
+                            let rest = long_names.get(start..).ok_or_else(|| {
+                                ParseError::new(offset as usize, format!("long name offset {} is outside the name table", start))
+                            })?;
+                            let end = rest.windows(2).position(|w| w == b"/\n").unwrap_or(rest.len());
+                            String::from_utf8_lossy(&rest[..end]).into_owned()
+                        }
+                        None => raw_name.strip_suffix('/').unwrap_or(&raw_name).to_string(),
+                    };
+                    member_at.insert(offset, archive.members.len());
+                    archive.members.push(ArchiveMember {
+                        name,
+                        data: contents.to_vec(),
+                        size,
+                        mtime: number(16, 12, 10, "timestamp")?,
+                        uid: number(28, 6, 10, "uid")? as u32,
+                        gid: number(34, 6, 10, "gid")? as u32,
+                        mode: number(40, 8, 8, "mode")? as u32,
+                    });
+                }
+            }
+
+            let stored_size = if stored { size } else { 0 };
+            offset = body + stored_size + (stored_size & 1);
+        }
+
+        for (name, header) in index {
+            let member = *member_at.get(&header).ok_or_else(|| {
+                ParseError::new(8, format!("symbol {} refers to offset {:#x}, which is not a member", name, header))
+            })?;
+            archive.symbols.push(ArchiveSymbol { name, member });
+        }
+        Ok(archive)
+    }
+
+    /// Writes a GNU archive with a symbol index, or a thin archive that refers
+    /// to the member files by name.
+    pub fn write(&self) -> Result<Vec<u8>, WriteError> {
+        let symbols = self.symbol_index();
+
+        // Names that do not fit in the header go to the long-name table; thin
+        // archives put every name there
+        let mut long_names = Vec::new();
+        let mut header_names = Vec::new();
+        for m in &self.members {
+            if m.name.is_empty() || m.name.contains('\n') {
+                return Err(format!("invalid archive member name {:?}", m.name).into());
+            }
+            if !self.thin && m.name.len() < 16 && !m.name.contains('/') {
+                header_names.push(format!("{}/", m.name));
+            } else {
+                header_names.push(format!("/{}", long_names.len()));
+                long_names.extend_from_slice(m.name.as_bytes());
+                long_names.extend_from_slice(b"/\n");
+            }
+        }
+
+        let padded = |size: usize| size + (size & 1);
+        let index_size = padded(4 + 4 * symbols.len() + symbols.iter().map(|s| s.name.len() + 1).sum::<usize>());
+        let mut offset = MAGIC.len() + HEADER_SIZE + index_size;
+        if !long_names.is_empty() {
+            offset += HEADER_SIZE + padded(long_names.len());
+        }
+        let mut member_offsets = Vec::new();
+        for m in &self.members {
+            member_offsets.push(offset);
+            offset += HEADER_SIZE + if self.thin { 0 } else { padded(m.data.len()) };
+        }
+        if offset > u32::MAX as usize {
+            return Err("archive is too large for a 32-bit symbol index".to_string().into());
+        }
+
+        let mut out = Vec::new();
+        out.extend_from_slice(if self.thin { THIN_MAGIC } else { MAGIC });
+
+        let mut index = Vec::with_capacity(index_size);
+        index.extend_from_slice(&(symbols.len() as u32).to_be_bytes());
+        for s in &symbols {
+            index.extend_from_slice(&(member_offsets[s.member] as u32).to_be_bytes());
+        }
+        for s in &symbols {
+            index.extend_from_slice(s.name.as_bytes());
+            index.push(0);
+        }
+        // GNU ar pads the index with NULs rather than the usual newline
+        index.resize(index_size, 0);
+        put_member(&mut out, "/", 0, 0, 0, 0, &index)?;
+        if !long_names.is_empty() {
+            // The name table has no owner, mode or timestamp, and its padding
+            // counts towards its size
+            long_names.resize(padded(long_names.len()), b'\n');
+            out.extend_from_slice(format!("{:<48}{:<10}`\n", "//", long_names.len()).as_bytes());
+            out.extend_from_slice(&long_names);
+        }
+
+        for (m, name) in self.members.iter().zip(&header_names) {
+            if self.thin {
+                put_header(&mut out, name, m.mtime, m.uid, m.gid, m.mode, m.data.len() as u64)?;
+            } else {
+                put_member(&mut out, name, m.mtime, m.uid, m.gid, m.mode, &m.data)?;
+            }
+        }
+        Ok(out)
+    }
+}
+
+/// Reads a `/` (4-byte) or `/SYM64/` (8-byte) big-endian symbol index.
+fn parse_symbol_index(r: &Reader, width: u64, base: u64) -> Result<Vec<(String, u64)>, ParseError> {
+    let word = |offset: u64| -> Result<u64, ParseError> {
+        let bytes = r.bytes(offset, width, "symbol index").map_err(|e| ParseError::new(base as usize + e.offset, e.message))?;
+        Ok(bytes.iter().fold(0u64, |acc, &b| acc << 8 | b as u64))
+    };
+    let count = word(0)?;
+    let mut names = &r.data[(width * (count + 1)).min(r.data.len() as u64) as usize..];
+    let mut entries = Vec::new();
+    for i in 0..count {
+        let header = word(width * (i + 1))?;
+        let end = names.iter().position(|&b| b == 0).ok_or_else(|| ParseError::new(base as usize, "symbol index names are truncated"))?;
+        entries.push((String::from_utf8_lossy(&names[..end]).into_owned(), header));
+        names = &names[end + 1..];
+    }
+    Ok(entries)
+}
+
+fn put_header(out: &mut Vec<u8>, name: &str, mtime: u64, uid: u32, gid: u32, mode: u32, size: u64) -> Result<(), WriteError> {
+    let header = format!("{:<16}{:<12}{:<6}{:<6}{:<8o}{:<10}`\n", name, mtime, uid, gid, mode, size);
+    if header.len() != HEADER_SIZE {
+        return Err(format!("archive member {} has a field too large for the header", name).into());
+    }
+    out.extend_from_slice(header.as_bytes());
+    Ok(())
+}
+
+fn put_member(out: &mut Vec<u8>, name: &str, mtime: u64, uid: u32, gid: u32, mode: u32, data: &[u8]) -> Result<(), WriteError> {
+    put_header(out, name, mtime, uid, gid, mode, data.len() as u64)?;
+    out.extend_from_slice(data);
+    if data.len() % 2 == 1 {
+        out.push(b'\n');
+    }
+    Ok(())
+}
@@ -0,0 +1,163 @@
+//! DEFLATE encoder (RFC 1951): LZ77 over a 32 KiB window with hash chains,
+//! coded with the fixed Huffman tables. Data that does not shrink is stored.
+
+const WINDOW: usize = 32 * 1024;
+const MIN_MATCH: usize = 3;
+const MAX_MATCH: usize = 258;
+/// Candidates examined per position; bounds the time spent on repetitive input.
+const MAX_CHAIN: usize = 128;
+const HASH_BITS: u32 = 15;
+
+pub(super) const LENGTH_BASE: [u16; 29] = [3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131, 163, 195, 227, 258];
+pub(super) const LENGTH_EXTRA: [u8; 29] = [0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0];
+pub(super) const DIST_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537, 2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+pub(super) const DIST_EXTRA: [u8; 30] = [0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13, 13];
+
+/// Collects bits least significant first, as DEFLATE packs them.
+struct BitWriter {
+    out: Vec<u8>,
+    bits: u64,
+    count: u32,
+}
+
+impl BitWriter {
+    fn put(&mut self, value: u32, count: u32) {
+        self.bits |= (value as u64) << self.count;
+        self.count += count;
+        while self.count >= 8 {
+            self.out.push(self.bits as u8);
+            self.bits >>= 8;
+            self.count -= 8;
+        }
+    }
+
+    /// Huffman codes are defined most significant bit first.
+    fn put_code(&mut self, code: u32, len: u32) {
+        self.put(code.reverse_bits() >> (32 - len), len);
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.count > 0 {
+            self.out.push(self.bits as u8);
+        }
+        self.out
+    }
+}
+
+/// Fixed literal/length code of `symbol` (RFC 1951, 3.2.6).
+fn fixed_code(symbol: u32) -> (u32, u32) {
+    match symbol {
+        0..=143 => (0x30 + symbol, 8),
+        144..=255 => (0x190 + symbol - 144, 9),
+        256..=279 => (symbol - 256, 7),
+        _ => (0xC0 + symbol - 280, 8),
+    }
+}
+
+/// Index of the last table entry whose base is at most `value`.
+fn bucket(base: &[u16], value: usize) -> usize {
+    base.iter().rposition(|&b| b as usize <= value).unwrap_or(0)
+}
+
+fn hash(data: &[u8], pos: usize) -> usize {
+    let v = (data[pos] as u32) << 16 | (data[pos + 1] as u32) << 8 | data[pos + 2] as u32;
+    (v.wrapping_mul(0x9E37_79B1) >> (32 - HASH_BITS)) as usize
+}
+
+/// Hash chains over the positions seen so far: `head` holds the latest
+/// position per hash, `prev` the one before it with the same hash.
+struct Matcher {
+    head: Vec<usize>,
+    prev: Vec<usize>,
+}
+
+impl Matcher {
+    fn insert(&mut self, data: &[u8], pos: usize) {
+        if pos + MIN_MATCH <= data.len() {
+            let h = hash(data, pos);
+            self.prev[pos % WINDOW] = self.head[h];
+            self.head[h] = pos;
+        }
+    }
+
+    /// Longest earlier match for `pos`, as (length, distance).
+    fn longest(&self, data: &[u8], pos: usize) -> Option<(usize, usize)> {
+        if pos + MIN_MATCH > data.len() {
+            return None;
+        }
+        let max = (data.len() - pos).min(MAX_MATCH);
+        let mut best: Option<(usize, usize)> = None;
+        let mut candidate = self.head[hash(data, pos)];
+        for _ in 0..MAX_CHAIN {
+            if candidate == usize::MAX || pos - candidate > WINDOW {
+                break;
+            }
+            let len = data[candidate..].iter().zip(&data[pos..pos + max]).take_while(|(a, b)| a == b).count();
+            if len >= MIN_MATCH && best.is_none_or(|(l, _)| len > l) {
+                best = Some((len, pos - candidate));
+                if len == max {
+                    break;
+                }
+            }
+            candidate = self.prev[candidate % WINDOW];
+        }
+        best
+    }
+}
+
+fn fixed_block(data: &[u8]) -> Vec<u8> {
+    let mut w = BitWriter { out: Vec::new(), bits: 0, count: 0 };
+    w.put(1, 1); // BFINAL
+    w.put(1, 2); // BTYPE = fixed Huffman
+
+    let mut matcher = Matcher { head: vec![usize::MAX; 1 << HASH_BITS], prev: vec![usize::MAX; WINDOW] };
+    let mut pos = 0;
+    while pos < data.len() {
+        match matcher.longest(data, pos) {
+            Some((len, dist)) => {
+                let l = bucket(&LENGTH_BASE, len);
+                let (code, bits) = fixed_code(257 + l as u32);
+                w.put_code(code, bits);
+                w.put((len - LENGTH_BASE[l] as usize) as u32, LENGTH_EXTRA[l] as u32);
+                let d = bucket(&DIST_BASE, dist);
+                w.put_code(d as u32, 5);
+                w.put((dist - DIST_BASE[d] as usize) as u32, DIST_EXTRA[d] as u32);
+                for p in pos..pos + len {
+                    matcher.insert(data, p);
+                }
+                pos += len;
+            }
+            None => {
+                let (code, bits) = fixed_code(data[pos] as u32);
+                w.put_code(code, bits);
+                matcher.insert(data, pos);
+                pos += 1;
+            }
+        }
+    }
+    let (code, bits) = fixed_code(256);
+    w.put_code(code, bits);
+    w.finish()
+}
+
+fn stored_blocks(data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(data.len() + 5 * (data.len() / 0xFFFF + 1));
+    let mut chunks = data.chunks(0xFFFF).peekable();
+    if chunks.peek().is_none() {
+        out.extend_from_slice(&[1, 0, 0, 0xFF, 0xFF]);
+    }
+    while let Some(chunk) = chunks.next() {
+        out.push(chunks.peek().is_none() as u8); // BFINAL, BTYPE = stored
+        out.extend_from_slice(&(chunk.len() as u16).to_le_bytes());
+        out.extend_from_slice(&(!(chunk.len() as u16)).to_le_bytes());
+        out.extend_from_slice(chunk);
+    }
+    out
+}
+
+pub(super) fn deflate(data: &[u8]) -> Vec<u8> {
+    let compressed = fixed_block(data);
+    if compressed.len() < data.len() + 5 { compressed } else { stored_blocks(data) }
+}
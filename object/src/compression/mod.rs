@@ -0,0 +1,132 @@
+//! Section compression for `SHF_COMPRESSED` ELF sections: zlib streams built
+//! on an in-crate DEFLATE coder, and Zstandard framing.
+
+mod deflate;
+mod inflate;
+mod zstd;
+
+use std::fmt;
+
+/// Compression format of a section, as recorded in `ch_type`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "yaml", derive(serde::Serialize, serde::Deserialize))]
+pub enum Compression {
+    /// `ELFCOMPRESS_ZLIB`: a zlib stream (RFC 1950).
+    Zlib,
+    /// `ELFCOMPRESS_ZSTD`: a Zstandard frame (RFC 8878).
+    Zstd,
+}
+
+/// Reason compressed section contents could not be expanded.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DecompressError {
+    /// The data is not a valid stream of the declared format.
+    Corrupt(String),
+    /// The stream is valid but uses a feature this crate does not decode.
+    Unsupported(String),
+}
+
+impl fmt::Display for DecompressError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DecompressError::Corrupt(message) => write!(f, "corrupt compressed data: {}", message),
+            DecompressError::Unsupported(message) => f.write_str(message),
+        }
+    }
+}
+
+impl std::error::Error for DecompressError {}
+
+impl Compression {
+    pub fn elf_type(self) -> u32 {
+        match self {
+            Compression::Zlib => 1,
+            Compression::Zstd => 2,
+        }
+    }
+
+    pub fn from_elf_type(ch_type: u32) -> Option<Self> {
+        match ch_type {
+            1 => Some(Compression::Zlib),
+            2 => Some(Compression::Zstd),
+            _ => None,
+        }
+    }
+
+    /// Parses the values of `--compress-debug-sections`; `none` gives `Ok(None)`.
+    pub fn from_name(name: &str) -> Result<Option<Self>, String> {
+        match name {
+            "none" => Ok(None),
+            "zlib" | "zlib-gabi" => Ok(Some(Compression::Zlib)),
+            "zstd" => Ok(Some(Compression::Zstd)),
+            other => Err(format!("unknown compression '{}' (expected none, zlib or zstd)", other)),
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Compression::Zlib => "zlib",
+            Compression::Zstd => "zstd",
+        }
+    }
+
+    pub fn compress(self, data: &[u8]) -> Vec<u8> {
+        match self {
+            Compression::Zlib => {
+                // CMF/FLG: 32 KiB window, default level, no dictionary
+                let mut out = vec![0x78, 0x9C];
+                out.extend_from_slice(&deflate::deflate(data));
+                out.extend_from_slice(&adler32(data).to_be_bytes());
+                out
+            }
+            Compression::Zstd => zstd::compress(data),
+        }
+    }
+
+    /// Expands `data`; `size` is the uncompressed size the container declares.
+    /// Decoding stops with [`DecompressError::Corrupt`] as soon as the output
+    /// would grow past it.
+    pub fn decompress(self, data: &[u8], size: usize) -> Result<Vec<u8>, DecompressError> {
+        let out = match self {
+            Compression::Zlib => {
+                let corrupt = |message: &str| DecompressError::Corrupt(message.to_string());
+                let (cmf, flg) = match data {
+                    [cmf, flg, ..] => (*cmf, *flg),
+                    _ => return Err(corrupt("zlib stream is truncated")),
+                };
+                if cmf & 0x0F != 8 || !(cmf as u16 * 256 + flg as u16).is_multiple_of(31) {
+                    return Err(corrupt("bad zlib header"));
+                }
+                if flg & 0x20 != 0 {
+                    return Err(DecompressError::Unsupported("zlib streams with a preset dictionary are not supported".into()));
+                }
+                let (out, used) = inflate::inflate(&data[2..], size).map_err(DecompressError::Corrupt)?;
+                let checksum = data.get(2 + used..6 + used).ok_or_else(|| corrupt("zlib checksum is missing"))?;
+                if u32::from_be_bytes([checksum[0], checksum[1], checksum[2], checksum[3]]) != adler32(&out) {
+                    return Err(corrupt("zlib checksum mismatch"));
+                }
+                out
+            }
+            Compression::Zstd => zstd::decompress(data, size)?,
+        };
+        if out.len() != size {
+            return Err(DecompressError::Corrupt(format!("expected {} bytes, got {}", size, out.len())));
+        }
+        Ok(out)
+    }
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    const MOD: u32 = 65521;
+    let (mut a, mut b) = (1u32, 0u32);
+    // 5552 bytes is the most that can be summed before `b` may overflow
+    for chunk in data.chunks(5552) {
+        for &byte in chunk {
+            a += byte as u32;
+            b += a;
+        }
+        a %= MOD;
+        b %= MOD;
+    }
+    b << 16 | a
+}
@@ -0,0 +1,99 @@
+//! Zstandard frames (RFC 8878) made of raw and RLE blocks. This is the
+//! framing `ELFCOMPRESS_ZSTD` sections need; entropy-coded blocks written by
+//! other tools are recognised but not decoded.
+
+use super::DecompressError;
+
+const MAGIC: u32 = 0xFD2F_B528;
+const MAX_BLOCK: usize = 128 * 1024;
+const RAW: u32 = 0;
+const RLE: u32 = 1;
+const COMPRESSED: u32 = 2;
+
+/// A single-segment frame with an 8-byte content size and no checksum. Runs
+/// of one byte become RLE blocks, everything else is stored raw.
+pub(super) fn compress(data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(data.len() + 16);
+    out.extend_from_slice(&MAGIC.to_le_bytes());
+    // Frame header descriptor: 8-byte content size, single segment
+    out.push(0xE0);
+    out.extend_from_slice(&(data.len() as u64).to_le_bytes());
+
+    let mut chunks = data.chunks(MAX_BLOCK).peekable();
+    if chunks.peek().is_none() {
+        put_block_header(&mut out, true, RAW, 0);
+    }
+    while let Some(chunk) = chunks.next() {
+        let last = chunks.peek().is_none();
+        if chunk.len() > 4 && chunk.iter().all(|&b| b == chunk[0]) {
+            put_block_header(&mut out, last, RLE, chunk.len());
+            out.push(chunk[0]);
+        } else {
+            put_block_header(&mut out, last, RAW, chunk.len());
+            out.extend_from_slice(chunk);
+        }
+    }
+    out
+}
+
+fn put_block_header(out: &mut Vec<u8>, last: bool, kind: u32, size: usize) {
+    let header = last as u32 | kind << 1 | (size as u32) << 3;
+    out.extend_from_slice(&header.to_le_bytes()[..3]);
+}
+
+/// Decodes a frame, failing as soon as the output would exceed `limit` bytes.
+pub(super) fn decompress(data: &[u8], limit: usize) -> Result<Vec<u8>, DecompressError> {
+    let corrupt = |message: &str| DecompressError::Corrupt(message.to_string());
+    let bytes = |start: usize, len: usize| data.get(start..start + len).ok_or_else(|| corrupt("zstd frame is truncated"));
+
+    if bytes(0, 4)? != MAGIC.to_le_bytes() {
+        return Err(corrupt("missing zstd frame magic"));
+    }
+    let descriptor = bytes(4, 1)?[0];
+    if descriptor & 0x08 != 0 {
+        return Err(corrupt("reserved bit set in zstd frame header"));
+    }
+    let single_segment = descriptor & 0x20 != 0;
+    let dictionary_size = [0, 1, 2, 4][(descriptor & 3) as usize];
+    let content_size_bytes = match descriptor >> 6 {
+        0 if single_segment => 1,
+        0 => 0,
+        1 => 2,
+        2 => 4,
+        _ => 8,
+    };
+    let mut pos = 5 + usize::from(!single_segment) + dictionary_size + content_size_bytes;
+    let checksum = descriptor & 0x04 != 0;
+
+    // RLE blocks make the ratio unbounded, so reserve no more than the input could fill raw
+    let mut out = Vec::with_capacity(limit.min(data.len()));
+    loop {
+        let header = bytes(pos, 3)?;
+        let header = u32::from_le_bytes([header[0], header[1], header[2], 0]);
+        let (last, kind, size) = (header & 1 != 0, (header >> 1) & 3, (header >> 3) as usize);
+        pos += 3;
+        if kind != COMPRESSED && size > limit - out.len() {
+            return Err(DecompressError::Corrupt(format!("zstd frame expands past the declared {} bytes", limit)));
+        }
+        match kind {
+            RAW => {
+                out.extend_from_slice(bytes(pos, size)?);
+                pos += size;
+            }
+            RLE => {
+                let byte = bytes(pos, 1)?[0];
+                out.resize(out.len() + size, byte);
+                pos += 1;
+            }
+            COMPRESSED => return Err(DecompressError::Unsupported("compressed zstd blocks are not supported".into())),
+            _ => return Err(corrupt("reserved zstd block type")),
+        }
+        if last {
+            break;
+        }
+    }
+    if checksum {
+        bytes(pos, 4)?;
+    }
+    Ok(out)
+}
@@ -0,0 +1,24 @@
+/// Target machine of an object file; selects the ELF `e_machine` and which
+/// relocation numbering applies.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "yaml", derive(serde::Serialize, serde::Deserialize))]
+pub enum Architecture {
+    X86_64,
+    I386,
+    AArch64,
+    RiscV64,
+    /// 64-bit PowerPC, either byte order (ELF only).
+    PowerPC64,
+    /// 32-bit MIPS with the o32 ABI, either byte order (ELF only).
+    Mips,
+}
+
+impl Architecture {
+    /// Size of an address in bytes.
+    pub fn word_size(&self) -> usize {
+        match self {
+            Architecture::I386 | Architecture::Mips => 4,
+            Architecture::X86_64 | Architecture::AArch64 | Architecture::RiscV64 | Architecture::PowerPC64 => 8,
+        }
+    }
+}
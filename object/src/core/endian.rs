@@ -0,0 +1,73 @@
+/// Byte order of an object file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "yaml", derive(serde::Serialize, serde::Deserialize))]
+pub enum Endian {
+    #[default]
+    Little,
+    Big,
+}
+
+impl Endian {
+    /// Encodes the low `width` bytes of `value`.
+    pub fn encode(self, value: u64, width: usize) -> Vec<u8> {
+        match self {
+            Endian::Little => value.to_le_bytes()[..width].to_vec(),
+            Endian::Big => value.to_be_bytes()[8 - width..].to_vec(),
+        }
+    }
+
+    /// Decodes an unsigned integer of up to 8 bytes.
+    pub fn decode(self, bytes: &[u8]) -> u64 {
+        match self {
+            Endian::Little => bytes.iter().rev().fold(0, |acc, &b| acc << 8 | b as u64),
+            Endian::Big => bytes.iter().fold(0, |acc, &b| acc << 8 | b as u64),
+        }
+    }
+}
+
+/// Byte order chosen at compile time, for encoders generic over it.
+pub trait Endianness: Copy + Default {
+    const ENDIAN: Endian;
+
+    fn u16_bytes(value: u16) -> [u8; 2];
+    fn u32_bytes(value: u32) -> [u8; 4];
+    fn u64_bytes(value: u64) -> [u8; 8];
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LittleEndian;
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct BigEndian;
+
+impl Endianness for LittleEndian {
+    const ENDIAN: Endian = Endian::Little;
+
+    fn u16_bytes(value: u16) -> [u8; 2] {
+        value.to_le_bytes()
+    }
+
+    fn u32_bytes(value: u32) -> [u8; 4] {
+        value.to_le_bytes()
+    }
+
+    fn u64_bytes(value: u64) -> [u8; 8] {
+        value.to_le_bytes()
+    }
+}
+
+impl Endianness for BigEndian {
+    const ENDIAN: Endian = Endian::Big;
+
+    fn u16_bytes(value: u16) -> [u8; 2] {
+        value.to_be_bytes()
+    }
+
+    fn u32_bytes(value: u32) -> [u8; 4] {
+        value.to_be_bytes()
+    }
+
+    fn u64_bytes(value: u64) -> [u8; 8] {
+        value.to_be_bytes()
+    }
+}
@@ -0,0 +1,70 @@
+use std::fmt;
+
+use crate::core::validate::Diagnostic;
+
+/// Malformed or unsupported input, with the file offset where it was detected.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    pub offset: usize,
+    pub message: String,
+}
+
+impl ParseError {
+    pub fn new(offset: usize, message: impl Into<String>) -> Self {
+        Self { offset, message: message.into() }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "offset {:#x}: {}", self.offset, self.message)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// Reason an [`ObjectFile`](crate::ObjectFile) could not be written.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WriteError {
+    /// A relocation refers to a name that is neither a symbol nor a section.
+    UnknownSymbol { symbol: String, section: String, offset: usize },
+    /// The object uses something the output format or architecture cannot express.
+    Unsupported(String),
+    /// The output stream failed.
+    Io(String),
+    /// [`ObjectFile::validate`](crate::ObjectFile::validate) found problems.
+    Invalid(Vec<Diagnostic>),
+}
+
+impl From<String> for WriteError {
+    fn from(message: String) -> Self {
+        WriteError::Unsupported(message)
+    }
+}
+
+impl From<std::io::Error> for WriteError {
+    fn from(err: std::io::Error) -> Self {
+        WriteError::Io(err.to_string())
+    }
+}
+
+impl fmt::Display for WriteError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WriteError::UnknownSymbol { symbol, section, offset } => {
+                write!(f, "{}+{:#x}: relocation against unknown symbol {}", section, offset, symbol)
+            }
+            WriteError::Unsupported(message) => f.write_str(message),
+            WriteError::Io(message) => write!(f, "write failed: {}", message),
+            WriteError::Invalid(problems) => match problems.as_slice() {
+                [problem] => write!(f, "invalid object: {}", problem),
+                _ => {
+                    write!(f, "invalid object ({} problems):", problems.len())?;
+                    problems.iter().try_for_each(|problem| write!(f, "\n  {}", problem))
+                }
+            },
+        }
+    }
+}
+
+impl std::error::Error for WriteError {}
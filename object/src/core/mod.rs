@@ -2,3 +2,8 @@ pub mod symbol;
 pub mod section;
 pub mod reloc;
 pub mod object;
+pub mod error;
+pub mod arch;
+pub mod endian;
+pub mod validate;
+pub mod property;
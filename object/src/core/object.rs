@@ -1,41 +1,356 @@
-use crate::core::section::{Section, SectionKind};
-use crate::core::symbol::ObjectSymbol;
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
+use std::io::Write;
+
+use crate::core::section::{Section, SectionFlags, SectionGroup, SectionKind, SectionType};
+use crate::core::symbol::{ObjectSymbol, SymbolBinding, SymbolKind, SymbolVisibility};
 use crate::core::reloc::ObjectRelocation;
+use crate::core::error::{ParseError, WriteError};
+use crate::core::arch::Architecture;
+use crate::core::endian::Endian;
+use crate::core::property::GnuProperties;
+use crate::compression::Compression;
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "yaml", derive(serde::Serialize, serde::Deserialize))]
 pub enum ObjectFormat {
+    ELF32,
     ELF64,
+    /// AMD64 COFF object (`.obj`) for Windows toolchains.
+    COFF,
+    /// 64-bit Mach-O object for macOS (x86-64 or arm64).
+    MachO64,
+}
+
+/// What [`ObjectFile::write`] does with relocations against names that are not in
+/// `symbols` and are not section names.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum UnknownSymbolPolicy {
+    /// Fail with [`WriteError::UnknownSymbol`].
+    #[default]
+    Error,
+    /// Declare them as undefined globals, as assemblers do for externs.
+    DeclareUndefined,
 }
 
+#[derive(Debug, Clone)]
 pub struct ObjectFile {
     pub format: ObjectFormat,
+    pub arch: Architecture,
+    /// Byte order of the file; only ELF can be big-endian.
+    pub endian: Endian,
+    pub unknown_symbols: UnknownSymbolPolicy,
     pub sections: Vec<Section>,
     pub symbols: Vec<ObjectSymbol>,
     pub relocations: Vec<ObjectRelocation>,
+    pub groups: Vec<SectionGroup>,
+    /// Written to `.note.gnu.property`; ELF only.
+    pub gnu_properties: GnuProperties,
 }
 
 impl ObjectFile {
+    /// Creates an empty object; ELF32 defaults to i386, the other formats to x86-64.
     pub fn new(format: ObjectFormat) -> Self {
+        let arch = match format {
+            ObjectFormat::ELF32 => Architecture::I386,
+            ObjectFormat::ELF64 | ObjectFormat::COFF | ObjectFormat::MachO64 => Architecture::X86_64,
+        };
         Self {
             format,
+            arch,
+            endian: Endian::Little,
+            unknown_symbols: UnknownSymbolPolicy::default(),
             sections: Vec::new(),
             symbols: Vec::new(),
             relocations: Vec::new(),
+            groups: Vec::new(),
+            gnu_properties: GnuProperties::default(),
         }
     }
 
     pub fn add_section(&mut self, name: &str, kind: SectionKind, align: u64) -> usize {
-        self.sections.push(Section {
-            name: name.to_string(),
-            kind,
-            data: Vec::new(),
-            align,
-        });
+        self.sections.push(Section::new(name, kind, align));
         self.sections.len() - 1
     }
 
-    pub fn write(&self) -> Result<Vec<u8>, String> {
-        match self.format {
-            ObjectFormat::ELF64 => crate::formats::elf::write_elf(self),
+    /// Adds a section with an explicit ELF type and flags, such as
+    /// `.note.GNU-stack`, `.init_array` or a mergeable string section.
+    pub fn add_elf_section(&mut self, name: &str, section_type: SectionType, flags: SectionFlags, align: u64) -> usize {
+        self.sections.push(Section::with_elf_type(name, section_type, flags, align));
+        self.sections.len() - 1
+    }
+
+    /// Puts `sections` in a COMDAT group, so the linker keeps a single copy of
+    /// them when several objects define the same `signature` symbol.
+    pub fn add_comdat_group(&mut self, signature: &str, sections: Vec<usize>) -> usize {
+        self.groups.push(SectionGroup { signature: signature.to_string(), sections, comdat: true });
+        self.groups.len() - 1
+    }
+
+    pub fn find_section(&self, name: &str) -> Option<usize> {
+        self.sections.iter().position(|s| s.name == name)
+    }
+
+    /// Removes a section together with its relocations and the symbols defined
+    /// in it, and renumbers everything that refers to later sections. Section
+    /// links to it are cleared, and groups left empty are dropped. Fails without
+    /// changing anything if a relocation in another section still refers to the
+    /// section or to a symbol defined in it.
+    pub fn remove_section(&mut self, index: usize) -> Result<Section, String> {
+        let mut removed = vec![false; self.sections.len()];
+        removed[index] = true;
+        self.check_unreferenced(&removed)?;
+        Ok(self.remove_section_unchecked(index))
+    }
+
+    /// Removes every section matching `remove` as [`ObjectFile::remove_section`]
+    /// does. Relocations among the removed sections may refer to each other; a
+    /// reference from a section that stays is an error. Returns the removed sections.
+    pub fn remove_sections(&mut self, remove: impl FnMut(&Section) -> bool) -> Result<Vec<Section>, String> {
+        let removed: Vec<bool> = self.sections.iter().map(remove).collect();
+        self.check_unreferenced(&removed)?;
+        let mut sections: Vec<Section> = (0..removed.len()).rev().filter(|&i| removed[i]).map(|i| self.remove_section_unchecked(i)).collect();
+        sections.reverse();
+        Ok(sections)
+    }
+
+    /// Fails if a relocation in a section that stays refers to a removed section,
+    /// by its name or through a symbol defined in it.
+    fn check_unreferenced(&self, removed: &[bool]) -> Result<(), String> {
+        let kept_names: HashSet<&str> = self.symbols.iter().filter(|s| !s.section_index.is_some_and(|i| removed[i])).map(|s| s.name.as_str()).collect();
+        // Names that resolve into a removed section -> that section
+        let mut doomed: HashMap<&str, usize> = HashMap::new();
+        for s in &self.symbols {
+            if let Some(i) = s.section_index.filter(|&i| removed[i]) {
+                doomed.insert(s.name.as_str(), i);
+            }
+        }
+        for (i, section) in self.sections.iter().enumerate() {
+            if removed[i] && !kept_names.contains(section.name.as_str()) {
+                doomed.entry(section.name.as_str()).or_insert(i);
+            }
+        }
+        for r in self.relocations.iter().filter(|r| !removed.get(r.section_index).copied().unwrap_or(false)) {
+            for name in std::iter::once(&r.symbol).chain(&r.subtrahend) {
+                if let Some(&i) = doomed.get(name.as_str()) {
+                    let from = self.sections.get(r.section_index).map_or_else(|| r.section_index.to_string(), |s| s.name.clone());
+                    return Err(format!("section {} is still referenced by a relocation in {} (against {})", self.sections[i].name, from, name));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn remove_section_unchecked(&mut self, index: usize) -> Section {
+        let section = self.sections.remove(index);
+        let renumber = |i: usize| if i > index { i - 1 } else { i };
+
+        self.symbols.retain(|s| s.section_index != Some(index));
+        for s in &mut self.symbols {
+            s.section_index = s.section_index.map(renumber);
+        }
+        self.relocations.retain(|r| r.section_index != index);
+        for r in &mut self.relocations {
+            r.section_index = renumber(r.section_index);
+        }
+        for s in &mut self.sections {
+            s.link = s.link.filter(|&l| l != index).map(renumber);
+            if s.flags.contains(SectionFlags::INFO_LINK) {
+                if s.info as usize == index {
+                    s.flags = SectionFlags(s.flags.0 & !SectionFlags::INFO_LINK.0);
+                    s.info = 0;
+                } else {
+                    s.info = renumber(s.info as usize) as u32;
+                }
+            }
+        }
+        for g in &mut self.groups {
+            g.sections.retain(|&i| i != index);
+            for i in &mut g.sections {
+                *i = renumber(*i);
+            }
+        }
+        self.groups.retain(|g| !g.sections.is_empty());
+        section
+    }
+
+    /// Renames a section. Relocations that refer to the section by name follow it.
+    pub fn rename_section(&mut self, index: usize, name: &str) {
+        let old = std::mem::replace(&mut self.sections[index].name, name.to_string());
+        if !self.symbols.iter().any(|s| s.name == old) {
+            for r in &mut self.relocations {
+                if r.symbol == old {
+                    r.symbol = name.to_string();
+                }
+            }
+        }
+    }
+
+    /// Replaces the ELF flags of a section; its kind is derived again from them.
+    pub fn set_section_flags(&mut self, index: usize, flags: SectionFlags) {
+        let section = &mut self.sections[index];
+        section.flags = flags;
+        section.kind = SectionKind::from_elf(section.section_type, flags);
+    }
+
+    /// Whether the code needs an executable stack, from the `.note.GNU-stack`
+    /// section ELF linkers look for. `None` if there is none; linkers then
+    /// assume that it does.
+    pub fn executable_stack(&self) -> Option<bool> {
+        self.find_section(".note.GNU-stack").map(|i| self.sections[i].flags.contains(SectionFlags::EXECINSTR))
+    }
+
+    /// Adds or updates the `.note.GNU-stack` section.
+    pub fn set_executable_stack(&mut self, executable: bool) {
+        let flags = if executable { SectionFlags::EXECINSTR } else { SectionFlags::empty() };
+        match self.find_section(".note.GNU-stack") {
+            Some(index) => self.set_section_flags(index, flags),
+            None => {
+                self.add_elf_section(".note.GNU-stack", SectionType::PROGBITS, flags, 1);
+            }
+        }
+    }
+
+    /// Sets the compression of every non-allocated `.debug*` section; `None`
+    /// writes them uncompressed. Returns how many sections were changed.
+    pub fn compress_debug_sections(&mut self, compression: Option<Compression>) -> usize {
+        let mut changed = 0;
+        for section in &mut self.sections {
+            let is_debug = section.name.starts_with(".debug") && !section.flags.contains(SectionFlags::ALLOC) && !section.is_nobits();
+            if is_debug && section.compression != compression {
+                section.compression = compression;
+                changed += 1;
+            }
+        }
+        changed
+    }
+
+    /// Renames a symbol and every relocation and group signature that uses it.
+    /// Returns whether the symbol exists.
+    pub fn rename_symbol(&mut self, old: &str, new: &str) -> bool {
+        let Some(symbol) = self.symbols.iter_mut().find(|s| s.name == old) else {
+            return false;
+        };
+        symbol.name = new.to_string();
+        for r in &mut self.relocations {
+            if r.symbol == old {
+                r.symbol = new.to_string();
+            }
+            if r.subtrahend.as_deref() == Some(old) {
+                r.subtrahend = Some(new.to_string());
+            }
+        }
+        for g in &mut self.groups {
+            if g.signature == old {
+                g.signature = new.to_string();
+            }
+        }
+        true
+    }
+
+    /// Changes the binding of a symbol, e.g. to localize or weaken it. Returns
+    /// whether the symbol exists.
+    pub fn set_symbol_binding(&mut self, name: &str, binding: SymbolBinding) -> bool {
+        match self.symbols.iter_mut().find(|s| s.name == name) {
+            Some(symbol) => {
+                symbol.binding = binding;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Removes the symbols matching `remove`, except those still used by a
+    /// relocation or as a group signature. Returns how many were removed.
+    pub fn strip_symbols(&mut self, mut remove: impl FnMut(&ObjectSymbol) -> bool) -> usize {
+        let mut needed: HashSet<&str> =
+            self.relocations.iter().flat_map(|r| std::iter::once(&r.symbol).chain(&r.subtrahend)).map(|n| n.as_str()).collect();
+        needed.extend(self.groups.iter().map(|g| g.signature.as_str()));
+        let before = self.symbols.len();
+        self.symbols.retain(|s| needed.contains(s.name.as_str()) || !remove(s));
+        before - self.symbols.len()
+    }
+
+    /// Reads a relocatable object file; the format is detected from its magic number.
+    pub fn parse(data: &[u8]) -> Result<Self, ParseError> {
+        if data.starts_with(b"\x7fELF") {
+            crate::formats::elf::parse_elf(data)
+        } else if data.starts_with(&[0xCF, 0xFA, 0xED, 0xFE]) {
+            crate::formats::macho::parse_macho(data)
+        } else if data.starts_with(&[0x64, 0x86]) {
+            crate::formats::coff::parse_coff(data)
+        } else {
+            Err(ParseError::new(0, "unrecognized object file format"))
+        }
+    }
+
+    pub fn write(&self) -> Result<Vec<u8>, WriteError> {
+        let mut out = Vec::new();
+        self.write_to(&mut out)?;
+        Ok(out)
+    }
+
+    /// Writes the object to `out` after checking it with [`ObjectFile::validate`].
+    /// ELF is streamed as it is encoded; COFF and Mach-O are assembled in memory first.
+    pub fn write_to<W: Write>(&self, out: &mut W) -> Result<(), WriteError> {
+        self.validate().map_err(WriteError::Invalid)?;
+        let unknown = self.unknown_symbol_names();
+        // Declarations go into a copy of the symbol list; the sections are not copied
+        let symbols = match (unknown.first(), self.unknown_symbols) {
+            (None, _) => Cow::Borrowed(self.symbols.as_slice()),
+            (Some(&(r, name)), UnknownSymbolPolicy::Error) => {
+                return Err(WriteError::UnknownSymbol {
+                    symbol: name.to_string(),
+                    section: self.sections.get(r.section_index).map_or_else(|| r.section_index.to_string(), |s| s.name.clone()),
+                    offset: r.offset,
+                });
+            }
+            (Some(_), UnknownSymbolPolicy::DeclareUndefined) => {
+                let mut symbols = self.symbols.clone();
+                for (_, name) in &unknown {
+                    symbols.push(ObjectSymbol {
+                        name: name.to_string(),
+                        section_index: None,
+                        value: 0,
+                        size: 0,
+                        binding: SymbolBinding::Global,
+                        visibility: SymbolVisibility::Default,
+                        kind: SymbolKind::NoType,
+                        absolute: false,
+                    });
+                }
+                Cow::Owned(symbols)
+            }
+        };
+        let bytes = match (self.format, self.endian) {
+            (ObjectFormat::ELF32 | ObjectFormat::ELF64, _) => return crate::formats::elf::write_elf(self, &symbols, out),
+            (format, Endian::Big) => return Err(WriteError::Unsupported(format!("{:?} objects are always little-endian", format))),
+            (format, _) if self.sections.iter().any(|s| s.compression.is_some()) => {
+                return Err(WriteError::Unsupported(format!("{:?} objects cannot have compressed sections", format)))
+            }
+            (format, _) if !self.gnu_properties.is_empty() => {
+                return Err(WriteError::Unsupported(format!("{:?} objects cannot have GNU properties", format)))
+            }
+            (ObjectFormat::COFF, Endian::Little) => crate::formats::coff::write_coff(self, &symbols)?,
+            (ObjectFormat::MachO64, Endian::Little) => crate::formats::macho::write_macho(self, &symbols)?,
+        };
+        out.write_all(&bytes)?;
+        Ok(())
+    }
+
+    /// Names referenced by relocations that resolve to no symbol or section, each
+    /// with its first reference, in order of appearance.
+    fn unknown_symbol_names(&self) -> Vec<(&ObjectRelocation, &str)> {
+        let mut known: HashSet<&str> = self.symbols.iter().map(|s| s.name.as_str()).collect();
+        known.extend(self.sections.iter().map(|s| s.name.as_str()));
+        let mut unknown = Vec::new();
+        for r in &self.relocations {
+            for name in std::iter::once(&r.symbol).chain(&r.subtrahend) {
+                if known.insert(name.as_str()) {
+                    unknown.push((r, name.as_str()));
+                }
+            }
         }
+        unknown
     }
 }
@@ -0,0 +1,109 @@
+use std::ops::{BitOr, BitOrAssign};
+
+use crate::core::endian::Endian;
+
+/// `pr_type` of `GNU_PROPERTY_X86_FEATURE_1_AND`.
+pub const GNU_PROPERTY_X86_FEATURE_1_AND: u32 = 0xC000_0002;
+
+/// `n_type` of a note holding program properties.
+const NT_GNU_PROPERTY_TYPE_0: u32 = 5;
+
+/// Bits of `GNU_PROPERTY_X86_FEATURE_1_AND`: the control-flow protection
+/// features (CET) the code supports.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct X86Features(pub u32);
+
+impl X86Features {
+    /// Indirect branch tracking: every indirect branch target starts with `endbr64`/`endbr32`.
+    pub const IBT: X86Features = X86Features(0x1);
+    /// Shadow stack: returns only go back to their call sites.
+    pub const SHSTK: X86Features = X86Features(0x2);
+
+    pub const fn empty() -> Self {
+        X86Features(0)
+    }
+
+    pub fn contains(&self, other: X86Features) -> bool {
+        self.0 & other.0 == other.0
+    }
+}
+
+impl BitOr for X86Features {
+    type Output = X86Features;
+
+    fn bitor(self, rhs: X86Features) -> X86Features {
+        X86Features(self.0 | rhs.0)
+    }
+}
+
+impl BitOrAssign for X86Features {
+    fn bitor_assign(&mut self, rhs: X86Features) {
+        self.0 |= rhs.0;
+    }
+}
+
+/// Program properties, written to `.note.gnu.property` in ELF objects. The
+/// linker ANDs the `*_AND` properties of its inputs, so a feature is only
+/// enabled for the program if every object claims it.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct GnuProperties {
+    /// `GNU_PROPERTY_X86_FEATURE_1_AND`; only valid for x86 objects.
+    pub x86_features: Option<X86Features>,
+    /// Other properties as (`pr_type`, `pr_data`), kept as read.
+    pub other: Vec<(u32, Vec<u8>)>,
+}
+
+impl GnuProperties {
+    pub fn is_empty(&self) -> bool {
+        self.x86_features.is_none() && self.other.is_empty()
+    }
+
+    /// Encodes the `NT_GNU_PROPERTY_TYPE_0` note. Properties are sorted by
+    /// type and each is padded to `align`, the word size of the file.
+    pub(crate) fn encode(&self, endian: Endian, align: usize) -> Vec<u8> {
+        let mut properties: Vec<(u32, Vec<u8>)> = self.other.clone();
+        if let Some(features) = self.x86_features {
+            properties.push((GNU_PROPERTY_X86_FEATURE_1_AND, endian.encode(features.0 as u64, 4)));
+        }
+        properties.sort_by_key(|&(pr_type, _)| pr_type);
+
+        let mut desc = Vec::new();
+        for (pr_type, data) in &properties {
+            desc.extend(endian.encode(*pr_type as u64, 4));
+            desc.extend(endian.encode(data.len() as u64, 4));
+            desc.extend_from_slice(data);
+            desc.resize(desc.len().next_multiple_of(align), 0);
+        }
+
+        let mut note = Vec::new();
+        note.extend(endian.encode(4, 4)); // n_namesz
+        note.extend(endian.encode(desc.len() as u64, 4));
+        note.extend(endian.encode(NT_GNU_PROPERTY_TYPE_0 as u64, 4));
+        note.extend_from_slice(b"GNU\0");
+        note.extend(desc);
+        note
+    }
+
+    /// Decodes a `.note.gnu.property` section; `None` if it is not a single
+    /// well-formed property note, in which case it is kept as a plain section.
+    pub(crate) fn decode(data: &[u8], endian: Endian, align: usize) -> Option<Self> {
+        let word = |at: usize| data.get(at..at + 4).map(|b| endian.decode(b) as u32);
+        let (namesz, descsz, n_type) = (word(0)?, word(4)? as usize, word(8)?);
+        if namesz != 4 || n_type != NT_GNU_PROPERTY_TYPE_0 || data.get(12..16)? != b"GNU\0" || data.len() != 16 + descsz {
+            return None;
+        }
+
+        let mut properties = GnuProperties::default();
+        let mut at = 16;
+        while at < data.len() {
+            let (pr_type, size) = (word(at)?, word(at + 4)? as usize);
+            let pr_data = data.get(at + 8..at + 8 + size)?;
+            match (pr_type, pr_data.len()) {
+                (GNU_PROPERTY_X86_FEATURE_1_AND, 4) => properties.x86_features = Some(X86Features(endian.decode(pr_data) as u32)),
+                _ => properties.other.push((pr_type, pr_data.to_vec())),
+            }
+            at = (at + 8 + size).next_multiple_of(align);
+        }
+        Some(properties)
+    }
+}
@@ -1,9 +1,406 @@
+use crate::core::arch::Architecture;
+
+/// How a relocation computes its value and where it stores it.
+///
+/// Kinds shared between architectures have descriptive CamelCase names
+/// (`Absolute32`, `Relative32`, `GlobDat`, ...). Kinds that belong to one ABI
+/// keep the name it gives them, without the `R_<ARCH>_`, `IMAGE_REL_AMD64_` or
+/// `ARM64_RELOC_` prefix (`PLT32`, `CALL26`, `ADDR32NB`, ...); new kinds follow
+/// the same rule. The writers map each kind to the number of the target format
+/// and reject kinds it cannot express.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "yaml", derive(serde::Serialize, serde::Deserialize))]
+#[allow(non_camel_case_types)]
 pub enum RelocKind {
+    /// No relocation (`R_*_NONE`).
+    NoReloc,
     Absolute64,
+    Absolute32,
+    Absolute32S,
+    Absolute16,
+    Absolute8,
+    Relative64,
     Relative32,
+    Relative16,
+    Relative8,
+    /// Size of the symbol plus the addend.
+    Size32,
+    Size64,
+    /// 32-bit address relative to the image base (`IMAGE_REL_AMD64_ADDR32NB`), used by PE unwind tables.
+    ADDR32NB,
+
+    // Dynamic relocations, created by the linker for the loader
+    /// Copy the symbol's initial value into the executable (`R_*_COPY`).
+    Copy,
+    /// GOT entry holding a symbol address (`R_*_GLOB_DAT`).
+    GlobDat,
+    /// PLT GOT entry, resolved lazily (`R_*_JUMP_SLOT`).
+    JumpSlot,
+    /// Load base plus addend (`R_*_RELATIVE`).
+    BaseRelative,
+    /// Result of calling the ifunc resolver at load base plus addend (`R_*_IRELATIVE`).
+    IRelative,
+
+    // x86-64 and i386 GOT and PLT references
     GOTPCREL,
     PLT32,
+    GOTPCRELX,
+    REX_GOTPCRELX,
+    /// i386 GOT slot reference that the linker may relax (`R_386_GOT32X`).
+    GOT32X,
+    /// Offset of the symbol's GOT entry from the GOT.
+    GOT32,
+    GOT64,
+    /// Symbol address relative to the GOT.
+    GOTOFF32,
+    GOTOFF64,
+    /// Address of the GOT relative to the place.
+    GOTPC32,
+    GOTPC64,
+    GOTPCREL64,
+    GOTPLT64,
+    PLTOFF64,
+    /// x32 load base plus addend, 64 bits wide.
+    RELATIVE64,
+
+    // Thread-local storage, x86 naming
+    TPOFF32,
+    GOTTPOFF,
+    TLSGD,
+    TLSLD,
+    DTPOFF32,
+    /// Module ID of the symbol's TLS block.
+    DTPMOD64,
+    /// Offset of the symbol in its TLS block.
+    DTPOFF64,
+    /// Offset of the symbol from the thread pointer.
+    TPOFF64,
+    GOTPC32_TLSDESC,
+    /// Marks the call through a TLS descriptor; has no field.
+    TLSDESC_CALL,
+    /// TLS descriptor, filled in by the loader.
+    TLSDESC,
+
+    // i386 thread-local storage
+    TLS_TPOFF,
+    TLS_IE,
+    TLS_GOTIE,
+    TLS_IE_32,
+    TLS_LE_32,
+    TLS_DTPMOD32,
+    TLS_DTPOFF32,
+    TLS_TPOFF32,
+    TLS_GOTDESC,
+
+    // AArch64
+    /// `bl` target, a 26-bit word offset (`R_AARCH64_CALL26`).
+    CALL26,
+    /// `b` target (`R_AARCH64_JUMP26`).
+    JUMP26,
+    /// `adrp`: 4 KiB page of the target relative to the page of the instruction.
+    ADR_PREL_PG_HI21,
+    /// Low 12 bits of the target address (`add`/`ldr`/`str` immediate). Mach-O
+    /// only (`ARM64_RELOC_PAGEOFF12`); ELF has a type per access size.
+    PAGEOFF12,
+    /// `adrp` to the page of the target's GOT slot.
+    ADR_GOT_PAGE,
+    /// `ldr` of the target's GOT slot within its page.
+    LD64_GOT_LO12_NC,
+    MOVW_UABS_G0,
+    MOVW_UABS_G0_NC,
+    MOVW_UABS_G1,
+    MOVW_UABS_G1_NC,
+    MOVW_UABS_G2,
+    MOVW_UABS_G2_NC,
+    MOVW_UABS_G3,
+    MOVW_SABS_G0,
+    MOVW_SABS_G1,
+    MOVW_SABS_G2,
+    LD_PREL_LO19,
+    ADR_PREL_LO21,
+    ADR_PREL_PG_HI21_NC,
+    ADD_ABS_LO12_NC,
+    LDST8_ABS_LO12_NC,
+    LDST16_ABS_LO12_NC,
+    LDST32_ABS_LO12_NC,
+    LDST64_ABS_LO12_NC,
+    LDST128_ABS_LO12_NC,
+    TSTBR14,
+    CONDBR19,
+    MOVW_PREL_G0,
+    MOVW_PREL_G0_NC,
+    MOVW_PREL_G1,
+    MOVW_PREL_G1_NC,
+    MOVW_PREL_G2,
+    MOVW_PREL_G2_NC,
+    MOVW_PREL_G3,
+    MOVW_GOTOFF_G0,
+    MOVW_GOTOFF_G0_NC,
+    MOVW_GOTOFF_G1,
+    MOVW_GOTOFF_G1_NC,
+    MOVW_GOTOFF_G2,
+    MOVW_GOTOFF_G2_NC,
+    MOVW_GOTOFF_G3,
+    GOTREL64,
+    GOTREL32,
+    GOT_LD_PREL19,
+    LD64_GOTOFF_LO15,
+    LD64_GOTPAGE_LO15,
+    TLSGD_ADR_PREL21,
+    TLSGD_ADR_PAGE21,
+    TLSGD_ADD_LO12_NC,
+    TLSGD_MOVW_G1,
+    TLSGD_MOVW_G0_NC,
+    TLSLD_ADR_PREL21,
+    TLSLD_ADR_PAGE21,
+    TLSLD_ADD_LO12_NC,
+    TLSLD_MOVW_G1,
+    TLSLD_MOVW_G0_NC,
+    TLSLD_LD_PREL19,
+    TLSLD_MOVW_DTPREL_G2,
+    TLSLD_MOVW_DTPREL_G1,
+    TLSLD_MOVW_DTPREL_G1_NC,
+    TLSLD_MOVW_DTPREL_G0,
+    TLSLD_MOVW_DTPREL_G0_NC,
+    TLSLD_ADD_DTPREL_HI12,
+    TLSLD_ADD_DTPREL_LO12,
+    TLSLD_ADD_DTPREL_LO12_NC,
+    TLSLD_LDST8_DTPREL_LO12,
+    TLSLD_LDST8_DTPREL_LO12_NC,
+    TLSLD_LDST16_DTPREL_LO12,
+    TLSLD_LDST16_DTPREL_LO12_NC,
+    TLSLD_LDST32_DTPREL_LO12,
+    TLSLD_LDST32_DTPREL_LO12_NC,
+    TLSLD_LDST64_DTPREL_LO12,
+    TLSLD_LDST64_DTPREL_LO12_NC,
+    TLSLD_LDST128_DTPREL_LO12,
+    TLSLD_LDST128_DTPREL_LO12_NC,
+    TLSIE_MOVW_GOTTPREL_G1,
+    TLSIE_MOVW_GOTTPREL_G0_NC,
+    TLSIE_ADR_GOTTPREL_PAGE21,
+    TLSIE_LD64_GOTTPREL_LO12_NC,
+    TLSIE_LD_GOTTPREL_PREL19,
+    TLSLE_MOVW_TPREL_G2,
+    TLSLE_MOVW_TPREL_G1,
+    TLSLE_MOVW_TPREL_G1_NC,
+    TLSLE_MOVW_TPREL_G0,
+    TLSLE_MOVW_TPREL_G0_NC,
+    TLSLE_ADD_TPREL_HI12,
+    TLSLE_ADD_TPREL_LO12,
+    TLSLE_ADD_TPREL_LO12_NC,
+    TLSLE_LDST8_TPREL_LO12,
+    TLSLE_LDST8_TPREL_LO12_NC,
+    TLSLE_LDST16_TPREL_LO12,
+    TLSLE_LDST16_TPREL_LO12_NC,
+    TLSLE_LDST32_TPREL_LO12,
+    TLSLE_LDST32_TPREL_LO12_NC,
+    TLSLE_LDST64_TPREL_LO12,
+    TLSLE_LDST64_TPREL_LO12_NC,
+    TLSLE_LDST128_TPREL_LO12,
+    TLSLE_LDST128_TPREL_LO12_NC,
+    TLSDESC_LD_PREL19,
+    TLSDESC_ADR_PREL21,
+    TLSDESC_ADR_PAGE21,
+    TLSDESC_LD64_LO12,
+    TLSDESC_ADD_LO12,
+    TLSDESC_OFF_G1,
+    TLSDESC_OFF_G0_NC,
+    TLSDESC_LDR,
+    TLSDESC_ADD,
+
+    // RISC-V
+    /// Conditional branch, 12-bit offset.
+    BRANCH,
+    /// `jal`, 20-bit offset.
+    JAL,
+    /// `auipc`+`jalr` pair.
+    CALL,
+    CALL_PLT,
+    GOT_HI20,
+    TLS_GOT_HI20,
+    TLS_GD_HI20,
+    PCREL_HI20,
+    /// Low 12 bits of the `PCREL_HI20` the symbol labels, I-type.
+    PCREL_LO12_I,
+    PCREL_LO12_S,
+    HI20,
+    LO12_I,
+    LO12_S,
+    TPREL_HI20,
+    TPREL_LO12_I,
+    TPREL_LO12_S,
+    /// Marks the thread pointer `add` of a local-exec sequence; has no field.
+    TPREL_ADD,
+    ADD8,
+    ADD16,
+    ADD32,
+    ADD64,
+    SUB8,
+    SUB16,
+    SUB32,
+    SUB64,
+    GOT32_PCREL,
+    /// Padding the linker may shrink when relaxing; has no field.
+    ALIGN,
+    RVC_BRANCH,
+    RVC_JUMP,
+    /// Allows the linker to relax the preceding relocation; has no field.
+    RELAX,
+    SUB6,
+    SET6,
+    SET8,
+    SET16,
+    SET32,
+    SET_ULEB128,
+    SUB_ULEB128,
+    TLSDESC_HI20,
+    TLSDESC_LOAD_LO12,
+
+    // PowerPC64
+    /// `bl` target, a 24-bit word offset.
+    REL24,
+    REL14,
+    ADDR16_LO,
+    ADDR16_HI,
+    /// High half adjusted for the sign of the low half, for `addis`.
+    ADDR16_HA,
+    ADDR16_DS,
+    ADDR16_LO_DS,
+    /// Offset from the TOC pointer (`r2`).
+    TOC16,
+    TOC16_LO,
+    TOC16_HI,
+    TOC16_HA,
+    TOC16_DS,
+    TOC16_LO_DS,
+    /// Address of the TOC base.
+    TOC,
+    REL16_LO,
+    REL16_HI,
+    REL16_HA,
+
+    // MIPS
+    /// `j`/`jal` target within the current 256 MiB region (`R_MIPS_26`; the
+    /// bare ABI name is not an identifier).
+    MIPS_26,
+    HI16,
+    LO16,
+    /// Offset from the global pointer (`$gp`).
+    GPREL16,
+    GPREL32,
+    /// GOT slot of a local page or a global symbol.
+    GOT16,
+    /// GOT slot of a called function.
+    CALL16,
+    /// Load base plus addend; the MIPS counterpart of `BaseRelative`.
+    REL32,
+
+    /// A relocation type without a dedicated variant, kept as its raw number in
+    /// the object format.
+    Raw(u32),
+}
+
+impl RelocKind {
+    pub fn is_tls(&self) -> bool {
+        use RelocKind::*;
+        matches!(
+            self,
+            TPOFF32 | GOTTPOFF | TLSGD | TLSLD | DTPOFF32 | DTPMOD64 | DTPOFF64 | TPOFF64 | GOTPC32_TLSDESC | TLSDESC_CALL | TLSDESC
+                | TLS_TPOFF | TLS_IE | TLS_GOTIE | TLS_IE_32 | TLS_LE_32 | TLS_DTPMOD32 | TLS_DTPOFF32 | TLS_TPOFF32 | TLS_GOTDESC
+                | TLSGD_ADR_PREL21 | TLSGD_ADR_PAGE21 | TLSGD_ADD_LO12_NC | TLSGD_MOVW_G1 | TLSGD_MOVW_G0_NC
+                | TLSLD_ADR_PREL21 | TLSLD_ADR_PAGE21 | TLSLD_ADD_LO12_NC | TLSLD_MOVW_G1 | TLSLD_MOVW_G0_NC | TLSLD_LD_PREL19
+                | TLSLD_MOVW_DTPREL_G2 | TLSLD_MOVW_DTPREL_G1 | TLSLD_MOVW_DTPREL_G1_NC | TLSLD_MOVW_DTPREL_G0 | TLSLD_MOVW_DTPREL_G0_NC
+                | TLSLD_ADD_DTPREL_HI12 | TLSLD_ADD_DTPREL_LO12 | TLSLD_ADD_DTPREL_LO12_NC
+                | TLSLD_LDST8_DTPREL_LO12 | TLSLD_LDST8_DTPREL_LO12_NC | TLSLD_LDST16_DTPREL_LO12 | TLSLD_LDST16_DTPREL_LO12_NC
+                | TLSLD_LDST32_DTPREL_LO12 | TLSLD_LDST32_DTPREL_LO12_NC | TLSLD_LDST64_DTPREL_LO12 | TLSLD_LDST64_DTPREL_LO12_NC
+                | TLSLD_LDST128_DTPREL_LO12 | TLSLD_LDST128_DTPREL_LO12_NC
+                | TLSIE_MOVW_GOTTPREL_G1 | TLSIE_MOVW_GOTTPREL_G0_NC | TLSIE_ADR_GOTTPREL_PAGE21 | TLSIE_LD64_GOTTPREL_LO12_NC
+                | TLSIE_LD_GOTTPREL_PREL19
+                | TLSLE_MOVW_TPREL_G2 | TLSLE_MOVW_TPREL_G1 | TLSLE_MOVW_TPREL_G1_NC | TLSLE_MOVW_TPREL_G0 | TLSLE_MOVW_TPREL_G0_NC
+                | TLSLE_ADD_TPREL_HI12 | TLSLE_ADD_TPREL_LO12 | TLSLE_ADD_TPREL_LO12_NC
+                | TLSLE_LDST8_TPREL_LO12 | TLSLE_LDST8_TPREL_LO12_NC | TLSLE_LDST16_TPREL_LO12 | TLSLE_LDST16_TPREL_LO12_NC
+                | TLSLE_LDST32_TPREL_LO12 | TLSLE_LDST32_TPREL_LO12_NC | TLSLE_LDST64_TPREL_LO12 | TLSLE_LDST64_TPREL_LO12_NC
+                | TLSLE_LDST128_TPREL_LO12 | TLSLE_LDST128_TPREL_LO12_NC
+                | TLSDESC_LD_PREL19 | TLSDESC_ADR_PREL21 | TLSDESC_ADR_PAGE21 | TLSDESC_LD64_LO12 | TLSDESC_ADD_LO12
+                | TLSDESC_OFF_G1 | TLSDESC_OFF_G0_NC | TLSDESC_LDR | TLSDESC_ADD
+                | TLS_GOT_HI20 | TLS_GD_HI20 | TPREL_HI20 | TPREL_LO12_I | TPREL_LO12_S | TPREL_ADD
+                | TLSDESC_HI20 | TLSDESC_LOAD_LO12
+        )
+    }
+
+    /// Whether the value is computed relative to the place being relocated (or
+    /// its page, for `adrp`-style kinds).
+    pub fn is_pc_relative(&self) -> bool {
+        use RelocKind::*;
+        matches!(
+            self,
+            Relative64 | Relative32 | Relative16 | Relative8
+                | GOTPCREL | PLT32 | GOTPCRELX | REX_GOTPCRELX | GOTPC32 | GOTPC64 | GOTPCREL64
+                | GOTTPOFF | TLSGD | TLSLD | GOTPC32_TLSDESC
+                | CALL26 | JUMP26 | ADR_PREL_PG_HI21 | ADR_GOT_PAGE | LD_PREL_LO19 | ADR_PREL_LO21 | ADR_PREL_PG_HI21_NC | TSTBR14 | CONDBR19
+                | MOVW_PREL_G0 | MOVW_PREL_G0_NC | MOVW_PREL_G1 | MOVW_PREL_G1_NC | MOVW_PREL_G2 | MOVW_PREL_G2_NC | MOVW_PREL_G3
+                | GOT_LD_PREL19 | TLSGD_ADR_PREL21 | TLSGD_ADR_PAGE21 | TLSLD_ADR_PREL21 | TLSLD_ADR_PAGE21 | TLSLD_LD_PREL19
+                | TLSIE_ADR_GOTTPREL_PAGE21 | TLSIE_LD_GOTTPREL_PREL19 | TLSDESC_LD_PREL19 | TLSDESC_ADR_PREL21 | TLSDESC_ADR_PAGE21
+                | BRANCH | JAL | CALL | CALL_PLT | GOT_HI20 | TLS_GOT_HI20 | TLS_GD_HI20 | PCREL_HI20 | PCREL_LO12_I | PCREL_LO12_S
+                | RVC_BRANCH | RVC_JUMP | GOT32_PCREL | TLSDESC_HI20 | TLSDESC_LOAD_LO12
+                | REL24 | REL14 | REL16_LO | REL16_HI | REL16_HA
+        )
+    }
+
+    /// Whether the linker has to create a GOT entry (or, for `GOTPC*`, the GOT
+    /// itself) for the reference.
+    pub fn uses_got(&self) -> bool {
+        use RelocKind::*;
+        matches!(
+            self,
+            GOTPCREL | GOTPCRELX | REX_GOTPCRELX | GOT32X | GOT32 | GOT64 | GOTOFF32 | GOTOFF64 | GOTPC32 | GOTPC64 | GOTPCREL64
+                | GOTPLT64 | PLTOFF64
+                | GOTTPOFF | TLSGD | TLSLD | GOTPC32_TLSDESC | TLS_IE | TLS_GOTIE | TLS_IE_32 | TLS_GOTDESC
+                | ADR_GOT_PAGE | LD64_GOT_LO12_NC
+                | MOVW_GOTOFF_G0 | MOVW_GOTOFF_G0_NC | MOVW_GOTOFF_G1 | MOVW_GOTOFF_G1_NC | MOVW_GOTOFF_G2 | MOVW_GOTOFF_G2_NC
+                | MOVW_GOTOFF_G3 | GOTREL64 | GOTREL32 | GOT_LD_PREL19 | LD64_GOTOFF_LO15 | LD64_GOTPAGE_LO15
+                | TLSGD_ADR_PREL21 | TLSGD_ADR_PAGE21 | TLSGD_ADD_LO12_NC | TLSGD_MOVW_G1 | TLSGD_MOVW_G0_NC
+                | TLSLD_ADR_PREL21 | TLSLD_ADR_PAGE21 | TLSLD_ADD_LO12_NC | TLSLD_MOVW_G1 | TLSLD_MOVW_G0_NC | TLSLD_LD_PREL19
+                | TLSIE_MOVW_GOTTPREL_G1 | TLSIE_MOVW_GOTTPREL_G0_NC | TLSIE_ADR_GOTTPREL_PAGE21 | TLSIE_LD64_GOTTPREL_LO12_NC
+                | TLSIE_LD_GOTTPREL_PREL19
+                | TLSDESC_LD_PREL19 | TLSDESC_ADR_PREL21 | TLSDESC_ADR_PAGE21 | TLSDESC_LD64_LO12 | TLSDESC_ADD_LO12
+                | GOT_HI20 | TLS_GOT_HI20 | TLS_GD_HI20 | GOT32_PCREL | TLSDESC_HI20 | TLSDESC_LOAD_LO12
+                | GOT16 | CALL16
+        )
+    }
+
+    /// Relocations only found in dynamic relocation sections.
+    pub fn is_dynamic(&self) -> bool {
+        use RelocKind::*;
+        matches!(
+            self,
+            Copy | GlobDat | JumpSlot | BaseRelative | IRelative | RELATIVE64 | DTPMOD64 | DTPOFF64 | TPOFF64 | TLSDESC
+                | TLS_TPOFF | TLS_DTPMOD32 | TLS_DTPOFF32 | TLS_TPOFF32 | REL32
+        )
+    }
+
+    /// Number of bytes the relocation patches on `arch`: the data word for data
+    /// kinds, the instruction for instruction kinds. `None` for marker kinds
+    /// without a field, ULEB128 fields and raw kinds.
+    pub fn field_size(&self, arch: Architecture) -> Option<usize> {
+        use RelocKind::*;
+        match self {
+            NoReloc | TLSDESC_CALL | TPREL_ADD | ALIGN | RELAX | SET_ULEB128 | SUB_ULEB128 | Raw(_) => None,
+            Absolute8 | Relative8 | ADD8 | SUB8 | SUB6 | SET6 | SET8 => Some(1),
+            Absolute16 | Relative16 | ADD16 | SUB16 | SET16 | RVC_BRANCH | RVC_JUMP => Some(2),
+            // The immediate half of a PowerPC instruction
+            ADDR16_LO | ADDR16_HI | ADDR16_HA | ADDR16_DS | ADDR16_LO_DS | TOC16 | TOC16_LO | TOC16_HI | TOC16_HA | TOC16_DS
+            | TOC16_LO_DS | REL16_LO | REL16_HI | REL16_HA => Some(2),
+            TOC => Some(8),
+            Absolute64 | Relative64 | Size64 | GOT64 | GOTOFF64 | GOTPC64 | GOTPCREL64 | GOTPLT64 | PLTOFF64 | RELATIVE64
+            | DTPMOD64 | DTPOFF64 | TPOFF64 | GOTREL64 | ADD64 | SUB64 => Some(8),
+            Copy | GlobDat | JumpSlot | BaseRelative | IRelative => Some(arch.word_size()),
+            TLSDESC => Some(2 * arch.word_size()),
+            // An auipc+jalr pair
+            CALL | CALL_PLT => Some(8),
+            _ => Some(4),
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -13,4 +410,7 @@ pub struct ObjectRelocation {
     pub symbol: String,
     pub addend: i64,
     pub kind: RelocKind,
+    /// Symbol subtracted from the result (`symbol - subtrahend + addend`), as in
+    /// Mach-O `SUBTRACTOR` pairs. Only absolute kinds may have one.
+    pub subtrahend: Option<String>,
 }
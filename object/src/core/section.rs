@@ -1,14 +1,184 @@
+use std::ops::{BitOr, BitOrAssign};
+
+use crate::compression::Compression;
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "yaml", derive(serde::Serialize, serde::Deserialize))]
 pub enum SectionKind {
     Text,
     Data,
     Bss,
     ReadOnlyData,
+    TlsData,
+    TlsBss,
+    /// Any other section type, kept as its raw ELF `sh_type`.
+    Other(u32),
+}
+
+impl SectionKind {
+    /// Sections that occupy no space in the file (`SHT_NOBITS`).
+    pub fn is_nobits(&self) -> bool {
+        matches!(self, SectionKind::Bss | SectionKind::TlsBss)
+    }
+
+    pub fn is_tls(&self) -> bool {
+        matches!(self, SectionKind::TlsData | SectionKind::TlsBss)
+    }
+
+    /// The ELF section type for sections of this kind.
+    pub fn elf_type(&self) -> SectionType {
+        match self {
+            SectionKind::Other(sh_type) => SectionType(*sh_type),
+            kind if kind.is_nobits() => SectionType::NOBITS,
+            _ => SectionType::PROGBITS,
+        }
+    }
+
+    /// The ELF section flags for sections of this kind.
+    pub fn elf_flags(&self) -> SectionFlags {
+        match self {
+            SectionKind::Text => SectionFlags::ALLOC | SectionFlags::EXECINSTR,
+            SectionKind::Data | SectionKind::Bss => SectionFlags::WRITE | SectionFlags::ALLOC,
+            SectionKind::ReadOnlyData => SectionFlags::ALLOC,
+            SectionKind::TlsData | SectionKind::TlsBss => SectionFlags::WRITE | SectionFlags::ALLOC | SectionFlags::TLS,
+            SectionKind::Other(_) => SectionFlags::empty(),
+        }
+    }
+
+    /// Classifies an ELF section by its type and flags.
+    pub fn from_elf(section_type: SectionType, flags: SectionFlags) -> Self {
+        match section_type {
+            SectionType::NOBITS if flags.contains(SectionFlags::TLS) => SectionKind::TlsBss,
+            SectionType::NOBITS => SectionKind::Bss,
+            SectionType::PROGBITS if !flags.contains(SectionFlags::ALLOC) => SectionKind::Other(SectionType::PROGBITS.0),
+            SectionType::PROGBITS if flags.contains(SectionFlags::TLS) => SectionKind::TlsData,
+            SectionType::PROGBITS if flags.contains(SectionFlags::EXECINSTR) => SectionKind::Text,
+            SectionType::PROGBITS if flags.contains(SectionFlags::WRITE) => SectionKind::Data,
+            SectionType::PROGBITS => SectionKind::ReadOnlyData,
+            SectionType(other) => SectionKind::Other(other),
+        }
+    }
+}
+
+/// An ELF `sh_type` value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SectionType(pub u32);
+
+impl SectionType {
+    pub const PROGBITS: SectionType = SectionType(1);
+    pub const SYMTAB: SectionType = SectionType(2);
+    pub const STRTAB: SectionType = SectionType(3);
+    pub const RELA: SectionType = SectionType(4);
+    pub const NOTE: SectionType = SectionType(7);
+    pub const NOBITS: SectionType = SectionType(8);
+    pub const REL: SectionType = SectionType(9);
+    pub const INIT_ARRAY: SectionType = SectionType(14);
+    pub const FINI_ARRAY: SectionType = SectionType(15);
+    pub const PREINIT_ARRAY: SectionType = SectionType(16);
+    pub const GROUP: SectionType = SectionType(17);
 }
 
+/// A set of ELF `SHF_*` section flags.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct SectionFlags(pub u64);
+
+impl SectionFlags {
+    pub const WRITE: SectionFlags = SectionFlags(0x1);
+    pub const ALLOC: SectionFlags = SectionFlags(0x2);
+    pub const EXECINSTR: SectionFlags = SectionFlags(0x4);
+    /// Elements of `entsize` bytes may be merged with identical ones.
+    pub const MERGE: SectionFlags = SectionFlags(0x10);
+    /// Elements are NUL-terminated strings.
+    pub const STRINGS: SectionFlags = SectionFlags(0x20);
+    pub const INFO_LINK: SectionFlags = SectionFlags(0x40);
+    pub const LINK_ORDER: SectionFlags = SectionFlags(0x80);
+    /// Member of a section group; writers set it from `ObjectFile::groups`.
+    pub const GROUP: SectionFlags = SectionFlags(0x200);
+    pub const TLS: SectionFlags = SectionFlags(0x400);
+    /// Contents start with a compression header (`Elf*_Chdr`).
+    pub const COMPRESSED: SectionFlags = SectionFlags(0x800);
+    /// Dropped from the output by the linker (GNU extension).
+    pub const EXCLUDE: SectionFlags = SectionFlags(0x8000_0000);
+
+    pub const fn empty() -> Self {
+        SectionFlags(0)
+    }
+
+    pub fn contains(&self, other: SectionFlags) -> bool {
+        self.0 & other.0 == other.0
+    }
+}
+
+impl BitOr for SectionFlags {
+    type Output = SectionFlags;
+
+    fn bitor(self, rhs: SectionFlags) -> SectionFlags {
+        SectionFlags(self.0 | rhs.0)
+    }
+}
+
+impl BitOrAssign for SectionFlags {
+    fn bitor_assign(&mut self, rhs: SectionFlags) {
+        self.0 |= rhs.0;
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct Section {
     pub name: String,
     pub kind: SectionKind,
     pub data: Vec<u8>,
     pub align: u64,
+    /// ELF section type; COFF and Mach-O writers go by `kind` instead.
+    pub section_type: SectionType,
+    pub flags: SectionFlags,
+    /// Size of one entry for tables and mergeable sections, 0 otherwise.
+    pub entsize: u64,
+    /// Section this one refers to (`sh_link`), as an index into `ObjectFile::sections`.
+    pub link: Option<usize>,
+    /// `sh_info`. With `SHF_INFO_LINK` it is the section it refers to, as an
+    /// index into `ObjectFile::sections`; otherwise it is written as is.
+    pub info: u32,
+    /// Compress the contents when writing (`SHF_COMPRESSED`). `data` always
+    /// holds the uncompressed bytes; the reader sets this for sections it expanded.
+    pub compression: Option<Compression>,
+}
+
+impl Section {
+    /// A section with the ELF type and flags that `kind` implies.
+    pub fn new(name: &str, kind: SectionKind, align: u64) -> Self {
+        Section {
+            name: name.to_string(),
+            kind,
+            data: Vec::new(),
+            align,
+            section_type: kind.elf_type(),
+            flags: kind.elf_flags(),
+            entsize: 0,
+            link: None,
+            info: 0,
+            compression: None,
+        }
+    }
+
+    /// A section with an explicit ELF type and flags; `kind` is derived from them.
+    pub fn with_elf_type(name: &str, section_type: SectionType, flags: SectionFlags, align: u64) -> Self {
+        Section { section_type, flags, ..Section::new(name, SectionKind::from_elf(section_type, flags), align) }
+    }
+
+    /// Whether the section occupies no space in an ELF file.
+    pub fn is_nobits(&self) -> bool {
+        self.section_type == SectionType::NOBITS
+    }
+}
+
+/// Sections the linker keeps or discards as a unit (an ELF `SHT_GROUP`).
+#[derive(Debug, Clone)]
+pub struct SectionGroup {
+    /// Name of the symbol that identifies the group across objects.
+    pub signature: String,
+    /// Member sections, as indices into `ObjectFile::sections`.
+    pub sections: Vec<usize>,
+    /// Keep only the first group with this signature (`GRP_COMDAT`).
+    pub comdat: bool,
 }
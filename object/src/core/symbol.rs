@@ -1,4 +1,5 @@
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "yaml", derive(serde::Serialize, serde::Deserialize))]
 pub enum SymbolBinding {
     Local,
     Global,
@@ -6,11 +7,27 @@ pub enum SymbolBinding {
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "yaml", derive(serde::Serialize, serde::Deserialize))]
 pub enum SymbolVisibility {
     Default,
     Hidden,
 }
 
+/// What a symbol names; becomes the ELF `STT_*` type.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "yaml", derive(serde::Serialize, serde::Deserialize))]
+pub enum SymbolKind {
+    NoType,
+    Func,
+    Object,
+    /// The start of a section; writers generate these themselves.
+    Section,
+    /// Source file name (`STT_FILE`).
+    File,
+    Tls,
+}
+
+#[derive(Debug, Clone)]
 pub struct ObjectSymbol {
     pub name: String,
     pub section_index: Option<usize>,
@@ -18,4 +35,7 @@ pub struct ObjectSymbol {
     pub size: u64,
     pub binding: SymbolBinding,
     pub visibility: SymbolVisibility,
+    pub kind: SymbolKind,
+    /// `value` is an absolute number rather than a section offset (`SHN_ABS`).
+    pub absolute: bool,
 }
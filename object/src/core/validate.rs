@@ -0,0 +1,162 @@
+use std::fmt;
+
+use crate::core::object::ObjectFile;
+use crate::core::reloc::RelocKind;
+use crate::core::section::SectionFlags;
+
+/// A problem found by [`ObjectFile::validate`]. Sections and symbols are
+/// named; relocations are identified by their index in `ObjectFile::relocations`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Diagnostic {
+    /// The alignment is zero or not a power of two.
+    BadAlignment { section: String, align: u64 },
+    /// `section_index` of a symbol is not a section of the object.
+    SymbolSectionOutOfRange { symbol: String, section_index: usize },
+    /// A symbol ends past the end of its section.
+    SymbolOutOfBounds { symbol: String, section: String, end: u64, section_size: u64 },
+    /// `section_index` of a relocation is not a section of the object.
+    RelocationSectionOutOfRange { relocation: usize, section_index: usize },
+    /// The field a relocation patches ends past the end of its section.
+    RelocationOutOfBounds { relocation: usize, section: String, offset: usize, end: usize, section_size: usize },
+    /// Two relocations patch overlapping bytes.
+    OverlappingRelocations { relocation: usize, other: usize, section: String, offset: usize },
+    /// `link` of a section is not a section of the object.
+    LinkOutOfRange { section: String, link: usize },
+    /// `info` of an `SHF_INFO_LINK` section is not a section of the object.
+    InfoLinkOutOfRange { section: String, info: usize },
+    /// A group lists a section that does not exist.
+    GroupMemberOutOfRange { signature: String, section_index: usize },
+    /// A section is a member of more than one group.
+    SectionInSeveralGroups { section: String },
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Diagnostic::BadAlignment { section, align } => write!(f, "section {}: alignment {} is not a power of two", section, align),
+            Diagnostic::SymbolSectionOutOfRange { symbol, section_index } => {
+                write!(f, "symbol {}: section index {} is out of range", symbol, section_index)
+            }
+            Diagnostic::SymbolOutOfBounds { symbol, section, end, section_size } => {
+                write!(f, "symbol {}: ends at {:#x}, past the end of {} ({:#x} bytes)", symbol, end, section, section_size)
+            }
+            Diagnostic::RelocationSectionOutOfRange { relocation, section_index } => {
+                write!(f, "relocation {}: section index {} is out of range", relocation, section_index)
+            }
+            Diagnostic::RelocationOutOfBounds { relocation, section, offset, end, section_size } => write!(
+                f,
+                "relocation {} at {}+{:#x}: field ends at {:#x}, past the end of the section ({:#x} bytes)",
+                relocation, section, offset, end, section_size
+            ),
+            Diagnostic::OverlappingRelocations { relocation, other, section, offset } => {
+                write!(f, "relocation {} at {}+{:#x} overlaps relocation {}", relocation, section, offset, other)
+            }
+            Diagnostic::LinkOutOfRange { section, link } => write!(f, "section {}: link {} is out of range", section, link),
+            Diagnostic::InfoLinkOutOfRange { section, info } => write!(f, "section {}: info {} is out of range", section, info),
+            Diagnostic::GroupMemberOutOfRange { signature, section_index } => {
+                write!(f, "group {}: member {} is out of range", signature, section_index)
+            }
+            Diagnostic::SectionInSeveralGroups { section } => write!(f, "section {} is in more than one group", section),
+        }
+    }
+}
+
+/// RISC-V `ADD*`/`SUB*`/`SET*` relocations come in pairs that patch the same
+/// field, so they may overlap each other.
+fn composes(kind: RelocKind) -> bool {
+    use RelocKind::*;
+    matches!(kind, ADD8 | ADD16 | ADD32 | ADD64 | SUB6 | SUB8 | SUB16 | SUB32 | SUB64 | SET6 | SET8 | SET16 | SET32)
+}
+
+impl ObjectFile {
+    /// Checks the invariants the writers and the linker rely on, and returns
+    /// every violation rather than stopping at the first.
+    pub fn validate(&self) -> Result<(), Vec<Diagnostic>> {
+        let mut problems = Vec::new();
+
+        for section in &self.sections {
+            if !section.align.is_power_of_two() {
+                problems.push(Diagnostic::BadAlignment { section: section.name.clone(), align: section.align });
+            }
+            if let Some(link) = section.link.filter(|&l| l >= self.sections.len()) {
+                problems.push(Diagnostic::LinkOutOfRange { section: section.name.clone(), link });
+            }
+            let info = section.info as usize;
+            if section.flags.contains(SectionFlags::INFO_LINK) && info >= self.sections.len() {
+                problems.push(Diagnostic::InfoLinkOutOfRange { section: section.name.clone(), info });
+            }
+        }
+
+        let mut group_of = vec![false; self.sections.len()];
+        for group in &self.groups {
+            for &member in &group.sections {
+                match group_of.get_mut(member) {
+                    Some(true) => problems.push(Diagnostic::SectionInSeveralGroups { section: self.sections[member].name.clone() }),
+                    Some(seen) => *seen = true,
+                    None => problems.push(Diagnostic::GroupMemberOutOfRange { signature: group.signature.clone(), section_index: member }),
+                }
+            }
+        }
+
+        for sym in &self.symbols {
+            let Some(index) = sym.section_index else { continue };
+            let Some(section) = self.sections.get(index) else {
+                problems.push(Diagnostic::SymbolSectionOutOfRange { symbol: sym.name.clone(), section_index: index });
+                continue;
+            };
+            // A symbol may end exactly at the end of its section, like `_end` labels
+            let end = sym.value.saturating_add(sym.size);
+            if end > section.data.len() as u64 {
+                problems.push(Diagnostic::SymbolOutOfBounds {
+                    symbol: sym.name.clone(),
+                    section: section.name.clone(),
+                    end,
+                    section_size: section.data.len() as u64,
+                });
+            }
+        }
+
+        // Fields per section as (offset, end, relocation index), for the overlap check
+        let mut fields: Vec<Vec<(usize, usize, usize)>> = vec![Vec::new(); self.sections.len()];
+        for (i, r) in self.relocations.iter().enumerate() {
+            let Some(section) = self.sections.get(r.section_index) else {
+                problems.push(Diagnostic::RelocationSectionOutOfRange { relocation: i, section_index: r.section_index });
+                continue;
+            };
+            let width = r.kind.field_size(self.arch).unwrap_or(0);
+            let end = r.offset.saturating_add(width);
+            if end > section.data.len() {
+                problems.push(Diagnostic::RelocationOutOfBounds {
+                    relocation: i,
+                    section: section.name.clone(),
+                    offset: r.offset,
+                    end,
+                    section_size: section.data.len(),
+                });
+            } else if width > 0 {
+                fields[r.section_index].push((r.offset, end, i));
+            }
+        }
+        for (section, fields) in self.sections.iter().zip(&mut fields) {
+            fields.sort_unstable();
+            // The field reaching furthest so far; each field is compared against it
+            let mut furthest: Option<(usize, usize)> = None;
+            for &(offset, end, i) in fields.iter() {
+                match furthest {
+                    Some((prev_end, prev)) if offset < prev_end => {
+                        let both_compose = composes(self.relocations[prev].kind) && composes(self.relocations[i].kind);
+                        if !both_compose {
+                            problems.push(Diagnostic::OverlappingRelocations { relocation: i, other: prev, section: section.name.clone(), offset });
+                        }
+                        if end > prev_end {
+                            furthest = Some((end, i));
+                        }
+                    }
+                    _ => furthest = Some((end, i)),
+                }
+            }
+        }
+
+        if problems.is_empty() { Ok(()) } else { Err(problems) }
+    }
+}
@@ -0,0 +1,231 @@
+//! Structural comparison of two objects: sections and symbols are matched by
+//! name and relocations by section and offset, so a reordered section table or
+//! symbol table is not reported as a difference.
+
+use std::collections::HashMap;
+use std::ops::Range;
+
+use crate::core::arch::Architecture;
+use crate::core::endian::Endian;
+use crate::core::object::{ObjectFile, ObjectFormat};
+use crate::core::property::GnuProperties;
+use crate::core::reloc::ObjectRelocation;
+use crate::core::section::Section;
+use crate::core::symbol::{ObjectSymbol, SymbolBinding, SymbolKind};
+
+/// Gaps of fewer equal bytes than this between two changed runs are merged,
+/// so a changed instruction is reported as one range rather than per byte.
+const MERGE_GAP: usize = 8;
+
+/// An item present in only one of the objects, or present in both with
+/// different contents.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Change<T> {
+    Added(T),
+    Removed(T),
+    Changed { old: T, new: T },
+}
+
+impl<T> Change<T> {
+    pub fn map<U>(self, f: impl Fn(T) -> U) -> Change<U> {
+        match self {
+            Change::Added(item) => Change::Added(f(item)),
+            Change::Removed(item) => Change::Removed(f(item)),
+            Change::Changed { old, new } => Change::Changed { old: f(old), new: f(new) },
+        }
+    }
+}
+
+/// Bytes that differ between the old and new contents of a section.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ByteRange {
+    pub old: Range<usize>,
+    pub new: Range<usize>,
+}
+
+/// A section found in both objects whose attributes or contents differ.
+#[derive(Debug, Clone)]
+pub struct SectionChange<'a> {
+    pub old: &'a Section,
+    pub new: &'a Section,
+    /// Changed regions of the contents; empty if only the attributes differ.
+    pub ranges: Vec<ByteRange>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ObjectDiff<'a> {
+    pub format: Option<(ObjectFormat, ObjectFormat)>,
+    pub arch: Option<(Architecture, Architecture)>,
+    pub endian: Option<(Endian, Endian)>,
+    pub gnu_properties: Option<(&'a GnuProperties, &'a GnuProperties)>,
+    pub added_sections: Vec<&'a Section>,
+    pub removed_sections: Vec<&'a Section>,
+    pub changed_sections: Vec<SectionChange<'a>>,
+    pub symbols: Vec<Change<&'a ObjectSymbol>>,
+    /// Relocations keyed by section name and offset.
+    pub relocations: Vec<Change<&'a ObjectRelocation>>,
+}
+
+impl ObjectDiff<'_> {
+    pub fn is_empty(&self) -> bool {
+        self.format.is_none()
+            && self.arch.is_none()
+            && self.endian.is_none()
+            && self.gnu_properties.is_none()
+            && self.added_sections.is_empty()
+            && self.removed_sections.is_empty()
+            && self.changed_sections.is_empty()
+            && self.symbols.is_empty()
+            && self.relocations.is_empty()
+    }
+}
+
+/// Compares `old` with `new`. Items with the same name are told apart by
+/// their order, so the second `.text` of one object matches the second of the other.
+pub fn diff<'a>(old: &'a ObjectFile, new: &'a ObjectFile) -> ObjectDiff<'a> {
+    let mut result = ObjectDiff {
+        format: differs(old.format, new.format),
+        arch: differs(old.arch, new.arch),
+        endian: differs(old.endian, new.endian),
+        gnu_properties: differs(&old.gnu_properties, &new.gnu_properties),
+        ..ObjectDiff::default()
+    };
+
+    for change in match_items(&old.sections, &new.sections, |s| s.name.as_str(), |s| s.name.as_str()) {
+        match change {
+            Change::Added(s) => result.added_sections.push(s),
+            Change::Removed(s) => result.removed_sections.push(s),
+            Change::Changed { old, new } => {
+                let same_attributes = old.section_type == new.section_type
+                    && old.flags == new.flags
+                    && old.align == new.align
+                    && old.entsize == new.entsize
+                    && old.compression == new.compression;
+                if same_attributes && old.data == new.data {
+                    continue;
+                }
+                // NOBITS contents are zeros; only their size matters
+                let ranges = if old.is_nobits() || new.is_nobits() { Vec::new() } else { byte_ranges(&old.data, &new.data) };
+                result.changed_sections.push(SectionChange { old, new, ranges });
+            }
+        }
+    }
+
+    // Symbols and relocations refer to sections by index; compare the names instead
+    let section_name = |obj: &'a ObjectFile, index: Option<usize>| index.and_then(|i| obj.sections.get(i)).map(|s| s.name.as_str());
+    // Section symbols come and go with the writer that produced the file
+    let named = |obj: &'a ObjectFile| obj.symbols.iter().filter(|s| s.kind != SymbolKind::Section).collect::<Vec<_>>();
+    let (old_symbols, new_symbols) = (named(old), named(new));
+    for change in match_items(&old_symbols, &new_symbols, |s| s.name.as_str(), |s| s.name.as_str()) {
+        let change = change.map(|s| *s);
+        if let Change::Changed { old: a, new: b } = change {
+            let same = section_name(old, a.section_index) == section_name(new, b.section_index)
+                && a.value == b.value
+                && a.size == b.size
+                && a.binding == b.binding
+                && a.visibility == b.visibility
+                && a.kind == b.kind
+                && a.absolute == b.absolute;
+            if same {
+                continue;
+            }
+        }
+        result.symbols.push(change);
+    }
+
+    let old_key = |r: &ObjectRelocation| (section_name(old, Some(r.section_index)), r.offset);
+    let new_key = |r: &ObjectRelocation| (section_name(new, Some(r.section_index)), r.offset);
+    let (old_targets, new_targets) = (Targets::new(old), Targets::new(new));
+    for change in match_items(&old.relocations, &new.relocations, old_key, new_key) {
+        if let Change::Changed { old: a, new: b } = change {
+            if a.kind == b.kind && a.subtrahend == b.subtrahend && old_targets.resolve(a) == new_targets.resolve(b) {
+                continue;
+            }
+        }
+        result.relocations.push(change);
+    }
+    result
+}
+
+/// Resolves relocation targets so that a local label and its section symbol
+/// plus the label's offset compare equal.
+struct Targets<'a> {
+    obj: &'a ObjectFile,
+    locals: HashMap<&'a str, &'a ObjectSymbol>,
+}
+
+impl<'a> Targets<'a> {
+    fn new(obj: &'a ObjectFile) -> Self {
+        let locals = obj
+            .symbols
+            .iter()
+            .filter(|s| s.binding == SymbolBinding::Local && s.section_index.is_some())
+            .map(|s| (s.name.as_str(), s))
+            .collect();
+        Targets { obj, locals }
+    }
+
+    fn resolve(&self, r: &'a ObjectRelocation) -> (&'a str, i64) {
+        match self.locals.get(r.symbol.as_str()).and_then(|s| Some((self.obj.sections.get(s.section_index?)?, s.value))) {
+            Some((section, value)) => (section.name.as_str(), r.addend.wrapping_add(value as i64)),
+            None => (r.symbol.as_str(), r.addend),
+        }
+    }
+}
+
+fn differs<T: PartialEq>(old: T, new: T) -> Option<(T, T)> {
+    (old != new).then_some((old, new))
+}
+
+/// Pairs the items of `old` and `new` with equal keys, the n-th occurrence of
+/// a key with the n-th. Pairs come in the order of `old`, followed by the
+/// items only `new` has.
+fn match_items<'a, T, K: Eq + std::hash::Hash>(
+    old: &'a [T],
+    new: &'a [T],
+    old_key: impl Fn(&'a T) -> K,
+    new_key: impl Fn(&'a T) -> K,
+) -> Vec<Change<&'a T>> {
+    let mut unmatched: HashMap<K, Vec<usize>> = HashMap::new();
+    for (i, item) in new.iter().enumerate().rev() {
+        unmatched.entry(new_key(item)).or_default().push(i);
+    }
+    let mut matched = vec![false; new.len()];
+    let mut changes = Vec::new();
+    for item in old {
+        match unmatched.get_mut(&old_key(item)).and_then(|indices| indices.pop()) {
+            Some(i) => {
+                matched[i] = true;
+                changes.push(Change::Changed { old: item, new: &new[i] });
+            }
+            None => changes.push(Change::Removed(item)),
+        }
+    }
+    changes.extend(new.iter().zip(matched).filter(|(_, m)| !m).map(|(item, _)| Change::Added(item)));
+    changes
+}
+
+/// Regions where `old` and `new` differ. After the common prefix and suffix,
+/// a middle part of equal length is compared byte by byte; otherwise bytes were
+/// inserted or removed and the whole middle is one range.
+fn byte_ranges(old: &[u8], new: &[u8]) -> Vec<ByteRange> {
+    let prefix = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+    let max_suffix = old.len().min(new.len()) - prefix;
+    let suffix = old.iter().rev().zip(new.iter().rev()).take(max_suffix).take_while(|(a, b)| a == b).count();
+    let (old_end, new_end) = (old.len() - suffix, new.len() - suffix);
+    if prefix == old_end && prefix == new_end {
+        return Vec::new();
+    }
+    if old_end != new_end {
+        return vec![ByteRange { old: prefix..old_end, new: prefix..new_end }];
+    }
+
+    let mut ranges: Vec<Range<usize>> = Vec::new();
+    for i in (prefix..old_end).filter(|&i| old[i] != new[i]) {
+        match ranges.last_mut() {
+            Some(last) if i - last.end < MERGE_GAP => last.end = i + 1,
+            _ => ranges.push(i..i + 1),
+        }
+    }
+    ranges.into_iter().map(|r| ByteRange { old: r.clone(), new: r }).collect()
+}
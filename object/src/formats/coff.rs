@@ -0,0 +1,450 @@
+//! AMD64 COFF relocatable objects (`.obj`), as consumed by `link.exe` and `lld-link`.
+//!
+//! COFF relocations carry their addend in the relocated field. `REL32` is
+//! relative to the end of its 4-byte field, so an `object` addend of `a` is
+//! stored as `a + 4`.
+
+use std::collections::HashMap;
+
+use crate::core::arch::Architecture;
+use crate::core::error::ParseError;
+use crate::core::object::{ObjectFile, ObjectFormat};
+use crate::core::reloc::{ObjectRelocation, RelocKind};
+use crate::core::section::{Section, SectionKind};
+use crate::core::symbol::{ObjectSymbol, SymbolBinding, SymbolKind, SymbolVisibility};
+use crate::formats::reader::{zero_fill, Reader};
+
+const IMAGE_FILE_MACHINE_AMD64: u16 = 0x8664;
+
+const FILE_HEADER_SIZE: u64 = 20;
+const SECTION_HEADER_SIZE: u64 = 40;
+const SYMBOL_SIZE: u64 = 18;
+const RELOC_SIZE: u64 = 10;
+
+const IMAGE_SCN_CNT_CODE: u32 = 0x20;
+const IMAGE_SCN_CNT_INITIALIZED_DATA: u32 = 0x40;
+const IMAGE_SCN_CNT_UNINITIALIZED_DATA: u32 = 0x80;
+const IMAGE_SCN_ALIGN_MASK: u32 = 0x00F0_0000;
+const IMAGE_SCN_MEM_DISCARDABLE: u32 = 0x0200_0000;
+const IMAGE_SCN_MEM_EXECUTE: u32 = 0x2000_0000;
+const IMAGE_SCN_MEM_READ: u32 = 0x4000_0000;
+const IMAGE_SCN_MEM_WRITE: u32 = 0x8000_0000;
+
+/// Largest alignment the `IMAGE_SCN_ALIGN_*` field can express.
+const MAX_ALIGN: u64 = 8192;
+
+const IMAGE_SYM_UNDEFINED: i16 = 0;
+const IMAGE_SYM_ABSOLUTE: i16 = -1;
+
+const IMAGE_SYM_CLASS_EXTERNAL: u8 = 2;
+const IMAGE_SYM_CLASS_STATIC: u8 = 3;
+const IMAGE_SYM_CLASS_LABEL: u8 = 6;
+const IMAGE_SYM_CLASS_WEAK_EXTERNAL: u8 = 105;
+
+/// `IMAGE_SYM_DTYPE_FUNCTION` in the complex-type nibble of a symbol's Type.
+const IMAGE_SYM_TYPE_FUNCTION: u16 = 0x20;
+
+const IMAGE_REL_AMD64_REL32: u16 = 4;
+
+/// `IMAGE_REL_AMD64_*` relocation types.
+const RELOC_TYPES: &[(RelocKind, u16)] = &[
+    (RelocKind::Absolute64, 1),
+    (RelocKind::Absolute32, 2),
+    (RelocKind::ADDR32NB, 3),
+    (RelocKind::Relative32, IMAGE_REL_AMD64_REL32),
+];
+
+fn coff_reloc_type(kind: RelocKind) -> Result<u16, String> {
+    match kind {
+        // There is no PLT; calls to DLL functions go through import thunks the linker provides
+        RelocKind::PLT32 => Ok(IMAGE_REL_AMD64_REL32),
+        RelocKind::Raw(raw) => u16::try_from(raw).map_err(|_| format!("relocation type {} does not fit in COFF", raw)),
+        kind => RELOC_TYPES
+            .iter()
+            .find(|(k, _)| *k == kind)
+            .map(|(_, t)| *t)
+            .ok_or_else(|| format!("{:?} relocations are not supported in COFF", kind)),
+    }
+}
+
+fn reloc_kind_from_coff(type_: u16) -> RelocKind {
+    RELOC_TYPES.iter().find(|(_, t)| *t == type_).map(|(k, _)| *k).unwrap_or(RelocKind::Raw(type_ as u32))
+}
+
+/// Width of the field holding the addend, and the bias between that field and the `object` addend.
+fn implicit_addend(type_: u16) -> Option<(usize, i64)> {
+    match type_ {
+        1 => Some((8, 0)),
+        2 | 3 => Some((4, 0)),
+        IMAGE_REL_AMD64_REL32 => Some((4, 4)),
+        _ => None,
+    }
+}
+
+fn section_characteristics(section: &Section) -> Result<u32, String> {
+    let contents = match section.kind {
+        SectionKind::Text => IMAGE_SCN_CNT_CODE | IMAGE_SCN_MEM_EXECUTE | IMAGE_SCN_MEM_READ,
+        SectionKind::Data | SectionKind::TlsData => IMAGE_SCN_CNT_INITIALIZED_DATA | IMAGE_SCN_MEM_READ | IMAGE_SCN_MEM_WRITE,
+        SectionKind::Bss | SectionKind::TlsBss => IMAGE_SCN_CNT_UNINITIALIZED_DATA | IMAGE_SCN_MEM_READ | IMAGE_SCN_MEM_WRITE,
+        SectionKind::ReadOnlyData => IMAGE_SCN_CNT_INITIALIZED_DATA | IMAGE_SCN_MEM_READ,
+        // Non-allocated ELF sections (notes, comments) are dropped from the image
+        SectionKind::Other(_) => IMAGE_SCN_CNT_INITIALIZED_DATA | IMAGE_SCN_MEM_READ | IMAGE_SCN_MEM_DISCARDABLE,
+    };
+    let align = section.align.max(1);
+    if !align.is_power_of_two() || align > MAX_ALIGN {
+        return Err(format!("section {} alignment {} cannot be represented in COFF", section.name, align));
+    }
+    // IMAGE_SCN_ALIGN_1BYTES is 1, each step doubles the alignment
+    Ok(contents | ((align.trailing_zeros() + 1) << 20))
+}
+
+fn section_kind(characteristics: u32) -> SectionKind {
+    if characteristics & IMAGE_SCN_CNT_CODE != 0 {
+        SectionKind::Text
+    } else if characteristics & IMAGE_SCN_CNT_UNINITIALIZED_DATA != 0 {
+        SectionKind::Bss
+    } else if characteristics & IMAGE_SCN_MEM_DISCARDABLE != 0 {
+        SectionKind::Other(1)
+    } else if characteristics & IMAGE_SCN_MEM_WRITE != 0 {
+        SectionKind::Data
+    } else {
+        SectionKind::ReadOnlyData
+    }
+}
+
+/// COFF string table; offsets count the leading 4-byte size field.
+struct StringTable(Vec<u8>);
+
+impl StringTable {
+    fn add(&mut self, name: &str) -> u32 {
+        let offset = 4 + self.0.len() as u32;
+        self.0.extend_from_slice(name.as_bytes());
+        self.0.push(0);
+        offset
+    }
+
+    /// Section header name: inline if it fits, otherwise `/<offset>`.
+    fn section_name(&mut self, name: &str) -> [u8; 8] {
+        let mut out = [0u8; 8];
+        if name.len() <= 8 {
+            out[..name.len()].copy_from_slice(name.as_bytes());
+        } else {
+            let long = format!("/{}", self.add(name));
+            out[..long.len()].copy_from_slice(long.as_bytes());
+        }
+        out
+    }
+
+    /// Symbol name: inline if it fits, otherwise four zero bytes and the offset.
+    fn symbol_name(&mut self, name: &str) -> [u8; 8] {
+        let mut out = [0u8; 8];
+        if name.len() <= 8 {
+            out[..name.len()].copy_from_slice(name.as_bytes());
+        } else {
+            out[4..].copy_from_slice(&self.add(name).to_le_bytes());
+        }
+        out
+    }
+}
+
+fn put_symbol(out: &mut Vec<u8>, name: [u8; 8], value: u32, section: i16, type_: u16, class: u8, aux: u8) {
+    out.extend_from_slice(&name);
+    out.extend_from_slice(&value.to_le_bytes());
+    out.extend_from_slice(&section.to_le_bytes());
+    out.extend_from_slice(&type_.to_le_bytes());
+    out.push(class);
+    out.push(aux);
+}
+
+/// Writes an AMD64 COFF object.
+///
+/// Every section gets a static section symbol with an auxiliary section record,
+/// followed by the object's own symbols. Weak definitions become ordinary
+/// externals; weak references are rejected since COFF needs a fallback symbol for them.
+/// The symbols come from `symbols` instead of `obj.symbols`.
+pub fn write_coff(obj: &ObjectFile, symbols: &[ObjectSymbol]) -> Result<Vec<u8>, String> {
+    if obj.arch != Architecture::X86_64 {
+        return Err(format!("COFF output is only supported for x86-64, not {:?}", obj.arch));
+    }
+    if let Some(group) = obj.groups.first() {
+        return Err(format!("section group {} cannot be written to COFF", group.signature));
+    }
+    if obj.sections.len() > i16::MAX as usize {
+        return Err("too many sections for COFF".into());
+    }
+
+    let mut strings = StringTable(Vec::new());
+
+    // Symbol table indices; a symbol shadows a section of the same name
+    let mut symbol_index = HashMap::new();
+    for (i, section) in obj.sections.iter().enumerate() {
+        symbol_index.insert(section.name.as_str(), 2 * i as u32);
+    }
+    // Section and file symbols from other formats are not copied; the former
+    // resolve to the generated section symbols
+    for sym in symbols.iter().filter(|s| s.kind == SymbolKind::Section) {
+        if let Some(section) = sym.section_index {
+            symbol_index.insert(sym.name.as_str(), 2 * section as u32);
+        }
+    }
+    let symbols: Vec<&ObjectSymbol> = symbols.iter().filter(|s| !matches!(s.kind, SymbolKind::Section | SymbolKind::File)).collect();
+    let first_symbol = 2 * obj.sections.len() as u32;
+    for (i, sym) in symbols.iter().enumerate() {
+        symbol_index.insert(sym.name.as_str(), first_symbol + i as u32);
+    }
+
+    // Relocation records and section contents with the addends written in place
+    let mut contents: Vec<Vec<u8>> = obj.sections.iter().map(|s| s.data.clone()).collect();
+    let mut relocs: Vec<Vec<u8>> = vec![Vec::new(); obj.sections.len()];
+    for r in &obj.relocations {
+        if let Some(subtrahend) = &r.subtrahend {
+            return Err(format!("COFF cannot express {} - {}", r.symbol, subtrahend));
+        }
+        let type_ = coff_reloc_type(r.kind)?;
+        let symbol = *symbol_index.get(r.symbol.as_str()).ok_or_else(|| format!("relocation against unknown symbol {}", r.symbol))?;
+        match implicit_addend(type_) {
+            Some((width, bias)) => {
+                let field = contents[r.section_index]
+                    .get_mut(r.offset..r.offset + width)
+                    .ok_or_else(|| format!("relocation at {:#x} is outside section {}", r.offset, obj.sections[r.section_index].name))?;
+                field.copy_from_slice(&(r.addend + bias).to_le_bytes()[..width]);
+            }
+            None if r.addend != 0 => return Err(format!("relocation type {} cannot carry an addend", type_)),
+            None => {}
+        }
+        let offset = u32::try_from(r.offset).map_err(|_| format!("relocation offset {:#x} is too large for COFF", r.offset))?;
+        let out = &mut relocs[r.section_index];
+        out.extend_from_slice(&offset.to_le_bytes());
+        out.extend_from_slice(&symbol.to_le_bytes());
+        out.extend_from_slice(&type_.to_le_bytes());
+    }
+
+    // Section headers, each followed in the file by its contents and relocations
+    let mut headers = Vec::new();
+    let mut offset = FILE_HEADER_SIZE + SECTION_HEADER_SIZE * obj.sections.len() as u64;
+    for (i, section) in obj.sections.iter().enumerate() {
+        let nrelocs = relocs[i].len() as u64 / RELOC_SIZE;
+        if nrelocs > u16::MAX as u64 {
+            return Err(format!("section {} has too many relocations for COFF", section.name));
+        }
+        let raw_pointer = if section.kind.is_nobits() { 0 } else { offset };
+        if !section.kind.is_nobits() {
+            offset += section.data.len() as u64;
+        }
+        let reloc_pointer = if nrelocs > 0 { offset } else { 0 };
+        offset += relocs[i].len() as u64;
+
+        headers.extend_from_slice(&strings.section_name(&section.name));
+        headers.extend_from_slice(&0u32.to_le_bytes()); // VirtualSize
+        headers.extend_from_slice(&0u32.to_le_bytes()); // VirtualAddress
+        headers.extend_from_slice(&(section.data.len() as u32).to_le_bytes());
+        headers.extend_from_slice(&(raw_pointer as u32).to_le_bytes());
+        headers.extend_from_slice(&(reloc_pointer as u32).to_le_bytes());
+        headers.extend_from_slice(&0u32.to_le_bytes()); // PointerToLinenumbers
+        headers.extend_from_slice(&(nrelocs as u16).to_le_bytes());
+        headers.extend_from_slice(&0u16.to_le_bytes()); // NumberOfLinenumbers
+        headers.extend_from_slice(&section_characteristics(section)?.to_le_bytes());
+    }
+    let symtab_pointer = offset;
+
+    let mut symtab = Vec::new();
+    for (i, section) in obj.sections.iter().enumerate() {
+        put_symbol(&mut symtab, strings.symbol_name(&section.name), 0, i as i16 + 1, 0, IMAGE_SYM_CLASS_STATIC, 1);
+        symtab.extend_from_slice(&(section.data.len() as u32).to_le_bytes());
+        symtab.extend_from_slice(&((relocs[i].len() as u64 / RELOC_SIZE) as u16).to_le_bytes());
+        symtab.extend_from_slice(&[0; 12]); // line numbers, checksum, COMDAT number/selection, padding
+    }
+    for sym in symbols {
+        let value = u32::try_from(sym.value).map_err(|_| format!("symbol {} value {:#x} is too large for COFF", sym.name, sym.value))?;
+        let section = match sym.section_index {
+            Some(i) => i as i16 + 1,
+            None if sym.absolute => IMAGE_SYM_ABSOLUTE,
+            None => IMAGE_SYM_UNDEFINED,
+        };
+        let class = match (sym.binding, sym.section_index) {
+            (SymbolBinding::Local, _) => IMAGE_SYM_CLASS_STATIC,
+            (SymbolBinding::Global, _) | (SymbolBinding::Weak, Some(_)) => IMAGE_SYM_CLASS_EXTERNAL,
+            (SymbolBinding::Weak, None) => return Err(format!("weak reference {} is not supported in COFF", sym.name)),
+        };
+        let type_ = if sym.kind == SymbolKind::Func { IMAGE_SYM_TYPE_FUNCTION } else { 0 };
+        put_symbol(&mut symtab, strings.symbol_name(&sym.name), value, section, type_, class, 0);
+    }
+    let nsymbols = symtab.len() as u64 / SYMBOL_SIZE;
+
+    if symtab_pointer + symtab.len() as u64 + 4 + strings.0.len() as u64 > u32::MAX as u64 {
+        return Err("object is too large for COFF".into());
+    }
+
+    let mut out = Vec::new();
+    out.extend_from_slice(&IMAGE_FILE_MACHINE_AMD64.to_le_bytes());
+    out.extend_from_slice(&(obj.sections.len() as u16).to_le_bytes());
+    out.extend_from_slice(&0u32.to_le_bytes()); // TimeDateStamp: keep output reproducible
+    out.extend_from_slice(&(symtab_pointer as u32).to_le_bytes());
+    out.extend_from_slice(&(nsymbols as u32).to_le_bytes());
+    out.extend_from_slice(&0u16.to_le_bytes()); // SizeOfOptionalHeader
+    out.extend_from_slice(&0u16.to_le_bytes()); // Characteristics
+    out.extend_from_slice(&headers);
+    for (i, section) in obj.sections.iter().enumerate() {
+        if !section.kind.is_nobits() {
+            out.extend_from_slice(&contents[i]);
+        }
+        out.extend_from_slice(&relocs[i]);
+    }
+    out.extend_from_slice(&symtab);
+    out.extend_from_slice(&(4 + strings.0.len() as u32).to_le_bytes());
+    out.extend_from_slice(&strings.0);
+
+    Ok(out)
+}
+
+/// Reads an AMD64 COFF object.
+///
+/// Section symbols are not returned as symbols; relocations against them use
+/// the section name, which `write_coff` resolves back to the section symbol.
+pub fn parse_coff(data: &[u8]) -> Result<ObjectFile, ParseError> {
+    let r = Reader { data };
+    let machine = r.u16(0, "Machine")?;
+    if machine != IMAGE_FILE_MACHINE_AMD64 {
+        return Err(ParseError::new(0, format!("unsupported COFF machine {:#x}", machine)));
+    }
+    let nsections = r.u16(2, "NumberOfSections")? as u64;
+    let symtab = r.u32(8, "PointerToSymbolTable")? as u64;
+    let nsymbols = r.u32(12, "NumberOfSymbols")? as u64;
+    if r.u16(16, "SizeOfOptionalHeader")? != 0 {
+        return Err(ParseError::new(16, "COFF file has an optional header; only objects are supported"));
+    }
+
+    let strtab_offset = symtab + SYMBOL_SIZE * nsymbols;
+    let strtab = if symtab == 0 {
+        &[][..]
+    } else {
+        let size = r.u32(strtab_offset, "string table size")? as u64;
+        r.bytes(strtab_offset, size.max(4), "string table")?
+    };
+    let string = |offset: u32, at: u64| -> Result<String, ParseError> {
+        let bytes = strtab.get(offset as usize..).filter(|_| offset >= 4).unwrap_or(&[]);
+        let len = bytes.iter().position(|&b| b == 0).ok_or_else(|| {
+            ParseError::new(at as usize, format!("string table offset {} is not NUL-terminated inside the table", offset))
+        })?;
+        Ok(String::from_utf8_lossy(&bytes[..len]).into_owned())
+    };
+    let inline = |bytes: &[u8]| {
+        let len = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+        String::from_utf8_lossy(&bytes[..len]).into_owned()
+    };
+
+    let mut obj = ObjectFile::new(ObjectFormat::COFF);
+    let mut reloc_tables = Vec::new();
+    for i in 0..nsections {
+        let hdr = FILE_HEADER_SIZE + SECTION_HEADER_SIZE * i;
+        let raw_name = r.bytes(hdr, 8, "section name")?;
+        let name = match raw_name.strip_prefix(b"/") {
+            Some(digits) => {
+                let offset = inline(digits).parse().map_err(|_| ParseError::new(hdr as usize, "malformed long section name"))?;
+                string(offset, hdr)?
+            }
+            None => inline(raw_name),
+        };
+        let size = r.u32(hdr + 16, "SizeOfRawData")? as u64;
+        let raw_pointer = r.u32(hdr + 20, "PointerToRawData")? as u64;
+        let reloc_pointer = r.u32(hdr + 24, "PointerToRelocations")? as u64;
+        let nrelocs = r.u16(hdr + 32, "NumberOfRelocations")? as u64;
+        let characteristics = r.u32(hdr + 36, "Characteristics")?;
+
+        let kind = section_kind(characteristics);
+        let data = if kind.is_nobits() {
+            zero_fill(size, hdr + 16, &name)?
+        } else {
+            r.bytes(raw_pointer, size, &format!("contents of section {}", name))?.to_vec()
+        };
+        // An unspecified alignment means the 16-byte default
+        let align = match (characteristics & IMAGE_SCN_ALIGN_MASK) >> 20 {
+            0 => 16,
+            n => 1 << (n - 1),
+        };
+        obj.sections.push(Section { data, ..Section::new(&name, kind, align) });
+        reloc_tables.push((reloc_pointer, nrelocs));
+    }
+
+    // Symbol table index -> name; auxiliary records map to None
+    let mut names: Vec<Option<String>> = Vec::new();
+    while (names.len() as u64) < nsymbols {
+        let off = symtab + SYMBOL_SIZE * names.len() as u64;
+        let raw_name = r.bytes(off, 8, "symbol name")?;
+        let name = if raw_name[..4] == [0; 4] { string(r.u32(off + 4, "symbol name offset")?, off)? } else { inline(raw_name) };
+        let value = r.u32(off + 8, "symbol value")? as u64;
+        let section = r.u16(off + 12, "symbol section number")? as i16;
+        let class = r.u8(off + 16, "symbol storage class")?;
+        let type_ = r.u16(off + 14, "symbol type")?;
+        let aux = r.u8(off + 17, "symbol auxiliary count")?;
+
+        let section_index = match section {
+            n if n > 0 => {
+                let idx = n as usize - 1;
+                if idx >= obj.sections.len() {
+                    return Err(ParseError::new(off as usize + 12, format!("symbol {} refers to invalid section {}", name, n)));
+                }
+                Some(idx)
+            }
+            _ => None,
+        };
+        let is_section_symbol =
+            class == IMAGE_SYM_CLASS_STATIC && aux > 0 && value == 0 && section_index.is_some_and(|i| obj.sections[i].name == name);
+        let binding = match class {
+            IMAGE_SYM_CLASS_EXTERNAL => Some(SymbolBinding::Global),
+            IMAGE_SYM_CLASS_WEAK_EXTERNAL => Some(SymbolBinding::Weak),
+            IMAGE_SYM_CLASS_STATIC | IMAGE_SYM_CLASS_LABEL if !is_section_symbol => Some(SymbolBinding::Local),
+            // Section symbols, file names and debug records
+            _ => None,
+        };
+        if let Some(binding) = binding {
+            obj.symbols.push(ObjectSymbol {
+                name: name.clone(),
+                section_index,
+                value,
+                size: 0,
+                binding,
+                visibility: SymbolVisibility::Default,
+                kind: if type_ & 0x30 == IMAGE_SYM_TYPE_FUNCTION { SymbolKind::Func } else { SymbolKind::NoType },
+                absolute: section == IMAGE_SYM_ABSOLUTE,
+            });
+        }
+        names.push(Some(name));
+        names.extend((0..aux).map(|_| None));
+    }
+
+    for (section_index, &(pointer, count)) in reloc_tables.iter().enumerate() {
+        for i in 0..count {
+            let off = pointer + RELOC_SIZE * i;
+            let offset = r.u32(off, "relocation address")? as usize;
+            let symbol_index = r.u32(off + 4, "relocation symbol index")?;
+            let type_ = r.u16(off + 8, "relocation type")?;
+            let symbol = names.get(symbol_index as usize).cloned().flatten().ok_or_else(|| {
+                ParseError::new(off as usize + 4, format!("relocation refers to invalid symbol {}", symbol_index))
+            })?;
+            let addend = match implicit_addend(type_) {
+                Some((width, bias)) => {
+                    let field = obj.sections[section_index].data.get(offset..offset + width).ok_or_else(|| {
+                        ParseError::new(off as usize, format!("relocation offset {:#x} is outside its section", offset))
+                    })?;
+                    let mut bytes = [0u8; 8];
+                    bytes[..width].copy_from_slice(field);
+                    // Sign-extend from the field width
+                    let shift = 64 - 8 * width as u32;
+                    ((i64::from_le_bytes(bytes) << shift) >> shift) - bias
+                }
+                None => 0,
+            };
+            obj.relocations.push(ObjectRelocation {
+                section_index,
+                offset,
+                symbol,
+                addend,
+                kind: reloc_kind_from_coff(type_),
+                subtrahend: None,
+            });
+        }
+    }
+
+    Ok(obj)
+}
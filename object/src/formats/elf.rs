@@ -1,11 +1,57 @@
-use crate::core::object::ObjectFile;
-use crate::core::section::SectionKind;
-use crate::core::symbol::SymbolBinding;
-use crate::core::reloc::RelocKind;
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
+use std::io::{self, Write};
+use std::marker::PhantomData;
+
+use crate::compression::{Compression, DecompressError};
+use crate::core::arch::Architecture;
+use crate::core::endian::{BigEndian, Endian, Endianness, LittleEndian};
+use crate::core::error::{ParseError, WriteError};
+use crate::core::object::{ObjectFile, ObjectFormat};
+use crate::core::property::GnuProperties;
+use crate::core::section::{Section, SectionFlags, SectionGroup, SectionType};
+use crate::core::symbol::{ObjectSymbol, SymbolBinding, SymbolKind, SymbolVisibility};
+use crate::core::reloc::{ObjectRelocation, RelocKind};
+use crate::formats::reader::{zero_fill, Reader};
+
+/// ELF file class. The header structures below are held at 64-bit width and
+/// narrowed when an ELF32 file is written.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ElfClass {
+    Elf32,
+    Elf64,
+}
+
+impl ElfClass {
+    fn ehsize(self) -> u16 {
+        match self { ElfClass::Elf32 => 52, ElfClass::Elf64 => 64 }
+    }
+
+    fn shentsize(self) -> u16 {
+        match self { ElfClass::Elf32 => 40, ElfClass::Elf64 => 64 }
+    }
+
+    fn symsize(self) -> u64 {
+        match self { ElfClass::Elf32 => 16, ElfClass::Elf64 => 24 }
+    }
+
+    /// Size of an `Elf_Rel` (or `Elf_Rela` when `rela` is set) entry.
+    fn relsize(self, rela: bool) -> u64 {
+        match (self, rela) {
+            (ElfClass::Elf32, false) => 8,
+            (ElfClass::Elf32, true) => 12,
+            (ElfClass::Elf64, false) => 16,
+            (ElfClass::Elf64, true) => 24,
+        }
+    }
+
+    fn word_align(self) -> u64 {
+        match self { ElfClass::Elf32 => 4, ElfClass::Elf64 => 8 }
+    }
+}
 
-#[repr(C)]
 #[derive(Default)]
-struct Elf64Header {
+struct ElfHeader {
     ident: [u8; 16],
     type_: u16,
     machine: u16,
@@ -22,9 +68,8 @@ struct Elf64Header {
     shstrndx: u16,
 }
 
-#[repr(C)]
 #[derive(Default, Clone, Copy)]
-struct Elf64Shdr {
+struct ElfShdr {
     name: u32,
     type_: u32,
     flags: u64,
@@ -37,9 +82,8 @@ struct Elf64Shdr {
     entsize: u64,
 }
 
-#[repr(C)]
 #[derive(Default)]
-struct Elf64Sym {
+struct ElfSym {
     name: u32,
     info: u8,
     other: u8,
@@ -48,231 +92,1466 @@ struct Elf64Sym {
     size: u64,
 }
 
-#[repr(C)]
 #[derive(Default)]
-struct Elf64Rela {
+struct ElfRel {
     offset: u64,
-    info: u64,
+    sym: u32,
+    type_: u32,
     addend: i64,
 }
 
-pub fn write_elf(obj: &ObjectFile) -> Result<Vec<u8>, String> {
-    let mut out = Vec::new();
-
-    // 1. String tables
-    let mut shstrtab = Vec::new();
-    shstrtab.push(0);
-    
-    let mut strtab = Vec::new();
-    strtab.push(0);
-
-    // 2. Build sections info
-    let mut elf_sections = Vec::new();
-    elf_sections.push(Elf64Shdr::default()); // Null section
-
-    let mut current_offset = 64; // Header size
-
-    // Section headers mapping
-    let mut section_to_shdr_idx = Vec::new();
-    
-    // Create section headers for user sections
-    for section in &obj.sections {
-        let name_idx = shstrtab.len() as u32;
-        shstrtab.extend_from_slice(section.name.as_bytes());
-        shstrtab.push(0);
+/// Encodes ELF structures in the byte order `E`, narrowing address-sized
+/// fields for ELF32.
+struct ElfWriter<E: Endianness> {
+    class: ElfClass,
+    endian: PhantomData<E>,
+}
 
-        let type_ = match section.kind {
-            SectionKind::Text | SectionKind::Data | SectionKind::ReadOnlyData => 1, // SHT_PROGBITS
-            SectionKind::Bss => 8, // SHT_NOBITS
-        };
+impl<E: Endianness> ElfWriter<E> {
+    fn new(class: ElfClass) -> Self {
+        ElfWriter { class, endian: PhantomData }
+    }
 
-        let flags = match section.kind {
-            SectionKind::Text => 6, // SHF_ALLOC | SHF_EXECINSTR
-            SectionKind::Data => 3, // SHF_WRITE | SHF_ALLOC
-            SectionKind::ReadOnlyData => 2, // SHF_ALLOC
-            SectionKind::Bss => 3, // SHF_WRITE | SHF_ALLOC
-        };
+    fn u16(&self, out: &mut impl Write, value: u16) -> io::Result<()> {
+        out.write_all(&E::u16_bytes(value))
+    }
 
-        let shdr = Elf64Shdr {
-            name: name_idx,
-            type_,
-            flags,
-            offset: current_offset,
-            size: section.data.len() as u64,
-            addralign: section.align,
-            ..Default::default()
-        };
+    fn u32(&self, out: &mut impl Write, value: u32) -> io::Result<()> {
+        out.write_all(&E::u32_bytes(value))
+    }
+
+    /// Writes an address-sized field (`Elf32_Addr`/`Elf64_Addr` and friends).
+    fn word(&self, out: &mut impl Write, value: u64) -> io::Result<()> {
+        match self.class {
+            ElfClass::Elf32 => out.write_all(&E::u32_bytes(value as u32)),
+            ElfClass::Elf64 => out.write_all(&E::u64_bytes(value)),
+        }
+    }
+
+    fn header(&self, out: &mut impl Write, h: &ElfHeader) -> io::Result<()> {
+        out.write_all(&h.ident)?;
+        self.u16(out, h.type_)?;
+        self.u16(out, h.machine)?;
+        self.u32(out, h.version)?;
+        self.word(out, h.entry)?;
+        self.word(out, h.phoff)?;
+        self.word(out, h.shoff)?;
+        self.u32(out, h.flags)?;
+        for half in [h.ehsize, h.phentsize, h.phnum, h.shentsize, h.shnum, h.shstrndx] {
+            self.u16(out, half)?;
+        }
+        Ok(())
+    }
+
+    fn shdr(&self, out: &mut impl Write, sh: &ElfShdr) -> io::Result<()> {
+        self.u32(out, sh.name)?;
+        self.u32(out, sh.type_)?;
+        self.word(out, sh.flags)?;
+        self.word(out, sh.addr)?;
+        self.word(out, sh.offset)?;
+        self.word(out, sh.size)?;
+        self.u32(out, sh.link)?;
+        self.u32(out, sh.info)?;
+        self.word(out, sh.addralign)?;
+        self.word(out, sh.entsize)
+    }
+
+    /// The `Elf32_Chdr` / `Elf64_Chdr` that starts a compressed section.
+    fn chdr(&self, out: &mut impl Write, compression: Compression, size: u64, align: u64) -> io::Result<()> {
+        self.u32(out, compression.elf_type())?;
+        if self.class == ElfClass::Elf64 {
+            self.u32(out, 0)?; // ch_reserved
+        }
+        self.word(out, size)?;
+        self.word(out, align)
+    }
+
+    fn sym(&self, out: &mut impl Write, sym: &ElfSym) -> io::Result<()> {
+        self.u32(out, sym.name)?;
+        match self.class {
+            ElfClass::Elf32 => {
+                self.word(out, sym.value)?;
+                self.word(out, sym.size)?;
+                out.write_all(&[sym.info, sym.other])?;
+                self.u16(out, sym.shndx)
+            }
+            ElfClass::Elf64 => {
+                out.write_all(&[sym.info, sym.other])?;
+                self.u16(out, sym.shndx)?;
+                self.word(out, sym.value)?;
+                self.word(out, sym.size)
+            }
+        }
+    }
+
+    fn rel(&self, out: &mut impl Write, rel: &ElfRel, rela: bool) -> io::Result<()> {
+        self.word(out, rel.offset)?;
+        match self.class {
+            ElfClass::Elf32 => self.u32(out, (rel.sym << 8) | (rel.type_ & 0xFF))?,
+            ElfClass::Elf64 => self.word(out, ((rel.sym as u64) << 32) | rel.type_ as u64)?,
+        }
+        if rela {
+            self.word(out, rel.addend as u64)?;
+        }
+        Ok(())
+    }
+}
+
+/// Section indices from here on are reserved; larger ones are escaped.
+const SHN_LORESERVE: usize = 0xFF00;
+const SHN_ABS: u16 = 0xFFF1;
+/// The real index is in `.symtab_shndx`, or in section 0 for `e_shstrndx`.
+const SHN_XINDEX: u16 = 0xFFFF;
+
+/// `st_shndx` for a symbol defined in section header `index`.
+fn symbol_shndx(index: usize) -> u16 {
+    if index >= SHN_LORESERVE { SHN_XINDEX } else { index as u16 }
+}
+
+/// `.symtab_shndx` entry for a symbol defined in section header `index`; only
+/// symbols whose `st_shndx` is `SHN_XINDEX` have one, the others get 0.
+fn symbol_xindex(index: usize) -> u32 {
+    if symbol_shndx(index) == SHN_XINDEX { index as u32 } else { 0 }
+}
+
+const STT_NOTYPE: u8 = 0;
+const STT_OBJECT: u8 = 1;
+const STT_FUNC: u8 = 2;
+const STT_SECTION: u8 = 3;
+const STT_FILE: u8 = 4;
+const STT_TLS: u8 = 6;
+const STV_DEFAULT: u8 = 0;
+const STV_HIDDEN: u8 = 2;
+
+const EM_386: u16 = 3;
+const EM_MIPS: u16 = 8;
+const EM_PPC64: u16 = 21;
+const EM_X86_64: u16 = 62;
+const EM_AARCH64: u16 = 183;
+const EM_RISCV: u16 = 243;
+
+/// RVC and the double-float ABI, as the default GNU toolchain builds rv64gc.
+const EF_RISCV_RVC_DOUBLE: u32 = 0x5;
+
+/// o32 ABI, MIPS32 instruction set.
+const EF_MIPS_O32_MIPS32: u32 = 0x5000_1000;
+
+/// `R_X86_64_*` relocation types, as listed in the x86-64 psABI.
+const X86_64_RELOC_TYPES: &[(RelocKind, u32)] = &[
+    (RelocKind::NoReloc, 0),
+    (RelocKind::Absolute64, 1),
+    (RelocKind::Relative32, 2),
+    (RelocKind::GOT32, 3),
+    (RelocKind::PLT32, 4),
+    (RelocKind::Copy, 5),
+    (RelocKind::GlobDat, 6),
+    (RelocKind::JumpSlot, 7),
+    (RelocKind::BaseRelative, 8),
+    (RelocKind::GOTPCREL, 9),
+    (RelocKind::Absolute32, 10),
+    (RelocKind::Absolute32S, 11),
+    (RelocKind::Absolute16, 12),
+    (RelocKind::Relative16, 13),
+    (RelocKind::Absolute8, 14),
+    (RelocKind::Relative8, 15),
+    (RelocKind::DTPMOD64, 16),
+    (RelocKind::DTPOFF64, 17),
+    (RelocKind::TPOFF64, 18),
+    (RelocKind::TLSGD, 19),
+    (RelocKind::TLSLD, 20),
+    (RelocKind::DTPOFF32, 21),
+    (RelocKind::GOTTPOFF, 22),
+    (RelocKind::TPOFF32, 23),
+    (RelocKind::Relative64, 24),
+    (RelocKind::GOTOFF64, 25),
+    (RelocKind::GOTPC32, 26),
+    (RelocKind::GOT64, 27),
+    (RelocKind::GOTPCREL64, 28),
+    (RelocKind::GOTPC64, 29),
+    (RelocKind::GOTPLT64, 30),
+    (RelocKind::PLTOFF64, 31),
+    (RelocKind::Size32, 32),
+    (RelocKind::Size64, 33),
+    (RelocKind::GOTPC32_TLSDESC, 34),
+    (RelocKind::TLSDESC_CALL, 35),
+    (RelocKind::TLSDESC, 36),
+    (RelocKind::IRelative, 37),
+    (RelocKind::RELATIVE64, 38),
+    (RelocKind::GOTPCRELX, 41),
+    (RelocKind::REX_GOTPCRELX, 42),
+];
+
+/// `R_386_*` relocation types from the i386 psABI (16- and 8-bit forms are GNU extensions).
+const I386_RELOC_TYPES: &[(RelocKind, u32)] = &[
+    (RelocKind::NoReloc, 0),
+    (RelocKind::Absolute32, 1),
+    (RelocKind::Relative32, 2),
+    (RelocKind::GOT32, 3),
+    (RelocKind::PLT32, 4),
+    (RelocKind::Copy, 5),
+    (RelocKind::GlobDat, 6),
+    (RelocKind::JumpSlot, 7),
+    (RelocKind::BaseRelative, 8),
+    (RelocKind::GOTOFF32, 9),
+    (RelocKind::GOTPC32, 10),
+    (RelocKind::TLS_TPOFF, 14),
+    (RelocKind::TLS_IE, 15),
+    (RelocKind::TLS_GOTIE, 16),
+    (RelocKind::TPOFF32, 17),
+    (RelocKind::TLSGD, 18),
+    (RelocKind::TLSLD, 19),
+    (RelocKind::Absolute16, 20),
+    (RelocKind::Relative16, 21),
+    (RelocKind::Absolute8, 22),
+    (RelocKind::Relative8, 23),
+    (RelocKind::DTPOFF32, 32),
+    (RelocKind::TLS_IE_32, 33),
+    (RelocKind::TLS_LE_32, 34),
+    (RelocKind::TLS_DTPMOD32, 35),
+    (RelocKind::TLS_DTPOFF32, 36),
+    (RelocKind::TLS_TPOFF32, 37),
+    (RelocKind::Size32, 38),
+    (RelocKind::TLS_GOTDESC, 39),
+    (RelocKind::TLSDESC_CALL, 40),
+    (RelocKind::TLSDESC, 41),
+    (RelocKind::IRelative, 42),
+    (RelocKind::GOT32X, 43),
+];
+
+/// `R_AARCH64_*` relocation types from the AArch64 ELF ABI (LP64). `PAGEOFF12`
+/// has no single ELF equivalent (the type depends on the access size) and is not listed.
+const AARCH64_RELOC_TYPES: &[(RelocKind, u32)] = &[
+    (RelocKind::NoReloc, 0),
+    (RelocKind::Absolute64, 257),
+    (RelocKind::Absolute32, 258),
+    (RelocKind::Absolute16, 259),
+    (RelocKind::Relative64, 260),
+    (RelocKind::Relative32, 261),
+    (RelocKind::Relative16, 262),
+    (RelocKind::MOVW_UABS_G0, 263),
+    (RelocKind::MOVW_UABS_G0_NC, 264),
+    (RelocKind::MOVW_UABS_G1, 265),
+    (RelocKind::MOVW_UABS_G1_NC, 266),
+    (RelocKind::MOVW_UABS_G2, 267),
+    (RelocKind::MOVW_UABS_G2_NC, 268),
+    (RelocKind::MOVW_UABS_G3, 269),
+    (RelocKind::MOVW_SABS_G0, 270),
+    (RelocKind::MOVW_SABS_G1, 271),
+    (RelocKind::MOVW_SABS_G2, 272),
+    (RelocKind::LD_PREL_LO19, 273),
+    (RelocKind::ADR_PREL_LO21, 274),
+    (RelocKind::ADR_PREL_PG_HI21, 275),
+    (RelocKind::ADR_PREL_PG_HI21_NC, 276),
+    (RelocKind::ADD_ABS_LO12_NC, 277),
+    (RelocKind::LDST8_ABS_LO12_NC, 278),
+    (RelocKind::TSTBR14, 279),
+    (RelocKind::CONDBR19, 280),
+    (RelocKind::JUMP26, 282),
+    (RelocKind::CALL26, 283),
+    (RelocKind::LDST16_ABS_LO12_NC, 284),
+    (RelocKind::LDST32_ABS_LO12_NC, 285),
+    (RelocKind::LDST64_ABS_LO12_NC, 286),
+    (RelocKind::MOVW_PREL_G0, 287),
+    (RelocKind::MOVW_PREL_G0_NC, 288),
+    (RelocKind::MOVW_PREL_G1, 289),
+    (RelocKind::MOVW_PREL_G1_NC, 290),
+    (RelocKind::MOVW_PREL_G2, 291),
+    (RelocKind::MOVW_PREL_G2_NC, 292),
+    (RelocKind::MOVW_PREL_G3, 293),
+    (RelocKind::LDST128_ABS_LO12_NC, 299),
+    (RelocKind::MOVW_GOTOFF_G0, 300),
+    (RelocKind::MOVW_GOTOFF_G0_NC, 301),
+    (RelocKind::MOVW_GOTOFF_G1, 302),
+    (RelocKind::MOVW_GOTOFF_G1_NC, 303),
+    (RelocKind::MOVW_GOTOFF_G2, 304),
+    (RelocKind::MOVW_GOTOFF_G2_NC, 305),
+    (RelocKind::MOVW_GOTOFF_G3, 306),
+    (RelocKind::GOTREL64, 307),
+    (RelocKind::GOTREL32, 308),
+    (RelocKind::GOT_LD_PREL19, 309),
+    (RelocKind::LD64_GOTOFF_LO15, 310),
+    (RelocKind::ADR_GOT_PAGE, 311),
+    (RelocKind::LD64_GOT_LO12_NC, 312),
+    (RelocKind::LD64_GOTPAGE_LO15, 313),
+    (RelocKind::TLSGD_ADR_PREL21, 512),
+    (RelocKind::TLSGD_ADR_PAGE21, 513),
+    (RelocKind::TLSGD_ADD_LO12_NC, 514),
+    (RelocKind::TLSGD_MOVW_G1, 515),
+    (RelocKind::TLSGD_MOVW_G0_NC, 516),
+    (RelocKind::TLSLD_ADR_PREL21, 517),
+    (RelocKind::TLSLD_ADR_PAGE21, 518),
+    (RelocKind::TLSLD_ADD_LO12_NC, 519),
+    (RelocKind::TLSLD_MOVW_G1, 520),
+    (RelocKind::TLSLD_MOVW_G0_NC, 521),
+    (RelocKind::TLSLD_LD_PREL19, 522),
+    (RelocKind::TLSLD_MOVW_DTPREL_G2, 523),
+    (RelocKind::TLSLD_MOVW_DTPREL_G1, 524),
+    (RelocKind::TLSLD_MOVW_DTPREL_G1_NC, 525),
+    (RelocKind::TLSLD_MOVW_DTPREL_G0, 526),
+    (RelocKind::TLSLD_MOVW_DTPREL_G0_NC, 527),
+    (RelocKind::TLSLD_ADD_DTPREL_HI12, 528),
+    (RelocKind::TLSLD_ADD_DTPREL_LO12, 529),
+    (RelocKind::TLSLD_ADD_DTPREL_LO12_NC, 530),
+    (RelocKind::TLSLD_LDST8_DTPREL_LO12, 531),
+    (RelocKind::TLSLD_LDST8_DTPREL_LO12_NC, 532),
+    (RelocKind::TLSLD_LDST16_DTPREL_LO12, 533),
+    (RelocKind::TLSLD_LDST16_DTPREL_LO12_NC, 534),
+    (RelocKind::TLSLD_LDST32_DTPREL_LO12, 535),
+    (RelocKind::TLSLD_LDST32_DTPREL_LO12_NC, 536),
+    (RelocKind::TLSLD_LDST64_DTPREL_LO12, 537),
+    (RelocKind::TLSLD_LDST64_DTPREL_LO12_NC, 538),
+    (RelocKind::TLSIE_MOVW_GOTTPREL_G1, 539),
+    (RelocKind::TLSIE_MOVW_GOTTPREL_G0_NC, 540),
+    (RelocKind::TLSIE_ADR_GOTTPREL_PAGE21, 541),
+    (RelocKind::TLSIE_LD64_GOTTPREL_LO12_NC, 542),
+    (RelocKind::TLSIE_LD_GOTTPREL_PREL19, 543),
+    (RelocKind::TLSLE_MOVW_TPREL_G2, 544),
+    (RelocKind::TLSLE_MOVW_TPREL_G1, 545),
+    (RelocKind::TLSLE_MOVW_TPREL_G1_NC, 546),
+    (RelocKind::TLSLE_MOVW_TPREL_G0, 547),
+    (RelocKind::TLSLE_MOVW_TPREL_G0_NC, 548),
+    (RelocKind::TLSLE_ADD_TPREL_HI12, 549),
+    (RelocKind::TLSLE_ADD_TPREL_LO12, 550),
+    (RelocKind::TLSLE_ADD_TPREL_LO12_NC, 551),
+    (RelocKind::TLSLE_LDST8_TPREL_LO12, 552),
+    (RelocKind::TLSLE_LDST8_TPREL_LO12_NC, 553),
+    (RelocKind::TLSLE_LDST16_TPREL_LO12, 554),
+    (RelocKind::TLSLE_LDST16_TPREL_LO12_NC, 555),
+    (RelocKind::TLSLE_LDST32_TPREL_LO12, 556),
+    (RelocKind::TLSLE_LDST32_TPREL_LO12_NC, 557),
+    (RelocKind::TLSLE_LDST64_TPREL_LO12, 558),
+    (RelocKind::TLSLE_LDST64_TPREL_LO12_NC, 559),
+    (RelocKind::TLSDESC_LD_PREL19, 560),
+    (RelocKind::TLSDESC_ADR_PREL21, 561),
+    (RelocKind::TLSDESC_ADR_PAGE21, 562),
+    (RelocKind::TLSDESC_LD64_LO12, 563),
+    (RelocKind::TLSDESC_ADD_LO12, 564),
+    (RelocKind::TLSDESC_OFF_G1, 565),
+    (RelocKind::TLSDESC_OFF_G0_NC, 566),
+    (RelocKind::TLSDESC_LDR, 567),
+    (RelocKind::TLSDESC_ADD, 568),
+    (RelocKind::TLSDESC_CALL, 569),
+    (RelocKind::TLSLE_LDST128_TPREL_LO12, 570),
+    (RelocKind::TLSLE_LDST128_TPREL_LO12_NC, 571),
+    (RelocKind::TLSLD_LDST128_DTPREL_LO12, 572),
+    (RelocKind::TLSLD_LDST128_DTPREL_LO12_NC, 573),
+    (RelocKind::Copy, 1024),
+    (RelocKind::GlobDat, 1025),
+    (RelocKind::JumpSlot, 1026),
+    (RelocKind::BaseRelative, 1027),
+    (RelocKind::DTPMOD64, 1028),
+    (RelocKind::DTPOFF64, 1029),
+    (RelocKind::TPOFF64, 1030),
+    (RelocKind::TLSDESC, 1031),
+    (RelocKind::IRelative, 1032),
+];
+
+/// `R_RISCV_*` relocation types from the RISC-V ELF psABI.
+const RISCV_RELOC_TYPES: &[(RelocKind, u32)] = &[
+    (RelocKind::NoReloc, 0),
+    (RelocKind::Absolute32, 1),
+    (RelocKind::Absolute64, 2),
+    (RelocKind::BaseRelative, 3),
+    (RelocKind::Copy, 4),
+    (RelocKind::JumpSlot, 5),
+    (RelocKind::TLS_DTPMOD32, 6),
+    (RelocKind::DTPMOD64, 7),
+    (RelocKind::TLS_DTPOFF32, 8),
+    (RelocKind::DTPOFF64, 9),
+    (RelocKind::TLS_TPOFF32, 10),
+    (RelocKind::TPOFF64, 11),
+    (RelocKind::TLSDESC, 12),
+    (RelocKind::BRANCH, 16),
+    (RelocKind::JAL, 17),
+    (RelocKind::CALL, 18),
+    (RelocKind::CALL_PLT, 19),
+    (RelocKind::GOT_HI20, 20),
+    (RelocKind::TLS_GOT_HI20, 21),
+    (RelocKind::TLS_GD_HI20, 22),
+    (RelocKind::PCREL_HI20, 23),
+    (RelocKind::PCREL_LO12_I, 24),
+    (RelocKind::PCREL_LO12_S, 25),
+    (RelocKind::HI20, 26),
+    (RelocKind::LO12_I, 27),
+    (RelocKind::LO12_S, 28),
+    (RelocKind::TPREL_HI20, 29),
+    (RelocKind::TPREL_LO12_I, 30),
+    (RelocKind::TPREL_LO12_S, 31),
+    (RelocKind::TPREL_ADD, 32),
+    (RelocKind::ADD8, 33),
+    (RelocKind::ADD16, 34),
+    (RelocKind::ADD32, 35),
+    (RelocKind::ADD64, 36),
+    (RelocKind::SUB8, 37),
+    (RelocKind::SUB16, 38),
+    (RelocKind::SUB32, 39),
+    (RelocKind::SUB64, 40),
+    (RelocKind::GOT32_PCREL, 41),
+    (RelocKind::ALIGN, 43),
+    (RelocKind::RVC_BRANCH, 44),
+    (RelocKind::RVC_JUMP, 45),
+    (RelocKind::RELAX, 51),
+    (RelocKind::SUB6, 52),
+    (RelocKind::SET6, 53),
+    (RelocKind::SET8, 54),
+    (RelocKind::SET16, 55),
+    (RelocKind::SET32, 56),
+    (RelocKind::Relative32, 57),
+    (RelocKind::IRelative, 58),
+    (RelocKind::PLT32, 59),
+    (RelocKind::SET_ULEB128, 60),
+    (RelocKind::SUB_ULEB128, 61),
+    (RelocKind::TLSDESC_HI20, 62),
+    (RelocKind::TLSDESC_LOAD_LO12, 63),
+    (RelocKind::TLSDESC_ADD_LO12, 64),
+    (RelocKind::TLSDESC_CALL, 65),
+];
+
+const PPC64_RELOC_TYPES: &[(RelocKind, u32)] = &[
+    (RelocKind::NoReloc, 0),
+    (RelocKind::Absolute32, 1),
+    (RelocKind::Absolute16, 3),
+    (RelocKind::ADDR16_LO, 4),
+    (RelocKind::ADDR16_HI, 5),
+    (RelocKind::ADDR16_HA, 6),
+    (RelocKind::REL24, 10),
+    (RelocKind::REL14, 11),
+    (RelocKind::Copy, 19),
+    (RelocKind::GlobDat, 20),
+    (RelocKind::JumpSlot, 21),
+    (RelocKind::BaseRelative, 22),
+    (RelocKind::Relative32, 26),
+    (RelocKind::Absolute64, 38),
+    (RelocKind::Relative64, 44),
+    (RelocKind::TOC16, 47),
+    (RelocKind::TOC16_LO, 48),
+    (RelocKind::TOC16_HI, 49),
+    (RelocKind::TOC16_HA, 50),
+    (RelocKind::TOC, 51),
+    (RelocKind::ADDR16_DS, 56),
+    (RelocKind::ADDR16_LO_DS, 57),
+    (RelocKind::TOC16_DS, 63),
+    (RelocKind::TOC16_LO_DS, 64),
+    (RelocKind::IRelative, 248),
+    (RelocKind::Relative16, 249),
+    (RelocKind::REL16_LO, 250),
+    (RelocKind::REL16_HI, 251),
+    (RelocKind::REL16_HA, 252),
+];
+
+const MIPS_RELOC_TYPES: &[(RelocKind, u32)] = &[
+    (RelocKind::NoReloc, 0),
+    (RelocKind::Absolute16, 1),
+    (RelocKind::Absolute32, 2),
+    (RelocKind::REL32, 3),
+    (RelocKind::MIPS_26, 4),
+    (RelocKind::HI16, 5),
+    (RelocKind::LO16, 6),
+    (RelocKind::GPREL16, 7),
+    (RelocKind::GOT16, 9),
+    (RelocKind::CALL16, 11),
+    (RelocKind::GPREL32, 12),
+    (RelocKind::Absolute64, 18),
+    (RelocKind::Copy, 126),
+    (RelocKind::JumpSlot, 127),
+    (RelocKind::Relative32, 248),
+];
+
+fn elf_machine(arch: Architecture) -> u16 {
+    match arch {
+        Architecture::X86_64 => EM_X86_64,
+        Architecture::I386 => EM_386,
+        Architecture::AArch64 => EM_AARCH64,
+        Architecture::RiscV64 => EM_RISCV,
+        Architecture::PowerPC64 => EM_PPC64,
+        Architecture::Mips => EM_MIPS,
+    }
+}
+
+fn elf_flags(arch: Architecture) -> u32 {
+    match arch {
+        Architecture::RiscV64 => EF_RISCV_RVC_DOUBLE,
+        Architecture::Mips => EF_MIPS_O32_MIPS32,
+        Architecture::X86_64 | Architecture::I386 | Architecture::AArch64 | Architecture::PowerPC64 => 0,
+    }
+}
 
-        if section.kind != SectionKind::Bss {
-            current_offset += section.data.len() as u64;
-        }
-        
-        section_to_shdr_idx.push(elf_sections.len());
-        elf_sections.push(shdr);
-    }
-
-    // Symbol table section
-    let symtab_shdr_idx = elf_sections.len();
-    let symtab_name = shstrtab.len() as u32;
-    shstrtab.extend_from_slice(b".symtab\0");
-    elf_sections.push(Elf64Shdr {
-        name: symtab_name,
-        type_: 2, // SHT_SYMTAB
-        link: symtab_shdr_idx as u32 + 1, // Next is strtab
-        entsize: 24,
-        addralign: 8,
-        ..Default::default()
-    });
-
-    // String table section
-    let strtab_name = shstrtab.len() as u32;
-    shstrtab.extend_from_slice(b".strtab\0");
-    elf_sections.push(Elf64Shdr {
-        name: strtab_name,
-        type_: 3, // SHT_STRTAB
-        addralign: 1,
-        ..Default::default()
-    });
-
-    // Relocation sections
-    let mut rela_sections = Vec::new();
-    for (sec_idx, _) in obj.sections.iter().enumerate() {
-        let has_relocs = obj.relocations.iter().any(|r| r.section_index == sec_idx);
-        if !has_relocs { continue; }
-
-        let name_idx = shstrtab.len() as u32;
-        let rela_name = format!(".rela{}", obj.sections[sec_idx].name);
-        shstrtab.extend_from_slice(rela_name.as_bytes());
+fn reloc_types(arch: Architecture) -> &'static [(RelocKind, u32)] {
+    match arch {
+        Architecture::X86_64 => X86_64_RELOC_TYPES,
+        Architecture::I386 => I386_RELOC_TYPES,
+        Architecture::AArch64 => AARCH64_RELOC_TYPES,
+        Architecture::RiscV64 => RISCV_RELOC_TYPES,
+        Architecture::PowerPC64 => PPC64_RELOC_TYPES,
+        Architecture::Mips => MIPS_RELOC_TYPES,
+    }
+}
+
+/// The i386 and MIPS o32 psABIs use `SHT_REL` with the addend stored in the
+/// relocated field.
+fn uses_rela(arch: Architecture) -> bool {
+    !matches!(arch, Architecture::I386 | Architecture::Mips)
+}
+
+fn elf_reloc_type(arch: Architecture, kind: RelocKind) -> Result<u32, String> {
+    match kind {
+        RelocKind::Raw(raw) => Ok(raw),
+        kind => reloc_types(arch)
+            .iter()
+            .find(|(k, _)| *k == kind)
+            .map(|(_, t)| *t)
+            .ok_or_else(|| format!("{:?} relocations are not supported for {:?}", kind, arch)),
+    }
+}
+
+fn reloc_kind_from_elf(arch: Architecture, type_: u32) -> RelocKind {
+    reloc_types(arch).iter().find(|(_, t)| *t == type_).map(|(k, _)| *k).unwrap_or(RelocKind::Raw(type_))
+}
+
+/// Width of the field an `SHT_REL` relocation stores its addend in.
+fn implicit_addend_width(arch: Architecture, kind: RelocKind) -> Option<usize> {
+    kind.field_size(arch).filter(|&width| width <= 8)
+}
+
+/// Bits of an instruction that hold an `SHT_REL` addend, as a mask and the
+/// number of low bits the instruction drops. Other fields hold the whole addend.
+fn implicit_addend_bits(arch: Architecture, kind: RelocKind) -> Option<(u64, u32)> {
+    match (arch, kind) {
+        (Architecture::Mips, RelocKind::MIPS_26) => Some((0x03FF_FFFF, 2)),
+        (Architecture::Mips, RelocKind::HI16 | RelocKind::LO16 | RelocKind::GPREL16 | RelocKind::GOT16 | RelocKind::CALL16) => Some((0xFFFF, 0)),
+        _ => None,
+    }
+}
+
+/// Stores `addend` in the relocated field. `HI16` gets the high half rounded
+/// for the sign of the `LO16` that follows it.
+fn put_implicit_addend(endian: Endian, arch: Architecture, kind: RelocKind, field: &mut [u8], addend: i64) {
+    let value = match implicit_addend_bits(arch, kind) {
+        None => addend as u64,
+        Some((mask, _)) if kind == RelocKind::HI16 => endian.decode(field) & !mask | ((addend + 0x8000) >> 16) as u64 & mask,
+        Some((mask, shift)) => endian.decode(field) & !mask | (addend >> shift) as u64 & mask,
+    };
+    field.copy_from_slice(&endian.encode(value, field.len()));
+}
+
+/// Reads the addend stored in a relocated field. For `HI16` this is only the
+/// high half; the caller adds the low half from the paired `LO16`.
+fn implicit_addend(endian: Endian, arch: Architecture, kind: RelocKind, field: &[u8]) -> i64 {
+    let value = endian.decode(field);
+    match implicit_addend_bits(arch, kind) {
+        None => {
+            // Sign-extend from the field width
+            let shift = 64 - 8 * field.len() as u32;
+            ((value << shift) as i64) >> shift
+        }
+        Some((mask, shift)) if kind == RelocKind::MIPS_26 => ((value & mask) << shift) as i64,
+        Some((mask, _)) if kind == RelocKind::HI16 => ((value & mask) as i16 as i64) << 16,
+        Some((mask, _)) => (value & mask) as i16 as i64,
+    }
+}
+
+/// Whether a relocation against a local symbol may be rewritten against its section.
+fn is_adjustable(kind: RelocKind) -> bool {
+    matches!(
+        kind,
+        RelocKind::Absolute64 | RelocKind::Absolute32 | RelocKind::Absolute32S | RelocKind::Absolute16 | RelocKind::Relative32 | RelocKind::Relative16
+    )
+}
+
+/// Writes an ELF relocatable object.
+///
+/// Every section gets an `STT_SECTION` symbol. Absolute and pc-relative
+/// relocations against local symbols are rewritten against the section symbol
+/// with the symbol's offset folded into the addend.
+///
+/// Section groups are written first, since a `.group` header has to precede
+/// the headers of its members. Relocation sections join their target's group.
+///
+/// The output is streamed to `out` in the byte order of `obj.endian`. The
+/// symbol table is written from `symbols` instead of `obj.symbols`.
+pub fn write_elf<W: Write>(obj: &ObjectFile, symbols: &[ObjectSymbol], out: &mut W) -> Result<(), WriteError> {
+    let class = match obj.format {
+        ObjectFormat::ELF32 => ElfClass::Elf32,
+        ObjectFormat::ELF64 => ElfClass::Elf64,
+        ObjectFormat::COFF | ObjectFormat::MachO64 => return Err(WriteError::Unsupported("write_elf called for a non-ELF object".into())),
+    };
+    if obj.arch.word_size() as u64 != class.word_align() {
+        let expected = if obj.arch.word_size() == 4 { "ELF32" } else { "ELF64" };
+        return Err(WriteError::Unsupported(format!("{:?} objects must be written as {}", obj.arch, expected)));
+    }
+    match obj.endian {
+        Endian::Little => ElfWriter::<LittleEndian>::new(class).write(obj, symbols, out),
+        Endian::Big => ElfWriter::<BigEndian>::new(class).write(obj, symbols, out),
+    }
+}
+
+impl<E: Endianness> ElfWriter<E> {
+    fn write(&self, obj: &ObjectFile, symbols: &[ObjectSymbol], out: &mut impl Write) -> Result<(), WriteError> {
+        let class = self.class;
+        if let Some(r) = obj.relocations.iter().find(|r| r.subtrahend.is_some()) {
+            return Err(format!("ELF cannot express {} - {}", r.symbol, r.subtrahend.as_deref().unwrap_or_default()).into());
+        }
+        if obj.gnu_properties.x86_features.is_some() && !matches!(obj.arch, Architecture::X86_64 | Architecture::I386) {
+            return Err(format!("{:?} objects cannot have x86 feature properties", obj.arch).into());
+        }
+        let rela = uses_rela(obj.arch);
+        let rel_prefix = if rela { ".rela" } else { ".rel" };
+
+        // Symbol table order: null, a section symbol per section, other locals, then
+        // globals. sh_info of .symtab is the index of the first global.
+        let is_generated = |s: &ObjectSymbol| s.kind == SymbolKind::Section && s.section_index.is_some();
+        let locals = symbols.iter().enumerate().filter(|(_, s)| s.binding == SymbolBinding::Local && !is_generated(s));
+        let globals = symbols.iter().enumerate().filter(|(_, s)| s.binding != SymbolBinding::Local);
+        let order: Vec<usize> = locals.chain(globals).map(|(i, _)| i).collect();
+        let first_symbol = 1 + obj.sections.len();
+        let first_global = first_symbol + order.iter().filter(|&&i| symbols[i].binding == SymbolBinding::Local).count();
+
+        let mut symbol_index = HashMap::new();
+        for (i, section) in obj.sections.iter().enumerate() {
+            symbol_index.insert(section.name.as_str(), (1 + i) as u32);
+        }
+        for s in symbols.iter().filter(|s| is_generated(s)) {
+            symbol_index.insert(s.name.as_str(), 1 + s.section_index.unwrap_or_default() as u32);
+        }
+        for (n, &i) in order.iter().enumerate() {
+            symbol_index.insert(symbols[i].name.as_str(), (first_symbol + n) as u32);
+        }
+        let symbols_by_name: HashMap<&str, &ObjectSymbol> = symbols.iter().map(|s| (s.name.as_str(), s)).collect();
+
+        let mut relocs = Vec::new();
+        for r in &obj.relocations {
+            let mut sym = *symbol_index.get(r.symbol.as_str()).ok_or_else(|| format!("relocation against unknown symbol {}", r.symbol))?;
+            let mut addend = r.addend;
+            // References to local labels go through the section symbol, like gas does,
+            // except where the linker needs the symbol itself (GOT, PLT and TLS) and
+            // in mergeable sections, whose entries the linker may merge or move
+            if let Some(target) = symbols_by_name.get(r.symbol.as_str()) {
+                if let Some(index) = target.section_index.filter(|_| target.binding == SymbolBinding::Local && is_adjustable(r.kind)) {
+                    let section = &obj.sections[index];
+                    if !section.kind.is_tls() && !section.flags.contains(SectionFlags::MERGE) && target.kind != SymbolKind::Tls {
+                        sym = 1 + index as u32;
+                        addend += target.value as i64;
+                    }
+                }
+            }
+            relocs.push((r, sym, addend));
+        }
+
+        // Section contents; SHT_REL targets get their addends written in place
+        let mut contents: Vec<Cow<[u8]>> = obj.sections.iter().map(|s| Cow::Borrowed(s.data.as_slice())).collect();
+        if !rela {
+            for &(r, _, addend) in &relocs {
+                let width = match implicit_addend_width(obj.arch, r.kind) {
+                    Some(width) => width,
+                    None if addend == 0 => continue,
+                    None => return Err(format!("{:?} relocation cannot carry an implicit addend", r.kind).into()),
+                };
+                let field = contents[r.section_index]
+                    .to_mut()
+                    .get_mut(r.offset..r.offset + width)
+                    .ok_or_else(|| format!("relocation at {:#x} is outside section {}", r.offset, obj.sections[r.section_index].name))?;
+                put_implicit_addend(E::ENDIAN, obj.arch, r.kind, field, addend);
+            }
+        }
+        // Compressed sections, once their addends are in place
+        for (section, data) in obj.sections.iter().zip(contents.iter_mut()) {
+            let Some(compression) = section.compression else { continue };
+            if section.flags.contains(SectionFlags::ALLOC) || section.is_nobits() {
+                return Err(format!("section {} is allocated and cannot be compressed", section.name).into());
+            }
+            let mut compressed = Vec::new();
+            self.chdr(&mut compressed, compression, data.len() as u64, section.align)?;
+            compressed.extend_from_slice(&compression.compress(data));
+            *data = Cow::Owned(compressed);
+        }
+
+        // 1. String tables
+        let mut shstrtab = Vec::new();
         shstrtab.push(0);
 
-        rela_sections.push((sec_idx, elf_sections.len()));
-        elf_sections.push(Elf64Shdr {
-            name: name_idx,
-            type_: 4, // SHT_RELA
-            flags: 0x40, // SHF_INFO_LINK
-            link: symtab_shdr_idx as u32,
-            info: section_to_shdr_idx[sec_idx] as u32,
-            addralign: 8,
-            entsize: 24,
+        let mut strtab = Vec::new();
+        strtab.push(0);
+
+        // 2. Build sections info
+        let mut elf_sections = Vec::new();
+        elf_sections.push(ElfShdr::default()); // Null section
+
+        let mut current_offset = class.ehsize() as u64; // Header size
+
+        let mut has_relocs = vec![false; obj.sections.len()];
+        for r in &obj.relocations {
+            if let Some(has) = has_relocs.get_mut(r.section_index) {
+                *has = true;
+            }
+        }
+        let mut section_group = vec![None; obj.sections.len()];
+        for (g, group) in obj.groups.iter().enumerate() {
+            for &member in &group.sections {
+                match section_group.get_mut(member) {
+                    Some(slot @ None) => *slot = Some(g),
+                    Some(Some(_)) => return Err(format!("section {} is in more than one group", obj.sections[member].name).into()),
+                    None => return Err(format!("group {} refers to missing section {}", group.signature, member).into()),
+                }
+            }
+        }
+
+        // Section groups; contents are filled in once the relocation sections are numbered
+        let mut group_shdrs = Vec::new();
+        let group_name = shstrtab.len() as u32;
+        if !obj.groups.is_empty() {
+            shstrtab.extend_from_slice(b".group\0");
+        }
+        for group in &obj.groups {
+            let signature = *symbol_index.get(group.signature.as_str()).ok_or_else(|| format!("group signature {} is not a symbol", group.signature))?;
+            let words = 1 + group.sections.len() + group.sections.iter().filter(|&&i| has_relocs[i]).count();
+            group_shdrs.push(elf_sections.len());
+            elf_sections.push(ElfShdr {
+                name: group_name,
+                type_: SHT_GROUP,
+                info: signature,
+                offset: current_offset,
+                size: 4 * words as u64,
+                addralign: 4,
+                entsize: 4,
+                ..Default::default()
+            });
+            current_offset += 4 * words as u64;
+        }
+
+        // Section headers mapping
+        let mut section_to_shdr_idx = Vec::new();
+
+        // Create section headers for user sections; they are numbered consecutively
+        let first_section_shdr = elf_sections.len();
+        for (sec_idx, section) in obj.sections.iter().enumerate() {
+            let name_idx = shstrtab.len() as u32;
+            shstrtab.extend_from_slice(section.name.as_bytes());
+            shstrtab.push(0);
+
+            let mut flags = section.flags;
+            if section_group[sec_idx].is_some() {
+                flags |= SectionFlags::GROUP;
+            }
+            // The header's alignment applies to the Chdr; the contents' own is in ch_addralign
+            let addralign = if section.compression.is_some() {
+                flags |= SectionFlags::COMPRESSED;
+                class.word_align()
+            } else {
+                section.align
+            };
+            let link = match section.link {
+                Some(i) if i < obj.sections.len() => (first_section_shdr + i) as u32,
+                Some(i) => return Err(format!("section {} links to missing section {}", section.name, i).into()),
+                None => 0,
+            };
+            let info = match section.info as usize {
+                i if !section.flags.contains(SectionFlags::INFO_LINK) => i as u32,
+                i if i < obj.sections.len() => (first_section_shdr + i) as u32,
+                i => return Err(format!("section {} info refers to missing section {}", section.name, i).into()),
+            };
+
+            // Notes are read in place, so their file offset has to be aligned as well
+            if section.section_type.0 == SHT_NOTE {
+                current_offset = current_offset.next_multiple_of(addralign.max(1));
+            }
+
+            let shdr = ElfShdr {
+                name: name_idx,
+                type_: section.section_type.0,
+                flags: flags.0,
+                offset: current_offset,
+                size: if section.is_nobits() { section.data.len() } else { contents[sec_idx].len() } as u64,
+                link,
+                info,
+                addralign,
+                entsize: section.entsize,
+                ..Default::default()
+            };
+
+            if !section.is_nobits() {
+                current_offset += contents[sec_idx].len() as u64;
+            }
+
+            section_to_shdr_idx.push(elf_sections.len());
+            elf_sections.push(shdr);
+        }
+
+        // Program properties; like gas, no section symbol refers to the note
+        let properties = (!obj.gnu_properties.is_empty()).then(|| {
+            let note = obj.gnu_properties.encode(E::ENDIAN, class.word_align() as usize);
+            let name = shstrtab.len() as u32;
+            shstrtab.extend_from_slice(b".note.gnu.property\0");
+            current_offset = current_offset.next_multiple_of(class.word_align());
+            elf_sections.push(ElfShdr {
+                name,
+                type_: SHT_NOTE,
+                flags: SectionFlags::ALLOC.0,
+                offset: current_offset,
+                size: note.len() as u64,
+                addralign: class.word_align(),
+                ..Default::default()
+            });
+            current_offset += note.len() as u64;
+            (elf_sections.len() - 1, note)
+        });
+
+        // Symbol table section
+        let symtab_shdr_idx = elf_sections.len();
+        let symtab_name = shstrtab.len() as u32;
+        shstrtab.extend_from_slice(b".symtab\0");
+        elf_sections.push(ElfShdr {
+            name: symtab_name,
+            type_: 2, // SHT_SYMTAB
+            link: symtab_shdr_idx as u32 + 1, // Next is strtab
+            info: first_global as u32,
+            entsize: class.symsize(),
+            addralign: class.word_align(),
             ..Default::default()
         });
-    }
 
-    // Section string table
-    let shstrtab_name = shstrtab.len() as u32;
-    shstrtab.extend_from_slice(b".shstrtab\0");
-    let shstrtab_idx = elf_sections.len();
-    elf_sections.push(Elf64Shdr {
-        name: shstrtab_name,
-        type_: 3, // SHT_STRTAB
-        addralign: 1,
-        ..Default::default()
-    });
-
-    // 3. Build Symbols
-    let mut elf_syms = Vec::new();
-    elf_syms.push(Elf64Sym::default()); // Null
-
-    for s in &obj.symbols {
-        let name_idx = strtab.len() as u32;
-        strtab.extend_from_slice(s.name.as_bytes());
-        strtab.push(0);
+        // String table section
+        let strtab_name = shstrtab.len() as u32;
+        shstrtab.extend_from_slice(b".strtab\0");
+        elf_sections.push(ElfShdr {
+            name: strtab_name,
+            type_: 3, // SHT_STRTAB
+            addralign: 1,
+            ..Default::default()
+        });
 
-        let bind = match s.binding {
-            SymbolBinding::Local => 0,
-            SymbolBinding::Global => 1,
-            SymbolBinding::Weak => 2,
-        };
+        // Every section has a section symbol, so the table is needed as soon as
+        // the last section header is past SHN_LORESERVE
+        let symtab_shndx_idx = section_to_shdr_idx.last().filter(|&&i| i >= SHN_LORESERVE).map(|_| {
+            let name = shstrtab.len() as u32;
+            shstrtab.extend_from_slice(b".symtab_shndx\0");
+            elf_sections.push(ElfShdr {
+                name,
+                type_: SHT_SYMTAB_SHNDX,
+                link: symtab_shdr_idx as u32,
+                addralign: 4,
+                entsize: 4,
+                ..Default::default()
+            });
+            elf_sections.len() - 1
+        });
+
+        // Relocation sections
+        let mut rela_sections = Vec::new();
+        for sec_idx in 0..obj.sections.len() {
+            if !has_relocs[sec_idx] { continue; }
 
-        let shndx = s.section_index.map(|i| section_to_shdr_idx[i] as u16).unwrap_or(0);
+            let name_idx = shstrtab.len() as u32;
+            let rela_name = format!("{}{}", rel_prefix, obj.sections[sec_idx].name);
+            shstrtab.extend_from_slice(rela_name.as_bytes());
+            shstrtab.push(0);
 
-        elf_syms.push(Elf64Sym {
-            name: name_idx,
-            info: (bind << 4),
-            shndx,
-            value: s.value,
-            size: s.size,
+            rela_sections.push((sec_idx, elf_sections.len()));
+            elf_sections.push(ElfShdr {
+                name: name_idx,
+                type_: if rela { 4 } else { 9 }, // SHT_RELA / SHT_REL
+                flags: if section_group[sec_idx].is_some() { (SectionFlags::INFO_LINK | SectionFlags::GROUP).0 } else { SectionFlags::INFO_LINK.0 },
+                link: symtab_shdr_idx as u32,
+                info: section_to_shdr_idx[sec_idx] as u32,
+                addralign: class.word_align(),
+                entsize: class.relsize(rela),
+                ..Default::default()
+            });
+        }
+
+        // Group contents: the flags word, then the members and their relocation sections
+        let mut group_contents = Vec::new();
+        for (group, &shdr_idx) in obj.groups.iter().zip(&group_shdrs) {
+            elf_sections[shdr_idx].link = symtab_shdr_idx as u32;
+            let mut words = vec![if group.comdat { GRP_COMDAT } else { 0 }];
+            words.extend(group.sections.iter().map(|&i| section_to_shdr_idx[i] as u32));
+            words.extend(rela_sections.iter().filter(|(i, _)| group.sections.contains(i)).map(|&(_, shdr)| shdr as u32));
+            group_contents.push(words);
+        }
+
+        // Section string table
+        let shstrtab_name = shstrtab.len() as u32;
+        shstrtab.extend_from_slice(b".shstrtab\0");
+        let shstrtab_idx = elf_sections.len();
+        elf_sections.push(ElfShdr {
+            name: shstrtab_name,
+            type_: 3, // SHT_STRTAB
+            addralign: 1,
             ..Default::default()
         });
-    }
 
-    // 4. Finalize offsets and build final buffer
-    // Set offsets for Symtab, Strtab, etc.
-    elf_sections[symtab_shdr_idx].offset = current_offset;
-    elf_sections[symtab_shdr_idx].size = (elf_syms.len() * 24) as u64;
-    current_offset += elf_sections[symtab_shdr_idx].size;
-
-    elf_sections[symtab_shdr_idx + 1].offset = current_offset;
-    elf_sections[symtab_shdr_idx + 1].size = strtab.len() as u64;
-    current_offset += elf_sections[symtab_shdr_idx + 1].size;
-
-    let mut elf_relas_groups = Vec::new();
-    for (sec_idx, shdr_idx) in &rela_sections {
-        let mut group = Vec::new();
-        for r in obj.relocations.iter().filter(|r| r.section_index == *sec_idx) {
-            let sym_idx = obj.symbols.iter().position(|s| s.name == r.symbol).map(|i| i + 1).unwrap_or(0);
-            let type_ = match r.kind {
-                RelocKind::Absolute64 => 1,
-                RelocKind::Relative32 => 2,
-                RelocKind::GOTPCREL => 9,
-                RelocKind::PLT32 => 4,
+        // 3. Build Symbols
+        let mut elf_syms = Vec::new();
+        elf_syms.push(ElfSym::default()); // Null
+        // .symtab_shndx entries, parallel to the symbol table
+        let mut xindex = vec![0u32];
+
+        for &shdr_idx in &section_to_shdr_idx {
+            elf_syms.push(ElfSym {
+                info: STT_SECTION, // STB_LOCAL
+                shndx: symbol_shndx(shdr_idx),
+                ..Default::default()
+            });
+            xindex.push(symbol_xindex(shdr_idx));
+        }
+
+        let tls_referenced: HashSet<&str> = obj.relocations.iter().filter(|r| r.kind.is_tls()).map(|r| r.symbol.as_str()).collect();
+        for s in order.iter().map(|&i| &symbols[i]) {
+            let name_idx = strtab.len() as u32;
+            strtab.extend_from_slice(s.name.as_bytes());
+            strtab.push(0);
+
+            let bind = match s.binding {
+                SymbolBinding::Local => 0,
+                SymbolBinding::Global => 1,
+                SymbolBinding::Weak => 2,
+            };
+
+            let shndx = match s.section_index {
+                Some(i) => symbol_shndx(section_to_shdr_idx[i]),
+                None if s.absolute || s.kind == SymbolKind::File => SHN_ABS,
+                None => 0, // SHN_UNDEF
             };
-            group.push(Elf64Rela {
-                offset: r.offset as u64,
-                info: ((sym_idx as u64) << 32) | (type_ as u64),
-                addend: r.addend,
+            xindex.push(s.section_index.map_or(0, |i| symbol_xindex(section_to_shdr_idx[i])));
+
+            let type_ = match s.kind {
+                SymbolKind::NoType => {
+                    // Symbols living in TLS sections or referenced by TLS relocations must be STT_TLS
+                    let is_tls = s.section_index.is_some_and(|i| obj.sections[i].flags.contains(SectionFlags::TLS))
+                        || tls_referenced.contains(s.name.as_str());
+                    if is_tls { STT_TLS } else { STT_NOTYPE }
+                }
+                SymbolKind::Object => STT_OBJECT,
+                SymbolKind::Func => STT_FUNC,
+                SymbolKind::Section => STT_SECTION,
+                SymbolKind::File => STT_FILE,
+                SymbolKind::Tls => STT_TLS,
+            };
+
+            elf_syms.push(ElfSym {
+                name: name_idx,
+                info: (bind << 4) | type_,
+                other: if s.visibility == SymbolVisibility::Hidden { STV_HIDDEN } else { STV_DEFAULT },
+                shndx,
+                value: s.value,
+                size: s.size,
             });
         }
-        elf_sections[*shdr_idx].offset = current_offset;
-        elf_sections[*shdr_idx].size = (group.len() * 24) as u64;
-        current_offset += elf_sections[*shdr_idx].size;
-        elf_relas_groups.push(group);
+
+        // 4. Finalize offsets and build final buffer
+        // Set offsets for Symtab, Strtab, etc.
+        elf_sections[symtab_shdr_idx].offset = current_offset;
+        elf_sections[symtab_shdr_idx].size = elf_syms.len() as u64 * class.symsize();
+        current_offset += elf_sections[symtab_shdr_idx].size;
+
+        elf_sections[symtab_shdr_idx + 1].offset = current_offset;
+        elf_sections[symtab_shdr_idx + 1].size = strtab.len() as u64;
+        current_offset += elf_sections[symtab_shdr_idx + 1].size;
+
+        if let Some(idx) = symtab_shndx_idx {
+            elf_sections[idx].offset = current_offset;
+            elf_sections[idx].size = 4 * xindex.len() as u64;
+            current_offset += elf_sections[idx].size;
+        }
+
+        let mut elf_relas_groups = Vec::new();
+        for (sec_idx, shdr_idx) in &rela_sections {
+            let mut group = Vec::new();
+            for &(r, sym, addend) in relocs.iter().filter(|(r, ..)| r.section_index == *sec_idx) {
+                let type_ = elf_reloc_type(obj.arch, r.kind)?;
+                if class == ElfClass::Elf32 && (type_ > 0xFF || sym > 0xFF_FFFF) {
+                    return Err(format!("relocation type {} against symbol {} does not fit in ELF32 r_info", type_, sym).into());
+                }
+                group.push(ElfRel { offset: r.offset as u64, sym, type_, addend });
+            }
+            elf_sections[*shdr_idx].offset = current_offset;
+            elf_sections[*shdr_idx].size = group.len() as u64 * class.relsize(rela);
+            current_offset += elf_sections[*shdr_idx].size;
+            elf_relas_groups.push(group);
+        }
+
+        elf_sections[shstrtab_idx].offset = current_offset;
+        elf_sections[shstrtab_idx].size = shstrtab.len() as u64;
+        current_offset += elf_sections[shstrtab_idx].size;
+
+        // Counts that do not fit the header go to section 0 (ELF extended numbering)
+        if elf_sections.len() > u32::MAX as usize || elf_syms.len() > u32::MAX as usize {
+            return Err(WriteError::Unsupported(format!("{} sections and {} symbols exceed the ELF limits", elf_sections.len(), elf_syms.len())));
+        }
+        let shnum = if elf_sections.len() >= SHN_LORESERVE {
+            elf_sections[0].size = elf_sections.len() as u64;
+            0
+        } else {
+            elf_sections.len() as u16
+        };
+        let shstrndx = if shstrtab_idx >= SHN_LORESERVE {
+            elf_sections[0].link = shstrtab_idx as u32;
+            SHN_XINDEX
+        } else {
+            shstrtab_idx as u16
+        };
+
+        let shdrs_size = elf_sections.len() as u64 * class.shentsize() as u64;
+        if class == ElfClass::Elf32 && current_offset + shdrs_size > u32::MAX as u64 {
+            return Err(WriteError::Unsupported("object is too large for ELF32".into()));
+        }
+
+        // Header
+        let elf_class = match class { ElfClass::Elf32 => 1, ElfClass::Elf64 => 2 };
+        let elf_data = match E::ENDIAN { Endian::Little => 1, Endian::Big => 2 };
+        let hdr = ElfHeader {
+            ident: [0x7f, b'E', b'L', b'F', elf_class, elf_data, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+            type_: 1, machine: elf_machine(obj.arch), version: 1, flags: elf_flags(obj.arch),
+            shoff: current_offset, shentsize: class.shentsize(), shnum, shstrndx,
+            ehsize: class.ehsize(), ..Default::default()
+        };
+
+        // 5. Write out
+        self.header(out, &hdr)?;
+        for &word in group_contents.iter().flatten() {
+            self.u32(out, word)?;
+        }
+        let mut written = class.ehsize() as u64 + 4 * group_contents.iter().map(Vec::len).sum::<usize>() as u64;
+        for ((sec, data), &shdr_idx) in obj.sections.iter().zip(&contents).zip(&section_to_shdr_idx) {
+            if !sec.is_nobits() {
+                pad_to(out, &mut written, elf_sections[shdr_idx].offset)?;
+                out.write_all(data)?;
+                written += data.len() as u64;
+            }
+        }
+        if let Some((shdr_idx, note)) = &properties {
+            pad_to(out, &mut written, elf_sections[*shdr_idx].offset)?;
+            out.write_all(note)?;
+        }
+        for sym in &elf_syms {
+            self.sym(out, sym)?;
+        }
+        out.write_all(&strtab)?;
+        if symtab_shndx_idx.is_some() {
+            for &index in &xindex {
+                self.u32(out, index)?;
+            }
+        }
+        for group in elf_relas_groups {
+            for rel in group {
+                self.rel(out, &rel, rela)?;
+            }
+        }
+        out.write_all(&shstrtab)?;
+        for shdr in &elf_sections {
+            self.shdr(out, shdr)?;
+        }
+
+        Ok(())
     }
+}
+
+/// Writes zeros up to `offset`, which is never behind `written`.
+fn pad_to(out: &mut impl Write, written: &mut u64, offset: u64) -> io::Result<()> {
+    out.write_all(&vec![0; (offset - *written) as usize])?;
+    *written = offset;
+    Ok(())
+}
+
+/// ELF reads whose layout depends on the file class and byte order.
+struct ElfReader<'a> {
+    r: Reader<'a>,
+    class: ElfClass,
+    endian: Endian,
+}
 
-    elf_sections[shstrtab_idx].offset = current_offset;
-    elf_sections[shstrtab_idx].size = shstrtab.len() as u64;
-    current_offset += elf_sections[shstrtab_idx].size;
+impl<'a> std::ops::Deref for ElfReader<'a> {
+    type Target = Reader<'a>;
 
-    // Header
-    let hdr = Elf64Header {
-        ident: [0x7f, b'E', b'L', b'F', 2, 1, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0],
-        type_: 1, machine: 0x3e, version: 1,
-        shoff: current_offset, shentsize: 64, shnum: elf_sections.len() as u16, shstrndx: shstrtab_idx as u16,
-        ehsize: 64, ..Default::default()
+    fn deref(&self) -> &Reader<'a> {
+        &self.r
+    }
+}
+
+impl<'a> ElfReader<'a> {
+    fn u16(&self, offset: u64, what: &str) -> Result<u16, ParseError> {
+        Ok(self.endian.decode(self.bytes(offset, 2, what)?) as u16)
+    }
+
+    fn u32(&self, offset: u64, what: &str) -> Result<u32, ParseError> {
+        Ok(self.endian.decode(self.bytes(offset, 4, what)?) as u32)
+    }
+
+    fn u64(&self, offset: u64, what: &str) -> Result<u64, ParseError> {
+        Ok(self.endian.decode(self.bytes(offset, 8, what)?))
+    }
+
+    /// Reads an address-sized field (`Elf32_Addr`/`Elf64_Addr` and friends).
+    fn word(&self, offset: u64, what: &str) -> Result<u64, ParseError> {
+        match self.class {
+            ElfClass::Elf32 => self.u32(offset, what).map(u64::from),
+            ElfClass::Elf64 => self.u64(offset, what),
+        }
+    }
+
+    /// Reads the NUL-terminated string at `index` in the string table at `table`.
+    fn string(&self, table: &ElfShdr, index: u32) -> Result<String, ParseError> {
+        let strings = self.bytes(table.offset, table.size, "string table")?;
+        let start = index as usize;
+        let len = strings.get(start..).and_then(|rest| rest.iter().position(|&b| b == 0)).ok_or_else(|| {
+            ParseError::new(table.offset as usize + start, format!("string index {} is not NUL-terminated inside its table", index))
+        })?;
+        Ok(String::from_utf8_lossy(&strings[start..start + len]).into_owned())
+    }
+
+    fn shdr(&self, offset: u64) -> Result<ElfShdr, ParseError> {
+        self.bytes(offset, self.class.shentsize() as u64, "section header")?;
+        let w = self.class.word_align();
+        Ok(ElfShdr {
+            name: self.u32(offset, "sh_name")?,
+            type_: self.u32(offset + 4, "sh_type")?,
+            flags: self.word(offset + 8, "sh_flags")?,
+            addr: self.word(offset + 8 + w, "sh_addr")?,
+            offset: self.word(offset + 8 + 2 * w, "sh_offset")?,
+            size: self.word(offset + 8 + 3 * w, "sh_size")?,
+            link: self.u32(offset + 8 + 4 * w, "sh_link")?,
+            info: self.u32(offset + 12 + 4 * w, "sh_info")?,
+            addralign: self.word(offset + 16 + 4 * w, "sh_addralign")?,
+            entsize: self.word(offset + 16 + 5 * w, "sh_entsize")?,
+        })
+    }
+
+    /// Replaces the contents of an `SHF_COMPRESSED` section with the expanded
+    /// bytes. Formats this crate cannot decode are left compressed, flag included.
+    fn decompress_section(&self, section: &mut Section, sh: &ElfShdr) -> Result<(), ParseError> {
+        let w = self.class.word_align();
+        let ch_type = self.u32(sh.offset, "ch_type")?;
+        // Elf64_Chdr has a reserved word after ch_type
+        let size_at = sh.offset + if self.class == ElfClass::Elf64 { 8 } else { 4 };
+        let size = self.word(size_at, "ch_size")?;
+        let align = self.word(size_at + w, "ch_addralign")?;
+        let Some(compression) = Compression::from_elf_type(ch_type) else {
+            return Ok(());
+        };
+        let header_size = size_at + 2 * w - sh.offset;
+        let compressed = section.data.get(header_size as usize..).ok_or_else(|| {
+            ParseError::new(sh.offset as usize, format!("section {} is too small for its compression header", section.name))
+        })?;
+        match compression.decompress(compressed, size as usize) {
+            Ok(data) => {
+                section.data = data;
+                section.align = align.max(1);
+                section.flags = SectionFlags(section.flags.0 & !SectionFlags::COMPRESSED.0);
+                section.compression = Some(compression);
+                Ok(())
+            }
+            Err(DecompressError::Unsupported(_)) => Ok(()),
+            Err(e) => Err(ParseError::new(sh.offset as usize, format!("section {}: {}", section.name, e))),
+        }
+    }
+
+    fn sym(&self, offset: u64) -> Result<ElfSym, ParseError> {
+        self.bytes(offset, self.class.symsize(), "symbol")?;
+        Ok(match self.class {
+            ElfClass::Elf32 => ElfSym {
+                name: self.u32(offset, "st_name")?,
+                value: self.word(offset + 4, "st_value")?,
+                size: self.word(offset + 8, "st_size")?,
+                info: self.u8(offset + 12, "st_info")?,
+                other: self.u8(offset + 13, "st_other")?,
+                shndx: self.u16(offset + 14, "st_shndx")?,
+            },
+            ElfClass::Elf64 => ElfSym {
+                name: self.u32(offset, "st_name")?,
+                info: self.u8(offset + 4, "st_info")?,
+                other: self.u8(offset + 5, "st_other")?,
+                shndx: self.u16(offset + 6, "st_shndx")?,
+                value: self.word(offset + 8, "st_value")?,
+                size: self.word(offset + 16, "st_size")?,
+            },
+        })
+    }
+
+    fn rel(&self, offset: u64, rela: bool) -> Result<ElfRel, ParseError> {
+        self.bytes(offset, self.class.relsize(rela), "relocation")?;
+        let w = self.class.word_align();
+        let info = self.word(offset + w, "r_info")?;
+        let (sym, type_) = match self.class {
+            ElfClass::Elf32 => ((info >> 8) as u32, (info & 0xFF) as u32),
+            ElfClass::Elf64 => ((info >> 32) as u32, info as u32),
+        };
+        let addend = match (rela, self.class) {
+            (false, _) => 0,
+            (true, ElfClass::Elf32) => self.u32(offset + 2 * w, "r_addend")? as i32 as i64,
+            (true, ElfClass::Elf64) => self.u64(offset + 2 * w, "r_addend")? as i64,
+        };
+        Ok(ElfRel { offset: self.word(offset, "r_offset")?, sym, type_, addend })
+    }
+}
+
+const SHT_SYMTAB: u32 = 2;
+const SHT_STRTAB: u32 = 3;
+const SHT_RELA: u32 = 4;
+const SHT_NOTE: u32 = 7;
+const SHT_NOBITS: u32 = 8;
+const SHT_REL: u32 = 9;
+const SHT_GROUP: u32 = 17;
+const SHT_SYMTAB_SHNDX: u32 = 18;
+const GRP_COMDAT: u32 = 1;
+
+/// Reads an ELF32 or ELF64 relocatable object of either byte order for x86-64,
+/// i386, AArch64, RISC-V, PowerPC64 or MIPS.
+///
+/// Symbol tables, string tables and relocation sections are decoded into the
+/// `ObjectFile` rather than kept as sections. Section symbols are named after
+/// their section so relocations against them keep working by name. `SHT_REL`
+/// addends are read from the relocated field.
+pub fn parse_elf(data: &[u8]) -> Result<ObjectFile, ParseError> {
+    let ident = Reader { data }.bytes(0, 16, "ELF identification")?;
+    let (class, format) = match ident[4] {
+        1 => (ElfClass::Elf32, ObjectFormat::ELF32),
+        2 => (ElfClass::Elf64, ObjectFormat::ELF64),
+        other => return Err(ParseError::new(4, format!("unsupported ELF class {}", other))),
+    };
+    let endian = match ident[5] {
+        1 => Endian::Little,
+        2 => Endian::Big,
+        other => return Err(ParseError::new(5, format!("unsupported ELF data encoding {}", other))),
     };
+    let r = ElfReader { r: Reader { data }, class, endian };
+    let type_ = r.u16(16, "e_type")?;
+    if type_ != 1 {
+        return Err(ParseError::new(16, format!("e_type {} is not a relocatable object (ET_REL)", type_)));
+    }
+    let arch = match r.u16(18, "e_machine")? {
+        EM_X86_64 => Architecture::X86_64,
+        EM_AARCH64 => Architecture::AArch64,
+        EM_386 => Architecture::I386,
+        EM_RISCV => Architecture::RiscV64,
+        EM_PPC64 => Architecture::PowerPC64,
+        EM_MIPS => Architecture::Mips,
+        other => return Err(ParseError::new(18, format!("unsupported machine {:#x}", other))),
+    };
+
+    // e_shoff follows e_entry and e_phoff; the half-word fields end the header
+    let w = class.word_align();
+    let shoff = r.word(24 + 2 * w, "e_shoff")?;
+    let halves = 28 + 3 * w;
+    let shentsize = r.u16(halves + 6, "e_shentsize")?;
+    let mut shnum = r.u16(halves + 8, "e_shnum")? as usize;
+    let mut shstrndx = r.u16(halves + 10, "e_shstrndx")? as usize;
+    if shentsize != class.shentsize() && (shnum != 0 || shoff != 0) {
+        return Err(ParseError::new(halves as usize + 6, format!("unexpected section header size {}", shentsize)));
+    }
+    // Extended numbering: counts that do not fit the header are kept in section 0
+    if shoff != 0 && (shnum == 0 || shstrndx == SHN_XINDEX as usize) {
+        let first = r.shdr(shoff)?;
+        if shnum == 0 {
+            shnum = first.size as usize;
+        }
+        if shstrndx == SHN_XINDEX as usize {
+            shstrndx = first.link as usize;
+        }
+    }
 
-    // 5. Write to buffer
-    unsafe {
-        let ptr = &hdr as *const Elf64Header as *const u8;
-        out.extend_from_slice(std::slice::from_raw_parts(ptr, 64));
+    let shentsize = shentsize as u64;
+    // The count may come from the file; check it before allocating for it
+    if (shnum as u64).checked_mul(shentsize).and_then(|size| size.checked_add(shoff)).is_none_or(|end| end > data.len() as u64) {
+        return Err(ParseError::new(shoff as usize, format!("{} section headers at {:#x} extend past the end of the file", shnum, shoff)));
     }
-    for sec in &obj.sections {
-        if sec.kind != SectionKind::Bss {
-            out.extend_from_slice(&sec.data);
+    let shdrs = (0..shnum).map(|i| r.shdr(shoff + shentsize * i as u64)).collect::<Result<Vec<_>, _>>()?;
+    let shstrtab = shdrs.get(shstrndx).ok_or_else(|| {
+        ParseError::new(halves as usize + 10, format!("e_shstrndx {} is out of range ({} sections)", shstrndx, shnum))
+    })?;
+    let shdr_offset = |i: usize| (shoff + shentsize * i as u64) as usize;
+    // Offsets of sh_link / sh_info inside a section header
+    let (link_field, info_field) = (8 + 4 * w as usize, 12 + 4 * w as usize);
+
+    let mut obj = ObjectFile::new(format);
+    obj.arch = arch;
+    obj.endian = endian;
+
+    // ELF section index -> index in obj.sections
+    let mut section_map = vec![None; shnum];
+    let mut property_section = None;
+    for (i, sh) in shdrs.iter().enumerate().skip(1) {
+        if matches!(sh.type_, SHT_SYMTAB | SHT_STRTAB | SHT_RELA | SHT_REL | SHT_GROUP | SHT_SYMTAB_SHNDX) {
+            continue;
+        }
+        let name = r.string(shstrtab, sh.name)?;
+        // Program properties are described by obj.gnu_properties, unless the note is malformed
+        if sh.type_ == SHT_NOTE && name == ".note.gnu.property" {
+            let note = r.bytes(sh.offset, sh.size, "contents of section .note.gnu.property")?;
+            if let Some(properties) = GnuProperties::decode(note, endian, class.word_align() as usize) {
+                obj.gnu_properties = properties;
+                property_section = Some(i);
+                continue;
+            }
+        }
+        // Group membership is described by obj.groups
+        let flags = SectionFlags(sh.flags & !SectionFlags::GROUP.0);
+        let mut section = Section::with_elf_type(&name, SectionType(sh.type_), flags, sh.addralign.max(1));
+        section.data = if sh.type_ == SHT_NOBITS {
+            zero_fill(sh.size, (shdr_offset(i) + 8 + 3 * w as usize) as u64, &name)?
+        } else {
+            r.bytes(sh.offset, sh.size, &format!("contents of section {}", name))?.to_vec()
+        };
+        if flags.contains(SectionFlags::COMPRESSED) && sh.type_ != SHT_NOBITS {
+            r.decompress_section(&mut section, sh)?;
         }
+        section.entsize = sh.entsize;
+        section.info = sh.info;
+        section_map[i] = Some(obj.sections.len());
+        obj.sections.push(section);
     }
-    for sym in &elf_syms {
-        unsafe {
-            let ptr = sym as *const Elf64Sym as *const u8;
-            out.extend_from_slice(std::slice::from_raw_parts(ptr, 24));
+    for (i, sh) in shdrs.iter().enumerate() {
+        if let (Some(index), true) = (section_map[i], sh.link != 0) {
+            obj.sections[index].link = Some(section_map.get(sh.link as usize).copied().flatten().ok_or_else(|| {
+                ParseError::new(shdr_offset(i) + link_field, format!("section links to missing section {}", sh.link))
+            })?);
+        }
+        if let (Some(index), true) = (section_map[i], sh.flags & SectionFlags::INFO_LINK.0 != 0) {
+            obj.sections[index].info = section_map.get(sh.info as usize).copied().flatten().ok_or_else(|| {
+                ParseError::new(shdr_offset(i) + info_field, format!("section info refers to missing section {}", sh.info))
+            })? as u32;
         }
     }
-    out.extend_from_slice(&strtab);
-    for group in elf_relas_groups {
-        for rela in group {
-            unsafe {
-                let ptr = &rela as *const Elf64Rela as *const u8;
-                out.extend_from_slice(std::slice::from_raw_parts(ptr, 24));
+
+    // ELF symbol index -> symbol name, for resolving relocations
+    let mut symbol_names = Vec::new();
+    if let Some((symtab_idx, symtab)) = shdrs.iter().enumerate().find(|(_, sh)| sh.type_ == SHT_SYMTAB) {
+        let strtab = shdrs.get(symtab.link as usize).ok_or_else(|| {
+            ParseError::new(shdr_offset(symtab_idx) + link_field, format!("symbol table links to missing string table {}", symtab.link))
+        })?;
+        let count = symtab.size / class.symsize();
+        let symtab_shndx = shdrs.iter().find(|sh| sh.type_ == SHT_SYMTAB_SHNDX && sh.link as usize == symtab_idx);
+        for i in 0..count {
+            let off = symtab.offset + class.symsize() * i;
+            let sym = r.sym(off)?;
+            if i == 0 {
+                symbol_names.push(String::new());
+                continue;
+            }
+
+            let shndx = match (sym.shndx, symtab_shndx) {
+                (SHN_XINDEX, Some(table)) => r.u32(table.offset + 4 * i, "extended section index")?,
+                (SHN_XINDEX, None) => return Err(ParseError::new(off as usize, format!("symbol {} uses SHN_XINDEX without a .symtab_shndx", i))),
+                (0xFF00..=0xFFFE, _) => 0,
+                (idx, _) => idx as u32,
+            };
+            if sym.info & 0xF == STT_SECTION && property_section == Some(shndx as usize) {
+                symbol_names.push(String::new());
+                continue;
+            }
+            let section_index = match shndx {
+                0 => None,
+                idx => Some(section_map.get(idx as usize).copied().flatten().ok_or_else(|| {
+                    ParseError::new(off as usize, format!("symbol {} refers to invalid section {}", i, idx))
+                })?),
+            };
+            let name = match (sym.info & 0xF, section_index) {
+                (STT_SECTION, Some(sec)) => obj.sections[sec].name.clone(),
+                _ => r.string(strtab, sym.name)?,
+            };
+            let binding = match sym.info >> 4 {
+                0 => SymbolBinding::Local,
+                2 => SymbolBinding::Weak,
+                _ => SymbolBinding::Global,
+            };
+            // STV_INTERNAL is a stricter form of hidden; STV_PROTECTED stays exported
+            let visibility = if matches!(sym.other & 3, 1 | 2) { SymbolVisibility::Hidden } else { SymbolVisibility::Default };
+            let kind = match sym.info & 0xF {
+                STT_OBJECT => SymbolKind::Object,
+                STT_FUNC => SymbolKind::Func,
+                STT_SECTION => SymbolKind::Section,
+                STT_FILE => SymbolKind::File,
+                STT_TLS => SymbolKind::Tls,
+                _ => SymbolKind::NoType,
+            };
+
+            symbol_names.push(name.clone());
+            obj.symbols.push(ObjectSymbol {
+                name,
+                section_index,
+                value: sym.value,
+                size: sym.size,
+                binding,
+                visibility,
+                kind,
+                absolute: sym.shndx == SHN_ABS,
+            });
+        }
+    }
+
+    for (i, sh) in shdrs.iter().enumerate() {
+        if sh.type_ != SHT_RELA && sh.type_ != SHT_REL {
+            continue;
+        }
+        let rela = sh.type_ == SHT_RELA;
+        let Some(section_index) = section_map.get(sh.info as usize).copied().flatten() else {
+            return Err(ParseError::new(shdr_offset(i) + info_field, format!("relocation section applies to invalid section {}", sh.info)));
+        };
+        let entsize = class.relsize(rela);
+        let first = obj.relocations.len();
+        for j in 0..sh.size / entsize {
+            let off = sh.offset + entsize * j;
+            let rel = r.rel(off, rela)?;
+            let kind = reloc_kind_from_elf(arch, rel.type_);
+            let symbol = symbol_names.get(rel.sym as usize).cloned().ok_or_else(|| {
+                ParseError::new(off as usize, format!("relocation refers to missing symbol {}", rel.sym))
+            })?;
+            let addend = if rela {
+                rel.addend
+            } else if let Some(width) = implicit_addend_width(arch, kind) {
+                let field = obj.sections[section_index].data.get(rel.offset as usize..rel.offset as usize + width).ok_or_else(|| {
+                    ParseError::new(off as usize, format!("relocation offset {:#x} is outside its section", rel.offset))
+                })?;
+                implicit_addend(endian, arch, kind, field)
+            } else if let RelocKind::Raw(_) = kind {
+                return Err(ParseError::new(off as usize, format!("cannot read the implicit addend of relocation type {}", rel.type_)));
+            } else {
+                // Marker relocations have no field
+                0
+            };
+            obj.relocations.push(ObjectRelocation {
+                section_index,
+                offset: rel.offset as usize,
+                symbol,
+                addend,
+                kind,
+                subtrahend: None,
+            });
+        }
+
+        // A HI16 addend is completed by the low half of the next LO16 against the same symbol
+        if !rela {
+            for h in first..obj.relocations.len() {
+                if obj.relocations[h].kind != RelocKind::HI16 {
+                    continue;
+                }
+                let symbol = &obj.relocations[h].symbol;
+                if let Some(lo) = obj.relocations[h + 1..].iter().find(|r| r.kind == RelocKind::LO16 && &r.symbol == symbol) {
+                    obj.relocations[h].addend += lo.addend;
+                }
             }
         }
     }
-    out.extend_from_slice(&shstrtab);
-    for shdr in &elf_sections {
-        unsafe {
-            let ptr = shdr as *const Elf64Shdr as *const u8;
-            out.extend_from_slice(std::slice::from_raw_parts(ptr, 64));
+
+    // Groups list relocation sections too; only the sections kept in obj are members here
+    for (i, sh) in shdrs.iter().enumerate() {
+        if sh.type_ != SHT_GROUP {
+            continue;
+        }
+        let signature = symbol_names.get(sh.info as usize).filter(|_| sh.info != 0).cloned().ok_or_else(|| {
+            ParseError::new(shdr_offset(i) + info_field, format!("group signature refers to missing symbol {}", sh.info))
+        })?;
+        let words = sh.size / 4;
+        let flags = r.u32(sh.offset, "group flags")?;
+        let mut sections = Vec::new();
+        for j in 1..words {
+            let off = sh.offset + 4 * j;
+            let member = r.u32(off, "group member")? as usize;
+            match shdrs.get(member) {
+                Some(_) => sections.extend(section_map[member]),
+                None => return Err(ParseError::new(off as usize, format!("group member {} is out of range", member))),
+            }
         }
+        obj.groups.push(SectionGroup { signature, sections, comdat: flags & GRP_COMDAT != 0 });
     }
 
-    Ok(out)
+    Ok(obj)
 }
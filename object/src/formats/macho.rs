@@ -0,0 +1,673 @@
+//! 64-bit Mach-O relocatable objects (`MH_OBJECT`) for x86-64 and arm64 macOS.
+//!
+//! All sections live in one unnamed `LC_SEGMENT_64` and get increasing
+//! addresses, so symbol values are addresses rather than section offsets.
+//! ELF-style section names (`.text`, `.data`, ...) are mapped to their
+//! `__SEGMENT,__section` equivalents and symbols get the leading underscore
+//! Darwin uses for C names.
+//!
+//! On x86-64 addends are stored in the relocated field; pc-relative fields hold
+//! the addend relative to the end of the 4-byte field. On arm64 only `UNSIGNED`
+//! does that: instruction relocations take their addend from a preceding
+//! `ARM64_RELOC_ADDEND` record.
+
+use std::collections::HashMap;
+
+use crate::core::arch::Architecture;
+use crate::core::error::ParseError;
+use crate::core::object::{ObjectFile, ObjectFormat};
+use crate::core::reloc::{ObjectRelocation, RelocKind};
+use crate::core::section::{Section, SectionKind};
+use crate::core::symbol::{ObjectSymbol, SymbolBinding, SymbolKind, SymbolVisibility};
+use crate::formats::reader::{zero_fill, Reader};
+
+const MH_MAGIC_64: u32 = 0xFEED_FACF;
+const MH_OBJECT: u32 = 1;
+const CPU_TYPE_X86_64: u32 = 0x0100_0007;
+const CPU_TYPE_ARM64: u32 = 0x0100_000C;
+const CPU_SUBTYPE_X86_64_ALL: u32 = 3;
+const CPU_SUBTYPE_ARM64_ALL: u32 = 0;
+
+const LC_SYMTAB: u32 = 0x2;
+const LC_DYSYMTAB: u32 = 0xB;
+const LC_SEGMENT_64: u32 = 0x19;
+const LC_BUILD_VERSION: u32 = 0x32;
+const PLATFORM_MACOS: u32 = 1;
+
+const HEADER_SIZE: u64 = 32;
+const SEGMENT_SIZE: u64 = 72;
+const SECTION_SIZE: u64 = 80;
+const BUILD_VERSION_SIZE: u64 = 24;
+const SYMTAB_SIZE: u64 = 24;
+const DYSYMTAB_SIZE: u64 = 80;
+const NLIST_SIZE: u64 = 16;
+const RELOC_SIZE: u64 = 8;
+
+const SECTION_TYPE: u32 = 0xFF;
+const S_REGULAR: u32 = 0x0;
+const S_ZEROFILL: u32 = 0x1;
+const S_COALESCED: u32 = 0xB;
+const S_THREAD_LOCAL_REGULAR: u32 = 0x11;
+const S_THREAD_LOCAL_ZEROFILL: u32 = 0x12;
+const S_ATTR_PURE_INSTRUCTIONS: u32 = 0x8000_0000;
+const S_ATTR_NO_TOC: u32 = 0x4000_0000;
+const S_ATTR_STRIP_STATIC_SYMS: u32 = 0x2000_0000;
+const S_ATTR_LIVE_SUPPORT: u32 = 0x0800_0000;
+const S_ATTR_DEBUG: u32 = 0x0200_0000;
+const S_ATTR_SOME_INSTRUCTIONS: u32 = 0x400;
+
+const N_STAB: u8 = 0xE0;
+const N_PEXT: u8 = 0x10;
+const N_TYPE: u8 = 0x0E;
+const N_EXT: u8 = 0x01;
+const N_UNDF: u8 = 0x0;
+const N_ABS: u8 = 0x2;
+const N_SECT: u8 = 0xE;
+const N_WEAK_REF: u16 = 0x40;
+const N_WEAK_DEF: u16 = 0x80;
+
+const RELOC_UNSIGNED: u8 = 0;
+const X86_64_RELOC_GOT_LOAD: u8 = 3;
+const X86_64_RELOC_SUBTRACTOR: u8 = 5;
+const ARM64_RELOC_SUBTRACTOR: u8 = 1;
+const ARM64_RELOC_ADDEND: u8 = 10;
+
+/// ELF-style section names and the Mach-O segment and section they become.
+const SECTION_NAMES: &[(&str, &str, &str)] = &[
+    (".text", "__TEXT", "__text"),
+    (".rodata", "__TEXT", "__const"),
+    (".eh_frame", "__TEXT", "__eh_frame"),
+    (".data", "__DATA", "__data"),
+    (".bss", "__DATA", "__bss"),
+    (".tdata", "__DATA", "__thread_data"),
+    (".tbss", "__DATA", "__thread_bss"),
+];
+
+/// `(kind, r_type, r_pcrel, r_length)` for `X86_64_RELOC_*`.
+const X86_64_RELOC_TYPES: &[(RelocKind, u8, bool, u8)] = &[
+    (RelocKind::Absolute64, RELOC_UNSIGNED, false, 3),
+    (RelocKind::Absolute32, RELOC_UNSIGNED, false, 2),
+    (RelocKind::Relative32, 1, true, 2),
+    (RelocKind::PLT32, 2, true, 2),
+    (RelocKind::GOTPCREL, 4, true, 2),
+];
+
+/// `(kind, r_type, r_pcrel, r_length)` for `ARM64_RELOC_*`.
+const ARM64_RELOC_TYPES: &[(RelocKind, u8, bool, u8)] = &[
+    (RelocKind::Absolute64, RELOC_UNSIGNED, false, 3),
+    (RelocKind::Absolute32, RELOC_UNSIGNED, false, 2),
+    (RelocKind::CALL26, 2, true, 2),
+    (RelocKind::ADR_PREL_PG_HI21, 3, true, 2),
+    (RelocKind::PAGEOFF12, 4, false, 2),
+    (RelocKind::ADR_GOT_PAGE, 5, true, 2),
+    (RelocKind::LD64_GOT_LO12_NC, 6, false, 2),
+];
+
+fn reloc_types(arch: Architecture) -> &'static [(RelocKind, u8, bool, u8)] {
+    match arch {
+        Architecture::AArch64 => ARM64_RELOC_TYPES,
+        _ => X86_64_RELOC_TYPES,
+    }
+}
+
+fn subtractor_type(arch: Architecture) -> u8 {
+    match arch {
+        Architecture::AArch64 => ARM64_RELOC_SUBTRACTOR,
+        _ => X86_64_RELOC_SUBTRACTOR,
+    }
+}
+
+fn reloc_kind_from_macho(arch: Architecture, type_: u8, length: u8) -> RelocKind {
+    match (arch, type_) {
+        // SIGNED_1/2/4 differ only in how a section-relative target is found
+        (Architecture::X86_64, 6..=8) => RelocKind::Relative32,
+        (Architecture::X86_64, X86_64_RELOC_GOT_LOAD) => RelocKind::REX_GOTPCRELX,
+        _ => reloc_types(arch)
+            .iter()
+            .find(|(_, t, _, l)| *t == type_ && (*t != RELOC_UNSIGNED || *l == length))
+            .map(|(k, ..)| *k)
+            .unwrap_or(RelocKind::Raw(type_ as u32)),
+    }
+}
+
+/// Segment and section name for an `object` section.
+fn macho_names(section: &Section) -> Result<(String, String), String> {
+    let (segment, name) = if let Some((_, seg, sect)) = SECTION_NAMES.iter().find(|(elf, ..)| *elf == section.name) {
+        (seg.to_string(), sect.to_string())
+    } else if let Some((seg, sect)) = section.name.split_once(',') {
+        (seg.to_string(), sect.to_string())
+    } else {
+        let segment = match section.kind {
+            SectionKind::Text | SectionKind::ReadOnlyData => "__TEXT",
+            SectionKind::Other(_) => "__DWARF",
+            _ => "__DATA",
+        };
+        (segment.to_string(), format!("__{}", section.name.trim_start_matches('.').replace('.', "_")))
+    };
+    if segment.len() > 16 || name.len() > 16 {
+        return Err(format!("section name {},{} does not fit in 16 bytes", segment, name));
+    }
+    Ok((segment, name))
+}
+
+fn elf_section_name(segment: &str, name: &str) -> String {
+    SECTION_NAMES
+        .iter()
+        .find(|(_, seg, sect)| *seg == segment && *sect == name)
+        .map(|(elf, ..)| elf.to_string())
+        .unwrap_or_else(|| format!("{},{}", segment, name))
+}
+
+fn section_flags(segment: &str, name: &str, kind: SectionKind) -> u32 {
+    if segment == "__TEXT" && name == "__eh_frame" {
+        return S_COALESCED | S_ATTR_NO_TOC | S_ATTR_STRIP_STATIC_SYMS | S_ATTR_LIVE_SUPPORT;
+    }
+    match kind {
+        SectionKind::Text => S_REGULAR | S_ATTR_PURE_INSTRUCTIONS | S_ATTR_SOME_INSTRUCTIONS,
+        SectionKind::Data | SectionKind::ReadOnlyData => S_REGULAR,
+        SectionKind::Bss => S_ZEROFILL,
+        SectionKind::TlsData => S_THREAD_LOCAL_REGULAR,
+        SectionKind::TlsBss => S_THREAD_LOCAL_ZEROFILL,
+        SectionKind::Other(_) => S_REGULAR | S_ATTR_DEBUG,
+    }
+}
+
+fn section_kind(segment: &str, flags: u32) -> SectionKind {
+    match flags & SECTION_TYPE {
+        S_ZEROFILL => SectionKind::Bss,
+        S_THREAD_LOCAL_REGULAR => SectionKind::TlsData,
+        S_THREAD_LOCAL_ZEROFILL => SectionKind::TlsBss,
+        _ if flags & (S_ATTR_PURE_INSTRUCTIONS | S_ATTR_SOME_INSTRUCTIONS) != 0 => SectionKind::Text,
+        _ if flags & S_ATTR_DEBUG != 0 => SectionKind::Other(1),
+        _ if segment == "__TEXT" => SectionKind::ReadOnlyData,
+        _ => SectionKind::Data,
+    }
+}
+
+fn fixed16(name: &str) -> [u8; 16] {
+    let mut out = [0u8; 16];
+    out[..name.len()].copy_from_slice(name.as_bytes());
+    out
+}
+
+fn align_up(value: u64, align: u64) -> u64 {
+    value.div_ceil(align) * align
+}
+
+/// One `relocation_info` record.
+struct MachReloc {
+    address: u32,
+    symbolnum: u32,
+    pcrel: bool,
+    /// log2 of the field size
+    length: u8,
+    external: bool,
+    type_: u8,
+}
+
+impl MachReloc {
+    fn put(&self, out: &mut Vec<u8>) {
+        let info = (self.symbolnum & 0x00FF_FFFF)
+            | (self.pcrel as u32) << 24
+            | (self.length as u32) << 25
+            | (self.external as u32) << 27
+            | (self.type_ as u32) << 28;
+        out.extend_from_slice(&self.address.to_le_bytes());
+        out.extend_from_slice(&info.to_le_bytes());
+    }
+
+    fn read(r: &Reader, offset: u64) -> Result<Self, ParseError> {
+        let address = r.u32(offset, "r_address")?;
+        let info = r.u32(offset + 4, "relocation info")?;
+        if address & 0x8000_0000 != 0 {
+            return Err(ParseError::new(offset as usize, "scattered relocations are not supported"));
+        }
+        Ok(MachReloc {
+            address,
+            symbolnum: info & 0x00FF_FFFF,
+            pcrel: info >> 24 & 1 != 0,
+            length: (info >> 25 & 3) as u8,
+            external: info >> 27 & 1 != 0,
+            type_: (info >> 28) as u8,
+        })
+    }
+}
+
+/// Writes a Mach-O object for x86-64 or arm64.
+///
+/// Symbols are grouped as `LC_DYSYMTAB` requires: locals in their original
+/// order, then defined externals and undefined symbols, each sorted by name.
+/// Hidden externals become private externs (`N_PEXT`). The symbols come from
+/// `symbols` instead of `obj.symbols`.
+pub fn write_macho(obj: &ObjectFile, symbols: &[ObjectSymbol]) -> Result<Vec<u8>, String> {
+    let (cputype, cpusubtype, minos) = match obj.arch {
+        Architecture::X86_64 => (CPU_TYPE_X86_64, CPU_SUBTYPE_X86_64_ALL, 10 << 16 | 13 << 8),
+        Architecture::AArch64 => (CPU_TYPE_ARM64, CPU_SUBTYPE_ARM64_ALL, 11 << 16),
+        arch => return Err(format!("Mach-O output is not supported for {:?}", arch)),
+    };
+    if obj.sections.len() > u8::MAX as usize {
+        return Err("too many sections for Mach-O".into());
+    }
+    if let Some(group) = obj.groups.first() {
+        return Err(format!("section group {} cannot be written to Mach-O", group.signature));
+    }
+
+    // Section addresses; file contents mirror the address layout
+    let cmds_size = SEGMENT_SIZE + SECTION_SIZE * obj.sections.len() as u64 + BUILD_VERSION_SIZE + SYMTAB_SIZE + DYSYMTAB_SIZE;
+    let data_start = HEADER_SIZE + cmds_size;
+    let mut offset = data_start;
+    let mut addr = 0;
+    let mut addrs = Vec::new();
+    let mut offsets = Vec::new();
+    for section in &obj.sections {
+        let align = section.align.max(1);
+        if !align.is_power_of_two() {
+            return Err(format!("section {} alignment {} is not a power of two", section.name, align));
+        }
+        addr = align_up(addr, align);
+        addrs.push(addr);
+        addr += section.data.len() as u64;
+        if section.kind.is_nobits() {
+            offsets.push(0);
+        } else {
+            offsets.push(data_start + addrs[addrs.len() - 1]);
+            offset = data_start + addr;
+        }
+    }
+    let (vmsize, filesize) = (addr, offset - data_start);
+
+    // Symbol table order; section and file symbols have no Mach-O counterpart
+    let is_local = |s: &ObjectSymbol| s.binding == SymbolBinding::Local && (s.section_index.is_some() || s.absolute);
+    let by_name = |a: &usize, b: &usize| symbols[*a].name.cmp(&symbols[*b].name);
+    let copied: Vec<usize> =
+        (0..symbols.len()).filter(|&i| !matches!(symbols[i].kind, SymbolKind::Section | SymbolKind::File)).collect();
+    let locals: Vec<usize> = copied.iter().copied().filter(|&i| is_local(&symbols[i])).collect();
+    let mut extdefs: Vec<usize> = copied
+        .iter()
+        .copied()
+        .filter(|&i| !is_local(&symbols[i]) && (symbols[i].section_index.is_some() || symbols[i].absolute))
+        .collect();
+    let mut undefs: Vec<usize> = copied
+        .iter()
+        .copied()
+        .filter(|&i| symbols[i].section_index.is_none() && !symbols[i].absolute)
+        .collect();
+    extdefs.sort_by(by_name);
+    undefs.sort_by(by_name);
+    let order: Vec<usize> = locals.iter().chain(&extdefs).chain(&undefs).copied().collect();
+
+    let mut symbol_index = HashMap::new();
+    for (n, &i) in order.iter().enumerate() {
+        symbol_index.entry(symbols[i].name.as_str()).or_insert(n as u32);
+    }
+
+    // Relocations, with x86-64 and UNSIGNED addends written into the section contents
+    let mut contents: Vec<Vec<u8>> = obj.sections.iter().map(|s| s.data.clone()).collect();
+    let mut relocs: Vec<Vec<u8>> = vec![Vec::new(); obj.sections.len()];
+    for r in &obj.relocations {
+        let kind = match r.kind {
+            RelocKind::GOTPCRELX | RelocKind::REX_GOTPCRELX if obj.arch == Architecture::X86_64 => RelocKind::GOTPCREL,
+            kind => kind,
+        };
+        let &(_, type_, pcrel, length) = reloc_types(obj.arch)
+            .iter()
+            .find(|(k, ..)| *k == kind)
+            .ok_or_else(|| format!("{:?} relocations are not supported in Mach-O for {:?}", r.kind, obj.arch))?;
+        let address = u32::try_from(r.offset).ok().filter(|a| *a < 0x8000_0000).ok_or_else(|| format!("relocation offset {:#x} is too large for Mach-O", r.offset))?;
+
+        // External relocations name a symbol, others a section ordinal
+        let (symbolnum, external, target_addr) = match symbol_index.get(r.symbol.as_str()) {
+            Some(&n) => (n, true, 0),
+            None => match obj.sections.iter().position(|s| s.name == r.symbol) {
+                Some(i) if type_ == RELOC_UNSIGNED && r.subtrahend.is_none() => (i as u32 + 1, false, addrs[i]),
+                // Like `leaq L_.str(%rip)` in clang's output
+                Some(i) if obj.arch == Architecture::X86_64 && kind == RelocKind::Relative32 && r.subtrahend.is_none() => {
+                    (i as u32 + 1, false, addrs[i])
+                }
+                Some(_) => return Err(format!("{:?} relocation against section {} needs a symbol in Mach-O", r.kind, r.symbol)),
+                None => return Err(format!("relocation against unknown symbol {}", r.symbol)),
+            },
+        };
+
+        let out = &mut relocs[r.section_index];
+        if let Some(subtrahend) = &r.subtrahend {
+            if type_ != RELOC_UNSIGNED {
+                return Err(format!("{:?} relocation cannot subtract {}", r.kind, subtrahend));
+            }
+            let &sub = symbol_index.get(subtrahend.as_str()).ok_or_else(|| format!("subtraction of unknown symbol {}", subtrahend))?;
+            MachReloc { address, symbolnum: sub, pcrel: false, length, external: true, type_: subtractor_type(obj.arch) }.put(out);
+        }
+
+        let field = match (obj.arch, type_) {
+            (_, RELOC_UNSIGNED) => Some(target_addr as i64 + r.addend),
+            (Architecture::X86_64, _) if external => Some(r.addend + 4),
+            // The displacement within the object's own address space
+            (Architecture::X86_64, _) => Some(target_addr as i64 + r.addend - (addrs[r.section_index] + r.offset as u64) as i64),
+            _ if r.addend == 0 => None,
+            _ if matches!(kind, RelocKind::ADR_GOT_PAGE | RelocKind::LD64_GOT_LO12_NC) => {
+                return Err(format!("GOT relocation against {} cannot have an addend", r.symbol));
+            }
+            _ => {
+                if !(-(1 << 23)..1 << 23).contains(&r.addend) {
+                    return Err(format!("addend {} against {} does not fit in ARM64_RELOC_ADDEND", r.addend, r.symbol));
+                }
+                MachReloc { address, symbolnum: r.addend as u32, pcrel: false, length: 2, external: false, type_: ARM64_RELOC_ADDEND }.put(out);
+                None
+            }
+        };
+        if let Some(value) = field {
+            let width = 1usize << length;
+            let data = contents[r.section_index]
+                .get_mut(r.offset..r.offset + width)
+                .ok_or_else(|| format!("relocation at {:#x} is outside section {}", r.offset, obj.sections[r.section_index].name))?;
+            data.copy_from_slice(&value.to_le_bytes()[..width]);
+        }
+        MachReloc { address, symbolnum, pcrel, length, external, type_ }.put(out);
+    }
+
+    let mut reloc_offsets = Vec::new();
+    offset = align_up(offset, 8);
+    for section_relocs in &relocs {
+        reloc_offsets.push(if section_relocs.is_empty() { 0 } else { offset });
+        offset += section_relocs.len() as u64;
+    }
+
+    // nlist_64 entries
+    let mut strtab = vec![0u8];
+    let mut symtab = Vec::new();
+    for &i in &order {
+        let sym = &symbols[i];
+        let strx = strtab.len() as u32;
+        strtab.push(b'_');
+        strtab.extend_from_slice(sym.name.as_bytes());
+        strtab.push(0);
+
+        let (mut type_, sect, value) = match sym.section_index {
+            Some(s) => (N_SECT, s as u8 + 1, addrs[s] + sym.value),
+            None if sym.absolute => (N_ABS, 0, sym.value),
+            None => (N_UNDF, 0, sym.value),
+        };
+        if sym.binding != SymbolBinding::Local {
+            type_ |= N_EXT;
+            if sym.visibility == SymbolVisibility::Hidden && type_ & N_TYPE != N_UNDF {
+                type_ |= N_PEXT;
+            }
+        }
+        let desc = match (sym.binding, type_ & N_TYPE) {
+            (SymbolBinding::Weak, N_UNDF) => N_WEAK_REF,
+            (SymbolBinding::Weak, _) => N_WEAK_DEF,
+            _ => 0,
+        };
+        symtab.extend_from_slice(&strx.to_le_bytes());
+        symtab.push(type_);
+        symtab.push(sect);
+        symtab.extend_from_slice(&desc.to_le_bytes());
+        symtab.extend_from_slice(&value.to_le_bytes());
+    }
+    strtab.resize(align_up(strtab.len() as u64, 8) as usize, 0);
+
+    let symoff = align_up(offset, 8);
+    let stroff = symoff + symtab.len() as u64;
+    if stroff + strtab.len() as u64 > u32::MAX as u64 {
+        return Err("object is too large for Mach-O".into());
+    }
+
+    let mut out = Vec::new();
+    for word in [MH_MAGIC_64, cputype, cpusubtype, MH_OBJECT, 4, cmds_size as u32, 0, 0] {
+        out.extend_from_slice(&word.to_le_bytes());
+    }
+
+    out.extend_from_slice(&LC_SEGMENT_64.to_le_bytes());
+    out.extend_from_slice(&((SEGMENT_SIZE + SECTION_SIZE * obj.sections.len() as u64) as u32).to_le_bytes());
+    out.extend_from_slice(&[0; 16]); // object files use a single unnamed segment
+    for value in [0, vmsize, data_start, filesize] {
+        out.extend_from_slice(&value.to_le_bytes());
+    }
+    for word in [7u32, 7, obj.sections.len() as u32, 0] {
+        out.extend_from_slice(&word.to_le_bytes()); // maxprot, initprot, nsects, flags
+    }
+    for (i, section) in obj.sections.iter().enumerate() {
+        let (segment, name) = macho_names(section)?;
+        out.extend_from_slice(&fixed16(&name));
+        out.extend_from_slice(&fixed16(&segment));
+        out.extend_from_slice(&addrs[i].to_le_bytes());
+        out.extend_from_slice(&(section.data.len() as u64).to_le_bytes());
+        for word in [
+            offsets[i] as u32,
+            section.align.max(1).trailing_zeros(),
+            reloc_offsets[i] as u32,
+            (relocs[i].len() as u64 / RELOC_SIZE) as u32,
+            section_flags(&segment, &name, section.kind),
+            0,
+            0,
+            0,
+        ] {
+            out.extend_from_slice(&word.to_le_bytes());
+        }
+    }
+
+    for word in [LC_BUILD_VERSION, BUILD_VERSION_SIZE as u32, PLATFORM_MACOS, minos, 0, 0] {
+        out.extend_from_slice(&word.to_le_bytes()); // no SDK version, no tools
+    }
+    for word in [LC_SYMTAB, SYMTAB_SIZE as u32, symoff as u32, order.len() as u32, stroff as u32, strtab.len() as u32] {
+        out.extend_from_slice(&word.to_le_bytes());
+    }
+    let mut dysymtab = [0u32; 20];
+    dysymtab[0] = LC_DYSYMTAB;
+    dysymtab[1] = DYSYMTAB_SIZE as u32;
+    dysymtab[3] = locals.len() as u32;
+    dysymtab[4] = locals.len() as u32;
+    dysymtab[5] = extdefs.len() as u32;
+    dysymtab[6] = (locals.len() + extdefs.len()) as u32;
+    dysymtab[7] = undefs.len() as u32;
+    for word in dysymtab {
+        out.extend_from_slice(&word.to_le_bytes());
+    }
+
+    for (i, section) in obj.sections.iter().enumerate() {
+        if !section.kind.is_nobits() {
+            out.resize(offsets[i] as usize, 0);
+            out.extend_from_slice(&contents[i]);
+        }
+    }
+    out.resize(align_up(out.len() as u64, 8) as usize, 0);
+    for section_relocs in &relocs {
+        out.extend_from_slice(section_relocs);
+    }
+    out.resize(symoff as usize, 0);
+    out.extend_from_slice(&symtab);
+    out.extend_from_slice(&strtab);
+
+    Ok(out)
+}
+
+/// Reads a 64-bit little-endian Mach-O object for x86-64 or arm64.
+///
+/// Section names are mapped back to their ELF-style names where one exists and
+/// kept as `__SEGMENT,__section` otherwise. One leading underscore is removed
+/// from symbol names; debugging (stab) entries are skipped.
+pub fn parse_macho(data: &[u8]) -> Result<ObjectFile, ParseError> {
+    let r = Reader { data };
+    if r.u32(0, "magic")? != MH_MAGIC_64 {
+        return Err(ParseError::new(0, "not a 64-bit little-endian Mach-O file"));
+    }
+    let arch = match r.u32(4, "cputype")? {
+        CPU_TYPE_X86_64 => Architecture::X86_64,
+        CPU_TYPE_ARM64 => Architecture::AArch64,
+        other => return Err(ParseError::new(4, format!("unsupported Mach-O CPU type {:#x}", other))),
+    };
+    let filetype = r.u32(12, "filetype")?;
+    if filetype != MH_OBJECT {
+        return Err(ParseError::new(12, format!("filetype {} is not a relocatable object (MH_OBJECT)", filetype)));
+    }
+    let ncmds = r.u32(16, "ncmds")?;
+
+    let mut obj = ObjectFile::new(ObjectFormat::MachO64);
+    obj.arch = arch;
+    let mut addrs = Vec::new();
+    let mut reloc_tables = Vec::new();
+    let mut symtab = None;
+
+    let mut cmd_offset = HEADER_SIZE;
+    for _ in 0..ncmds {
+        let cmd = r.u32(cmd_offset, "load command")?;
+        let cmdsize = r.u32(cmd_offset + 4, "cmdsize")? as u64;
+        if cmdsize < 8 {
+            return Err(ParseError::new(cmd_offset as usize + 4, format!("load command size {} is too small", cmdsize)));
+        }
+        match cmd {
+            LC_SEGMENT_64 => {
+                let nsects = r.u32(cmd_offset + 64, "nsects")? as u64;
+                for i in 0..nsects {
+                    let hdr = cmd_offset + SEGMENT_SIZE + SECTION_SIZE * i;
+                    let cstr = |bytes: &[u8]| {
+                        let len = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+                        String::from_utf8_lossy(&bytes[..len]).into_owned()
+                    };
+                    let name = cstr(r.bytes(hdr, 16, "sectname")?);
+                    let segment = cstr(r.bytes(hdr + 16, 16, "segname")?);
+                    let addr = r.u64(hdr + 32, "section addr")?;
+                    let size = r.u64(hdr + 40, "section size")?;
+                    let offset = r.u32(hdr + 48, "section offset")? as u64;
+                    let align = r.u32(hdr + 52, "section align")?;
+                    let reloff = r.u32(hdr + 56, "reloff")? as u64;
+                    let nreloc = r.u32(hdr + 60, "nreloc")? as u64;
+                    let flags = r.u32(hdr + 64, "section flags")?;
+
+                    let kind = section_kind(&segment, flags);
+                    let name = elf_section_name(&segment, &name);
+                    let data = if kind.is_nobits() {
+                        zero_fill(size, hdr + 40, &name)?
+                    } else {
+                        r.bytes(offset, size, &format!("contents of section {}", name))?.to_vec()
+                    };
+                    if align >= 64 {
+                        return Err(ParseError::new(hdr as usize + 52, format!("section alignment 2^{} is out of range", align)));
+                    }
+                    obj.sections.push(Section { data, ..Section::new(&name, kind, 1 << align) });
+                    addrs.push(addr);
+                    reloc_tables.push((reloff, nreloc));
+                }
+            }
+            LC_SYMTAB => {
+                symtab = Some((
+                    r.u32(cmd_offset + 8, "symoff")? as u64,
+                    r.u32(cmd_offset + 12, "nsyms")? as u64,
+                    r.u32(cmd_offset + 16, "stroff")? as u64,
+                    r.u32(cmd_offset + 20, "strsize")? as u64,
+                ));
+            }
+            _ => {}
+        }
+        cmd_offset += cmdsize;
+    }
+
+    // nlist index -> symbol name; stabs map to None
+    let mut names = Vec::new();
+    if let Some((symoff, nsyms, stroff, strsize)) = symtab {
+        let strings = r.bytes(stroff, strsize, "string table")?;
+        for i in 0..nsyms {
+            let off = symoff + NLIST_SIZE * i;
+            let strx = r.u32(off, "n_strx")? as usize;
+            let type_ = r.u8(off + 4, "n_type")?;
+            let sect = r.u8(off + 5, "n_sect")?;
+            let desc = r.u16(off + 6, "n_desc")?;
+            let value = r.u64(off + 8, "n_value")?;
+            if type_ & N_STAB != 0 {
+                names.push(None);
+                continue;
+            }
+
+            let raw = strings.get(strx..).and_then(|s| s.iter().position(|&b| b == 0).map(|len| &s[..len])).ok_or_else(|| {
+                ParseError::new(off as usize, format!("symbol name offset {} is outside the string table", strx))
+            })?;
+            let raw = String::from_utf8_lossy(raw);
+            let name = raw.strip_prefix('_').unwrap_or(&raw).to_string();
+
+            let (section_index, value) = match type_ & N_TYPE {
+                N_SECT => {
+                    let idx = (sect as usize).checked_sub(1).filter(|&i| i < obj.sections.len()).ok_or_else(|| {
+                        ParseError::new(off as usize + 5, format!("symbol {} refers to invalid section {}", name, sect))
+                    })?;
+                    (Some(idx), value.wrapping_sub(addrs[idx]))
+                }
+                _ => (None, value),
+            };
+            let binding = match (type_ & N_EXT != 0, desc & (N_WEAK_REF | N_WEAK_DEF) != 0) {
+                (false, _) => SymbolBinding::Local,
+                (true, false) => SymbolBinding::Global,
+                (true, true) => SymbolBinding::Weak,
+            };
+            names.push(Some(name.clone()));
+            obj.symbols.push(ObjectSymbol {
+                name,
+                section_index,
+                value,
+                size: 0,
+                binding,
+                visibility: if type_ & N_PEXT != 0 { SymbolVisibility::Hidden } else { SymbolVisibility::Default },
+                kind: SymbolKind::NoType,
+                absolute: type_ & N_TYPE == N_ABS,
+            });
+        }
+    }
+
+    for (section_index, &(reloff, nreloc)) in reloc_tables.iter().enumerate() {
+        let mut addend = None;
+        let mut subtrahend = None;
+        for i in 0..nreloc {
+            let off = reloff + RELOC_SIZE * i;
+            let rel = MachReloc::read(&r, off)?;
+            let target = |rel: &MachReloc| -> Result<(String, u64), ParseError> {
+                if rel.external {
+                    let name = names.get(rel.symbolnum as usize).cloned().flatten();
+                    name.map(|n| (n, 0)).ok_or_else(|| ParseError::new(off as usize + 4, format!("relocation refers to invalid symbol {}", rel.symbolnum)))
+                } else {
+                    let idx = (rel.symbolnum as usize).checked_sub(1).filter(|&i| i < obj.sections.len()).ok_or_else(|| {
+                        ParseError::new(off as usize + 4, format!("relocation refers to invalid section {}", rel.symbolnum))
+                    })?;
+                    Ok((obj.sections[idx].name.clone(), addrs[idx]))
+                }
+            };
+
+            if rel.type_ == subtractor_type(arch) && rel.external {
+                subtrahend = Some(target(&rel)?.0);
+                continue;
+            }
+            if arch == Architecture::AArch64 && rel.type_ == ARM64_RELOC_ADDEND {
+                // 24-bit signed addend in r_symbolnum
+                addend = Some(((rel.symbolnum << 8) as i32 >> 8) as i64);
+                continue;
+            }
+
+            let (symbol, target_addr) = target(&rel)?;
+            let offset = rel.address as usize;
+            let field = |width: usize| -> Result<i64, ParseError> {
+                let bytes = obj.sections[section_index].data.get(offset..offset + width).ok_or_else(|| {
+                    ParseError::new(off as usize, format!("relocation offset {:#x} is outside its section", offset))
+                })?;
+                let mut raw = [0u8; 8];
+                raw[..width].copy_from_slice(bytes);
+                // Sign-extend from the field width
+                let shift = 64 - 8 * width as u32;
+                Ok((i64::from_le_bytes(raw) << shift) >> shift)
+            };
+            let value = match (arch, rel.type_) {
+                (_, RELOC_UNSIGNED) => field(1 << rel.length)? - target_addr as i64,
+                (Architecture::X86_64, _) if rel.external => field(4)? - 4,
+                (Architecture::X86_64, _) => field(4)? + (addrs[section_index] + offset as u64) as i64 - target_addr as i64,
+                _ => addend.take().unwrap_or(0),
+            };
+            obj.relocations.push(ObjectRelocation {
+                section_index,
+                offset,
+                symbol,
+                addend: value,
+                kind: reloc_kind_from_macho(arch, rel.type_, rel.length),
+                subtrahend: subtrahend.take(),
+            });
+        }
+    }
+
+    Ok(obj)
+}
@@ -1 +1,4 @@
 pub mod elf;
+pub mod coff;
+pub mod macho;
+pub(crate) mod reader;
@@ -0,0 +1,51 @@
+use crate::core::error::ParseError;
+
+/// Largest section without file contents (`.bss`) the readers fill with zeros;
+/// a bigger size in a header is reported rather than allocated.
+pub(crate) const MAX_ZERO_FILL: u64 = 1 << 30;
+
+/// The contents of a section without file contents, whose size field is at `offset`.
+pub(crate) fn zero_fill(size: u64, offset: u64, name: &str) -> Result<Vec<u8>, ParseError> {
+    if size > MAX_ZERO_FILL {
+        return Err(ParseError::new(
+            offset as usize,
+            format!("section {} is {:#x} bytes, more than the {:#x} supported without file contents", name, size, MAX_ZERO_FILL),
+        ));
+    }
+    Ok(vec![0; size as usize])
+}
+
+/// Bounds-checked little-endian reads that report the offset of the failure.
+pub(crate) struct Reader<'a> {
+    pub data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    pub fn bytes(&self, offset: u64, len: u64, what: &str) -> Result<&'a [u8], ParseError> {
+        let start = usize::try_from(offset).ok();
+        let end = start.zip(usize::try_from(len).ok()).and_then(|(s, l)| s.checked_add(l));
+        match (start, end) {
+            (Some(start), Some(end)) if end <= self.data.len() => Ok(&self.data[start..end]),
+            _ => Err(ParseError::new(
+                offset as usize,
+                format!("{} ({} bytes) extends past the end of the file ({} bytes)", what, len, self.data.len()),
+            )),
+        }
+    }
+
+    pub fn u8(&self, offset: u64, what: &str) -> Result<u8, ParseError> {
+        Ok(self.bytes(offset, 1, what)?[0])
+    }
+
+    pub fn u16(&self, offset: u64, what: &str) -> Result<u16, ParseError> {
+        Ok(u16::from_le_bytes(self.bytes(offset, 2, what)?.try_into().unwrap()))
+    }
+
+    pub fn u32(&self, offset: u64, what: &str) -> Result<u32, ParseError> {
+        Ok(u32::from_le_bytes(self.bytes(offset, 4, what)?.try_into().unwrap()))
+    }
+
+    pub fn u64(&self, offset: u64, what: &str) -> Result<u64, ParseError> {
+        Ok(u64::from_le_bytes(self.bytes(offset, 8, what)?.try_into().unwrap()))
+    }
+}
@@ -1,7 +1,759 @@
 pub mod core;
 pub mod formats;
+pub mod archive;
+pub mod compression;
+pub mod diff;
+#[cfg(feature = "yaml")]
+pub mod yaml;
 
 pub use core::symbol::*;
 pub use core::section::*;
 pub use core::reloc::*;
 pub use core::object::*;
+pub use core::error::*;
+pub use core::arch::*;
+pub use core::endian::*;
+pub use core::validate::*;
+pub use core::property::*;
+pub use compression::Compression;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample() -> ObjectFile {
+        let mut obj = ObjectFile::new(ObjectFormat::ELF64);
+        let text = obj.add_section(".text", SectionKind::Text, 16);
+        obj.sections[text].data = vec![0xE8, 0, 0, 0, 0, 0xC3];
+        let note = obj.add_section(".note.test", SectionKind::Other(7), 4);
+        obj.sections[note].data = vec![1, 2, 3, 4];
+        obj.symbols.push(ObjectSymbol {
+            name: "main".into(),
+            section_index: Some(text),
+            value: 0,
+            size: 6,
+            binding: SymbolBinding::Global,
+            visibility: SymbolVisibility::Default,
+            kind: SymbolKind::NoType,
+            absolute: false,
+        });
+        obj.symbols.push(ObjectSymbol {
+            name: "puts".into(),
+            section_index: None,
+            value: 0,
+            size: 0,
+            binding: SymbolBinding::Global,
+            visibility: SymbolVisibility::Default,
+            kind: SymbolKind::NoType,
+            absolute: false,
+        });
+        obj.relocations.push(ObjectRelocation { section_index: text, offset: 1, symbol: "puts".into(), addend: -4, kind: RelocKind::PLT32, subtrahend: None });
+        obj.relocations.push(ObjectRelocation { section_index: text, offset: 1, symbol: "main".into(), addend: 0, kind: RelocKind::Raw(250), subtrahend: None });
+        obj
+    }
+
+    #[test]
+    fn elf_round_trip() {
+        let parsed = ObjectFile::parse(&sample().write().unwrap()).unwrap();
+
+        let sections: Vec<_> = parsed.sections.iter().map(|s| (s.name.as_str(), s.kind, s.data.len(), s.align)).collect();
+        assert_eq!(sections, vec![(".text", SectionKind::Text, 6, 16), (".note.test", SectionKind::Other(7), 4, 4)]);
+
+        let symbols: Vec<_> = parsed.symbols.iter().map(|s| (s.name.as_str(), s.section_index, s.size)).collect();
+        assert_eq!(symbols, vec![(".text", Some(0), 0), (".note.test", Some(1), 0), ("main", Some(0), 6), ("puts", None, 0)]);
+
+        let relocs: Vec<_> = parsed.relocations.iter().map(|r| (r.offset, r.symbol.as_str(), r.kind, r.addend)).collect();
+        assert_eq!(relocs, vec![(1, "puts", RelocKind::PLT32, -4), (1, "main", RelocKind::Raw(250), 0)]);
+    }
+
+    #[test]
+    fn elf32_i386_uses_rel_with_implicit_addends() {
+        let mut obj = ObjectFile::new(ObjectFormat::ELF32);
+        assert_eq!(obj.arch, Architecture::I386);
+        let text = obj.add_section(".text", SectionKind::Text, 16);
+        obj.sections[text].data = vec![0xE8, 0, 0, 0, 0, 0xA1, 0, 0, 0, 0, 0xC3];
+        obj.symbols.push(ObjectSymbol {
+            name: "counter".into(),
+            section_index: None,
+            value: 0,
+            size: 0,
+            binding: SymbolBinding::Global,
+            visibility: SymbolVisibility::Default,
+            kind: SymbolKind::NoType,
+            absolute: false,
+        });
+        obj.relocations.push(ObjectRelocation { section_index: text, offset: 1, symbol: "counter".into(), addend: -4, kind: RelocKind::PLT32, subtrahend: None });
+        obj.relocations.push(ObjectRelocation { section_index: text, offset: 6, symbol: "counter".into(), addend: 8, kind: RelocKind::Absolute32, subtrahend: None });
+
+        let bytes = obj.write().unwrap();
+        assert_eq!((bytes[4], u16::from_le_bytes([bytes[18], bytes[19]])), (1, 3)); // ELFCLASS32, EM_386
+
+        let parsed = ObjectFile::parse(&bytes).unwrap();
+        assert_eq!(parsed.arch, Architecture::I386);
+        assert_eq!(parsed.sections[0].data, vec![0xE8, 0xFC, 0xFF, 0xFF, 0xFF, 0xA1, 8, 0, 0, 0, 0xC3]);
+        let relocs: Vec<_> = parsed.relocations.iter().map(|r| (r.offset, r.kind, r.addend)).collect();
+        assert_eq!(relocs, vec![(1, RelocKind::PLT32, -4), (6, RelocKind::Absolute32, 8)]);
+
+        obj.relocations[0].kind = RelocKind::GOTPCREL;
+        assert!(obj.write().is_err());
+    }
+
+    #[test]
+    fn elf_riscv64_relocations_round_trip() {
+        let mut obj = ObjectFile::new(ObjectFormat::ELF64);
+        obj.arch = Architecture::RiscV64;
+        let text = obj.add_section(".text", SectionKind::Text, 4);
+        obj.sections[text].data = vec![0; 12];
+        obj.symbols.push(symbol("printf", None, 0, SymbolBinding::Global));
+        for (offset, kind) in [(0, RelocKind::CALL_PLT), (0, RelocKind::RELAX), (8, RelocKind::TLS_GD_HI20)] {
+            obj.relocations.push(ObjectRelocation { section_index: text, offset, symbol: "printf".into(), addend: 0, kind, subtrahend: None });
+        }
+
+        let bytes = obj.write().unwrap();
+        assert_eq!(u16::from_le_bytes([bytes[18], bytes[19]]), 243); // EM_RISCV
+        let parsed = ObjectFile::parse(&bytes).unwrap();
+        assert_eq!(parsed.arch, Architecture::RiscV64);
+        let kinds: Vec<_> = parsed.relocations.iter().map(|r| r.kind).collect();
+        assert_eq!(kinds, vec![RelocKind::CALL_PLT, RelocKind::RELAX, RelocKind::TLS_GD_HI20]);
+
+        assert!(RelocKind::CALL_PLT.is_pc_relative() && !RelocKind::CALL_PLT.uses_got());
+        assert!(RelocKind::TLS_GD_HI20.is_tls() && RelocKind::TLS_GD_HI20.uses_got());
+        assert_eq!(RelocKind::RELAX.field_size(Architecture::RiscV64), None);
+        assert_eq!(RelocKind::GlobDat.field_size(Architecture::I386), Some(4));
+
+        obj.relocations[0].kind = RelocKind::GOTPCRELX;
+        assert!(obj.write().is_err());
+    }
+
+    #[test]
+    fn elf_section_types_flags_and_links_round_trip() {
+        let mut obj = sample();
+        let strings = obj.add_elf_section(".rodata.str1.1", SectionType::PROGBITS, SectionFlags::ALLOC | SectionFlags::MERGE | SectionFlags::STRINGS, 1);
+        obj.sections[strings].data = b"hi\0".to_vec();
+        obj.sections[strings].entsize = 1;
+        let init = obj.add_elf_section(".init_array", SectionType::INIT_ARRAY, SectionFlags::WRITE | SectionFlags::ALLOC, 8);
+        obj.sections[init].data = vec![0; 8];
+        obj.sections[init].entsize = 8;
+        let order = obj.add_elf_section(".text.order", SectionType::PROGBITS, SectionFlags::ALLOC | SectionFlags::LINK_ORDER, 4);
+        obj.sections[order].link = Some(0);
+        obj.add_elf_section(".note.GNU-stack", SectionType::PROGBITS, SectionFlags::empty(), 1);
+        assert_eq!(obj.sections[strings].kind, SectionKind::ReadOnlyData);
+
+        let parsed = ObjectFile::parse(&obj.write().unwrap()).unwrap();
+        let sections: Vec<_> = parsed.sections[2..].iter().map(|s| (s.name.as_str(), s.section_type, s.flags, s.entsize, s.link)).collect();
+        assert_eq!(sections, vec![
+            (".rodata.str1.1", SectionType::PROGBITS, SectionFlags(0x32), 1, None),
+            (".init_array", SectionType::INIT_ARRAY, SectionFlags(0x3), 8, None),
+            (".text.order", SectionType::PROGBITS, SectionFlags(0x82), 0, Some(0)),
+            (".note.GNU-stack", SectionType::PROGBITS, SectionFlags::empty(), 0, None),
+        ]);
+        assert_eq!(parsed.sections[0].flags, SectionFlags::ALLOC | SectionFlags::EXECINSTR);
+    }
+
+    #[test]
+    fn references_into_mergeable_sections_keep_their_symbol() {
+        let mut obj = sample();
+        let strings = obj.add_elf_section(".rodata.str1.1", SectionType::PROGBITS, SectionFlags::ALLOC | SectionFlags::MERGE | SectionFlags::STRINGS, 1);
+        obj.sections[strings].data = b"abc\0xyz\0".to_vec();
+        obj.sections[strings].entsize = 1;
+        let consts = obj.add_elf_section(".rodata.cst8", SectionType::PROGBITS, SectionFlags::ALLOC | SectionFlags::MERGE, 8);
+        obj.sections[consts].data = vec![0; 16];
+        obj.sections[consts].entsize = 8;
+        let data = obj.add_section(".rodata", SectionKind::ReadOnlyData, 1);
+        obj.sections[data].data = vec![0; 8];
+        obj.symbols.push(symbol(".LC0", Some(strings), 4, SymbolBinding::Local));
+        obj.symbols.push(symbol(".LC1", Some(consts), 8, SymbolBinding::Local));
+        obj.symbols.push(symbol(".LC2", Some(data), 2, SymbolBinding::Local));
+        obj.sections[0].data.extend_from_slice(&[0; 12]);
+        for (offset, name) in [(6, ".LC0"), (10, ".LC1"), (14, ".LC2")] {
+            obj.relocations.push(ObjectRelocation { section_index: 0, offset, symbol: name.into(), addend: -4, kind: RelocKind::Relative32, subtrahend: None });
+        }
+
+        let parsed = ObjectFile::parse(&obj.write().unwrap()).unwrap();
+        let relocs: Vec<_> = parsed.relocations[2..].iter().map(|r| (r.symbol.as_str(), r.addend)).collect();
+        // Only the plain section is referenced through its section symbol
+        assert_eq!(relocs, vec![(".LC0", -4), (".LC1", -4), (".rodata", -2)]);
+    }
+
+    #[test]
+    fn elf_comdat_groups_round_trip() {
+        let mut obj = sample();
+        let text = obj.add_section(".text.max", SectionKind::Text, 16);
+        obj.sections[text].data = vec![0xE8, 0, 0, 0, 0, 0xC3];
+        let data = obj.add_section(".data.max", SectionKind::Data, 8);
+        obj.sections[data].data = vec![0; 8];
+        obj.symbols.push(symbol("max", Some(text), 0, SymbolBinding::Weak));
+        obj.relocations.push(ObjectRelocation { section_index: text, offset: 1, symbol: "puts".into(), addend: -4, kind: RelocKind::PLT32, subtrahend: None });
+        obj.add_comdat_group("max", vec![text, data]);
+
+        let parsed = ObjectFile::parse(&obj.write().unwrap()).unwrap();
+        let names: Vec<_> = parsed.sections.iter().map(|s| s.name.as_str()).collect();
+        assert_eq!(names, vec![".text", ".note.test", ".text.max", ".data.max"]);
+        let groups: Vec<_> = parsed.groups.iter().map(|g| (g.signature.as_str(), g.sections.clone(), g.comdat)).collect();
+        assert_eq!(groups, vec![("max", vec![2, 3], true)]);
+
+        obj.groups[0].signature = "nope".into();
+        assert!(obj.write().is_err());
+    }
+
+    #[test]
+    fn elf_symtab_orders_locals_first_and_uses_section_symbols() {
+        let mut obj = ObjectFile::new(ObjectFormat::ELF64);
+        let text = obj.add_section(".text", SectionKind::Text, 16);
+        obj.sections[text].data = vec![0x90; 16];
+        let mut start = symbol("_start", Some(text), 0, SymbolBinding::Global);
+        start.kind = SymbolKind::Func;
+        start.visibility = SymbolVisibility::Hidden;
+        obj.symbols.push(start);
+        obj.symbols.push(symbol(".loop", Some(text), 8, SymbolBinding::Local));
+        obj.relocations.push(ObjectRelocation { section_index: text, offset: 1, symbol: ".loop".into(), addend: -4, kind: RelocKind::Relative32, subtrahend: None });
+        obj.relocations.push(ObjectRelocation { section_index: text, offset: 6, symbol: ".loop".into(), addend: 0, kind: RelocKind::GOTPCREL, subtrahend: None });
+
+        let parsed = ObjectFile::parse(&obj.write().unwrap()).unwrap();
+        let symbols: Vec<_> = parsed.symbols.iter().map(|s| (s.name.as_str(), s.binding, s.kind, s.visibility)).collect();
+        assert_eq!(symbols, vec![
+            (".text", SymbolBinding::Local, SymbolKind::Section, SymbolVisibility::Default),
+            (".loop", SymbolBinding::Local, SymbolKind::NoType, SymbolVisibility::Default),
+            ("_start", SymbolBinding::Global, SymbolKind::Func, SymbolVisibility::Hidden),
+        ]);
+
+        // Local references go through the section symbol; GOT references keep the label
+        let relocs: Vec<_> = parsed.relocations.iter().map(|r| (r.symbol.as_str(), r.addend)).collect();
+        assert_eq!(relocs, vec![(".text", 4), (".loop", 0)]);
+    }
+
+    #[test]
+    fn unknown_relocation_symbols_follow_policy() {
+        let mut obj = sample();
+        obj.sections[0].data.extend_from_slice(&[0; 4]);
+        obj.relocations.push(ObjectRelocation { section_index: 0, offset: 6, symbol: "exit".into(), addend: 0, kind: RelocKind::Absolute32, subtrahend: None });
+        assert_eq!(obj.write().unwrap_err(), WriteError::UnknownSymbol { symbol: "exit".into(), section: ".text".into(), offset: 6 });
+
+        obj.unknown_symbols = UnknownSymbolPolicy::DeclareUndefined;
+        let parsed = ObjectFile::parse(&obj.write().unwrap()).unwrap();
+        let exit = parsed.symbols.iter().find(|s| s.name == "exit").unwrap();
+        assert_eq!((exit.section_index, exit.binding), (None, SymbolBinding::Global));
+        assert_eq!(parsed.relocations[2].symbol, "exit");
+    }
+
+    #[test]
+    fn coff_round_trip() {
+        let mut obj = ObjectFile::new(ObjectFormat::COFF);
+        let text = obj.add_section(".text", SectionKind::Text, 16);
+        obj.sections[text].data = vec![0xE8, 0, 0, 0, 0, 0xC3];
+        let rdata = obj.add_section(".rdata$unwind_info", SectionKind::ReadOnlyData, 8);
+        obj.sections[rdata].data = vec![0; 12];
+        let bss = obj.add_section(".bss", SectionKind::Bss, 4);
+        obj.sections[bss].data = vec![0; 32];
+        for (name, section_index, binding) in [
+            ("a_rather_long_function_name", Some(text), SymbolBinding::Global),
+            ("counter", Some(bss), SymbolBinding::Local),
+            ("ext", None, SymbolBinding::Global),
+        ] {
+            obj.symbols.push(ObjectSymbol {
+                name: name.into(),
+                section_index,
+                value: 0,
+                size: 0,
+                binding,
+                visibility: SymbolVisibility::Default,
+                kind: SymbolKind::NoType,
+                absolute: false,
+            });
+        }
+        obj.relocations.push(ObjectRelocation { section_index: text, offset: 1, symbol: "ext".into(), addend: -4, kind: RelocKind::Relative32, subtrahend: None });
+        obj.relocations.push(ObjectRelocation { section_index: rdata, offset: 0, symbol: "counter".into(), addend: 8, kind: RelocKind::Absolute64, subtrahend: None });
+        obj.relocations.push(ObjectRelocation { section_index: rdata, offset: 8, symbol: ".text".into(), addend: 0, kind: RelocKind::ADDR32NB, subtrahend: None });
+
+        let bytes = obj.write().unwrap();
+        let parsed = ObjectFile::parse(&bytes).unwrap();
+
+        let sections: Vec<_> = parsed.sections.iter().map(|s| (s.name.as_str(), s.kind, s.data.len(), s.align)).collect();
+        assert_eq!(sections, vec![(".text", SectionKind::Text, 6, 16), (".rdata$unwind_info", SectionKind::ReadOnlyData, 12, 8), (".bss", SectionKind::Bss, 32, 4)]);
+        // REL32 stores the addend relative to the end of the field
+        assert_eq!(parsed.sections[0].data[1..5], [0, 0, 0, 0]);
+
+        let symbols: Vec<_> = parsed.symbols.iter().map(|s| (s.name.as_str(), s.section_index, s.binding)).collect();
+        assert_eq!(symbols, vec![
+            ("a_rather_long_function_name", Some(0), SymbolBinding::Global),
+            ("counter", Some(2), SymbolBinding::Local),
+            ("ext", None, SymbolBinding::Global),
+        ]);
+
+        let relocs: Vec<_> = parsed.relocations.iter().map(|r| (r.section_index, r.offset, r.symbol.as_str(), r.kind, r.addend)).collect();
+        assert_eq!(relocs, vec![
+            (0, 1, "ext", RelocKind::Relative32, -4),
+            (1, 0, "counter", RelocKind::Absolute64, 8),
+            (1, 8, ".text", RelocKind::ADDR32NB, 0),
+        ]);
+
+        obj.relocations[0].kind = RelocKind::GOTPCREL;
+        assert!(obj.write().is_err());
+    }
+
+    fn symbol(name: &str, section_index: Option<usize>, value: u64, binding: SymbolBinding) -> ObjectSymbol {
+        ObjectSymbol { name: name.into(), section_index, value, size: 0, binding, visibility: SymbolVisibility::Default, kind: SymbolKind::NoType, absolute: false }
+    }
+
+    #[test]
+    fn macho_x86_64_round_trip() {
+        let mut obj = ObjectFile::new(ObjectFormat::MachO64);
+        let text = obj.add_section(".text", SectionKind::Text, 16);
+        obj.sections[text].data = vec![0xE8, 0, 0, 0, 0, 0x48, 0x8D, 0x05, 0, 0, 0, 0, 0xC3];
+        let data = obj.add_section(".data", SectionKind::Data, 8);
+        obj.sections[data].data = vec![0; 16];
+        obj.symbols.push(symbol("table", Some(data), 0, SymbolBinding::Local));
+        // lea rax, [rel .data + 8] without a symbol
+        obj.relocations.push(ObjectRelocation { section_index: text, offset: 8, symbol: ".data".into(), addend: 4, kind: RelocKind::Relative32, subtrahend: None });
+        obj.symbols.push(symbol("puts", None, 0, SymbolBinding::Global));
+        obj.symbols.push(symbol("main", Some(text), 0, SymbolBinding::Global));
+        obj.relocations.push(ObjectRelocation { section_index: text, offset: 1, symbol: "puts".into(), addend: -4, kind: RelocKind::PLT32, subtrahend: None });
+        obj.relocations.push(ObjectRelocation { section_index: data, offset: 0, symbol: "main".into(), addend: 2, kind: RelocKind::Absolute64, subtrahend: None });
+        obj.relocations.push(ObjectRelocation { section_index: data, offset: 8, symbol: "main".into(), addend: 0, kind: RelocKind::Absolute64, subtrahend: Some("table".into()) });
+
+        let bytes = obj.write().unwrap();
+        assert_eq!(&bytes[..8], &[0xCF, 0xFA, 0xED, 0xFE, 0x07, 0, 0, 0x01]);
+        let parsed = ObjectFile::parse(&bytes).unwrap();
+
+        let sections: Vec<_> = parsed.sections.iter().map(|s| (s.name.as_str(), s.kind, s.align)).collect();
+        assert_eq!(sections, vec![(".text", SectionKind::Text, 16), (".data", SectionKind::Data, 8)]);
+        // Locals first, then defined and undefined externals
+        let symbols: Vec<_> = parsed.symbols.iter().map(|s| (s.name.as_str(), s.section_index, s.value, s.binding)).collect();
+        assert_eq!(symbols, vec![
+            ("table", Some(1), 0, SymbolBinding::Local),
+            ("main", Some(0), 0, SymbolBinding::Global),
+            ("puts", None, 0, SymbolBinding::Global),
+        ]);
+        let relocs: Vec<_> = parsed.relocations.iter().map(|r| (r.offset, r.symbol.as_str(), r.kind, r.addend, r.subtrahend.as_deref())).collect();
+        assert_eq!(relocs, vec![
+            (8, ".data", RelocKind::Relative32, 4, None),
+            (1, "puts", RelocKind::PLT32, -4, None),
+            (0, "main", RelocKind::Absolute64, 2, None),
+            (8, "main", RelocKind::Absolute64, 0, Some("table")),
+        ]);
+    }
+
+    #[test]
+    fn macho_arm64_addends_use_addend_records() {
+        let mut obj = ObjectFile::new(ObjectFormat::MachO64);
+        obj.arch = Architecture::AArch64;
+        let text = obj.add_section(".text", SectionKind::Text, 4);
+        // adrp x0, msg@PAGE+16; add x0, x0, msg@PAGEOFF+16; bl puts
+        obj.sections[text].data = vec![0x00, 0x00, 0x00, 0x90, 0x00, 0x00, 0x00, 0x91, 0x00, 0x00, 0x00, 0x94];
+        obj.symbols.push(symbol("msg", None, 0, SymbolBinding::Global));
+        obj.symbols.push(symbol("puts", None, 0, SymbolBinding::Global));
+        obj.relocations.push(ObjectRelocation { section_index: text, offset: 0, symbol: "msg".into(), addend: 16, kind: RelocKind::ADR_PREL_PG_HI21, subtrahend: None });
+        obj.relocations.push(ObjectRelocation { section_index: text, offset: 4, symbol: "msg".into(), addend: 16, kind: RelocKind::PAGEOFF12, subtrahend: None });
+        obj.relocations.push(ObjectRelocation { section_index: text, offset: 8, symbol: "puts".into(), addend: 0, kind: RelocKind::CALL26, subtrahend: None });
+
+        let parsed = ObjectFile::parse(&obj.write().unwrap()).unwrap();
+        assert_eq!(parsed.arch, Architecture::AArch64);
+        // The instructions themselves are left untouched
+        assert_eq!(parsed.sections[0].data, obj.sections[0].data);
+        let relocs: Vec<_> = parsed.relocations.iter().map(|r| (r.offset, r.symbol.as_str(), r.kind, r.addend)).collect();
+        assert_eq!(relocs, vec![(0, "msg", RelocKind::ADR_PREL_PG_HI21, 16), (4, "msg", RelocKind::PAGEOFF12, 16), (8, "puts", RelocKind::CALL26, 0)]);
+
+        obj.relocations[0].kind = RelocKind::ADR_GOT_PAGE;
+        assert!(obj.write().is_err());
+    }
+
+    #[test]
+    fn big_endian_elf_round_trip() {
+        let mut obj = sample();
+        obj.arch = Architecture::PowerPC64;
+        obj.endian = Endian::Big;
+        obj.relocations[0].kind = RelocKind::REL24;
+        let mut bytes = Vec::new();
+        obj.write_to(&mut bytes).unwrap();
+        assert_eq!(bytes[5], 2, "EI_DATA is ELFDATA2MSB");
+        assert_eq!(&bytes[18..20], &[0, 21], "e_machine is EM_PPC64, big-endian");
+        let parsed = ObjectFile::parse(&bytes).unwrap();
+        assert_eq!((parsed.arch, parsed.endian), (Architecture::PowerPC64, Endian::Big));
+        assert_eq!(parsed.relocations[0].kind, RelocKind::REL24);
+        assert_eq!(parsed.relocations[0].addend, -4);
+
+        // MIPS o32 keeps addends in the instructions, around the opcode bits
+        let mut obj = ObjectFile::new(ObjectFormat::ELF32);
+        obj.arch = Architecture::Mips;
+        obj.endian = Endian::Big;
+        let text = obj.add_section(".text", SectionKind::Text, 4);
+        // jal 0; lui $2, 0; addiu $2, $2, 0
+        obj.sections[text].data = vec![0x0C, 0, 0, 0, 0x3C, 0x02, 0, 0, 0x24, 0x42, 0, 0];
+        obj.symbols.push(symbol("g", None, 0, SymbolBinding::Global));
+        for (offset, kind, addend) in [(0, RelocKind::MIPS_26, 8), (4, RelocKind::HI16, 0x1234_8000), (8, RelocKind::LO16, 0x1234_8000)] {
+            obj.relocations.push(ObjectRelocation { section_index: text, offset, symbol: "g".into(), addend, kind, subtrahend: None });
+        }
+        let parsed = ObjectFile::parse(&obj.write().unwrap()).unwrap();
+        assert_eq!(parsed.sections[0].data, vec![0x0C, 0, 0, 2, 0x3C, 0x02, 0x12, 0x35, 0x24, 0x42, 0x80, 0]);
+        let addends: Vec<_> = parsed.relocations.iter().map(|r| r.addend).collect();
+        assert_eq!(addends, vec![8, 0x1234_8000, -0x8000]);
+    }
+
+    #[test]
+    fn section_and_symbol_edits_keep_references_consistent() {
+        let mut obj = sample();
+        obj.relocations.truncate(1);
+        let debug = obj.add_section(".debug_info", SectionKind::Other(1), 1);
+        let data = obj.add_section(".data", SectionKind::Data, 8);
+        obj.sections[data].data = vec![0; 8];
+        obj.sections[data].link = Some(debug);
+        obj.symbols.push(symbol("table", Some(data), 0, SymbolBinding::Global));
+        obj.symbols.push(symbol("info", Some(debug), 0, SymbolBinding::Local));
+        obj.relocations.push(ObjectRelocation { section_index: debug, offset: 0, symbol: ".text".into(), addend: 0, kind: RelocKind::Absolute32, subtrahend: None });
+        obj.relocations.push(ObjectRelocation { section_index: data, offset: 0, symbol: ".text".into(), addend: 0, kind: RelocKind::Absolute64, subtrahend: None });
+
+        obj.remove_section(debug).unwrap();
+        assert_eq!(obj.find_section(".data"), Some(2));
+        assert!(obj.symbols.iter().all(|s| s.name != "info"));
+        assert_eq!(obj.symbols.iter().find(|s| s.name == "table").unwrap().section_index, Some(2));
+        assert_eq!(obj.sections[2].link, None);
+        let relocs: Vec<_> = obj.relocations.iter().map(|r| (r.section_index, r.symbol.as_str())).collect();
+        assert_eq!(relocs, vec![(0, "puts"), (2, ".text")]);
+
+        obj.rename_section(0, ".text.boot");
+        assert_eq!(obj.relocations[1].symbol, ".text.boot");
+        assert!(obj.rename_symbol("puts", "wave_puts"));
+        assert_eq!(obj.relocations[0].symbol, "wave_puts");
+        assert!(obj.set_symbol_binding("main", SymbolBinding::Weak));
+        assert!(!obj.set_symbol_binding("missing", SymbolBinding::Weak));
+        obj.set_section_flags(2, SectionFlags::ALLOC);
+        assert_eq!(obj.sections[2].kind, SectionKind::ReadOnlyData);
+
+        // wave_puts is still referenced and survives
+        assert_eq!(obj.strip_symbols(|_| true), 2);
+        let names: Vec<_> = obj.symbols.iter().map(|s| s.name.as_str()).collect();
+        assert_eq!(names, vec!["wave_puts"]);
+        assert!(ObjectFile::parse(&obj.write().unwrap()).is_ok());
+    }
+
+    #[test]
+    fn sections_still_referenced_elsewhere_are_not_removed() {
+        let mut obj = sample();
+        obj.relocations.truncate(1);
+        let eh_frame = obj.add_elf_section(".eh_frame", SectionType::PROGBITS, SectionFlags::ALLOC, 8);
+        obj.sections[eh_frame].data = vec![0; 16];
+        obj.relocations.push(ObjectRelocation { section_index: eh_frame, offset: 0, symbol: "main".into(), addend: 0, kind: RelocKind::Absolute64, subtrahend: None });
+        obj.relocations.push(ObjectRelocation { section_index: eh_frame, offset: 8, symbol: ".text".into(), addend: 0, kind: RelocKind::Absolute64, subtrahend: None });
+        let unwind = obj.add_elf_section(".unwind_info", SectionType::PROGBITS, SectionFlags::INFO_LINK, 1);
+        obj.sections[unwind].info = eh_frame as u32;
+
+        // sh_info of SHF_INFO_LINK sections is a section index and survives a round trip
+        let parsed = ObjectFile::parse(&obj.write().unwrap()).unwrap();
+        assert_eq!(parsed.sections[parsed.find_section(".unwind_info").unwrap()].info as usize, parsed.find_section(".eh_frame").unwrap());
+
+        let err = obj.remove_section(0).unwrap_err();
+        assert!(err.contains(".text") && err.contains(".eh_frame") && err.contains("main"), "{}", err);
+        obj.relocations.remove(1);
+        let err = obj.remove_section(0).unwrap_err();
+        assert!(err.contains("against .text"), "{}", err);
+        assert_eq!(obj.sections.len(), 4);
+        assert_eq!(obj.symbols.len(), 2);
+
+        obj.remove_section(1).unwrap();
+        assert_eq!(obj.sections[2].info, 1);
+        let removed = obj.remove_sections(|s| s.name == ".text" || s.name == ".eh_frame").unwrap();
+        assert_eq!(removed.iter().map(|s| s.name.as_str()).collect::<Vec<_>>(), vec![".text", ".eh_frame"]);
+        assert!(obj.relocations.is_empty());
+        assert_eq!(obj.sections[0].flags, SectionFlags::empty());
+        assert!(ObjectFile::parse(&obj.write().unwrap()).is_ok());
+    }
+
+    #[test]
+    fn archive_round_trip_with_symbol_index() {
+        use archive::{Archive, ArchiveMember};
+
+        let mut archive = Archive::new();
+        archive.members.push(ArchiveMember::new("main.o", sample().write().unwrap()));
+        archive.members.push(ArchiveMember::new("notes.txt", b"odd".to_vec()));
+        archive.members.push(ArchiveMember::new("a_rather_long_member_name.o", sample().write().unwrap()));
+
+        let bytes = archive.write().unwrap();
+        assert_eq!(&bytes[..8], b"!<arch>\n");
+        let parsed = Archive::parse(&bytes).unwrap();
+        assert_eq!(parsed.members, archive.members);
+        // Only defined globals are indexed; `puts` is undefined
+        let symbols: Vec<_> = parsed.symbols.iter().map(|s| (s.name.as_str(), s.member)).collect();
+        assert_eq!(symbols, vec![("main", 0), ("main", 2)]);
+        assert_eq!(parsed.find_symbol("main"), Some(0));
+
+        archive.thin = true;
+        let thin = Archive::parse(&archive.write().unwrap()).unwrap();
+        assert!(thin.thin);
+        assert_eq!(thin.members[2].name, "a_rather_long_member_name.o");
+        assert!(thin.members[2].data.is_empty());
+        assert_eq!(thin.members[2].size, archive.members[2].data.len() as u64);
+        assert_eq!(thin.symbols.len(), 2);
+    }
+
+    #[test]
+    fn compressed_debug_sections_round_trip() {
+        let mut obj = sample();
+        let debug = obj.add_elf_section(".debug_str", SectionType::PROGBITS, SectionFlags::MERGE | SectionFlags::STRINGS, 1);
+        obj.sections[debug].data = b"main\0puts\0".repeat(200);
+        assert_eq!(obj.compress_debug_sections(Some(Compression::Zlib)), 1);
+
+        let bytes = obj.write().unwrap();
+        assert!(bytes.len() < sample().write().unwrap().len() + 1000);
+        let parsed = ObjectFile::parse(&bytes).unwrap();
+        let section = &parsed.sections[parsed.find_section(".debug_str").unwrap()];
+        assert_eq!(section.data, obj.sections[debug].data);
+        assert_eq!(section.compression, Some(Compression::Zlib));
+        assert_eq!(section.flags, SectionFlags::MERGE | SectionFlags::STRINGS);
+
+        obj.compress_debug_sections(Some(Compression::Zstd));
+        obj.endian = Endian::Big;
+        obj.arch = Architecture::PowerPC64;
+        obj.relocations.clear();
+        let parsed = ObjectFile::parse(&obj.write().unwrap()).unwrap();
+        let section = &parsed.sections[parsed.find_section(".debug_str").unwrap()];
+        assert_eq!((section.compression, &section.data), (Some(Compression::Zstd), &obj.sections[debug].data));
+
+        // Allocated sections are never compressed
+        obj.sections[0].compression = Some(Compression::Zlib);
+        assert!(obj.write().is_err());
+    }
+
+    #[test]
+    fn decompression_stops_at_the_declared_size() {
+        use crate::compression::DecompressError;
+
+        let zeros = vec![0u8; 1 << 20];
+        for compression in [Compression::Zlib, Compression::Zstd] {
+            let packed = compression.compress(&zeros);
+            assert_eq!(compression.decompress(&packed, zeros.len()).unwrap(), zeros);
+            assert!(matches!(compression.decompress(&packed, 1000), Err(DecompressError::Corrupt(_))));
+        }
+
+        // A zstd frame of 3000 maximal RLE blocks would expand to about 375 MiB
+        let mut bomb = vec![0x28, 0xB5, 0x2F, 0xFD, 0x00, 0x00];
+        for i in 0..3000u32 {
+            let header = u32::from(i == 2999) | 1 << 1 | (128 * 1024) << 3;
+            bomb.extend_from_slice(&header.to_le_bytes()[..3]);
+            bomb.push(0xAA);
+        }
+        assert!(matches!(Compression::Zstd.decompress(&bomb, 1), Err(DecompressError::Corrupt(_))));
+    }
+
+    #[test]
+    fn diff_matches_by_name_and_reports_changed_bytes() {
+        use crate::diff::{diff, ByteRange, Change};
+
+        let old = sample();
+        assert!(diff(&old, &ObjectFile::parse(&old.write().unwrap()).unwrap()).is_empty());
+
+        let mut new = sample();
+        new.sections.swap(0, 1);
+        new.relocations.iter_mut().for_each(|r| r.section_index = 1);
+        new.symbols[0].section_index = Some(1);
+        new.sections[1].data[5] = 0x90;
+        new.symbols[0].size = 7;
+        new.symbols.push(symbol("helper", Some(1), 5, SymbolBinding::Local));
+        let d = diff(&old, &new);
+        assert!(d.added_sections.is_empty() && d.removed_sections.is_empty());
+        assert_eq!(d.changed_sections.len(), 1);
+        assert_eq!(d.changed_sections[0].ranges, vec![ByteRange { old: 5..6, new: 5..6 }]);
+        assert!(matches!(d.symbols[0], Change::Changed { old, new } if old.size == 6 && new.size == 7));
+        assert!(matches!(d.symbols[1], Change::Added(s) if s.name == "helper"));
+        assert!(d.relocations.is_empty());
+    }
+
+    #[test]
+    fn extended_section_numbering_round_trip() {
+        let mut obj = ObjectFile::new(ObjectFormat::ELF64);
+        for i in 0..0xFF10 {
+            let text = obj.add_section(&format!(".text.f{}", i), SectionKind::Text, 1);
+            obj.sections[text].data = vec![0xC3];
+        }
+        let last = obj.sections.len() - 1;
+        obj.symbols.push(symbol("last", Some(last), 0, SymbolBinding::Global));
+        obj.symbols.push(symbol("first", Some(0), 0, SymbolBinding::Global));
+
+        let bytes = obj.write().unwrap();
+        // e_shnum and e_shstrndx are escaped (ELF64 header fields at 60 and 62)
+        assert_eq!(&bytes[60..64], &[0, 0, 0xFF, 0xFF]);
+        let parsed = ObjectFile::parse(&bytes).unwrap();
+        assert_eq!(parsed.sections.len(), obj.sections.len());
+        assert!(parsed.find_section(".symtab_shndx").is_none());
+        let section_of = |name: &str| parsed.symbols.iter().find(|s| s.name == name).and_then(|s| s.section_index);
+        assert_eq!(section_of("last"), Some(last));
+        assert_eq!(section_of("first"), Some(0));
+
+        // A .symtab_shndx entry is only set for symbols whose st_shndx is SHN_XINDEX
+        let u16_at = |at: usize| u16::from_le_bytes(bytes[at..at + 2].try_into().unwrap()) as usize;
+        let u32_at = |at: usize| u32::from_le_bytes(bytes[at..at + 4].try_into().unwrap()) as usize;
+        let u64_at = |at: usize| u64::from_le_bytes(bytes[at..at + 8].try_into().unwrap()) as usize;
+        let shoff = u64_at(40);
+        let shdrs: Vec<_> = (0..u64_at(shoff + 32)).map(|i| shoff + 64 * i).collect();
+        let table = |type_: u32| shdrs.iter().find(|&&h| u32_at(h + 4) == type_ as usize).map(|&h| (u64_at(h + 24), u64_at(h + 32))).unwrap();
+        let (symtab, symtab_size) = table(2);
+        let (shndx, _) = table(18);
+        let mut escaped = 0;
+        for i in 0..symtab_size / 24 {
+            let (st_shndx, entry) = (u16_at(symtab + 24 * i + 6), u32_at(shndx + 4 * i));
+            assert_eq!(st_shndx == 0xFFFF, entry != 0, "symbol {}", i);
+            escaped += usize::from(entry != 0);
+        }
+        assert!(escaped > 2);
+    }
+
+    #[test]
+    fn validate_reports_every_problem() {
+        let mut obj = sample();
+        assert_eq!(obj.validate(), Ok(()));
+        obj.sections[0].align = 3;
+        obj.symbols[0].size = 7;
+        obj.relocations.push(ObjectRelocation { section_index: 0, offset: 3, symbol: "puts".into(), addend: 0, kind: RelocKind::Absolute16, subtrahend: None });
+        obj.relocations.push(ObjectRelocation { section_index: 5, offset: 0, symbol: "puts".into(), addend: 0, kind: RelocKind::Absolute32, subtrahend: None });
+        let problems = obj.validate().unwrap_err();
+        assert_eq!(
+            problems,
+            vec![
+                Diagnostic::BadAlignment { section: ".text".into(), align: 3 },
+                Diagnostic::SymbolOutOfBounds { symbol: "main".into(), section: ".text".into(), end: 7, section_size: 6 },
+                Diagnostic::RelocationSectionOutOfRange { relocation: 3, section_index: 5 },
+                Diagnostic::OverlappingRelocations { relocation: 2, other: 0, section: ".text".into(), offset: 3 },
+            ]
+        );
+        assert!(matches!(obj.write(), Err(WriteError::Invalid(p)) if p.len() == 4));
+
+        // RISC-V ADD/SUB pairs patch the same field
+        let mut rv = ObjectFile::new(ObjectFormat::ELF64);
+        rv.arch = Architecture::RiscV64;
+        let data = rv.add_section(".data", SectionKind::Data, 8);
+        rv.sections[data].data = vec![0; 4];
+        for kind in [RelocKind::ADD32, RelocKind::SUB32] {
+            rv.relocations.push(ObjectRelocation { section_index: data, offset: 0, symbol: "x".into(), addend: 0, kind, subtrahend: None });
+        }
+        assert_eq!(rv.validate(), Ok(()));
+    }
+
+    #[test]
+    fn gnu_property_notes_round_trip() {
+        for (format, arch, word) in [(ObjectFormat::ELF64, Architecture::X86_64, 8), (ObjectFormat::ELF32, Architecture::I386, 4)] {
+            let mut obj = sample();
+            obj.format = format;
+            obj.arch = arch;
+            obj.relocations.clear();
+            obj.set_executable_stack(false);
+            obj.gnu_properties.x86_features = Some(X86Features::IBT | X86Features::SHSTK);
+            obj.gnu_properties.other.push((0xC000_8002, vec![1, 0, 0, 0]));
+
+            let bytes = obj.write().unwrap();
+            // One note, properties sorted by type and padded to the word size
+            let mut note = vec![4, 0, 0, 0, (8 + word) as u8 * 2, 0, 0, 0, 5, 0, 0, 0];
+            note.extend_from_slice(b"GNU\0");
+            note.extend_from_slice(&[2, 0, 0, 0xC0, 4, 0, 0, 0, 3, 0, 0, 0]);
+            note.resize(note.len() + word - 4, 0);
+            note.extend_from_slice(&[2, 0x80, 0, 0xC0, 4, 0, 0, 0, 1, 0, 0, 0]);
+            note.resize(note.len() + word - 4, 0);
+            let at = bytes.windows(note.len()).position(|w| w == note.as_slice()).unwrap();
+            assert_eq!(at % word, 0);
+
+            let parsed = ObjectFile::parse(&bytes).unwrap();
+            assert_eq!(parsed.gnu_properties, obj.gnu_properties);
+            assert!(parsed.find_section(".note.gnu.property").is_none());
+            assert_eq!(parsed.executable_stack(), Some(false));
+        }
+
+        let mut obj = sample();
+        assert_eq!(obj.executable_stack(), None);
+        obj.set_executable_stack(true);
+        obj.set_executable_stack(true);
+        assert_eq!(obj.executable_stack(), Some(true));
+        assert_eq!(obj.sections.len(), 3);
+
+        obj.gnu_properties.x86_features = Some(X86Features::IBT);
+        obj.arch = Architecture::AArch64;
+        assert!(obj.write().is_err());
+        obj.format = ObjectFormat::COFF;
+        obj.arch = Architecture::X86_64;
+        assert!(obj.write().is_err());
+    }
+
+    #[cfg(feature = "yaml")]
+    #[test]
+    fn yaml_description_builds_the_same_object() {
+        let text = "
+format: ELF64
+sections:
+  - name: .text
+    kind: Text
+    align: 16
+    data: e8 00 00 00 00 c3
+  - name: .note.test
+    kind: 7
+    align: 4
+    data: '01020304'
+symbols:
+  - name: main
+    section: .text
+    size: 6
+    binding: Global
+  - name: puts
+    binding: Global
+relocations:
+  - section: .text
+    offset: 1
+    symbol: puts
+    addend: -4
+    kind: PLT32
+  - section: .text
+    offset: 1
+    symbol: main
+    kind: 250
+";
+        let obj = ObjectFile::from_yaml(text).unwrap();
+        assert_eq!(obj.write().unwrap(), sample().write().unwrap());
+
+        // Sections sharing a name are referred to by index
+        let mut obj = sample();
+        let text = obj.add_section(".text", SectionKind::Text, 1);
+        obj.sections[text].data = vec![0xC3];
+        obj.add_comdat_group("f", vec![text]);
+        obj.symbols.push(symbol("f", Some(text), 0, SymbolBinding::Weak));
+        let yaml = obj.to_yaml();
+        assert!(yaml.contains("sections:\n  - 2\n"), "{}", yaml);
+        assert!(diff::diff(&obj, &ObjectFile::from_yaml(&yaml).unwrap()).is_empty());
+
+        let err = ObjectFile::from_yaml("format: ELF64\nsymbols:\n  - name: x\n    section: .data\n").unwrap_err();
+        assert_eq!(err, "symbol x: no section named '.data'");
+    }
+
+    fn parse_error(bytes: &[u8]) -> ParseError {
+        match ObjectFile::parse(bytes) {
+            Err(e) => e,
+            Ok(_) => panic!("malformed input was accepted"),
+        }
+    }
+
+    #[test]
+    fn malformed_input_reports_offset() {
+        let bytes = sample().write().unwrap();
+        let err = parse_error(&bytes[..100]);
+        assert!(err.offset > 100, "{}", err);
+
+        let mut wrong_class = bytes.clone();
+        wrong_class[4] = 3;
+        assert_eq!(parse_error(&wrong_class).offset, 4);
+
+        assert_eq!(parse_error(b"MZ").offset, 0);
+
+        // A .bss size is not allocated beyond what a header can sensibly ask for
+        let mut obj = sample();
+        let bss = obj.add_section(".bss", SectionKind::Bss, 8);
+        obj.sections[bss].data = vec![0; 16];
+        let mut bytes = obj.write().unwrap();
+        let shoff = u64::from_le_bytes(bytes[40..48].try_into().unwrap()) as usize;
+        let header = (0..).map(|i| shoff + 64 * i).find(|&h| bytes[h + 4] == 8).unwrap(); // SHT_NOBITS
+        bytes[header + 32..header + 40].copy_from_slice(&u64::MAX.to_le_bytes());
+        assert_eq!(parse_error(&bytes).offset, header + 32);
+
+        obj.format = ObjectFormat::COFF;
+        obj.relocations.clear();
+        let mut bytes = obj.write().unwrap();
+        let header = 20 + 40 * 2; // the third section header
+        assert_eq!(&bytes[header..header + 4], b".bss");
+        bytes[header + 16..header + 20].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert_eq!(parse_error(&bytes).offset, header + 16);
+    }
+}
@@ -0,0 +1,516 @@
+//! A textual description of objects in the spirit of LLVM's yaml2obj and
+//! obj2yaml: section contents are hex strings, and symbols, relocations and
+//! groups refer to sections by name. Tests can describe their inputs this way
+//! instead of building them byte by byte, and any object can be turned into an
+//! editable fixture with [`ObjectFile::to_yaml`].
+//!
+//! ```yaml
+//! format: ELF64
+//! arch: X86_64
+//! sections:
+//!   - name: .text
+//!     kind: Text
+//!     align: 16
+//!     data: e800000000c3
+//! symbols:
+//!   - name: main
+//!     section: .text
+//!     size: 6
+//!     binding: Global
+//!   - name: puts
+//!     binding: Global
+//! relocations:
+//!   - section: .text
+//!     offset: 1
+//!     symbol: puts
+//!     addend: -4
+//!     kind: PLT32
+//! ```
+//!
+//! The types are plain serde types, so JSON (which is also valid YAML) or any
+//! other serde format works as well.
+
+use std::collections::HashMap;
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::compression::Compression;
+use crate::core::arch::Architecture;
+use crate::core::endian::Endian;
+use crate::core::object::{ObjectFile, ObjectFormat};
+use crate::core::property::{GnuProperties, X86Features};
+use crate::core::reloc::{ObjectRelocation, RelocKind};
+use crate::core::section::{Section, SectionFlags, SectionGroup, SectionKind, SectionType};
+use crate::core::symbol::{ObjectSymbol, SymbolBinding, SymbolKind, SymbolVisibility};
+
+/// Names of the section flags, in the order they are written.
+const FLAG_NAMES: [(&str, SectionFlags); 11] = [
+    ("WRITE", SectionFlags::WRITE),
+    ("ALLOC", SectionFlags::ALLOC),
+    ("EXECINSTR", SectionFlags::EXECINSTR),
+    ("MERGE", SectionFlags::MERGE),
+    ("STRINGS", SectionFlags::STRINGS),
+    ("INFO_LINK", SectionFlags::INFO_LINK),
+    ("LINK_ORDER", SectionFlags::LINK_ORDER),
+    ("GROUP", SectionFlags::GROUP),
+    ("TLS", SectionFlags::TLS),
+    ("COMPRESSED", SectionFlags::COMPRESSED),
+    ("EXCLUDE", SectionFlags::EXCLUDE),
+];
+
+/// A section, by name or by index into `sections`. Names are used unless
+/// several sections share one.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum SectionRef {
+    Index(usize),
+    Name(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ObjectYaml {
+    pub format: ObjectFormat,
+    /// Defaults to the architecture [`ObjectFile::new`] picks for `format`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub arch: Option<Architecture>,
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub endian: Endian,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub sections: Vec<SectionYaml>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub symbols: Vec<SymbolYaml>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub relocations: Vec<RelocationYaml>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub groups: Vec<GroupYaml>,
+    /// The `.note.gnu.property` contents; ELF only.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub properties: Option<PropertiesYaml>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SectionYaml {
+    pub name: String,
+    #[serde(with = "section_kind")]
+    pub kind: SectionKind,
+    #[serde(default = "one")]
+    pub align: u64,
+    /// ELF `sh_type`, when it is not the one `kind` implies.
+    #[serde(rename = "type", default, skip_serializing_if = "Option::is_none")]
+    pub section_type: Option<u32>,
+    /// Flag names such as `ALLOC`, or hex numbers for other bits, when they are
+    /// not the flags `kind` implies.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub flags: Option<Vec<String>>,
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub entsize: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub link: Option<SectionRef>,
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub info: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub compression: Option<Compression>,
+    /// Contents as hex; whitespace between bytes is ignored.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub data: String,
+    /// Size of the section; `data` is padded with zeros up to it. Written
+    /// instead of `data` for sections without file contents such as `.bss`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub size: Option<u64>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SymbolYaml {
+    pub name: String,
+    /// Defining section; undefined symbols have none.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub section: Option<SectionRef>,
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub value: u64,
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub size: u64,
+    #[serde(default = "local")]
+    pub binding: SymbolBinding,
+    #[serde(default = "default_visibility", skip_serializing_if = "is_default_visibility")]
+    pub visibility: SymbolVisibility,
+    #[serde(default = "no_type", skip_serializing_if = "is_no_type")]
+    pub kind: SymbolKind,
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub absolute: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RelocationYaml {
+    pub section: SectionRef,
+    pub offset: usize,
+    pub symbol: String,
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub addend: i64,
+    #[serde(with = "reloc_kind")]
+    pub kind: RelocKind,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub subtrahend: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct GroupYaml {
+    pub signature: String,
+    pub sections: Vec<SectionRef>,
+    #[serde(default)]
+    pub comdat: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PropertiesYaml {
+    /// `IBT`, `SHSTK`, or hex numbers for other bits.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub x86_features: Option<Vec<String>>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub other: Vec<PropertyYaml>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PropertyYaml {
+    #[serde(rename = "type")]
+    pub pr_type: u32,
+    /// `pr_data` as hex.
+    #[serde(default)]
+    pub data: String,
+}
+
+/// `SectionKind::Other` and `RelocKind::Raw` are written as bare numbers, so
+/// descriptions need no YAML tags and stay valid JSON.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum NumberOr<T> {
+    Number(u32),
+    Named(T),
+}
+
+mod section_kind {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(kind: &SectionKind, serializer: S) -> Result<S::Ok, S::Error> {
+        match kind {
+            SectionKind::Other(sh_type) => serializer.serialize_u32(*sh_type),
+            kind => kind.serialize(serializer),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<SectionKind, D::Error> {
+        Ok(match NumberOr::deserialize(deserializer)? {
+            NumberOr::Number(sh_type) => SectionKind::Other(sh_type),
+            NumberOr::Named(kind) => kind,
+        })
+    }
+}
+
+mod reloc_kind {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(kind: &RelocKind, serializer: S) -> Result<S::Ok, S::Error> {
+        match kind {
+            RelocKind::Raw(r_type) => serializer.serialize_u32(*r_type),
+            kind => kind.serialize(serializer),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<RelocKind, D::Error> {
+        Ok(match NumberOr::deserialize(deserializer)? {
+            NumberOr::Number(r_type) => RelocKind::Raw(r_type),
+            NumberOr::Named(kind) => kind,
+        })
+    }
+}
+
+fn is_default<T: Default + PartialEq>(value: &T) -> bool {
+    *value == T::default()
+}
+
+fn one() -> u64 {
+    1
+}
+
+fn local() -> SymbolBinding {
+    SymbolBinding::Local
+}
+
+fn default_visibility() -> SymbolVisibility {
+    SymbolVisibility::Default
+}
+
+fn is_default_visibility(visibility: &SymbolVisibility) -> bool {
+    *visibility == SymbolVisibility::Default
+}
+
+fn no_type() -> SymbolKind {
+    SymbolKind::NoType
+}
+
+fn is_no_type(kind: &SymbolKind) -> bool {
+    *kind == SymbolKind::NoType
+}
+
+fn flag_names(flags: SectionFlags) -> Vec<String> {
+    let mut names = Vec::new();
+    let mut rest = flags.0;
+    for (name, flag) in FLAG_NAMES {
+        if flags.contains(flag) {
+            names.push(name.to_string());
+            rest &= !flag.0;
+        }
+    }
+    if rest != 0 {
+        names.push(format!("{:#x}", rest));
+    }
+    names
+}
+
+fn parse_flags(names: &[String]) -> Result<SectionFlags, String> {
+    let mut flags = SectionFlags::empty();
+    for name in names {
+        if let Some((_, flag)) = FLAG_NAMES.iter().find(|(n, _)| n == name) {
+            flags |= *flag;
+        } else if let Some(bits) = name.strip_prefix("0x").and_then(|hex| u64::from_str_radix(hex, 16).ok()) {
+            flags |= SectionFlags(bits);
+        } else {
+            return Err(format!("unknown section flag '{}'", name));
+        }
+    }
+    Ok(flags)
+}
+
+const X86_FEATURE_NAMES: [(&str, X86Features); 2] = [("IBT", X86Features::IBT), ("SHSTK", X86Features::SHSTK)];
+
+fn x86_feature_names(features: X86Features) -> Vec<String> {
+    let mut names = Vec::new();
+    let mut rest = features.0;
+    for (name, bit) in X86_FEATURE_NAMES {
+        if features.contains(bit) {
+            names.push(name.to_string());
+            rest &= !bit.0;
+        }
+    }
+    if rest != 0 {
+        names.push(format!("{:#x}", rest));
+    }
+    names
+}
+
+fn parse_x86_features(names: &[String]) -> Result<X86Features, String> {
+    let mut features = X86Features::empty();
+    for name in names {
+        if let Some((_, bit)) = X86_FEATURE_NAMES.iter().find(|(n, _)| n == name) {
+            features |= *bit;
+        } else if let Some(bits) = name.strip_prefix("0x").and_then(|hex| u32::from_str_radix(hex, 16).ok()) {
+            features |= X86Features(bits);
+        } else {
+            return Err(format!("unknown x86 feature '{}'", name));
+        }
+    }
+    Ok(features)
+}
+
+fn to_hex(data: &[u8]) -> String {
+    data.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn from_hex(text: &str) -> Option<Vec<u8>> {
+    let digits: Vec<u8> = text.bytes().filter(|b| !b.is_ascii_whitespace()).collect();
+    if !digits.len().is_multiple_of(2) {
+        return None;
+    }
+    digits
+        .chunks(2)
+        .map(|pair| std::str::from_utf8(pair).ok().and_then(|s| u8::from_str_radix(s, 16).ok()))
+        .collect()
+}
+
+impl From<&ObjectFile> for ObjectYaml {
+    fn from(obj: &ObjectFile) -> Self {
+        let mut name_count: HashMap<&str, usize> = HashMap::new();
+        for s in &obj.sections {
+            *name_count.entry(s.name.as_str()).or_default() += 1;
+        }
+        let section_ref = |index: usize| match obj.sections.get(index) {
+            Some(s) if name_count[s.name.as_str()] == 1 => SectionRef::Name(s.name.clone()),
+            _ => SectionRef::Index(index),
+        };
+
+        let sections = obj
+            .sections
+            .iter()
+            .map(|s| {
+                let nobits = s.is_nobits() && s.data.iter().all(|&b| b == 0);
+                SectionYaml {
+                    name: s.name.clone(),
+                    kind: s.kind,
+                    align: s.align,
+                    section_type: (s.section_type != s.kind.elf_type()).then_some(s.section_type.0),
+                    flags: (s.flags != s.kind.elf_flags()).then(|| flag_names(s.flags)),
+                    entsize: s.entsize,
+                    link: s.link.map(section_ref),
+                    info: s.info,
+                    compression: s.compression,
+                    data: if nobits { String::new() } else { to_hex(&s.data) },
+                    size: nobits.then_some(s.data.len() as u64),
+                }
+            })
+            .collect();
+
+        // Writers generate section symbols themselves
+        let symbols = obj
+            .symbols
+            .iter()
+            .filter(|s| s.kind != SymbolKind::Section)
+            .map(|s| SymbolYaml {
+                name: s.name.clone(),
+                section: s.section_index.map(section_ref),
+                value: s.value,
+                size: s.size,
+                binding: s.binding,
+                visibility: s.visibility,
+                kind: s.kind,
+                absolute: s.absolute,
+            })
+            .collect();
+
+        let relocations = obj
+            .relocations
+            .iter()
+            .map(|r| RelocationYaml {
+                section: section_ref(r.section_index),
+                offset: r.offset,
+                symbol: r.symbol.clone(),
+                addend: r.addend,
+                kind: r.kind,
+                subtrahend: r.subtrahend.clone(),
+            })
+            .collect();
+
+        let groups = obj
+            .groups
+            .iter()
+            .map(|g| GroupYaml {
+                signature: g.signature.clone(),
+                sections: g.sections.iter().map(|&i| section_ref(i)).collect(),
+                comdat: g.comdat,
+            })
+            .collect();
+
+        let properties = (!obj.gnu_properties.is_empty()).then(|| PropertiesYaml {
+            x86_features: obj.gnu_properties.x86_features.map(x86_feature_names),
+            other: obj.gnu_properties.other.iter().map(|(pr_type, data)| PropertyYaml { pr_type: *pr_type, data: to_hex(data) }).collect(),
+        });
+
+        ObjectYaml { format: obj.format, arch: Some(obj.arch), endian: obj.endian, sections, symbols, relocations, groups, properties }
+    }
+}
+
+impl ObjectYaml {
+    /// Builds the object. Fails on unknown section names, out-of-range section
+    /// indices, bad hex and unknown flag or feature names.
+    pub fn into_object(self) -> Result<ObjectFile, String> {
+        let mut obj = ObjectFile::new(self.format);
+        if let Some(arch) = self.arch {
+            obj.arch = arch;
+        }
+        obj.endian = self.endian;
+
+        // A name refers to the first section with that name
+        let count = self.sections.len();
+        let mut by_name: HashMap<String, usize> = HashMap::new();
+        for (i, s) in self.sections.iter().enumerate() {
+            by_name.entry(s.name.clone()).or_insert(i);
+        }
+        let resolve = |r: &SectionRef| match r {
+            SectionRef::Index(i) if *i < count => Ok(*i),
+            SectionRef::Index(i) => Err(format!("section index {} is out of range", i)),
+            SectionRef::Name(name) => by_name.get(name).copied().ok_or_else(|| format!("no section named '{}'", name)),
+        };
+
+        for s in self.sections {
+            let mut data = from_hex(&s.data).ok_or_else(|| format!("section {}: data is not a hex string", s.name))?;
+            if let Some(size) = s.size {
+                if (data.len() as u64) > size {
+                    return Err(format!("section {}: data is longer than its size {}", s.name, size));
+                }
+                data.resize(size as usize, 0);
+            }
+            let mut section = Section::new(&s.name, s.kind, s.align);
+            if let Some(section_type) = s.section_type {
+                section.section_type = SectionType(section_type);
+            }
+            if let Some(flags) = &s.flags {
+                section.flags = parse_flags(flags).map_err(|e| format!("section {}: {}", s.name, e))?;
+            }
+            section.data = data;
+            section.entsize = s.entsize;
+            section.link = s.link.as_ref().map(resolve).transpose().map_err(|e| format!("section {}: {}", s.name, e))?;
+            section.info = s.info;
+            section.compression = s.compression;
+            obj.sections.push(section);
+        }
+
+        for s in self.symbols {
+            obj.symbols.push(ObjectSymbol {
+                section_index: s.section.as_ref().map(resolve).transpose().map_err(|e| format!("symbol {}: {}", s.name, e))?,
+                name: s.name,
+                value: s.value,
+                size: s.size,
+                binding: s.binding,
+                visibility: s.visibility,
+                kind: s.kind,
+                absolute: s.absolute,
+            });
+        }
+
+        for (i, r) in self.relocations.into_iter().enumerate() {
+            obj.relocations.push(ObjectRelocation {
+                section_index: resolve(&r.section).map_err(|e| format!("relocation {}: {}", i, e))?,
+                offset: r.offset,
+                symbol: r.symbol,
+                addend: r.addend,
+                kind: r.kind,
+                subtrahend: r.subtrahend,
+            });
+        }
+
+        for g in self.groups {
+            let sections = g.sections.iter().map(resolve).collect::<Result<_, _>>().map_err(|e| format!("group {}: {}", g.signature, e))?;
+            obj.groups.push(SectionGroup { signature: g.signature, sections, comdat: g.comdat });
+        }
+
+        if let Some(p) = self.properties {
+            let x86_features = p.x86_features.as_deref().map(parse_x86_features).transpose().map_err(|e| format!("properties: {}", e))?;
+            let other = p
+                .other
+                .into_iter()
+                .map(|o| from_hex(&o.data).map(|data| (o.pr_type, data)).ok_or_else(|| format!("property {:#x}: data is not a hex string", o.pr_type)))
+                .collect::<Result<_, _>>()?;
+            obj.gnu_properties = GnuProperties { x86_features, other };
+        }
+
+        Ok(obj)
+    }
+}
+
+impl ObjectFile {
+    /// Reads an object from its YAML (or JSON) description.
+    pub fn from_yaml(text: &str) -> Result<ObjectFile, String> {
+        let description: ObjectYaml = serde_yaml::from_str(text).map_err(|e| e.to_string())?;
+        description.into_object()
+    }
+
+    /// Describes the object as YAML, in the form [`ObjectFile::from_yaml`] reads.
+    pub fn to_yaml(&self) -> String {
+        serde_yaml::to_string(&ObjectYaml::from(self)).expect("object descriptions always serialize")
+    }
+}
@@ -11,9 +11,13 @@ pub fn run() {
     };
 
     match cmd.as_str() {
+        "ar" => commands::ar::run(args.collect()),
         "asm" => commands::asm::run(args.collect()),
         "object" => commands::object::run(args.collect()),
+        "objcopy" => commands::objcopy::run(args.collect()),
+        "objdiff" => commands::objdiff::run(args.collect()),
         "link" => commands::linker::run(args.collect()),
+        "readobj" => commands::readobj::run(args.collect()),
         "ir" => commands::ir::run(args.collect()),
         _ => {
             eprintln!("Unknown command: {}", cmd);
@@ -24,14 +28,22 @@ pub fn run() {
 
 fn print_help() {
     println!("Usage:");
+    println!("  whale ar <rcs|t|x>[modifiers] <archive> [files...]");
     println!("  whale asm [--amd64 | --aarch64] <input> -o <output>");
     println!("  whale object <input> -o <output>");
+    println!("  whale objcopy [options] <input> [<output>]");
+    println!("  whale objdiff [-q] <old.o> <new.o>");
     println!("  whale link <...>");
+    println!("  whale readobj [options] <object>");
     println!("  whale ir <subcommand> [options]");
     println!();
     println!("Commands:");
+    println!("  ar      Create, list and extract static archives");
     println!("  asm     Assemble source file");
     println!("  object  Generate object file from binary or IR");
+    println!("  objcopy Strip, rename, add, remove and extract sections and symbols");
+    println!("  objdiff Compare the sections, symbols and relocations of two objects");
     println!("  link    Link object files into an executable");
+    println!("  readobj Show the headers, sections, symbols and relocations of an object");
     println!("  ir      IR tools (lower/print/verify demos)");
 }
@@ -0,0 +1,234 @@
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use std::process;
+use std::time::UNIX_EPOCH;
+
+use object::archive::{Archive, ArchiveMember};
+
+pub fn run(args: Vec<String>) {
+    if args.len() < 2 || args[0] == "--help" {
+        print_help();
+        return;
+    }
+
+    let spec = args[0].trim_start_matches('-');
+    let archive_path = &args[1];
+    let files = &args[2..];
+
+    let mut operation = None;
+    let mut create = false;
+    let mut thin = false;
+    let mut deterministic = true;
+    let mut verbose = false;
+    for c in spec.chars() {
+        match c {
+            'r' | 't' | 'x' if operation.is_none() => operation = Some(c),
+            'c' => create = true,
+            // The symbol index is always written
+            's' => {}
+            'T' => thin = true,
+            'D' => deterministic = true,
+            'U' => deterministic = false,
+            'v' => verbose = true,
+            _ => {
+                eprintln!("Error: unknown ar operation or modifier '{}' in '{}'", c, spec);
+                process::exit(1);
+            }
+        }
+    }
+
+    match operation {
+        Some('r') => replace(archive_path, files, create, thin, deterministic, verbose),
+        Some('t') => list(&open(archive_path), verbose),
+        Some('x') => extract(&open(archive_path), files, verbose),
+        _ => {
+            eprintln!("Error: no operation given; expected one of r, t, x");
+            process::exit(1);
+        }
+    }
+}
+
+fn open(path: &str) -> Archive {
+    let bytes = fs::read(path).unwrap_or_else(|e| {
+        eprintln!("Failed to read {}: {}", path, e);
+        process::exit(1);
+    });
+    Archive::parse(&bytes).unwrap_or_else(|e| {
+        eprintln!("Error: {}: {}", path, e);
+        process::exit(1);
+    })
+}
+
+/// Adds `files` to the archive, replacing members with the same name.
+fn replace(archive_path: &str, files: &[String], create: bool, thin: bool, deterministic: bool, verbose: bool) {
+    let archive_dir = Path::new(archive_path).parent().unwrap_or(Path::new("")).to_path_buf();
+
+    let mut archive = if Path::new(archive_path).exists() {
+        let mut archive = open(archive_path);
+        if archive.thin != thin {
+            eprintln!("Error: {} is {} thin archive", archive_path, if archive.thin { "a" } else { "not a" });
+            process::exit(1);
+        }
+        // Thin archives only record names; reload the members to rebuild the index
+        if archive.thin {
+            for m in &mut archive.members {
+                m.data = read_file(&archive_dir.join(&m.name));
+            }
+        }
+        archive
+    } else {
+        if !create {
+            eprintln!("whale ar: creating {}", archive_path);
+        }
+        Archive { thin, ..Archive::new() }
+    };
+
+    for file in files {
+        let data = read_file(Path::new(file));
+        let name = if thin {
+            relative_to(Path::new(file), &archive_dir)
+        } else {
+            Path::new(file).file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_else(|| file.clone())
+        };
+        let mut member = ArchiveMember::new(&name, data);
+        if !deterministic {
+            if let Ok(meta) = fs::metadata(file) {
+                member.mtime = meta.modified().ok().and_then(|t| t.duration_since(UNIX_EPOCH).ok()).map_or(0, |d| d.as_secs());
+                set_owner(&mut member, &meta);
+            }
+        }
+
+        match archive.members.iter().position(|m| m.name == name) {
+            Some(index) => {
+                if verbose {
+                    println!("r - {}", name);
+                }
+                archive.members[index] = member;
+            }
+            None => {
+                if verbose {
+                    println!("a - {}", name);
+                }
+                archive.members.push(member);
+            }
+        }
+    }
+
+    let bytes = archive.write().unwrap_or_else(|e| {
+        eprintln!("Error: {}: {}", archive_path, e);
+        process::exit(1);
+    });
+    fs::write(archive_path, bytes).unwrap_or_else(|e| {
+        eprintln!("Failed to write {}: {}", archive_path, e);
+        process::exit(1);
+    });
+}
+
+fn list(archive: &Archive, verbose: bool) {
+    for m in &archive.members {
+        if verbose {
+            println!("{} {}/{} {:>8} {}", mode_string(m.mode), m.uid, m.gid, m.size, m.name);
+        } else {
+            println!("{}", m.name);
+        }
+    }
+}
+
+/// Writes the named members, or all of them, to the current directory.
+fn extract(archive: &Archive, names: &[String], verbose: bool) {
+    if archive.thin {
+        eprintln!("Error: cannot extract members from a thin archive");
+        process::exit(1);
+    }
+    for name in names {
+        if !archive.members.iter().any(|m| &m.name == name) {
+            eprintln!("Error: no member named '{}'", name);
+            process::exit(1);
+        }
+    }
+
+    for m in &archive.members {
+        if !names.is_empty() && !names.contains(&m.name) {
+            continue;
+        }
+        // Member names come from the file; never let them leave the directory
+        let path = Path::new(&m.name);
+        if path.components().count() != 1 || !matches!(path.components().next(), Some(Component::Normal(_))) {
+            eprintln!("Error: refusing to extract member with path '{}'", m.name);
+            process::exit(1);
+        }
+        if verbose {
+            println!("x - {}", m.name);
+        }
+        fs::write(path, &m.data).unwrap_or_else(|e| {
+            eprintln!("Failed to write {}: {}", m.name, e);
+            process::exit(1);
+        });
+    }
+}
+
+fn read_file(path: &Path) -> Vec<u8> {
+    fs::read(path).unwrap_or_else(|e| {
+        eprintln!("Failed to read {}: {}", path.display(), e);
+        process::exit(1);
+    })
+}
+
+/// Thin archives name their members relative to the directory of the archive.
+fn relative_to(file: &Path, dir: &Path) -> String {
+    let absolute = |p: &Path| fs::canonicalize(if p.as_os_str().is_empty() { Path::new(".") } else { p }).ok();
+    let (Some(file_abs), Some(dir_abs)) = (absolute(file), absolute(dir)) else {
+        return file.to_string_lossy().into_owned();
+    };
+
+    let file_parts: Vec<_> = file_abs.components().collect();
+    let dir_parts: Vec<_> = dir_abs.components().collect();
+    let common = file_parts.iter().zip(&dir_parts).take_while(|(a, b)| a == b).count();
+    let mut relative = PathBuf::new();
+    for _ in common..dir_parts.len() {
+        relative.push("..");
+    }
+    for part in &file_parts[common..] {
+        relative.push(part);
+    }
+    relative.to_string_lossy().into_owned()
+}
+
+#[cfg(unix)]
+fn set_owner(member: &mut ArchiveMember, meta: &fs::Metadata) {
+    use std::os::unix::fs::MetadataExt;
+    member.uid = meta.uid();
+    member.gid = meta.gid();
+    member.mode = meta.mode() & 0o777;
+}
+
+#[cfg(not(unix))]
+fn set_owner(_: &mut ArchiveMember, _: &fs::Metadata) {}
+
+fn mode_string(mode: u32) -> String {
+    let mut s = String::new();
+    for shift in [6, 3, 0] {
+        let bits = mode >> shift;
+        s.push(if bits & 4 != 0 { 'r' } else { '-' });
+        s.push(if bits & 2 != 0 { 'w' } else { '-' });
+        s.push(if bits & 1 != 0 { 'x' } else { '-' });
+    }
+    s
+}
+
+fn print_help() {
+    println!("Usage: whale ar <operation>[modifiers] <archive> [files...]");
+    println!();
+    println!("Operations:");
+    println!("  r   Insert files into the archive, replacing members with the same name");
+    println!("  t   List the members");
+    println!("  x   Extract the named members, or all of them, into the current directory");
+    println!();
+    println!("Modifiers:");
+    println!("  c   Create the archive without a warning");
+    println!("  s   Write a symbol index (always done)");
+    println!("  T   Thin archive: store paths to the members instead of their contents");
+    println!("  D   Zero timestamps and owners (default)");
+    println!("  U   Keep the real timestamps, owners and modes of the files");
+    println!("  v   Verbose");
+}
@@ -2,11 +2,11 @@ use std::fs;
 use std::process;
 use std::time::Instant;
 
-use assembler::{assemble, isa::AMD64, AssemblerOutput};
+use assembler::{assemble_with_options, isa::AMD64, link_flat, AsmOptions, AssemblerOutput, RelocKind as AsmRelocKind};
 use assembler::isa::amd64::parser::parse;
 use assembler::tokens::tokenize;
 
-use object::{ObjectFile, ObjectFormat, SectionKind};
+use object::{Compression, ObjectFile, ObjectFormat, ObjectRelocation, ObjectSymbol, RelocKind, SectionKind, SectionType, SymbolBinding, SymbolKind, SymbolVisibility, WriteError, X86Features};
 
 pub fn run(args: Vec<String>) {
     if args.is_empty() {
@@ -17,6 +17,7 @@ pub fn run(args: Vec<String>) {
     let mut arch = None;
     let mut input = None;
     let mut output = None;
+    let mut format = None;
 
     let mut debug_mode = false;
     let mut show_ast = false;
@@ -29,6 +30,10 @@ pub fn run(args: Vec<String>) {
     let mut _no_warn_ext = false;
     let mut show_stats = false;
     let mut trace_enable = false;
+    let mut options = AsmOptions::default();
+    let mut compress_debug = None;
+    let mut cet = None;
+    let mut exec_stack = false;
 
     let mut i = 0;
     while i < args.len() {
@@ -41,11 +46,24 @@ pub fn run(args: Vec<String>) {
             "--amd64" => arch = Some("amd64"),
             "--aarch64" => arch = Some("aarch64"),
 
-            "-o" => {
-                if i + 1 < args.len() {
-                    output = Some(args[i + 1].clone());
-                    i += 1;
-                }
+            "--no-optimize" => options.optimize = false,
+            s if s == "--compress-debug-sections" || s.starts_with("--compress-debug-sections=") => {
+                compress_debug = compress_debug_sections_option(s);
+            }
+
+            "-z" if i + 1 < args.len() => {
+                z_option(&args[i + 1], &mut cet, &mut exec_stack);
+                i += 1;
+            }
+
+            "-o" if i + 1 < args.len() => {
+                output = Some(args[i + 1].clone());
+                i += 1;
+            }
+
+            "-f" if i + 1 < args.len() => {
+                format = Some(args[i + 1].clone());
+                i += 1;
             }
 
             "--debug-whale" => debug_mode = true,
@@ -86,10 +104,31 @@ pub fn run(args: Vec<String>) {
         process::exit(1);
     });
 
-    if !output.ends_with(".o") {
-        eprintln!("Error: for now, asm output must be .o (object).");
+    // `.o` outputs default to an ELF object, `.obj` to COFF, everything else to a flat binary
+    let object_format = match format.as_deref() {
+        Some("bin") => None,
+        Some("elf32") => Some(ObjectFormat::ELF32),
+        Some("elf64") => Some(ObjectFormat::ELF64),
+        Some("win64") => Some(ObjectFormat::COFF),
+        Some("macho64") => Some(ObjectFormat::MachO64),
+        Some(other) => {
+            eprintln!("Error: unknown output format '{}' (expected bin, elf32, elf64, win64 or macho64)", other);
+            process::exit(1);
+        }
+        None if output.ends_with(".o") => Some(ObjectFormat::ELF64),
+        None if output.ends_with(".obj") => Some(ObjectFormat::COFF),
+        None => None,
+    };
+    let flat = object_format.is_none();
+
+    // CET is on by default for ELF objects, as with GCC's -fcf-protection on most distributions
+    let is_elf = matches!(object_format, Some(ObjectFormat::ELF32 | ObjectFormat::ELF64));
+    let cet = cet.unwrap_or(is_elf);
+    if (cet || exec_stack) && !is_elf {
+        eprintln!("Error: -z cet and -z execstack need an ELF output format");
         process::exit(1);
     }
+    options.endbr = cet;
 
     if trace_enable {
         println!("[trace] reading input file: {}", input);
@@ -125,11 +164,29 @@ pub fn run(args: Vec<String>) {
 
     if trace_enable { println!("[trace] assemble start"); }
     let start_time = Instant::now();
-    let out = assemble(&src, &AMD64).expect("Assemble error");
+    let out = assemble_with_options(&src, &AMD64, &options).expect("Assemble error");
     let elapsed = start_time.elapsed();
 
-    if trace_enable { println!("[trace] creating object file"); }
-    let final_bytes = build_elf_from_asm_output(&out);
+    let final_bytes = match object_format {
+        None => {
+            if trace_enable { println!("[trace] linking flat binary"); }
+            link_flat(&out).unwrap_or_else(|e| {
+                eprintln!("Error: {}", e);
+                process::exit(1);
+            })
+        }
+        Some(object_format) => {
+            if out.org.is_some() {
+                eprintln!("Error: org is only valid for flat binary output (-f bin)");
+                process::exit(1);
+            }
+            if trace_enable { println!("[trace] creating object file"); }
+            build_object_from_asm_output(&out, object_format, compress_debug, cet, exec_stack).unwrap_or_else(|e| {
+                eprintln!("Error: failed to create object file: {}", e);
+                process::exit(1);
+            })
+        }
+    };
 
     fs::write(&output, &final_bytes).unwrap_or_else(|e| {
         eprintln!("Failed to write {}: {}", output, e);
@@ -137,12 +194,12 @@ pub fn run(args: Vec<String>) {
     });
 
     if debug_mode && (show_bytes || dump_hex || dump_bin || dump_json) {
-        dump_bytes("object", &final_bytes, show_bytes, dump_hex, dump_bin, dump_json);
+        dump_bytes(if flat { "binary" } else { "object" }, &final_bytes, show_bytes, dump_hex, dump_bin, dump_json);
     }
 
     if debug_mode && show_stats {
         println!(
-            "== STATS ==\nTokens: {}\nAST nodes: {}\nObject bytes: {}\nTime: {} ms",
+            "== STATS ==\nTokens: {}\nAST nodes: {}\nOutput bytes: {}\nTime: {} ms",
             token_len.unwrap_or(0),
             ast_items_len.unwrap_or(0),
             final_bytes.len(),
@@ -153,29 +210,122 @@ pub fn run(args: Vec<String>) {
     println!("Wrote {} bytes to {}", final_bytes.len(), output);
 }
 
-fn build_elf_from_asm_output(out: &AssemblerOutput) -> Vec<u8> {
-    let mut obj = ObjectFile::new(ObjectFormat::ELF64);
+fn build_object_from_asm_output(
+    out: &AssemblerOutput,
+    format: ObjectFormat,
+    compress_debug: Option<Compression>,
+    cet: bool,
+    exec_stack: bool,
+) -> Result<Vec<u8>, WriteError> {
+    let mut obj = ObjectFile::new(format);
 
     // AssemblerOutput의 sections를 그대로 ELF 섹션으로 옮김
-    // (심볼/리로케이션 매핑은 out.symbols/out.relocs 구조 확정되면 다음 단계에서 붙이는 게 맞음)
-    for sec in &out.sections {
-        let kind = match sec.name.as_str() {
+    for (sec_idx, sec) in out.sections.iter().enumerate() {
+        // `.text.hot`, `.rodata.str1.1` and the like are of the same kind as their base section
+        let base = [".text", ".data", ".rodata", ".bss", ".tdata", ".tbss"]
+            .into_iter()
+            .find(|base| sec.name.strip_prefix(base).is_some_and(|rest| rest.is_empty() || rest.starts_with('.')))
+            .unwrap_or(sec.name.as_str());
+        let kind = match base {
             ".text" => SectionKind::Text,
             ".data" => SectionKind::Data,
-            ".rodata" => SectionKind::ReadOnlyData,
+            ".rodata" | ".eh_frame" => SectionKind::ReadOnlyData,
             ".bss" => SectionKind::Bss,
+            ".tdata" => SectionKind::TlsData,
+            ".tbss" => SectionKind::TlsBss,
+            name if name.starts_with(".debug") => SectionKind::Other(SectionType::PROGBITS.0),
             _ => SectionKind::Data,
         };
 
-        let align = if sec.name == ".text" { 16 } else { 1 };
+        let align = match base {
+            ".text" => 16,
+            ".eh_frame" | ".tdata" | ".tbss" => 8,
+            _ => 1,
+        };
         let idx = obj.add_section(&sec.name, kind, align);
         obj.sections[idx].data = sec.data.clone();
+
+        for r in &sec.relocs {
+            let kind = match r.kind {
+                AsmRelocKind::Absolute64 => RelocKind::Absolute64,
+                AsmRelocKind::Absolute32 => RelocKind::Absolute32,
+                AsmRelocKind::Absolute32S => RelocKind::Absolute32S,
+                AsmRelocKind::Absolute16 => RelocKind::Absolute16,
+                AsmRelocKind::Relative32 => RelocKind::Relative32,
+                AsmRelocKind::Relative16 => RelocKind::Relative16,
+                AsmRelocKind::Plt32 => RelocKind::PLT32,
+                AsmRelocKind::GotPcRel => RelocKind::GOTPCREL,
+                AsmRelocKind::GotPcRelX => RelocKind::GOTPCRELX,
+                AsmRelocKind::RexGotPcRelX => RelocKind::REX_GOTPCRELX,
+                AsmRelocKind::TpOff32 => RelocKind::TPOFF32,
+                AsmRelocKind::GotTpOff => RelocKind::GOTTPOFF,
+                AsmRelocKind::TlsGd => RelocKind::TLSGD,
+                AsmRelocKind::TlsLd => RelocKind::TLSLD,
+                AsmRelocKind::DtpOff32 => RelocKind::DTPOFF32,
+            };
+            obj.relocations.push(ObjectRelocation {
+                section_index: sec_idx,
+                offset: r.offset,
+                symbol: r.symbol.clone(),
+                addend: r.addend,
+                kind,
+                subtrahend: None,
+            });
+        }
     }
 
-    obj.write().expect("Failed to create ELF object")
+    // .note.GNU-stack tells ELF linkers whether the code needs an executable stack;
+    // one written in the source is kept unless -z execstack is given
+    if matches!(format, ObjectFormat::ELF32 | ObjectFormat::ELF64) && (exec_stack || obj.executable_stack().is_none()) {
+        obj.set_executable_stack(exec_stack);
+    }
+    if cet {
+        obj.gnu_properties.x86_features = Some(X86Features::IBT | X86Features::SHSTK);
+    }
+
+    for sym in &out.symbols {
+        obj.symbols.push(ObjectSymbol {
+            name: sym.name.clone(),
+            section_index: sym.section_index,
+            value: sym.offset as u64,
+            size: 0,
+            binding: if sym.is_global { SymbolBinding::Global } else { SymbolBinding::Local },
+            visibility: SymbolVisibility::Default,
+            kind: SymbolKind::NoType,
+            absolute: false,
+        });
+    }
+
+    obj.compress_debug_sections(compress_debug);
+    obj.write()
+}
+
+/// Parses the keyword of a `-z` option, named as in GNU ld: `cet`/`nocet` mark the
+/// object as supporting IBT and shadow stacks, `execstack`/`noexecstack` set its
+/// `.note.GNU-stack`.
+pub(crate) fn z_option(keyword: &str, cet: &mut Option<bool>, exec_stack: &mut bool) {
+    match keyword {
+        "cet" => *cet = Some(true),
+        "nocet" => *cet = Some(false),
+        "execstack" => *exec_stack = true,
+        "noexecstack" => *exec_stack = false,
+        other => {
+            eprintln!("Error: unknown -z keyword '{}' (expected cet, nocet, execstack or noexecstack)", other);
+            process::exit(1);
+        }
+    }
+}
+
+/// Parses `--compress-debug-sections[=none|zlib|zstd]`; without a value it means zlib, as in GNU as.
+pub(crate) fn compress_debug_sections_option(arg: &str) -> Option<Compression> {
+    let value = arg.split_once('=').map_or("zlib", |(_, value)| value);
+    Compression::from_name(value).unwrap_or_else(|e| {
+        eprintln!("Error: --compress-debug-sections: {}", e);
+        process::exit(1);
+    })
 }
 
-fn dump_bytes(label: &str, bytes: &[u8], show_bytes: bool, dump_hex: bool, dump_bin: bool, dump_json: bool) {
+pub(crate) fn dump_bytes(label: &str, bytes: &[u8], show_bytes: bool, dump_hex: bool, dump_bin: bool, dump_json: bool) {
     const LIMIT: usize = 256;
     let n = bytes.len().min(LIMIT);
     let head = &bytes[..n];
@@ -221,9 +371,16 @@ fn dump_bytes(label: &str, bytes: &[u8], show_bytes: bool, dump_hex: bool, dump_
 
 fn print_help() {
     println!("Usage:");
-    println!("  whale asm --amd64 <input> -o <output.o>");
+    println!("  whale asm --amd64 <input> -o <output.o>     ELF object");
+    println!("  whale asm --amd64 <input> -o <output.bin>   flat binary");
     println!();
     println!("Options:");
+    println!("  -f <bin|elf32|elf64|win64|macho64>  output format (default: elf64 for .o, win64 for .obj, bin otherwise)");
+    println!("  --no-optimize   always use the long instruction encodings");
+    println!("  -z cet          start global functions with endbr64 and mark the object as");
+    println!("                  IBT and SHSTK compatible (default for ELF output; -z nocet to disable)");
+    println!("  -z execstack    mark the object as needing an executable stack (-z noexecstack: default)");
+    println!("  --compress-debug-sections[=none|zlib|zstd]  compress .debug* sections (default: zlib)");
     println!("  --debug-whale   enable debug features");
     println!("  --ast           print parser AST (debug)");
     println!("  --token         print tokens (debug)");
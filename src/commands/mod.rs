@@ -1,6 +1,10 @@
+pub mod ar;
 pub mod asm;
 pub mod object;
+pub mod objcopy;
+pub mod objdiff;
 pub mod linker;
+pub mod readobj;
 
 #[cfg(feature = "socket-cli")]
 pub mod ir;
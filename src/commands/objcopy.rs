@@ -0,0 +1,244 @@
+use std::collections::HashSet;
+use std::fs;
+use std::process;
+
+use object::{Compression, ObjectFile, Section, SectionFlags, SectionType, SymbolBinding};
+
+/// One edit, applied in the order of `run`'s phases rather than command-line order.
+enum Edit {
+    RemoveSection(String),
+    RenameSection(String, String),
+    SetFlags(String, SectionFlags),
+    AddSection(String, String),
+    DumpSection(String, String),
+    StripSymbol(String),
+    RedefineSymbol(String, String),
+    Binding(String, SymbolBinding),
+}
+
+pub fn run(args: Vec<String>) {
+    if args.is_empty() {
+        print_help();
+        return;
+    }
+
+    let mut files = Vec::new();
+    let mut edits = Vec::new();
+    let mut strip_debug = false;
+    let mut discard_locals = false;
+    let mut strip_all = false;
+    // Outer None keeps the input's compression
+    let mut debug_compression: Option<Option<Compression>> = None;
+
+    let mut i = 0;
+    while i < args.len() {
+        let arg = args[i].as_str();
+        // Options take their value either inline (`--opt=value`) or as the next argument
+        let (option, inline) = match arg.split_once('=') {
+            Some((option, value)) if option.starts_with("--") => (option, Some(value.to_string())),
+            _ => (arg, None),
+        };
+        let mut value = || -> String {
+            if let Some(value) = &inline {
+                return value.clone();
+            }
+            i += 1;
+            args.get(i).cloned().unwrap_or_else(|| {
+                eprintln!("Error: {} requires an argument", option);
+                process::exit(1);
+            })
+        };
+
+        match option {
+            "--help" => {
+                print_help();
+                return;
+            }
+            "-g" | "--strip-debug" => strip_debug = true,
+            "-x" | "--discard-all" => discard_locals = true,
+            "-S" | "--strip-all" => strip_all = true,
+            "-R" | "--remove-section" => edits.push(Edit::RemoveSection(value())),
+            "--rename-section" => {
+                let (old, new) = pair(&value(), option);
+                edits.push(Edit::RenameSection(old, new));
+            }
+            "--set-section-flags" => {
+                let (name, flags) = pair(&value(), option);
+                edits.push(Edit::SetFlags(name, parse_flags(&flags)));
+            }
+            "--add-section" => {
+                let (name, file) = pair(&value(), option);
+                edits.push(Edit::AddSection(name, file));
+            }
+            "--dump-section" => {
+                let (name, file) = pair(&value(), option);
+                edits.push(Edit::DumpSection(name, file));
+            }
+            // The value is optional, so it can only be given inline
+            "--compress-debug-sections" => {
+                let name = inline.as_deref().unwrap_or("zlib");
+                debug_compression = Some(Compression::from_name(name).unwrap_or_else(|e| fail(&format!("{}: {}", option, e))));
+            }
+            "--decompress-debug-sections" => debug_compression = Some(None),
+            "-N" | "--strip-symbol" => edits.push(Edit::StripSymbol(value())),
+            "--redefine-sym" => {
+                let (old, new) = pair(&value(), option);
+                edits.push(Edit::RedefineSymbol(old, new));
+            }
+            "-L" | "--localize-symbol" => edits.push(Edit::Binding(value(), SymbolBinding::Local)),
+            "-W" | "--weaken-symbol" => edits.push(Edit::Binding(value(), SymbolBinding::Weak)),
+            "--globalize-symbol" => edits.push(Edit::Binding(value(), SymbolBinding::Global)),
+            s if !s.starts_with('-') => files.push(s.to_string()),
+            s => {
+                eprintln!("Error: unknown option '{}'", s);
+                process::exit(1);
+            }
+        }
+        i += 1;
+    }
+
+    let (input, output) = match files.as_slice() {
+        [input] => (input.clone(), input.clone()),
+        [input, output] => (input.clone(), output.clone()),
+        [] => fail("missing input file."),
+        _ => fail("expected at most an input and an output file."),
+    };
+
+    let bytes = fs::read(&input).unwrap_or_else(|e| fail(&format!("failed to read {}: {}", input, e)));
+    let mut obj = ObjectFile::parse(&bytes).unwrap_or_else(|e| fail(&format!("{}: {}", input, e)));
+
+    // Sections are dumped as they are in the input
+    for edit in &edits {
+        if let Edit::DumpSection(name, file) = edit {
+            let index = section(&obj, name);
+            fs::write(file, &obj.sections[index].data).unwrap_or_else(|e| fail(&format!("failed to write {}: {}", file, e)));
+        }
+    }
+
+    // Removed together, so sections that only refer to each other can all go
+    let mut removed = HashSet::new();
+    for edit in &edits {
+        if let Edit::RemoveSection(name) = edit {
+            section(&obj, name);
+            removed.insert(name.as_str());
+        }
+    }
+    let strip_debug = strip_debug || strip_all;
+    obj.remove_sections(|s| removed.contains(s.name.as_str()) || (strip_debug && (s.name.starts_with(".debug") || s.name.starts_with(".zdebug"))))
+        .unwrap_or_else(|e| fail(&format!("{}: {}", input, e)));
+
+    for edit in &edits {
+        match edit {
+            Edit::RenameSection(old, new) => {
+                let index = section(&obj, old);
+                obj.rename_section(index, new);
+            }
+            Edit::SetFlags(name, flags) => {
+                let index = section(&obj, name);
+                obj.set_section_flags(index, *flags);
+            }
+            Edit::AddSection(name, file) => {
+                if obj.find_section(name).is_some() {
+                    fail(&format!("section {} already exists", name));
+                }
+                let data = fs::read(file).unwrap_or_else(|e| fail(&format!("failed to read {}: {}", file, e)));
+                obj.sections.push(Section { data, ..Section::with_elf_type(name, SectionType::PROGBITS, SectionFlags::empty(), 1) });
+            }
+            _ => {}
+        }
+    }
+
+    for edit in &edits {
+        let missing = match edit {
+            Edit::RedefineSymbol(old, new) => (!obj.rename_symbol(old, new)).then_some(old),
+            Edit::Binding(name, binding) => (!obj.set_symbol_binding(name, *binding)).then_some(name),
+            Edit::StripSymbol(name) => {
+                let exists = obj.symbols.iter().any(|s| &s.name == name);
+                if exists && obj.strip_symbols(|s| &s.name == name) == 0 {
+                    fail(&format!("symbol {} is used by a relocation and cannot be stripped", name));
+                }
+                (!exists).then_some(name)
+            }
+            _ => None,
+        };
+        if let Some(name) = missing {
+            fail(&format!("no symbol named '{}'", name));
+        }
+    }
+    // Symbols needed by relocations survive stripping
+    if strip_all {
+        obj.strip_symbols(|_| true);
+    } else if discard_locals {
+        obj.strip_symbols(|s| s.binding == SymbolBinding::Local);
+    }
+
+    if let Some(compression) = debug_compression {
+        obj.compress_debug_sections(compression);
+    }
+
+    let bytes = obj.write().unwrap_or_else(|e| fail(&format!("{}: {}", output, e)));
+    fs::write(&output, bytes).unwrap_or_else(|e| fail(&format!("failed to write {}: {}", output, e)));
+}
+
+fn fail(message: &str) -> ! {
+    eprintln!("Error: {}", message);
+    process::exit(1);
+}
+
+fn section(obj: &ObjectFile, name: &str) -> usize {
+    obj.find_section(name).unwrap_or_else(|| fail(&format!("no section named '{}'", name)))
+}
+
+/// Splits `a=b` option values.
+fn pair(value: &str, option: &str) -> (String, String) {
+    match value.split_once('=') {
+        Some((a, b)) if !a.is_empty() && !b.is_empty() => (a.to_string(), b.to_string()),
+        _ => fail(&format!("{} expects <a>=<b>, got '{}'", option, value)),
+    }
+}
+
+/// Parses objcopy's comma-separated section flag words. Allocated sections are
+/// writable unless `readonly` is given.
+fn parse_flags(words: &str) -> SectionFlags {
+    let mut flags = SectionFlags::empty();
+    let mut readonly = false;
+    for word in words.split(',') {
+        match word.trim() {
+            "alloc" => flags |= SectionFlags::ALLOC,
+            "code" => flags |= SectionFlags::EXECINSTR,
+            "readonly" => readonly = true,
+            "merge" => flags |= SectionFlags::MERGE,
+            "strings" => flags |= SectionFlags::STRINGS,
+            "exclude" => flags |= SectionFlags::EXCLUDE,
+            "load" | "contents" | "data" | "debug" => {}
+            other => fail(&format!("unknown section flag '{}'", other)),
+        }
+    }
+    if flags.contains(SectionFlags::ALLOC) && !readonly {
+        flags |= SectionFlags::WRITE;
+    }
+    flags
+}
+
+fn print_help() {
+    println!("Usage: whale objcopy [options] <input> [<output>]");
+    println!();
+    println!("Rewrites a relocatable object; without <output> the input is modified in place.");
+    println!();
+    println!("Options:");
+    println!("  -g, --strip-debug                      Remove .debug* sections");
+    println!("  -x, --discard-all                      Remove local symbols not used by relocations");
+    println!("  -S, --strip-all                        Remove debug sections and every symbol not used by relocations");
+    println!("  -R, --remove-section <name>            Remove a section with its relocations and symbols");
+    println!("      --rename-section <old>=<new>       Rename a section");
+    println!("      --set-section-flags <name>=<flags> Set flags: alloc, code, readonly, merge, strings, exclude");
+    println!("      --add-section <name>=<file>        Add the contents of a file as a section");
+    println!("      --dump-section <name>=<file>       Write the contents of a section to a file");
+    println!("      --compress-debug-sections[=<type>] Compress .debug* sections: none, zlib (default) or zstd");
+    println!("      --decompress-debug-sections        Write .debug* sections uncompressed");
+    println!("  -N, --strip-symbol <name>              Remove a symbol");
+    println!("      --redefine-sym <old>=<new>         Rename a symbol");
+    println!("  -L, --localize-symbol <name>           Make a symbol local");
+    println!("  -W, --weaken-symbol <name>             Make a symbol weak");
+    println!("      --globalize-symbol <name>          Make a symbol global");
+}
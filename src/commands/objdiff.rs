@@ -0,0 +1,187 @@
+use std::fs;
+use std::process;
+
+use object::diff::{diff, ByteRange, Change, SectionChange};
+use object::{GnuProperties, ObjectFile, ObjectRelocation, ObjectSymbol, Section};
+
+use crate::commands::readobj::{format_addend, section_flag_letters, section_type_name, symbol_section, x86_feature_names};
+
+/// Bytes shown per side of a changed range; longer ranges are cut off with `...`.
+const SHOWN_BYTES: usize = 32;
+
+pub fn run(args: Vec<String>) {
+    if args.is_empty() {
+        print_help();
+        return;
+    }
+
+    let mut files = Vec::new();
+    let mut quiet = false;
+    for arg in &args {
+        match arg.as_str() {
+            "--help" => {
+                print_help();
+                return;
+            }
+            "-q" | "--quiet" => quiet = true,
+            s if !s.starts_with('-') => files.push(s.to_string()),
+            s => fail(&format!("unknown option '{}'", s)),
+        }
+    }
+    let [old_path, new_path] = files.as_slice() else {
+        fail("expected two object files");
+    };
+
+    let old = open(old_path);
+    let new = open(new_path);
+    let d = diff(&old, &new);
+    if d.is_empty() {
+        return;
+    }
+    if quiet {
+        process::exit(1);
+    }
+
+    println!("--- {}", old_path);
+    println!("+++ {}", new_path);
+    if let Some((a, b)) = d.format {
+        println!("format: {:?} -> {:?}", a, b);
+    }
+    if let Some((a, b)) = d.arch {
+        println!("architecture: {:?} -> {:?}", a, b);
+    }
+    if let Some((a, b)) = d.endian {
+        println!("byte order: {:?} -> {:?}", a, b);
+    }
+    if let Some((a, b)) = d.gnu_properties {
+        let features = |p: &GnuProperties| p.x86_features.map_or_else(|| "none".to_string(), |f| x86_feature_names(f).join(", "));
+        if a.x86_features != b.x86_features {
+            println!("x86 features: {} -> {}", features(a), features(b));
+        }
+        if a.other != b.other {
+            let types = |p: &GnuProperties| p.other.iter().map(|(t, _)| format!("{:#x}", t)).collect::<Vec<_>>().join(", ");
+            println!("other properties: [{}] -> [{}]", types(a), types(b));
+        }
+    }
+
+    for s in &d.removed_sections {
+        println!("- section {} ({:#x} bytes)", s.name, s.data.len());
+    }
+    for s in &d.added_sections {
+        println!("+ section {} ({:#x} bytes)", s.name, s.data.len());
+    }
+    for change in &d.changed_sections {
+        print_section_change(change);
+    }
+
+    for change in &d.symbols {
+        match change {
+            Change::Removed(s) => println!("- symbol {} {}", s.name, location(&old, s)),
+            Change::Added(s) => println!("+ symbol {} {}", s.name, location(&new, s)),
+            Change::Changed { old: a, new: b } => print_symbol_change(&old, &new, a, b),
+        }
+    }
+
+    for change in &d.relocations {
+        match change {
+            Change::Removed(r) => println!("- reloc {}", reloc(&old, r)),
+            Change::Added(r) => println!("+ reloc {}", reloc(&new, r)),
+            Change::Changed { old: a, new: b } => println!("~ reloc {} -> {}", reloc(&old, a), reloc(&new, b)),
+        }
+    }
+    process::exit(1);
+}
+
+fn open(path: &str) -> ObjectFile {
+    let bytes = fs::read(path).unwrap_or_else(|e| fail(&format!("failed to read {}: {}", path, e)));
+    ObjectFile::parse(&bytes).unwrap_or_else(|e| fail(&format!("{}: {}", path, e)))
+}
+
+/// Trouble exits with 2, so regression scripts can tell it apart from a difference.
+fn fail(message: &str) -> ! {
+    eprintln!("Error: {}", message);
+    process::exit(2);
+}
+
+fn print_section_change(change: &SectionChange) {
+    let (a, b) = (change.old, change.new);
+    if a.data.len() == b.data.len() {
+        println!("~ section {} ({:#x} bytes)", a.name, a.data.len());
+    } else {
+        println!("~ section {}: size {:#x} -> {:#x}", a.name, a.data.len(), b.data.len());
+    }
+    let attribute = |what: &str, old: String, new: String| {
+        if old != new {
+            println!("    {}: {} -> {}", what, old, new);
+        }
+    };
+    attribute("type", section_type_name(a.section_type), section_type_name(b.section_type));
+    attribute("flags", section_flag_letters(a.flags), section_flag_letters(b.flags));
+    attribute("align", a.align.to_string(), b.align.to_string());
+    attribute("entsize", a.entsize.to_string(), b.entsize.to_string());
+    let compression = |s: &Section| s.compression.map_or("none", |c| c.name()).to_string();
+    attribute("compression", compression(a), compression(b));
+    for ByteRange { old, new } in &change.ranges {
+        println!("    -{:#06x}: {}", old.start, hex(&a.data[old.clone()]));
+        println!("    +{:#06x}: {}", new.start, hex(&b.data[new.clone()]));
+    }
+}
+
+fn print_symbol_change(old: &ObjectFile, new: &ObjectFile, a: &ObjectSymbol, b: &ObjectSymbol) {
+    let mut changes = Vec::new();
+    let (from, to) = (location(old, a), location(new, b));
+    if from != to {
+        changes.push(format!("moved {} -> {}", from, to));
+    }
+    if a.size != b.size {
+        changes.push(format!("size {} -> {}", a.size, b.size));
+    }
+    if a.binding != b.binding {
+        changes.push(format!("binding {:?} -> {:?}", a.binding, b.binding));
+    }
+    if a.kind != b.kind {
+        changes.push(format!("type {:?} -> {:?}", a.kind, b.kind));
+    }
+    if a.visibility != b.visibility {
+        changes.push(format!("visibility {:?} -> {:?}", a.visibility, b.visibility));
+    }
+    println!("~ symbol {}: {}", a.name, changes.join(", "));
+}
+
+/// `section+offset`, `ABS+value` or `UND`.
+fn location(obj: &ObjectFile, sym: &ObjectSymbol) -> String {
+    match (sym.section_index, sym.absolute) {
+        (None, false) => symbol_section(obj, sym),
+        _ => format!("{}+{:#x}", symbol_section(obj, sym), sym.value),
+    }
+}
+
+fn reloc(obj: &ObjectFile, r: &ObjectRelocation) -> String {
+    let section = obj.sections.get(r.section_index).map_or("?", |s| s.name.as_str());
+    let target = match &r.subtrahend {
+        Some(subtrahend) => format!("{} - {}", r.symbol, subtrahend),
+        None => r.symbol.clone(),
+    };
+    format!("{}+{:#x} {:?} {} {}", section, r.offset, r.kind, target, format_addend(r.addend))
+}
+
+fn hex(bytes: &[u8]) -> String {
+    let mut s: Vec<String> = bytes.iter().take(SHOWN_BYTES).map(|b| format!("{:02x}", b)).collect();
+    if bytes.len() > SHOWN_BYTES {
+        s.push(format!("... ({} bytes)", bytes.len()));
+    }
+    if s.is_empty() {
+        s.push("(none)".into());
+    }
+    s.join(" ")
+}
+
+fn print_help() {
+    println!("Usage: whale objdiff [-q] <old.o> <new.o>");
+    println!();
+    println!("Compares two objects section by section, symbol by symbol and relocation by relocation.");
+    println!("Exits with 0 if they are equivalent, 1 if they differ and 2 on errors.");
+    println!();
+    println!("Options:");
+    println!("  -q, --quiet  Print nothing; only set the exit status");
+}
@@ -1,7 +1,9 @@
 use std::fs;
 use std::process;
 use std::time::Instant;
-use object::{ObjectFile, ObjectFormat, ObjectSymbol, SectionKind, SymbolBinding};
+use object::{Compression, ObjectFile, ObjectFormat, ObjectSymbol, SectionFlags, SectionKind, SectionType, SymbolBinding, SymbolKind, SymbolVisibility, X86Features};
+
+use crate::commands::asm::{compress_debug_sections_option, dump_bytes, z_option};
 
 pub fn run(args: Vec<String>) {
     if args.is_empty() {
@@ -11,6 +13,16 @@ pub fn run(args: Vec<String>) {
 
     let mut input = None;
     let mut output = None;
+    let mut section_name = None;
+    let mut kind = SectionKind::Text;
+    let mut align = None;
+    let mut writable = None;
+    let mut prefix = None;
+    // Outer None keeps the compression YAML input records
+    let mut compress_debug: Option<Option<Compression>> = None;
+    let mut from_yaml = false;
+    let mut cet = None;
+    let mut exec_stack = false;
 
     let mut debug_mode = false;
     let mut _show_ast = false;
@@ -28,12 +40,47 @@ pub fn run(args: Vec<String>) {
     while i < args.len() {
         match args[i].as_str() {
             "--help" => { print_help(); return; }
-            "-o" => { if i + 1 < args.len() { output = Some(args[i + 1].clone()); i += 1; } }
+            "-o" if i + 1 < args.len() => { output = Some(args[i + 1].clone()); i += 1; }
+            "--section" if i + 1 < args.len() => { section_name = Some(args[i + 1].clone()); i += 1; }
+            "--kind" if i + 1 < args.len() => {
+                kind = match args[i + 1].as_str() {
+                    "text" => SectionKind::Text,
+                    "data" => SectionKind::Data,
+                    "rodata" => SectionKind::ReadOnlyData,
+                    other => {
+                        eprintln!("Error: unknown section kind '{}' (expected text, data or rodata)", other);
+                        process::exit(1);
+                    }
+                };
+                i += 1;
+            }
+            "--align" if i + 1 < args.len() => {
+                align = match args[i + 1].parse::<u64>() {
+                    Ok(n) if n.is_power_of_two() => Some(n),
+                    _ => {
+                        eprintln!("Error: --align expects a power of two, got '{}'", args[i + 1]);
+                        process::exit(1);
+                    }
+                };
+                i += 1;
+            }
+            "--writable" => writable = Some(true),
+            "--readonly" => writable = Some(false),
+            "--symbol-prefix" if i + 1 < args.len() => { prefix = Some(args[i + 1].clone()); i += 1; }
+            s if s == "--compress-debug-sections" || s.starts_with("--compress-debug-sections=") => {
+                compress_debug = Some(compress_debug_sections_option(s));
+            }
+            "--from-yaml" => from_yaml = true,
+            "-z" if i + 1 < args.len() => { z_option(&args[i + 1], &mut cet, &mut exec_stack); i += 1; }
             "--debug-whale" => debug_mode = true,
+            "--ast" => _show_ast = true,
+            "--token" => _show_token = true,
             "--bytes" => show_bytes = true,
             "--dump-hex" => dump_hex = true,
             "--dump-bin" => dump_bin = true,
             "--dump-json" => dump_json = true,
+            "--no-color" => _no_color = true,
+            "--no-warn-extension" => _no_warn_ext = true,
             "--stats" => show_stats = true,
             "--trace" => trace_enable = true,
             s if input.is_none() && !s.starts_with('-') => input = Some(s.to_string()),
@@ -49,31 +96,130 @@ pub fn run(args: Vec<String>) {
     let start_time = Instant::now();
 
     let bytes = fs::read(&input).unwrap_or_else(|e| { eprintln!("Failed to read input: {}", e); process::exit(1); });
+    let len = bytes.len() as u64;
 
-    let mut obj = ObjectFile::new(ObjectFormat::ELF64);
-    let sec_idx = obj.add_section(".text", SectionKind::Text, 16);
-    obj.sections[sec_idx].data = bytes;
-    
-    obj.symbols.push(ObjectSymbol {
-        name: "start".to_string(),
-        section_index: Some(sec_idx),
-        value: 0,
-        size: 0,
-        binding: SymbolBinding::Global,
-        visibility: object::SymbolVisibility::Default,
-    });
+    let mut obj = if from_yaml {
+        let text = String::from_utf8(bytes).unwrap_or_else(|_| { eprintln!("Error: {}: not UTF-8 text", input); process::exit(1); });
+        ObjectFile::from_yaml(&text).unwrap_or_else(|e| { eprintln!("Error: {}: {}", input, e); process::exit(1); })
+    } else {
+        wrap(&input, bytes, section_name, kind, align, writable, prefix)
+    };
 
-    let out_bytes = obj.write().expect("Failed to write ELF");
+    // The wrapped bytes are opaque, so CET is only claimed on request
+    if exec_stack {
+        obj.set_executable_stack(true);
+    }
+    if let Some(cet) = cet {
+        obj.gnu_properties.x86_features = cet.then_some(X86Features::IBT | X86Features::SHSTK);
+    }
+    if let Some(compression) = compress_debug {
+        obj.compress_debug_sections(compression);
+    }
+
+    if trace_enable { println!("[trace] writing object"); }
+    let out_bytes = obj.write().unwrap_or_else(|e| { eprintln!("Error: failed to create object file: {}", e); process::exit(1); });
     let elapsed = start_time.elapsed();
 
-    if debug_mode && (show_bytes || dump_hex || dump_bin) { /* ... same as before ... */ }
+    if debug_mode && (show_bytes || dump_hex || dump_bin || dump_json) {
+        dump_bytes("object", &out_bytes, show_bytes, dump_hex, dump_bin, dump_json);
+    }
 
-    fs::write(&output, &out_bytes).expect("Failed to write output");
+    fs::write(&output, &out_bytes).unwrap_or_else(|e| { eprintln!("Failed to write {}: {}", output, e); process::exit(1); });
 
     if debug_mode && show_stats {
-        println!("== STATS ==\nInput bytes: {}\nOutput bytes: {}\nTime: {} ms", obj.sections[sec_idx].data.len(), out_bytes.len(), elapsed.as_millis());
+        println!("== STATS ==\nInput bytes: {}\nOutput bytes: {}\nTime: {} ms", len, out_bytes.len(), elapsed.as_millis());
     }
     println!("Created object file {} ({} bytes)", output, out_bytes.len());
 }
 
-pub fn print_help() { /* ... same as before ... */ }
+/// Builds the object that wraps the contents of `input`.
+fn wrap(
+    input: &str,
+    bytes: Vec<u8>,
+    section_name: Option<String>,
+    kind: SectionKind,
+    align: Option<u64>,
+    writable: Option<bool>,
+    prefix: Option<String>,
+) -> ObjectFile {
+    let len = bytes.len() as u64;
+    let default_name = match kind {
+        SectionKind::Text => ".text",
+        SectionKind::ReadOnlyData => ".rodata",
+        _ => ".data",
+    };
+    let default_align = if kind == SectionKind::Text { 16 } else { 1 };
+
+    let mut obj = ObjectFile::new(ObjectFormat::ELF64);
+    let name = section_name.as_deref().unwrap_or(default_name);
+    // Debug info is not loaded, which also lets --compress-debug-sections apply to it
+    let sec_idx = if name.starts_with(".debug") {
+        obj.add_elf_section(name, SectionType::PROGBITS, SectionFlags::empty(), align.unwrap_or(1))
+    } else {
+        obj.add_section(name, kind, align.unwrap_or(default_align))
+    };
+    obj.sections[sec_idx].data = bytes;
+    if let Some(writable) = writable {
+        let flags = obj.sections[sec_idx].flags;
+        let flags = if writable { flags | SectionFlags::WRITE } else { SectionFlags(flags.0 & !SectionFlags::WRITE.0) };
+        obj.set_section_flags(sec_idx, flags);
+    }
+
+    // Like `whale asm`, say the object does not need an executable stack
+    obj.set_executable_stack(false);
+
+    // Same names as `objcopy -I binary`: the input path with every other character replaced by '_'
+    let prefix = prefix.unwrap_or_else(|| {
+        let mangled: String = input.chars().map(|c| if c.is_ascii_alphanumeric() { c } else { '_' }).collect();
+        format!("_binary_{}", mangled)
+    });
+    let symbol = |name: String, section_index: Option<usize>, value: u64, absolute: bool| ObjectSymbol {
+        name,
+        section_index,
+        value,
+        size: 0,
+        binding: SymbolBinding::Global,
+        visibility: SymbolVisibility::Default,
+        kind: SymbolKind::NoType,
+        absolute,
+    };
+    // Wrapped code keeps its `start` entry label
+    if obj.sections[sec_idx].kind == SectionKind::Text {
+        obj.symbols.push(ObjectSymbol { kind: SymbolKind::Func, ..symbol("start".to_string(), Some(sec_idx), 0, false) });
+    }
+    obj.symbols.push(symbol(format!("{}_start", prefix), Some(sec_idx), 0, false));
+    obj.symbols.push(symbol(format!("{}_end", prefix), Some(sec_idx), len, false));
+    obj.symbols.push(symbol(format!("{}_size", prefix), None, len, true));
+    obj
+}
+
+pub fn print_help() {
+    println!("Usage:");
+    println!("  whale object <input> -o <output.o> [options]");
+    println!("  whale object --from-yaml <input.yaml> -o <output.o>");
+    println!();
+    println!("Wraps a file in an ELF64 object as one section, with <prefix>_start, <prefix>_end");
+    println!("and <prefix>_size symbols (prefix: _binary_<input path with non-alphanumerics as _>).");
+    println!("With --from-yaml the input describes the whole object instead, in the form");
+    println!("`whale readobj --to-yaml` prints.");
+    println!();
+    println!("Options:");
+    println!("  --section <name>          section name (default: .text, .data or .rodata by kind)");
+    println!("  --kind <text|data|rodata> section kind (default: text)");
+    println!("  --align <n>               section alignment (default: 16 for text, 1 otherwise)");
+    println!("  --writable                make the section writable");
+    println!("  --readonly                make the section read-only");
+    println!("  --symbol-prefix <prefix>  use <prefix>_start/_end/_size instead of _binary_<input>_*");
+    println!("  --compress-debug-sections[=none|zlib|zstd]");
+    println!("                            compress a non-allocated .debug* section (default: zlib)");
+    println!("  --from-yaml               build the object from a YAML or JSON description");
+    println!("  -z cet                    mark the object as IBT and SHSTK compatible (-z nocet: default)");
+    println!("  -z execstack              mark the object as needing an executable stack");
+    println!("  --debug-whale             enable debug features");
+    println!("  --bytes                   print object bytes (debug)");
+    println!("  --dump-hex                print object bytes as hex (debug)");
+    println!("  --dump-bin                print object bytes as binary (debug)");
+    println!("  --dump-json               print object bytes as json (debug)");
+    println!("  --stats                   show stats (debug)");
+    println!("  --trace                   trace logs");
+}
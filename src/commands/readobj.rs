@@ -0,0 +1,516 @@
+use std::fs;
+use std::process;
+
+use object::{
+    Diagnostic, ObjectFile, ObjectFormat, ObjectRelocation, ObjectSymbol, Section, SectionFlags, SectionType, SymbolBinding, SymbolKind,
+    SymbolVisibility, X86Features,
+};
+
+pub fn run(args: Vec<String>) {
+    if args.is_empty() {
+        print_help();
+        return;
+    }
+
+    let mut input = None;
+    let mut show_header = false;
+    let mut show_sections = false;
+    let mut show_symbols = false;
+    let mut show_relocs = false;
+    let mut hex_dumps = Vec::new();
+    let mut json = false;
+    let mut validate = false;
+    let mut to_yaml = false;
+
+    let mut i = 0;
+    while i < args.len() {
+        match args[i].as_str() {
+            "--help" => {
+                print_help();
+                return;
+            }
+            "-h" | "--file-header" => show_header = true,
+            "-S" | "--sections" => show_sections = true,
+            "-s" | "--symbols" => show_symbols = true,
+            "-r" | "--relocs" => show_relocs = true,
+            "-a" | "--all" => {
+                show_header = true;
+                show_sections = true;
+                show_symbols = true;
+                show_relocs = true;
+            }
+            "-x" if i + 1 < args.len() => {
+                hex_dumps.push(args[i + 1].clone());
+                i += 1;
+            }
+            s if s.starts_with("--hex-dump=") => hex_dumps.push(s["--hex-dump=".len()..].to_string()),
+            "--json" => json = true,
+            "--validate" => validate = true,
+            "--to-yaml" => to_yaml = true,
+            s if input.is_none() && !s.starts_with('-') => input = Some(s.to_string()),
+            s => {
+                eprintln!("Error: unknown option '{}'", s);
+                process::exit(1);
+            }
+        }
+        i += 1;
+    }
+
+    let input = input.unwrap_or_else(|| {
+        eprintln!("Error: missing input file.");
+        process::exit(1);
+    });
+
+    // Without a selection everything but the hex dumps is shown
+    if !(show_header || show_sections || show_symbols || show_relocs || validate) && hex_dumps.is_empty() {
+        show_header = true;
+        show_sections = true;
+        show_symbols = true;
+        show_relocs = true;
+    }
+
+    let bytes = fs::read(&input).unwrap_or_else(|e| {
+        eprintln!("Failed to read {}: {}", input, e);
+        process::exit(1);
+    });
+    let obj = ObjectFile::parse(&bytes).unwrap_or_else(|e| {
+        eprintln!("Error: {}: {}", input, e);
+        process::exit(1);
+    });
+
+    // The description is the whole object, so the other selections do not apply
+    if to_yaml {
+        print!("{}", obj.to_yaml());
+        return;
+    }
+
+    let mut dumps = Vec::new();
+    for name in &hex_dumps {
+        match find_section(&obj, name) {
+            Some(index) => dumps.push(index),
+            None => {
+                eprintln!("Error: no section named '{}'", name);
+                process::exit(1);
+            }
+        }
+    }
+
+    let problems = validate.then(|| obj.validate().err().unwrap_or_default());
+
+    if json {
+        print_json(&obj, show_header, show_sections, show_symbols, show_relocs, &dumps, problems.as_deref());
+    } else {
+        print_text(&obj, show_header, show_sections, show_symbols, show_relocs, &dumps, problems.as_deref());
+    }
+    if problems.is_some_and(|p| !p.is_empty()) {
+        process::exit(1);
+    }
+}
+
+fn print_text(
+    obj: &ObjectFile,
+    show_header: bool,
+    show_sections: bool,
+    show_symbols: bool,
+    show_relocs: bool,
+    dumps: &[usize],
+    problems: Option<&[Diagnostic]>,
+) {
+
+    if show_header {
+        print_header(obj);
+    }
+    if show_sections {
+        print_sections(obj);
+    }
+    if show_symbols {
+        print_symbols(obj);
+    }
+    if show_relocs {
+        print_relocs(obj);
+    }
+    for &index in dumps {
+        print_hex_dump(&obj.sections[index]);
+    }
+    if let Some(problems) = problems {
+        print_problems(problems);
+    }
+}
+
+/// Sections are selected by name or by their index in the section table.
+fn find_section(obj: &ObjectFile, name: &str) -> Option<usize> {
+    obj.sections.iter().position(|s| s.name == name).or_else(|| name.parse().ok().filter(|&i: &usize| i < obj.sections.len()))
+}
+
+fn format_name(format: ObjectFormat) -> &'static str {
+    match format {
+        ObjectFormat::ELF32 => "ELF32",
+        ObjectFormat::ELF64 => "ELF64",
+        ObjectFormat::COFF => "COFF",
+        ObjectFormat::MachO64 => "Mach-O 64",
+    }
+}
+
+pub(crate) fn section_type_name(section_type: SectionType) -> String {
+    match section_type {
+        SectionType::PROGBITS => "PROGBITS".into(),
+        SectionType::SYMTAB => "SYMTAB".into(),
+        SectionType::STRTAB => "STRTAB".into(),
+        SectionType::RELA => "RELA".into(),
+        SectionType::NOTE => "NOTE".into(),
+        SectionType::NOBITS => "NOBITS".into(),
+        SectionType::REL => "REL".into(),
+        SectionType::INIT_ARRAY => "INIT_ARRAY".into(),
+        SectionType::FINI_ARRAY => "FINI_ARRAY".into(),
+        SectionType::PREINIT_ARRAY => "PREINIT_ARRAY".into(),
+        SectionType::GROUP => "GROUP".into(),
+        SectionType(other) => format!("{:#x}", other),
+    }
+}
+
+/// Flag letters as readelf prints them.
+pub(crate) fn section_flag_letters(flags: SectionFlags) -> String {
+    const LETTERS: &[(SectionFlags, char)] = &[
+        (SectionFlags::WRITE, 'W'),
+        (SectionFlags::ALLOC, 'A'),
+        (SectionFlags::EXECINSTR, 'X'),
+        (SectionFlags::MERGE, 'M'),
+        (SectionFlags::STRINGS, 'S'),
+        (SectionFlags::INFO_LINK, 'I'),
+        (SectionFlags::LINK_ORDER, 'L'),
+        (SectionFlags::GROUP, 'G'),
+        (SectionFlags::TLS, 'T'),
+        (SectionFlags::COMPRESSED, 'C'),
+        (SectionFlags::EXCLUDE, 'E'),
+    ];
+    LETTERS.iter().filter(|(flag, _)| flags.contains(*flag)).map(|(_, c)| *c).collect()
+}
+
+fn binding_name(binding: SymbolBinding) -> &'static str {
+    match binding {
+        SymbolBinding::Local => "LOCAL",
+        SymbolBinding::Global => "GLOBAL",
+        SymbolBinding::Weak => "WEAK",
+    }
+}
+
+fn symbol_kind_name(kind: SymbolKind) -> &'static str {
+    match kind {
+        SymbolKind::NoType => "NOTYPE",
+        SymbolKind::Func => "FUNC",
+        SymbolKind::Object => "OBJECT",
+        SymbolKind::Section => "SECTION",
+        SymbolKind::File => "FILE",
+        SymbolKind::Tls => "TLS",
+    }
+}
+
+fn visibility_name(visibility: SymbolVisibility) -> &'static str {
+    match visibility {
+        SymbolVisibility::Default => "DEFAULT",
+        SymbolVisibility::Hidden => "HIDDEN",
+    }
+}
+
+/// Where a symbol is defined: a section name, `ABS` or `UND`.
+pub(crate) fn symbol_section(obj: &ObjectFile, sym: &ObjectSymbol) -> String {
+    match sym.section_index {
+        Some(i) => obj.sections.get(i).map_or_else(|| i.to_string(), |s| s.name.clone()),
+        None if sym.absolute => "ABS".into(),
+        None => "UND".into(),
+    }
+}
+
+pub(crate) fn format_addend(addend: i64) -> String {
+    if addend < 0 {
+        format!("- {:#x}", addend.unsigned_abs())
+    } else {
+        format!("+ {:#x}", addend)
+    }
+}
+
+fn print_header(obj: &ObjectFile) {
+    println!("File header:");
+    println!("  Format:       {}", format_name(obj.format));
+    println!("  Machine:      {:?}", obj.arch);
+    println!("  Byte order:   {:?}", obj.endian);
+    println!("  Sections:     {}", obj.sections.len());
+    println!("  Symbols:      {}", obj.symbols.len());
+    println!("  Relocations:  {}", obj.relocations.len());
+    println!("  Groups:       {}", obj.groups.len());
+    if matches!(obj.format, ObjectFormat::ELF32 | ObjectFormat::ELF64) {
+        let stack = match obj.executable_stack() {
+            Some(true) => "executable",
+            Some(false) => "non-executable",
+            None => "not marked",
+        };
+        println!("  Stack:        {}", stack);
+    }
+    if let Some(features) = obj.gnu_properties.x86_features {
+        println!("  x86 features: {}", x86_feature_names(features).join(", "));
+    }
+    for (pr_type, data) in &obj.gnu_properties.other {
+        println!("  Property:     {:#x} ({} bytes)", pr_type, data.len());
+    }
+    println!();
+}
+
+/// Names of the CET bits, then any unknown bits as one hex number.
+pub(crate) fn x86_feature_names(features: X86Features) -> Vec<String> {
+    let mut names = Vec::new();
+    for (bit, name) in [(X86Features::IBT, "IBT"), (X86Features::SHSTK, "SHSTK")] {
+        if features.contains(bit) {
+            names.push(name.to_string());
+        }
+    }
+    let rest = features.0 & !(X86Features::IBT | X86Features::SHSTK).0;
+    if rest != 0 || names.is_empty() {
+        names.push(format!("{:#x}", rest));
+    }
+    names
+}
+
+fn print_sections(obj: &ObjectFile) {
+    println!("Sections:");
+    println!("  [Nr] {:<20} {:<12} {:<6} {:>10} {:>6} {:>7} Link", "Name", "Type", "Flags", "Size", "Align", "EntSize");
+    for (i, s) in obj.sections.iter().enumerate() {
+        let link = s.link.map_or_else(|| "-".to_string(), |l| l.to_string());
+        // Contents are shown decompressed; the flag still tells how they are stored
+        let flags = if s.compression.is_some() { s.flags | SectionFlags::COMPRESSED } else { s.flags };
+        println!(
+            "  [{:>2}] {:<20} {:<12} {:<6} {:>#10x} {:>6} {:>7} {}",
+            i,
+            s.name,
+            section_type_name(s.section_type),
+            section_flag_letters(flags),
+            s.data.len(),
+            s.align,
+            s.entsize,
+            link
+        );
+    }
+    for group in &obj.groups {
+        let members: Vec<&str> = group.sections.iter().filter_map(|&i| obj.sections.get(i)).map(|s| s.name.as_str()).collect();
+        println!("  {} group [{}]: {}", if group.comdat { "COMDAT" } else { "Section" }, group.signature, members.join(", "));
+    }
+    println!();
+}
+
+fn print_symbols(obj: &ObjectFile) {
+    println!("Symbols:");
+    println!("  {:>4} {:>16} {:>6} {:<8} {:<6} {:<8} {:<16} Name", "Num", "Value", "Size", "Type", "Bind", "Vis", "Section");
+    for (i, sym) in obj.symbols.iter().enumerate() {
+        println!(
+            "  {:>4} {:016x} {:>6} {:<8} {:<6} {:<8} {:<16} {}",
+            i,
+            sym.value,
+            sym.size,
+            symbol_kind_name(sym.kind),
+            binding_name(sym.binding),
+            visibility_name(sym.visibility),
+            symbol_section(obj, sym),
+            sym.name
+        );
+    }
+    println!();
+}
+
+fn print_relocs(obj: &ObjectFile) {
+    for (index, section) in obj.sections.iter().enumerate() {
+        let relocs: Vec<&ObjectRelocation> = obj.relocations.iter().filter(|r| r.section_index == index).collect();
+        if relocs.is_empty() {
+            continue;
+        }
+        println!("Relocations for '{}' ({} entries):", section.name, relocs.len());
+        println!("  {:>10} {:<28} Symbol + Addend", "Offset", "Kind");
+        for r in relocs {
+            let target = match &r.subtrahend {
+                Some(subtrahend) => format!("{} - {}", r.symbol, subtrahend),
+                None => r.symbol.clone(),
+            };
+            println!("  {:>#10x} {:<28} {} {}", r.offset, format!("{:?}", r.kind), target, format_addend(r.addend));
+        }
+        println!();
+    }
+}
+
+fn print_problems(problems: &[Diagnostic]) {
+    println!("Validation:");
+    if problems.is_empty() {
+        println!("  no problems found");
+    }
+    for problem in problems {
+        println!("  {}", problem);
+    }
+    println!();
+}
+
+fn print_hex_dump(section: &Section) {
+    println!("Hex dump of section '{}':", section.name);
+    for (i, chunk) in section.data.chunks(16).enumerate() {
+        let mut hex = String::new();
+        for (j, b) in chunk.iter().enumerate() {
+            if j > 0 && j % 4 == 0 {
+                hex.push(' ');
+            }
+            hex.push_str(&format!("{:02x}", b));
+        }
+        let ascii: String = chunk.iter().map(|&b| if b.is_ascii_graphic() || b == b' ' { b as char } else { '.' }).collect();
+        println!("  {:#010x} {:<35} {}", i * 16, hex, ascii);
+    }
+    println!();
+}
+
+fn json_string(s: &str) -> String {
+    let mut out = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+fn print_json(obj: &ObjectFile, header: bool, sections: bool, symbols: bool, relocs: bool, dumps: &[usize], problems: Option<&[Diagnostic]>) {
+    let mut fields = Vec::new();
+
+    if header {
+        let features = obj.gnu_properties.x86_features.map_or_else(
+            || "null".to_string(),
+            |f| format!("[{}]", x86_feature_names(f).iter().map(|n| json_string(n)).collect::<Vec<_>>().join(", ")),
+        );
+        let properties: Vec<String> = obj
+            .gnu_properties
+            .other
+            .iter()
+            .map(|(pr_type, data)| {
+                let hex: String = data.iter().map(|b| format!("{:02x}", b)).collect();
+                format!("{{\"type\": {}, \"data\": {}}}", pr_type, json_string(&hex))
+            })
+            .collect();
+        fields.push(format!(
+            "  \"header\": {{\"format\": {}, \"machine\": {}, \"endian\": {}, \"executable_stack\": {}, \"x86_features\": {}, \"properties\": [{}]}}",
+            json_string(format_name(obj.format)),
+            json_string(&format!("{:?}", obj.arch)),
+            json_string(&format!("{:?}", obj.endian)),
+            obj.executable_stack().map_or_else(|| "null".to_string(), |e| e.to_string()),
+            features,
+            properties.join(", ")
+        ));
+    }
+
+    if sections {
+        let items: Vec<String> = obj
+            .sections
+            .iter()
+            .enumerate()
+            .map(|(i, s)| {
+                format!(
+                    "    {{\"index\": {}, \"name\": {}, \"kind\": {}, \"type\": {}, \"flags\": {}, \"size\": {}, \"align\": {}, \"entsize\": {}, \"link\": {}, \"info\": {}, \"compression\": {}}}",
+                    i,
+                    json_string(&s.name),
+                    json_string(&format!("{:?}", s.kind)),
+                    s.section_type.0,
+                    s.flags.0,
+                    s.data.len(),
+                    s.align,
+                    s.entsize,
+                    s.link.map_or_else(|| "null".to_string(), |l| l.to_string()),
+                    s.info,
+                    s.compression.map_or_else(|| "null".to_string(), |c| json_string(c.name()))
+                )
+            })
+            .collect();
+        fields.push(format!("  \"sections\": [\n{}\n  ]", items.join(",\n")));
+
+        let groups: Vec<String> = obj
+            .groups
+            .iter()
+            .map(|g| {
+                let members: Vec<String> = g.sections.iter().map(|i| i.to_string()).collect();
+                format!("    {{\"signature\": {}, \"comdat\": {}, \"sections\": [{}]}}", json_string(&g.signature), g.comdat, members.join(", "))
+            })
+            .collect();
+        fields.push(format!("  \"groups\": [\n{}\n  ]", groups.join(",\n")));
+    }
+
+    if symbols {
+        let items: Vec<String> = obj
+            .symbols
+            .iter()
+            .map(|sym| {
+                format!(
+                    "    {{\"name\": {}, \"value\": {}, \"size\": {}, \"type\": {}, \"binding\": {}, \"visibility\": {}, \"section\": {}}}",
+                    json_string(&sym.name),
+                    sym.value,
+                    sym.size,
+                    json_string(symbol_kind_name(sym.kind)),
+                    json_string(binding_name(sym.binding)),
+                    json_string(visibility_name(sym.visibility)),
+                    json_string(&symbol_section(obj, sym))
+                )
+            })
+            .collect();
+        fields.push(format!("  \"symbols\": [\n{}\n  ]", items.join(",\n")));
+    }
+
+    if relocs {
+        let items: Vec<String> = obj
+            .relocations
+            .iter()
+            .map(|r| {
+                format!(
+                    "    {{\"section\": {}, \"offset\": {}, \"kind\": {}, \"symbol\": {}, \"subtrahend\": {}, \"addend\": {}}}",
+                    json_string(obj.sections.get(r.section_index).map_or("", |s| s.name.as_str())),
+                    r.offset,
+                    json_string(&format!("{:?}", r.kind)),
+                    json_string(&r.symbol),
+                    r.subtrahend.as_deref().map_or_else(|| "null".to_string(), json_string),
+                    r.addend
+                )
+            })
+            .collect();
+        fields.push(format!("  \"relocations\": [\n{}\n  ]", items.join(",\n")));
+    }
+
+    if !dumps.is_empty() {
+        let items: Vec<String> = dumps
+            .iter()
+            .map(|&i| {
+                let section = &obj.sections[i];
+                let hex: String = section.data.iter().map(|b| format!("{:02x}", b)).collect();
+                format!("    {{\"section\": {}, \"data\": {}}}", json_string(&section.name), json_string(&hex))
+            })
+            .collect();
+        fields.push(format!("  \"hex_dumps\": [\n{}\n  ]", items.join(",\n")));
+    }
+
+    if let Some(problems) = problems {
+        let items: Vec<String> = problems.iter().map(|p| format!("    {}", json_string(&p.to_string()))).collect();
+        fields.push(format!("  \"problems\": [{}]", if items.is_empty() { String::new() } else { format!("\n{}\n  ", items.join(",\n")) }));
+    }
+
+    println!("{{\n{}\n}}", fields.join(",\n"));
+}
+
+fn print_help() {
+    println!("Usage:");
+    println!("  whale readobj [options] <object>");
+    println!();
+    println!("Options:");
+    println!("  -h, --file-header        show the file header");
+    println!("  -S, --sections           show the section table and groups");
+    println!("  -s, --symbols            show the symbol table");
+    println!("  -r, --relocs             show relocations, grouped by section");
+    println!("  -a, --all                all of the above (the default)");
+    println!("  -x, --hex-dump=<section> hex dump a section, by name or index (repeatable)");
+    println!("  --validate               check the object for problems; exits with 1 if there are any");
+    println!("  --json                   print the selected parts as JSON");
+    println!("  --to-yaml                describe the whole object as YAML, for `whale object --from-yaml`");
+}
@@ -0,0 +1,221 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+
+/// Runs the `whale` binary with `args` in `dir`.
+fn whale(dir: &Path, args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_whale")).args(args).current_dir(dir).output().unwrap()
+}
+
+/// Runs `whale` and fails the test unless it exits successfully; returns stdout.
+fn whale_ok(dir: &Path, args: &[&str]) -> String {
+    let out = whale(dir, args);
+    assert!(out.status.success(), "whale {:?} failed: {}", args, String::from_utf8_lossy(&out.stderr));
+    String::from_utf8(out.stdout).unwrap()
+}
+
+/// A fresh scratch directory for one test.
+fn scratch(test: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("whale-cli-{}-{}", std::process::id(), test));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// Assembles `source` into the ELF object `name` in `dir`.
+fn assemble(dir: &Path, name: &str, source: &str) {
+    let asm = format!("{}.asm", name);
+    fs::write(dir.join(&asm), source).unwrap();
+    whale_ok(dir, &["asm", "--amd64", &asm, "-o", name]);
+}
+
+#[test]
+fn yaml_round_trip_keeps_section_compression() {
+    let dir = scratch("yaml");
+    fs::write(dir.join("in.yaml"), "format: ELF64\narch: X86_64\nsections:\n  - name: .debug_str\n    kind: 1\n    data: \"6d61696e00\"\n    compression: Zstd\n").unwrap();
+
+    whale_ok(&dir, &["object", "--from-yaml", "in.yaml", "-o", "first.o"]);
+    let described = whale_ok(&dir, &["readobj", "--to-yaml", "first.o"]);
+    assert!(described.contains("compression: Zstd"), "{}", described);
+    fs::write(dir.join("in.yaml"), &described).unwrap();
+    whale_ok(&dir, &["object", "--from-yaml", "in.yaml", "-o", "second.o"]);
+    assert_eq!(fs::read(dir.join("first.o")).unwrap(), fs::read(dir.join("second.o")).unwrap());
+
+    // The option still overrides what the YAML says
+    whale_ok(&dir, &["object", "--from-yaml", "in.yaml", "--compress-debug-sections=none", "-o", "second.o"]);
+    assert!(!whale_ok(&dir, &["readobj", "--to-yaml", "second.o"]).contains("compression"));
+}
+
+#[test]
+fn objdiff_exit_status_tells_equal_different_and_broken_apart() {
+    let dir = scratch("objdiff");
+    assemble(&dir, "a.o", "global f\nsection .text\nf:\nret\n");
+    assemble(&dir, "same.o", "global f\nsection .text\nf:\nret\n");
+    assemble(&dir, "b.o", "global f\nsection .text\nf:\nnop\nret\n");
+
+    let status = |args: &[&str]| whale(&dir, args).status.code();
+    assert_eq!(status(&["objdiff", "a.o", "same.o"]), Some(0));
+    assert_eq!(status(&["objdiff", "a.o", "b.o"]), Some(1));
+    assert_eq!(status(&["objdiff", "-q", "a.o", "b.o"]), Some(1));
+    assert_eq!(status(&["objdiff", "a.o", "missing.o"]), Some(2));
+    assert_eq!(status(&["objdiff", "a.o", "b.o.asm"]), Some(2));
+
+    let out = whale(&dir, &["objdiff", "a.o", "b.o"]);
+    assert!(String::from_utf8_lossy(&out.stdout).contains(".text"));
+    assert!(whale(&dir, &["objdiff", "-q", "a.o", "b.o"]).stdout.is_empty());
+}
+
+#[test]
+fn ar_creates_lists_and_extracts_archives() {
+    let dir = scratch("ar");
+    fs::create_dir_all(dir.join("lib")).unwrap();
+    fs::create_dir_all(dir.join("out")).unwrap();
+    assemble(&dir, "lib/f.o", "global f\nsection .text\nf:\nret\n");
+    assemble(&dir, "lib/g.o", "global g\nsection .text\ng:\nret\n");
+
+    whale_ok(&dir, &["ar", "rcs", "lib.a", "lib/f.o", "lib/g.o"]);
+    assert_eq!(whale_ok(&dir, &["ar", "t", "lib.a"]), "f.o\ng.o\n");
+    let archive = object::archive::Archive::parse(&fs::read(dir.join("lib.a")).unwrap()).unwrap();
+    assert_eq!((archive.find_symbol("f"), archive.find_symbol("g")), (Some(0), Some(1)));
+    assert!(archive.members.iter().all(|m| m.mtime == 0 && m.uid == 0));
+
+    whale_ok(&dir.join("out"), &["ar", "x", "../lib.a"]);
+    for name in ["f.o", "g.o"] {
+        assert_eq!(fs::read(dir.join("out").join(name)).unwrap(), fs::read(dir.join("lib").join(name)).unwrap());
+    }
+}
+
+#[test]
+fn thin_archives_refer_to_members_by_path() {
+    let dir = scratch("ar-thin");
+    fs::create_dir_all(dir.join("lib")).unwrap();
+    assemble(&dir, "lib/f.o", "global f\nsection .text\nf:\nret\n");
+    assemble(&dir, "lib/g.o", "global g\nsection .text\ng:\nret\n");
+
+    whale_ok(&dir, &["ar", "rcsT", "thin.a", "lib/f.o"]);
+    whale_ok(&dir, &["ar", "rcsT", "thin.a", "lib/g.o"]);
+    let bytes = fs::read(dir.join("thin.a")).unwrap();
+    assert!(bytes.starts_with(b"!<thin>\n"));
+    assert!(bytes.len() < fs::metadata(dir.join("lib/f.o")).unwrap().len() as usize);
+    assert_eq!(whale_ok(&dir, &["ar", "t", "thin.a"]), "lib/f.o\nlib/g.o\n");
+    let archive = object::archive::Archive::parse(&bytes).unwrap();
+    assert_eq!((archive.find_symbol("f"), archive.find_symbol("g")), (Some(0), Some(1)));
+
+    // Mixing the two kinds is refused, as is extracting from a thin archive
+    assert_eq!(whale(&dir, &["ar", "rcs", "thin.a", "lib/f.o"]).status.code(), Some(1));
+    assert_eq!(whale(&dir, &["ar", "x", "thin.a"]).status.code(), Some(1));
+}
+
+#[test]
+fn objcopy_removes_renames_and_strips() {
+    let dir = scratch("objcopy");
+    assemble(&dir, "a.o", "global f\nsection .text\nf:\nret\nhelper:\nret\nsection .data\nptr:\ndq f\nsection .debug_info\ndb 1, 2, 3\n");
+    let parse = |name: &str| object::ObjectFile::parse(&fs::read(dir.join(name)).unwrap()).unwrap();
+
+    whale_ok(&dir, &["objcopy", "--rename-section", ".text=.text.boot", "-g", "-x", "a.o", "b.o"]);
+    let b = parse("b.o");
+    let names: Vec<_> = b.sections.iter().map(|s| s.name.as_str()).collect();
+    assert_eq!(names, vec![".text.boot", ".data", ".note.GNU-stack"]);
+    assert!(b.symbols.iter().all(|s| s.name != "helper" && s.name != "ptr"));
+    let f = b.symbols.iter().find(|s| s.name == "f").unwrap();
+    assert_eq!(f.section_index, Some(0));
+    assert_eq!((b.relocations[0].section_index, b.relocations[0].symbol.as_str()), (1, "f"));
+
+    // .data still refers to f in .text, so .text cannot go alone
+    let out = whale(&dir, &["objcopy", "-R", ".text", "a.o", "c.o"]);
+    assert_eq!(out.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&out.stderr).contains("section .text is still referenced by a relocation in .data"));
+    assert!(!dir.join("c.o").exists());
+    whale_ok(&dir, &["objcopy", "-R", ".text", "-R", ".data", "a.o", "c.o"]);
+    let c = parse("c.o");
+    assert_eq!(c.sections.iter().map(|s| s.name.as_str()).collect::<Vec<_>>(), vec![".debug_info", ".note.GNU-stack"]);
+    assert!(c.relocations.is_empty());
+
+    // Symbols that relocations use are kept
+    assert_eq!(whale(&dir, &["objcopy", "-N", "f", "a.o", "d.o"]).status.code(), Some(1));
+    whale_ok(&dir, &["objcopy", "-S", "a.o", "d.o"]);
+    assert_eq!(parse("d.o").symbols.iter().filter(|s| s.name == "f").count(), 1);
+}
+
+#[test]
+fn object_wraps_a_blob_with_start_end_and_absolute_size() {
+    let dir = scratch("blob");
+    fs::write(dir.join("blob.bin"), vec![0x5A; 1000]).unwrap();
+    let parse = |name: &str| object::ObjectFile::parse(&fs::read(dir.join(name)).unwrap()).unwrap();
+    let symbol = |obj: &object::ObjectFile, name: &str| obj.symbols.iter().find(|s| s.name == name).cloned().unwrap();
+
+    whale_ok(&dir, &["object", "blob.bin", "--kind", "rodata", "-o", "blob.o"]);
+    let obj = parse("blob.o");
+    let rodata = obj.find_section(".rodata").unwrap();
+    assert_eq!(obj.sections[rodata].data, vec![0x5A; 1000]);
+    assert_eq!((symbol(&obj, "_binary_blob_bin_start").section_index, symbol(&obj, "_binary_blob_bin_start").value), (Some(rodata), 0));
+    assert_eq!((symbol(&obj, "_binary_blob_bin_end").section_index, symbol(&obj, "_binary_blob_bin_end").value), (Some(rodata), 1000));
+    let size = symbol(&obj, "_binary_blob_bin_size");
+    assert_eq!((size.section_index, size.absolute, size.value), (None, true, 1000));
+
+    whale_ok(&dir, &["object", "blob.bin", "--section", ".assets", "--kind", "data", "--align", "64", "--symbol-prefix", "logo", "-o", "logo.o"]);
+    let obj = parse("logo.o");
+    let assets = &obj.sections[obj.find_section(".assets").unwrap()];
+    assert_eq!(assets.align, 64);
+    assert!(assets.flags.contains(object::SectionFlags::WRITE));
+    assert!(symbol(&obj, "logo_size").absolute);
+
+    assert!(whale_ok(&dir, &["object", "blob.bin", "-o", "blob.o", "--debug-whale", "--dump-hex"]).contains("0000: 7F 45 4C 46"));
+}
+
+#[test]
+fn readobj_prints_tables_and_json() {
+    let dir = scratch("readobj");
+    assemble(&dir, "a.o", "global f\nsection .text\nf:\nret\nsection .data\nptr:\ndq f\n");
+
+    let text = whale_ok(&dir, &["readobj", "a.o"]);
+    for expected in ["Format:       ELF64", "[ 1] .data", "GLOBAL DEFAULT  .text            f", "Relocations for '.data' (1 entries):", "Absolute64"] {
+        assert!(text.contains(expected), "missing {:?} in\n{}", expected, text);
+    }
+    let json = whale_ok(&dir, &["readobj", "--json", "-r", "-x", ".data", "a.o"]);
+    assert!(json.contains(r#"{"section": ".data", "offset": 0, "kind": "Absolute64", "symbol": "f", "subtrahend": null, "addend": 0}"#), "{}", json);
+    assert!(json.contains(r#"{"section": ".data", "data": "0000000000000000"}"#), "{}", json);
+
+    assert_eq!(whale(&dir, &["readobj", "-x", ".bss", "a.o"]).status.code(), Some(1));
+    assert_eq!(whale(&dir, &["readobj", "a.o.asm"]).status.code(), Some(1));
+}
+
+#[test]
+fn asm_subsections_get_the_flags_of_their_base_section() {
+    let dir = scratch("asm-sections");
+    assemble(&dir, "a.o", "global f\nsection .text.hot\nf:\nret\nsection .rodata.msg\ndb 1\nsection .bss.buf\ndb 0\nsection .textual\ndb 2\n");
+    let obj = object::ObjectFile::parse(&fs::read(dir.join("a.o")).unwrap()).unwrap();
+    let flags = |name: &str| obj.sections[obj.find_section(name).unwrap()].flags;
+    use object::SectionFlags as F;
+
+    assert_eq!(flags(".text.hot"), F::ALLOC | F::EXECINSTR);
+    assert_eq!(obj.sections[obj.find_section(".text.hot").unwrap()].align, 16);
+    assert_eq!(flags(".rodata.msg"), F::ALLOC);
+    assert_eq!(flags(".bss.buf"), F::ALLOC | F::WRITE);
+    assert!(obj.sections[obj.find_section(".bss.buf").unwrap()].is_nobits());
+    assert_eq!(flags(".textual"), F::ALLOC | F::WRITE);
+}
+
+#[test]
+fn asm_marks_elf_objects_for_cet_unless_told_not_to() {
+    let dir = scratch("asm-cet");
+    let source = "global f\nglobal table\nsection .text.hot\nf:\nret\ntable:\ndq 0\n";
+    fs::write(dir.join("a.asm"), source).unwrap();
+    let parse = |name: &str| object::ObjectFile::parse(&fs::read(dir.join(name)).unwrap()).unwrap();
+
+    whale_ok(&dir, &["asm", "--amd64", "a.asm", "-o", "cet.o"]);
+    let obj = parse("cet.o");
+    assert_eq!(obj.gnu_properties.x86_features, Some(object::X86Features::IBT | object::X86Features::SHSTK));
+    let mut expected = vec![0xF3, 0x0F, 0x1E, 0xFA, 0xC3];
+    expected.extend_from_slice(&[0; 8]);
+    assert_eq!(obj.sections[obj.find_section(".text.hot").unwrap()].data, expected);
+
+    whale_ok(&dir, &["asm", "--amd64", "-z", "nocet", "a.asm", "-o", "nocet.o"]);
+    let obj = parse("nocet.o");
+    assert!(obj.gnu_properties.is_empty());
+    assert_eq!(obj.sections[obj.find_section(".text.hot").unwrap()].data[0], 0xC3);
+
+    // Other formats have no property notes, so only an explicit request is an error
+    whale_ok(&dir, &["asm", "--amd64", "a.asm", "-o", "a.obj"]);
+    assert_eq!(whale(&dir, &["asm", "--amd64", "-z", "cet", "a.asm", "-o", "a.obj"]).status.code(), Some(1));
+}